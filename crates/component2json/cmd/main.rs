@@ -20,9 +20,11 @@ async fn main() -> Result<()> {
     config.async_support(true);
     let engine = Arc::new(Engine::new(&config)?);
 
-    let component = Arc::new(Component::from_file(&engine, path)?);
+    let wasm = std::fs::read(path)?;
+    let component = Arc::new(Component::new(&engine, &wasm)?);
 
-    let schema = component2json::component_exports_to_json_schema(&component, &engine, true);
+    let schema =
+        component2json::component_exports_to_json_schema(&component, &engine, &wasm, true, true);
     if let Some(arr) = schema["tools"].as_array() {
         for t in arr {
             let name = t["name"].as_str().unwrap_or("<unnamed>").to_string();
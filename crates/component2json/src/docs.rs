@@ -0,0 +1,421 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Documentation comments recovered from the WIT embedded in a component
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use wit_parser::{Docs, Function, Resolve, Type, TypeDefKind, WorldId};
+
+use crate::{FunctionIdentifier, TypeDefs};
+
+/// The doc comments of a component's exported functions and of the types they use, decoded from
+/// the WIT embedded in the component binary.
+///
+/// Docs are only available if the binary carries them in a `package-docs` custom section, as WIT
+/// packages encoded as components do. For other components no docs are found, and the generated
+/// descriptions are used instead.
+pub struct ComponentDocs {
+    resolve: Resolve,
+    functions: HashMap<(Option<String>, String), Function>,
+}
+
+impl ComponentDocs {
+    /// Decodes the WIT of the given component binary
+    pub fn decode(wasm: &[u8]) -> anyhow::Result<Self> {
+        let mut functions = HashMap::new();
+        let resolve = match wit_component::decode(wasm)? {
+            wit_component::DecodedWasm::Component(resolve, world) => {
+                collect_world_functions(&resolve, world, &mut functions);
+                resolve
+            }
+            wit_component::DecodedWasm::WitPackage(resolve, package) => {
+                let package = &resolve.packages[package];
+                for &id in package.interfaces.values() {
+                    let interface_name = resolve.id_of(id);
+                    for func in resolve.interfaces[id].functions.values() {
+                        functions.insert((interface_name.clone(), func.name.clone()), func.clone());
+                    }
+                }
+                for &world in package.worlds.values() {
+                    collect_world_functions(&resolve, world, &mut functions);
+                }
+                resolve
+            }
+        };
+        Ok(Self { resolve, functions })
+    }
+
+    pub(crate) fn function(&self, identifier: &FunctionIdentifier) -> Option<FunctionDocs<'_>> {
+        let key = (
+            identifier.interface_name.clone(),
+            identifier.function_name.clone(),
+        );
+        self.functions.get(&key).map(|func| FunctionDocs {
+            resolve: &self.resolve,
+            func,
+        })
+    }
+}
+
+fn collect_world_functions(
+    resolve: &Resolve,
+    world: WorldId,
+    functions: &mut HashMap<(Option<String>, String), Function>,
+) {
+    for (key, item) in &resolve.worlds[world].exports {
+        match item {
+            wit_parser::WorldItem::Interface { id, .. } => {
+                let interface_name = resolve.name_world_key(key);
+                for func in resolve.interfaces[*id].functions.values() {
+                    functions.insert(
+                        (Some(interface_name.clone()), func.name.clone()),
+                        func.clone(),
+                    );
+                }
+            }
+            wit_parser::WorldItem::Function(func) => {
+                functions.insert((None, func.name.clone()), func.clone());
+            }
+            wit_parser::WorldItem::Type(_) => {}
+        }
+    }
+}
+
+/// The WIT definition of a single exported function
+#[derive(Clone, Copy)]
+pub(crate) struct FunctionDocs<'a> {
+    resolve: &'a Resolve,
+    func: &'a Function,
+}
+
+impl FunctionDocs<'_> {
+    /// The doc comment of the function
+    pub(crate) fn description(&self) -> Option<&str> {
+        self.func.docs.contents.as_deref()
+    }
+
+    /// Adds the docs of the parameter types to the `properties` of an input schema
+    pub(crate) fn annotate_params(&self, properties: &mut Value, mut defs: Option<&mut TypeDefs>) {
+        for (name, ty) in &self.func.params {
+            if let Some(schema) = properties.get_mut(name) {
+                self.annotate(schema, ty, defs.as_deref_mut());
+            }
+        }
+    }
+
+    /// Adds the docs of the result type to an output schema
+    pub(crate) fn annotate_result(&self, schema: &mut Value, defs: Option<&mut TypeDefs>) {
+        if let Some(ty) = &self.func.result {
+            self.annotate(schema, ty, defs);
+        }
+    }
+
+    /// Adds the docs of `ty` and of its record fields, variant and enum cases and flags to the
+    /// matching parts of `schema`, which must have been generated for the same type
+    fn annotate(&self, schema: &mut Value, ty: &Type, mut defs: Option<&mut TypeDefs>) {
+        let Type::Id(id) = ty else {
+            return;
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let reference = reference.to_string();
+            if let Some(defs) = defs {
+                if let Some(mut def) = defs.take_schema(&reference) {
+                    self.annotate(&mut def, ty, Some(&mut *defs));
+                    defs.restore_schema(&reference, def);
+                }
+            }
+            return;
+        }
+
+        let typedef = &self.resolve.types[*id];
+        match &typedef.kind {
+            TypeDefKind::Record(record) => {
+                for field in &record.fields {
+                    if let Some(property) =
+                        schema.pointer_mut(&format!("/properties/{}", field.name))
+                    {
+                        self.annotate(property, &field.ty, defs.as_deref_mut());
+                        set_description(property, &field.docs);
+                    }
+                }
+            }
+            TypeDefKind::Variant(variant) => {
+                for (i, case) in variant.cases.iter().enumerate() {
+                    if let Some(case_schema) = schema.pointer_mut(&format!("/oneOf/{i}")) {
+                        if let Some(ty) = &case.ty {
+                            if let Some(val) = case_schema.pointer_mut("/properties/val") {
+                                self.annotate(val, ty, defs.as_deref_mut());
+                            }
+                        }
+                        set_description(case_schema, &case.docs);
+                    }
+                }
+            }
+            TypeDefKind::Enum(enum_) => {
+                let cases = enum_.cases.iter().map(|case| (&case.name, &case.docs));
+                set_documented_names(schema, cases);
+            }
+            TypeDefKind::Flags(flags) => {
+                if let Some(items) = schema.get_mut("items") {
+                    // Flags represented as an array of names
+                    let names = flags.flags.iter().map(|flag| (&flag.name, &flag.docs));
+                    set_documented_names(items, names);
+                }
+                for flag in &flags.flags {
+                    if let Some(property) =
+                        schema.pointer_mut(&format!("/properties/{}", flag.name))
+                    {
+                        set_description(property, &flag.docs);
+                    }
+                }
+            }
+            TypeDefKind::Tuple(tuple) => {
+                for (i, ty) in tuple.types.iter().enumerate() {
+                    if let Some(item) = schema.pointer_mut(&format!("/prefixItems/{i}")) {
+                        self.annotate(item, ty, defs.as_deref_mut());
+                    }
+                }
+            }
+            TypeDefKind::List(ty) => {
+                if let Some(items) = schema.get_mut("items") {
+                    self.annotate(items, ty, defs);
+                }
+            }
+            TypeDefKind::Option(ty) => {
+                if let Some(some) = schema.pointer_mut("/anyOf/1") {
+                    self.annotate(some, ty, defs);
+                }
+            }
+            TypeDefKind::Result(result) => {
+                if let Some(ok) = &result.ok {
+                    if let Some(ok_schema) = schema.pointer_mut("/oneOf/0/properties/ok") {
+                        self.annotate(ok_schema, ok, defs.as_deref_mut());
+                    }
+                }
+                if let Some(err) = &result.err {
+                    if let Some(err_schema) = schema.pointer_mut("/oneOf/1/properties/err") {
+                        self.annotate(err_schema, err, defs);
+                    }
+                }
+            }
+            TypeDefKind::Type(ty) => self.annotate(schema, ty, defs),
+            _ => {}
+        }
+        set_description(schema, &typedef.docs);
+    }
+}
+
+/// Adds a `oneOf` with a documented `const` for every name to the schema of a string that can
+/// only take the given names, if any of them has docs
+fn set_documented_names<'a>(
+    schema: &mut Value,
+    names: impl Iterator<Item = (&'a String, &'a Docs)> + Clone,
+) {
+    if !names.clone().any(|(_, docs)| docs.contents.is_some()) {
+        return;
+    }
+    let cases: Vec<Value> = names
+        .map(|(name, docs)| {
+            let mut case_schema = json!({ "const": name });
+            set_description(&mut case_schema, docs);
+            case_schema
+        })
+        .collect();
+    schema["oneOf"] = Value::Array(cases);
+}
+
+fn set_description(schema: &mut Value, docs: &Docs) {
+    if let (Some(schema), Some(docs)) = (schema.as_object_mut(), &docs.contents) {
+        schema.insert("description".to_string(), json!(docs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::component::Component;
+    use wasmtime::Engine;
+
+    use super::*;
+    use crate::{component_exports_to_json_schema, component_exports_to_tools};
+
+    const DOCUMENTED_WIT: &str = r#"
+package test:docs;
+
+interface shapes {
+    /// A point in 2D space
+    record point {
+        /// Horizontal position
+        x: s32,
+        y: s32,
+    }
+
+    enum color {
+        /// The color of blood
+        red,
+        green,
+    }
+
+    flags style {
+        /// Drawn with a thicker line
+        bold,
+        dashed,
+    }
+
+    /// Moves a point by the given offset
+    translate: func(p: point, dx: s32, c: color, s: style) -> point;
+
+    undocumented: func(p: point);
+}
+"#;
+
+    fn documented_component() -> Vec<u8> {
+        let mut resolve = Resolve::default();
+        let package = resolve.push_str("docs.wit", DOCUMENTED_WIT).unwrap();
+        wit_component::encode(&resolve, package).unwrap()
+    }
+
+    #[test]
+    fn test_tool_descriptions_from_docs() {
+        let engine = Engine::default();
+        let wasm = documented_component();
+        let component = Component::new(&engine, &wasm).unwrap();
+
+        let tools = component_exports_to_tools(&component, &engine, &wasm, true, true);
+        let tool = |name: &str| {
+            &tools
+                .iter()
+                .find(|t| t.identifier.function_name == name)
+                .unwrap_or_else(|| panic!("No tool for {name}"))
+                .schema
+        };
+
+        let translate = tool("translate");
+        assert_eq!(
+            translate["description"],
+            "Moves a point by the given offset"
+        );
+        let point = &translate["inputSchema"]["properties"]["p"];
+        assert_eq!(point["description"], "A point in 2D space");
+        assert_eq!(
+            point["properties"]["x"]["description"],
+            "Horizontal position"
+        );
+        assert!(point["properties"]["y"].get("description").is_none());
+        assert_eq!(
+            translate["outputSchema"]["description"],
+            "A point in 2D space"
+        );
+
+        let color = &translate["inputSchema"]["properties"]["c"];
+        assert_eq!(color["enum"], json!(["red", "green"]));
+        assert_eq!(
+            color["oneOf"],
+            json!([
+                { "const": "red", "description": "The color of blood" },
+                { "const": "green" },
+            ])
+        );
+
+        let style = &translate["inputSchema"]["properties"]["s"];
+        assert_eq!(
+            style["items"]["oneOf"],
+            json!([
+                { "const": "bold", "description": "Drawn with a thicker line" },
+                { "const": "dashed" },
+            ])
+        );
+
+        // Functions without docs keep the generated description
+        assert_eq!(
+            tool("undocumented")["description"],
+            "Auto-generated schema for function 'test_docs_shapes_undocumented'"
+        );
+
+        // The docs are part of the hash
+        assert_eq!(
+            translate[crate::TOOL_HASH_FIELD],
+            crate::tool_schema_hash(translate)
+        );
+
+        // Without `include_docs` the docs aren't decoded
+        let tools = component_exports_to_tools(&component, &engine, &wasm, true, false);
+        assert!(tools.iter().all(|tool| tool.schema["description"]
+            .as_str()
+            .unwrap()
+            .starts_with("Auto-generated schema")));
+    }
+
+    #[test]
+    fn test_docs_on_shared_definitions() {
+        let engine = Engine::default();
+        let wasm = documented_component();
+        let component = Component::new(&engine, &wasm).unwrap();
+
+        // `point` is used by both functions, so its docs end up in `$defs`
+        let schema = component_exports_to_json_schema(&component, &engine, &wasm, true, true);
+        let defs = schema["$defs"].as_object().unwrap();
+        let point = defs.values().find(|def| def["type"] == "object").unwrap();
+        assert_eq!(point["description"], "A point in 2D space");
+        assert_eq!(
+            point["properties"]["x"]["description"],
+            "Horizontal position"
+        );
+
+        // Hashes match the ones of the self-contained tool schemas
+        let tools = component_exports_to_tools(&component, &engine, &wasm, true, true);
+        for tool in &tools {
+            let in_document = schema["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|t| t["name"] == tool.normalized_name.as_str())
+                .unwrap();
+            assert_eq!(
+                in_document[crate::TOOL_HASH_FIELD],
+                tool.schema[crate::TOOL_HASH_FIELD]
+            );
+        }
+    }
+
+    #[test]
+    fn test_docs_from_package_docs_section() {
+        // The layout `wit-component` uses for a WIT package with a single interface, with the docs
+        // in a version 0 `package-docs` section
+        let wat = r#"(component
+            (type (component
+                (type (instance
+                    (type (record (field "x" s32) (field "y" s32)))
+                    (export "point" (type (eq 0)))
+                    (type (func (param "p" 1) (result 1)))
+                    (export "mirror" (func (type 2)))
+                ))
+                (export "test:docs/shapes" (instance (type 0)))
+            ))
+            (export "shapes" (type 0))
+            (@custom "package-docs" "\00{\22interfaces\22:{\22shapes\22:{\22funcs\22:{\22mirror\22:\22Mirrors a point\22},\22types\22:{\22point\22:{\22docs\22:\22A point in 2D space\22,\22items\22:{\22x\22:\22Horizontal position\22}}}}}}")
+        )"#;
+        let engine = Engine::default();
+        let wasm = wat::parse_str(wat).unwrap();
+        let component = Component::new(&engine, &wasm).unwrap();
+
+        let tools = component_exports_to_tools(&component, &engine, &wasm, true, true);
+        assert_eq!(tools.len(), 1);
+        let mirror = &tools[0].schema;
+        assert_eq!(mirror["description"], "Mirrors a point");
+        let point = &mirror["inputSchema"]["properties"]["p"];
+        assert_eq!(point["description"], "A point in 2D space");
+        assert_eq!(
+            point["properties"]["x"]["description"],
+            "Horizontal position"
+        );
+        assert_eq!(mirror["outputSchema"]["description"], "A point in 2D space");
+    }
+
+    #[test]
+    fn test_decode_invalid_component() {
+        assert!(ComponentDocs::decode(b"not a component").is_err());
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Rendering component values as WIT-like literals, for logs and debugging output
+
+use std::fmt::Write;
+
+use wasmtime::component::{Type, Val};
+
+/// Renders a value of the given type in a WIT-like literal syntax, e.g. `some("x")`, `ok(42)`,
+/// `circle(3)` or `{name: "a", value: 3}`.
+///
+/// Unlike [`crate::val_to_json`], the rendering keeps the distinction between options, results,
+/// variants and enums, which makes it better suited for log messages. Strings and chars are quoted
+/// and escaped, flags are rendered as `{read, write}` and resources as `own<resource>` or
+/// `borrow<resource>`. If `val` doesn't match `ty`, it is rendered from the value alone.
+pub fn format_val(val: &Val, ty: &Type) -> String {
+    let mut out = String::new();
+    write_val(&mut out, val, Some(ty));
+    out
+}
+
+fn write_val(out: &mut String, val: &Val, ty: Option<&Type>) {
+    // Writing to a `String` can't fail
+    match val {
+        Val::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Val::S8(n) => write!(out, "{n}").unwrap(),
+        Val::U8(n) => write!(out, "{n}").unwrap(),
+        Val::S16(n) => write!(out, "{n}").unwrap(),
+        Val::U16(n) => write!(out, "{n}").unwrap(),
+        Val::S32(n) => write!(out, "{n}").unwrap(),
+        Val::U32(n) => write!(out, "{n}").unwrap(),
+        Val::S64(n) => write!(out, "{n}").unwrap(),
+        Val::U64(n) => write!(out, "{n}").unwrap(),
+        Val::Float32(f) => write!(out, "{f:?}").unwrap(),
+        Val::Float64(f) => write!(out, "{f:?}").unwrap(),
+        Val::Char(c) => write!(out, "{c:?}").unwrap(),
+        Val::String(s) => write!(out, "{s:?}").unwrap(),
+
+        Val::List(items) => {
+            let item_ty = match ty {
+                Some(Type::List(list)) => Some(list.ty()),
+                _ => None,
+            };
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_val(out, item, item_ty.as_ref());
+            }
+            out.push(']');
+        }
+        Val::Record(fields) => {
+            let record = match ty {
+                Some(Type::Record(record)) => Some(record),
+                _ => None,
+            };
+            out.push('{');
+            for (i, (name, field)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                let field_ty = record.and_then(|record| {
+                    record
+                        .fields()
+                        .find(|field| field.name == name)
+                        .map(|field| field.ty)
+                });
+                write!(out, "{name}: ").unwrap();
+                write_val(out, field, field_ty.as_ref());
+            }
+            out.push('}');
+        }
+        Val::Tuple(items) => {
+            let mut item_types = match ty {
+                Some(Type::Tuple(tuple)) => Some(tuple.types()),
+                _ => None,
+            };
+            out.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                let item_ty = item_types.as_mut().and_then(Iterator::next);
+                write_val(out, item, item_ty.as_ref());
+            }
+            out.push(')');
+        }
+
+        Val::Variant(case, payload) => {
+            out.push_str(case);
+            if let Some(payload) = payload {
+                let payload_ty = match ty {
+                    Some(Type::Variant(variant)) => {
+                        variant.cases().find(|c| c.name == case).and_then(|c| c.ty)
+                    }
+                    _ => None,
+                };
+                write_payload(out, payload, payload_ty.as_ref());
+            }
+        }
+        Val::Enum(case) => out.push_str(case),
+        Val::Flags(flags) => {
+            out.push('{');
+            for (i, flag) in flags.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(flag);
+            }
+            out.push('}');
+        }
+
+        Val::Option(None) => out.push_str("none"),
+        Val::Option(Some(val)) => {
+            let some_ty = match ty {
+                Some(Type::Option(option)) => Some(option.ty()),
+                _ => None,
+            };
+            out.push_str("some");
+            write_payload(out, val, some_ty.as_ref());
+        }
+        Val::Result(result) => {
+            let result_ty = match ty {
+                Some(Type::Result(result)) => Some(result),
+                _ => None,
+            };
+            let (case, payload, payload_ty) = match result {
+                Ok(payload) => ("ok", payload, result_ty.and_then(|r| r.ok())),
+                Err(payload) => ("err", payload, result_ty.and_then(|r| r.err())),
+            };
+            out.push_str(case);
+            if let Some(payload) = payload {
+                write_payload(out, payload, payload_ty.as_ref());
+            }
+        }
+
+        Val::Resource(_) => match ty {
+            Some(Type::Borrow(_)) => out.push_str("borrow<resource>"),
+            _ => out.push_str("own<resource>"),
+        },
+    }
+}
+
+/// Writes the payload of a case, option or result in parentheses
+fn write_payload(out: &mut String, payload: &Val, ty: Option<&Type>) {
+    out.push('(');
+    write_val(out, payload, ty);
+    out.push(')');
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::component::Component;
+    use wasmtime::Engine;
+
+    use super::*;
+    use crate::exported_functions;
+
+    /// Returns the parameter types of a function taking one parameter of every kind of type
+    fn param_types() -> Vec<(String, Type)> {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (record (field "name" string) (field "value" u32)))
+                    (export "entry" (type (eq 0)))
+                    (type (variant (case "circle" u32) (case "empty")))
+                    (export "shape" (type (eq 2)))
+                    (type (enum "red" "green"))
+                    (export "color" (type (eq 4)))
+                    (type (flags "read" "write"))
+                    (export "mode" (type (eq 6)))
+                    (type (option string))
+                    (type (result u32 (error string)))
+                    (type (list 1))
+                    (type (tuple string 8))
+                    (type (result))
+                    (type (list 10))
+                    (type (func
+                        (param "entry" 1) (param "shape" 3) (param "color" 5) (param "mode" 7)
+                        (param "maybe" 8) (param "outcome" 9) (param "entries" 10)
+                        (param "pair" 11) (param "done" 12) (param "nested" 13)
+                        (param "letter" char) (param "text" string) (param "number" float64)
+                        (param "flag" bool) (param "big" s64)
+                    ))
+                    (export "show" (func (type 14)))
+                ))
+                (export "test:fmt/fmt" (component (type 0)))
+            ))
+            (export "fmt" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let (_, func) = exported_functions(&component, &engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        func.params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect()
+    }
+
+    fn format_param(name: &str, val: &Val) -> String {
+        let types = param_types();
+        let (_, ty) = types.iter().find(|(n, _)| n == name).unwrap();
+        format_val(val, ty)
+    }
+
+    fn entry(name: &str, value: u32) -> Val {
+        Val::Record(vec![
+            ("name".to_string(), Val::String(name.to_string())),
+            ("value".to_string(), Val::U32(value)),
+        ])
+    }
+
+    #[test]
+    fn test_format_primitives() {
+        assert_eq!(format_param("flag", &Val::Bool(true)), "true");
+        assert_eq!(format_param("big", &Val::S64(-42)), "-42");
+        assert_eq!(format_param("number", &Val::Float64(3.0)), "3.0");
+        assert_eq!(format_param("number", &Val::Float64(f64::NAN)), "NaN");
+        assert_eq!(format_param("letter", &Val::Char('x')), "'x'");
+        assert_eq!(
+            format_param("text", &Val::String("say \"hi\"\n".to_string())),
+            r#""say \"hi\"\n""#
+        );
+    }
+
+    #[test]
+    fn test_format_records_and_collections() {
+        assert_eq!(
+            format_param("entry", &entry("a", 3)),
+            r#"{name: "a", value: 3}"#
+        );
+        assert_eq!(
+            format_param("entries", &Val::List(vec![entry("a", 1), entry("b", 2)])),
+            r#"[{name: "a", value: 1}, {name: "b", value: 2}]"#
+        );
+        assert_eq!(format_param("entries", &Val::List(vec![])), "[]");
+        assert_eq!(
+            format_param(
+                "pair",
+                &Val::Tuple(vec![
+                    Val::String("x".to_string()),
+                    Val::Option(Some(Box::new(Val::String("y".to_string())))),
+                ])
+            ),
+            r#"("x", some("y"))"#
+        );
+        assert_eq!(
+            format_param(
+                "nested",
+                &Val::List(vec![Val::List(vec![entry("a", 1)]), Val::List(vec![])])
+            ),
+            r#"[[{name: "a", value: 1}], []]"#
+        );
+    }
+
+    #[test]
+    fn test_format_variants_and_enums() {
+        assert_eq!(
+            format_param(
+                "shape",
+                &Val::Variant("circle".to_string(), Some(Box::new(Val::U32(3))))
+            ),
+            "circle(3)"
+        );
+        assert_eq!(
+            format_param("shape", &Val::Variant("empty".to_string(), None)),
+            "empty"
+        );
+        assert_eq!(format_param("color", &Val::Enum("red".to_string())), "red");
+        assert_eq!(
+            format_param(
+                "mode",
+                &Val::Flags(vec!["read".to_string(), "write".to_string()])
+            ),
+            "{read, write}"
+        );
+        assert_eq!(format_param("mode", &Val::Flags(vec![])), "{}");
+    }
+
+    #[test]
+    fn test_format_options_and_results() {
+        assert_eq!(
+            format_param(
+                "maybe",
+                &Val::Option(Some(Box::new(Val::String("x".to_string()))))
+            ),
+            r#"some("x")"#
+        );
+        assert_eq!(format_param("maybe", &Val::Option(None)), "none");
+        assert_eq!(
+            format_param("outcome", &Val::Result(Ok(Some(Box::new(Val::U32(42)))))),
+            "ok(42)"
+        );
+        assert_eq!(
+            format_param(
+                "outcome",
+                &Val::Result(Err(Some(Box::new(Val::String("boom".to_string())))))
+            ),
+            r#"err("boom")"#
+        );
+        assert_eq!(format_param("done", &Val::Result(Ok(None))), "ok");
+        assert_eq!(format_param("done", &Val::Result(Err(None))), "err");
+    }
+
+    #[test]
+    fn test_format_mismatched_type() {
+        // The value is rendered on its own if it doesn't match the type
+        assert_eq!(
+            format_param("text", &Val::Option(Some(Box::new(Val::U8(1))))),
+            "some(1)"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Schemas of the functions a component imports
+
+use serde_json::{json, Value};
+use wasmtime::component::types::ComponentFunc;
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::{component_func_to_schema, gather_functions, FunctionIdentifier, SchemaOptions};
+
+/// Given a component and a wasmtime engine, return a JSON object describing the functions the
+/// component imports, i.e. what a host has to provide to instantiate it:
+///
+/// ```json
+/// { "imports": [{ "interface": "wasi:cli/environment@0.2.0", "name": "get-environment",
+///                 "inputSchema": { ... }, "outputSchema": { ... } }] }
+/// ```
+///
+/// `interface` is `null` for functions imported directly by the component. The schemas are the
+/// same as the ones of [`crate::component_exports_to_json_schema`], without shared `$defs`.
+pub fn component_imports_to_json_schema(component: &Component, engine: &Engine) -> Value {
+    let imports: Vec<Value> = imported_functions(component, engine)
+        .iter()
+        .map(|(identifier, func)| {
+            let mut schema = component_func_to_schema(
+                &identifier.function_name,
+                func,
+                true,
+                None,
+                None,
+                SchemaOptions::default(),
+            );
+            let mut import = json!({
+                "interface": identifier.interface_name,
+                "name": identifier.function_name,
+                "inputSchema": schema["inputSchema"].take(),
+            });
+            if let Some(output_schema) = schema.get_mut("outputSchema") {
+                import["outputSchema"] = output_schema.take();
+            }
+            import
+        })
+        .collect();
+    json!({ "imports": imports })
+}
+
+fn imported_functions(
+    component: &Component,
+    engine: &Engine,
+) -> Vec<(FunctionIdentifier, ComponentFunc)> {
+    let mut functions = Vec::new();
+    for (import_name, import_item) in component.component_type().imports(engine) {
+        gather_functions(
+            import_name,
+            None,
+            None,
+            &import_item,
+            engine,
+            &mut functions,
+        );
+    }
+    functions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_imports() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (import "test:host/log@0.1.0" (instance
+                (export "write" (func (param "level" u8) (param "message" string)))
+            ))
+            (import "now" (func (result u64)))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let schema = component_imports_to_json_schema(&component, &engine);
+
+        assert_eq!(
+            schema["imports"],
+            json!([
+                {
+                    "interface": "test:host/log@0.1.0",
+                    "name": "write",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "level": { "type": "number" },
+                            "message": { "type": "string" }
+                        },
+                        "required": ["level", "message"]
+                    }
+                },
+                {
+                    "interface": null,
+                    "name": "now",
+                    "inputSchema": { "type": "object", "properties": {}, "required": [] },
+                    "outputSchema": { "type": "number" }
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_exports_are_not_imports() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (core module $m (func (export "f")))
+            (core instance $i (instantiate $m))
+            (func (export "run") (canon lift (core func $i "f")))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let schema = component_imports_to_json_schema(&component, &engine);
+        assert_eq!(schema, json!({ "imports": [] }));
+    }
+}
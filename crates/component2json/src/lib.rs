@@ -3,12 +3,34 @@
 
 #![doc = include_str!("../README.md")]
 
+mod docs;
+mod format;
+mod imports;
+mod openapi;
+mod schema_cache;
+mod typescript;
+mod validate;
+
 use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use wasmtime::component::types::{ComponentFunc, ComponentItem};
-use wasmtime::component::{Component, Type, Val};
+use wasmtime::component::{Component, ResourceAny, Type, Val};
 use wasmtime::Engine;
 
+pub use crate::docs::ComponentDocs;
+use crate::docs::FunctionDocs;
+pub use crate::format::format_val;
+pub use crate::imports::component_imports_to_json_schema;
+pub use crate::openapi::{
+    component_exports_to_openapi, component_exports_to_openapi_with_options, OPENAPI_VERSION,
+};
+pub use crate::schema_cache::{ContentHash, SchemaCache};
+pub use crate::typescript::{
+    component_exports_to_typescript, component_exports_to_typescript_with_options,
+};
+pub use crate::validate::validate_json_for_type;
+
 /// Function identifier for tools, containing WIT package, WIT interface, and function names.
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionIdentifier {
@@ -43,10 +65,10 @@ pub enum ValError {
     #[error("cannot interpret number as i64 or f64: {0}")]
     NumberError(String),
 
-    /// A character field was invalid, for example an empty or multi-character string
-    /// when you expected a single char.
-    #[error("invalid char: {0}")]
-    InvalidChar(String),
+    /// A value for a `char` wasn't a single unicode scalar value, for example an empty string or
+    /// a character made of several scalar values like an emoji with a skin tone modifier.
+    #[error("invalid char {value}: {reason}")]
+    InvalidChar { value: String, reason: String },
 
     /// An object had an unexpected shape for a particular conceptual type.
     #[error("expected object shape for {0}, found: {1}")]
@@ -56,11 +78,130 @@ pub enum ValError {
     #[error("unknown object shape: {0:?}")]
     UnknownShape(serde_json::Map<String, Value>),
 
+    /// A JSON object had keys that don't correspond to any parameter or record field. Only
+    /// returned by [`json_to_vals_strict`] and in [`JsonToValOptions::strict`] mode.
+    #[error("unknown fields: {}", .0.join(", "))]
+    UnknownFields(Vec<String>),
+
     /// Could not interpret a resource from the JSON field(s).
     #[error("cannot interpret resource from JSON")]
     ResourceError,
 }
 
+/// The largest integer that can be represented exactly by a 64-bit float, which is what JSON
+/// numbers are parsed into by JavaScript clients
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Options for converting JSON to [`Val`]s, see [`json_to_vals_with_options`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonToValOptions {
+    /// Reject keys that aren't parameter or record field names, see [`json_to_vals_strict`]
+    pub strict: bool,
+    /// Also accept a JSON number as a `char`, interpreted as a unicode codepoint (`65` is `'A'`)
+    pub char_codepoints: bool,
+}
+
+/// Options for converting [`Val`]s to JSON, see [`vals_to_json_with_options`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValToJsonOptions {
+    /// Emit `u64` and `s64` values outside of the range that 64-bit floats can represent exactly
+    /// (`±(2^53 - 1)`) as decimal strings instead of numbers, so that clients that parse JSON
+    /// numbers as floats don't silently lose precision.
+    pub large_integers_as_strings: bool,
+    /// How flags are represented, see [`FlagsRepr`]
+    pub flags: FlagsRepr,
+}
+
+/// How the value of a `flags` type is represented in JSON.
+///
+/// [`json_to_vals`] accepts both representations, this only affects the generated schemas (see
+/// [`SchemaOptions`]) and the JSON produced by [`vals_to_json_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlagsRepr {
+    /// An array with the names of the flags that are set, e.g. `["read", "write"]`
+    #[default]
+    Array,
+    /// An object with a boolean for every flag, e.g. `{"read": true, "write": false}`. Flags that
+    /// are missing from the object are not set. When converting values, only the flags that are
+    /// set are emitted.
+    Object,
+}
+
+/// Options for generating schemas from component exports, see
+/// [`component_exports_to_tools_with_options`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchemaOptions {
+    /// How flags are represented. Should match the [`ValToJsonOptions`] used for the results, so
+    /// that the output schemas describe the JSON that is actually returned.
+    pub flags: FlagsRepr,
+}
+
+/// The field of the JSON object that represents a resource handle, e.g. `{"$resource": 0}`
+pub const RESOURCE_HANDLE_FIELD: &str = "$resource";
+
+/// The field of the JSON object that represents a float that JSON numbers can't hold:
+/// `{"$float": "nan"}`, `{"$float": "inf"}` or `{"$float": "-inf"}`
+pub const FLOAT_FIELD: &str = "$float";
+
+/// Looks up the resource behind a handle ID, so that resource handles received as JSON can be
+/// passed back to a component. See [`json_to_vals_with_resources`].
+pub trait ResourceResolver {
+    /// Returns the resource with the given handle ID, if there is one
+    fn resolve(&self, id: u32) -> Option<ResourceAny>;
+}
+
+impl<F: Fn(u32) -> Option<ResourceAny>> ResourceResolver for F {
+    fn resolve(&self, id: u32) -> Option<ResourceAny> {
+        self(id)
+    }
+}
+
+/// A table of the resources returned by components, which assigns each resource a stable handle
+/// ID.
+///
+/// The owner of the table keeps the resources alive for as long as their handles may be passed
+/// back to a component.
+#[derive(Debug, Default)]
+pub struct ResourceHandles {
+    resources: Vec<ResourceAny>,
+}
+
+impl ResourceHandles {
+    /// Creates an empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a resource to the table and returns its handle ID. Adding the same resource again
+    /// returns the same ID.
+    pub fn insert(&mut self, resource: ResourceAny) -> u32 {
+        let index = match self.resources.iter().position(|r| *r == resource) {
+            Some(index) => index,
+            None => {
+                self.resources.push(resource);
+                self.resources.len() - 1
+            }
+        };
+        u32::try_from(index).expect("Too many resource handles")
+    }
+
+    /// Returns the number of resources in the table
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    /// Returns `true` if the table doesn't contain any resources
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+impl ResourceResolver for ResourceHandles {
+    fn resolve(&self, id: u32) -> Option<ResourceAny> {
+        self.resources.get(usize::try_from(id).ok()?).copied()
+    }
+}
+
 /// Validates a tool name according to MCP specification
 pub fn validate_tool_name(tool_name: &str) -> Result<(), ValidationError> {
     if tool_name.len() > 128 {
@@ -122,49 +263,177 @@ pub fn normalize_tool_name(identifier: &FunctionIdentifier) -> String {
 /// Given a component and a wasmtime engine, return structured tool metadata with normalized names.
 ///
 /// The `output` parameter determines whether to include the output schema for functions.
+///
+/// If `include_docs` is set, the WIT embedded in `wasm`, the binary the component was compiled
+/// from, is decoded and its doc comments are used as the tool descriptions and as the
+/// descriptions of record fields, variant cases, enum cases and flags, see [`ComponentDocs`].
+/// Functions without docs, and binaries whose WIT can't be decoded, keep the generated
+/// descriptions. Decoding is skipped otherwise.
 pub fn component_exports_to_tools(
     component: &Component,
     engine: &Engine,
+    wasm: &[u8],
     output: bool,
+    include_docs: bool,
 ) -> Vec<ToolMetadata> {
-    let mut tools = Vec::new();
+    let docs = decode_docs(wasm, include_docs);
+    exported_tools(
+        component,
+        engine,
+        output,
+        docs.as_ref(),
+        SchemaOptions::default(),
+    )
+}
 
-    for (export_name, export_item) in component.component_type().exports(engine) {
-        gather_exported_functions_with_metadata(
-            export_name,
-            None,
-            None,
-            &export_item,
-            engine,
-            &mut tools,
-            output,
-        );
-    }
+/// Same as [`component_exports_to_tools`], with docs already decoded with [`ComponentDocs::decode`]
+/// and the given [`SchemaOptions`]
+pub fn component_exports_to_tools_with_options(
+    component: &Component,
+    engine: &Engine,
+    output: bool,
+    docs: Option<&ComponentDocs>,
+    options: SchemaOptions,
+) -> Vec<ToolMetadata> {
+    exported_tools(component, engine, output, docs, options)
+}
 
-    tools
+/// Decodes the docs of the component binary `wasm` if `include_docs` is set
+fn decode_docs(wasm: &[u8], include_docs: bool) -> Option<ComponentDocs> {
+    include_docs
+        .then(|| ComponentDocs::decode(wasm).ok())
+        .flatten()
+}
+
+fn exported_tools(
+    component: &Component,
+    engine: &Engine,
+    output: bool,
+    docs: Option<&ComponentDocs>,
+    options: SchemaOptions,
+) -> Vec<ToolMetadata> {
+    exported_functions(component, engine)
+        .into_iter()
+        .map(|(identifier, func)| {
+            let normalized_name = normalize_tool_name(&identifier);
+            let func_docs = docs.and_then(|docs| docs.function(&identifier));
+            let schema =
+                component_func_to_schema(&normalized_name, &func, output, None, func_docs, options);
+            ToolMetadata {
+                identifier,
+                normalized_name,
+                schema,
+            }
+        })
+        .collect()
 }
 
 /// Given a component and a wasmtime engine, return a full JSON schema of the component's exports.
 ///
-/// The `output` parameter determines whether to include the output schema for functions.
+/// The `output` parameter determines whether to include the output schema for functions, and
+/// `include_docs` whether to describe them with the doc comments of the WIT embedded in `wasm`,
+/// see [`component_exports_to_tools`].
+///
+/// Records, variants, enums and flags that are used more than once across the component's
+/// functions are emitted once in a `$defs` map at the root of the document and referenced with
+/// `$ref` pointers (`#/$defs/<name>`). The `$defs` map is always present, and empty if no types
+/// are shared. The tool schemas returned by [`component_exports_to_tools`] are always
+/// self-contained.
 pub fn component_exports_to_json_schema(
+    component: &Component,
+    engine: &Engine,
+    wasm: &[u8],
+    output: bool,
+    include_docs: bool,
+) -> Value {
+    let docs = decode_docs(wasm, include_docs);
+    exported_json_schema(
+        component,
+        engine,
+        output,
+        docs.as_ref(),
+        SchemaOptions::default(),
+    )
+}
+
+/// Same as [`component_exports_to_json_schema`], with docs already decoded with
+/// [`ComponentDocs::decode`] and the given [`SchemaOptions`]
+pub fn component_exports_to_json_schema_with_options(
+    component: &Component,
+    engine: &Engine,
+    output: bool,
+    docs: Option<&ComponentDocs>,
+    options: SchemaOptions,
+) -> Value {
+    exported_json_schema(component, engine, output, docs, options)
+}
+
+fn exported_json_schema(
     component: &Component,
     engine: &Engine,
     output: bool,
+    docs: Option<&ComponentDocs>,
+    options: SchemaOptions,
 ) -> Value {
-    let tools = component_exports_to_tools(component, engine, output);
-    json!({ "tools": tools.into_iter().map(|t| t.schema).collect::<Vec<_>>() })
+    let functions = exported_functions(component, engine);
+
+    let mut defs = TypeDefs::default();
+    for (_, func) in &functions {
+        defs.count_function(func, output);
+    }
+
+    let tools: Vec<Value> = functions
+        .iter()
+        .map(|(identifier, func)| {
+            let name = normalize_tool_name(identifier);
+            let func_docs = docs.and_then(|docs| docs.function(identifier));
+            component_func_to_schema(&name, func, output, Some(&mut defs), func_docs, options)
+        })
+        .collect();
+
+    json!({ "tools": tools, "$defs": defs.into_defs() })
 }
 
 /// Converts a slice of component model [`Val`] objects into a JSON representation.
 pub fn vals_to_json(vals: &[Val]) -> Value {
+    vals_to_json_with_options(vals, ValToJsonOptions::default())
+}
+
+/// Same as [`vals_to_json`], but with the given [`ValToJsonOptions`].
+pub fn vals_to_json_with_options(vals: &[Val], options: ValToJsonOptions) -> Value {
+    vals_to_json_inner(
+        vals,
+        &mut |res| Value::String(format!("resource: {res:?}")),
+        options,
+    )
+}
+
+/// Same as [`vals_to_json`], but resources are stored in `resources` and encoded as a
+/// `{"$resource": <id>}` object, so that they can be passed back to a component with
+/// [`json_to_vals_with_resources`].
+pub fn vals_to_json_with_resources(vals: &[Val], resources: &mut ResourceHandles) -> Value {
+    vals_to_json_inner(
+        vals,
+        &mut |res| json!({ RESOURCE_HANDLE_FIELD: resources.insert(*res) }),
+        ValToJsonOptions::default(),
+    )
+}
+
+fn vals_to_json_inner(
+    vals: &[Val],
+    resource_to_json: &mut dyn FnMut(&ResourceAny) -> Value,
+    options: ValToJsonOptions,
+) -> Value {
     match vals.len() {
         0 => Value::Null,
-        1 => val_to_json(&vals[0]),
+        1 => val_to_json_with(&vals[0], resource_to_json, options),
         _ => {
             let mut map = Map::new();
             for (i, v) in vals.iter().enumerate() {
-                map.insert(format!("val{i}"), val_to_json(v));
+                map.insert(
+                    format!("val{i}"),
+                    val_to_json_with(v, resource_to_json, options),
+                );
             }
             Value::Object(map)
         }
@@ -172,16 +441,70 @@ pub fn vals_to_json(vals: &[Val]) -> Value {
 }
 
 /// Converts a JSON object to a vector of `Val` objects based on the provided type mappings for each
-/// field.
+/// field. Missing `option` parameters are `none`.
+///
+/// Resource handles can't be converted and result in a [`ValError::ResourceError`], use
+/// [`json_to_vals_with_resources`] for functions that take resources.
 pub fn json_to_vals(value: &Value, types: &[(String, Type)]) -> Result<Vec<Val>, ValError> {
+    json_to_vals_inner(value, types, None, JsonToValOptions::default())
+}
+
+/// Same as [`json_to_vals`], but keys of the JSON object that aren't parameter names, and keys of
+/// nested record objects that aren't field names, are rejected with a
+/// [`ValError::UnknownFields`] error instead of being ignored. This catches misspelled or made-up
+/// argument names.
+pub fn json_to_vals_strict(value: &Value, types: &[(String, Type)]) -> Result<Vec<Val>, ValError> {
+    let options = JsonToValOptions {
+        strict: true,
+        ..Default::default()
+    };
+    json_to_vals_inner(value, types, None, options)
+}
+
+/// Same as [`json_to_vals`], but with the given [`JsonToValOptions`].
+pub fn json_to_vals_with_options(
+    value: &Value,
+    types: &[(String, Type)],
+    options: JsonToValOptions,
+) -> Result<Vec<Val>, ValError> {
+    json_to_vals_inner(value, types, None, options)
+}
+
+/// Same as [`json_to_vals`], but resource handles encoded as `{"$resource": <id>}` (see
+/// [`vals_to_json_with_resources`]) are turned back into resources using `resources`.
+pub fn json_to_vals_with_resources(
+    value: &Value,
+    types: &[(String, Type)],
+    resources: &dyn ResourceResolver,
+) -> Result<Vec<Val>, ValError> {
+    json_to_vals_inner(value, types, Some(resources), JsonToValOptions::default())
+}
+
+fn json_to_vals_inner(
+    value: &Value,
+    types: &[(String, Type)],
+    resources: Option<&dyn ResourceResolver>,
+    options: JsonToValOptions,
+) -> Result<Vec<Val>, ValError> {
     match value {
         Value::Object(obj) => {
+            if options.strict {
+                let known: Vec<&str> = types.iter().map(|(name, _)| name.as_str()).collect();
+                check_unknown_fields(obj, &known)?;
+            }
             let mut results = Vec::new();
             for (name, ty) in types {
-                let value = obj.get(name).ok_or_else(|| {
-                    ValError::ShapeError("object", format!("missing field {name}"))
-                })?;
-                results.push(json_to_val(value, ty)?);
+                let val = match (obj.get(name), ty) {
+                    (Some(value), ty) => json_to_val_with(value, ty, resources, options)?,
+                    (None, Type::Option(_)) => Val::Option(None),
+                    (None, _) => {
+                        return Err(ValError::ShapeError(
+                            "object",
+                            format!("missing field {name}"),
+                        ))
+                    }
+                };
+                results.push(val);
             }
             Ok(results)
         }
@@ -192,13 +515,38 @@ pub fn json_to_vals(value: &Value, types: &[(String, Type)]) -> Result<Vec<Val>,
     }
 }
 
+/// Returns a [`ValError::UnknownFields`] error if `obj` has keys that aren't in `known`
+fn check_unknown_fields(obj: &Map<String, Value>, known: &[&str]) -> Result<(), ValError> {
+    let unknown: Vec<String> = obj
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(ValError::UnknownFields(unknown))
+    }
+}
+
 /// Prepares a placeholder `Vec<Val>` to receive the results of a component function call.
 /// The vector will have the correct length and correctly-typed (but empty/zeroed) values.
 pub fn create_placeholder_results(results: &[Type]) -> Vec<Val> {
     results.iter().map(default_val_for_type).collect()
 }
 
-fn type_to_json_schema(t: &Type) -> Value {
+fn type_to_json_schema(t: &Type, mut defs: Option<&mut TypeDefs>, options: SchemaOptions) -> Value {
+    if let Some(reference) = defs
+        .as_deref_mut()
+        .and_then(|defs| defs.reference(t, options))
+    {
+        return reference;
+    }
+    inline_type_schema(t, defs, options)
+}
+
+/// Returns the schema of `t` itself, even if it is shared. Nested types may still be references
+fn inline_type_schema(t: &Type, mut defs: Option<&mut TypeDefs>, options: SchemaOptions) -> Value {
     match t {
         Type::Bool => json!({ "type": "boolean" }),
         Type::S8
@@ -219,7 +567,7 @@ fn type_to_json_schema(t: &Type) -> Value {
 
         // represent a `list<T>` as an array with items = schema-of-T
         Type::List(list_handle) => {
-            let elem_schema = type_to_json_schema(&list_handle.ty());
+            let elem_schema = type_to_json_schema(&list_handle.ty(), defs.as_deref_mut(), options);
             json!({
                 "type": "array",
                 "items": elem_schema
@@ -230,8 +578,14 @@ fn type_to_json_schema(t: &Type) -> Value {
             let mut props = serde_json::Map::new();
             let mut required_fields = Vec::new();
             for field in r.fields() {
-                required_fields.push(field.name.to_string());
-                props.insert(field.name.to_string(), type_to_json_schema(&field.ty));
+                // Optional fields can be left out, see `json_to_val_with`
+                if !matches!(field.ty, Type::Option(_)) {
+                    required_fields.push(field.name.to_string());
+                }
+                props.insert(
+                    field.name.to_string(),
+                    type_to_json_schema(&field.ty, defs.as_deref_mut(), options),
+                );
             }
             json!({
                 "type": "object",
@@ -241,7 +595,10 @@ fn type_to_json_schema(t: &Type) -> Value {
         }
 
         Type::Tuple(tup) => {
-            let items: Vec<Value> = tup.types().map(|ty| type_to_json_schema(&ty)).collect();
+            let items: Vec<Value> = tup
+                .types()
+                .map(|ty| type_to_json_schema(&ty, defs.as_deref_mut(), options))
+                .collect();
             json!({
                 "type": "array",
                 "prefixItems": items,
@@ -259,7 +616,7 @@ fn type_to_json_schema(t: &Type) -> Value {
                         "type": "object",
                         "properties": {
                             "tag": { "const": case_name },
-                            "val": type_to_json_schema(payload_ty)
+                            "val": type_to_json_schema(payload_ty, defs.as_deref_mut(), options)
                         },
                         "required": ["tag", "val"]
                     }));
@@ -285,7 +642,7 @@ fn type_to_json_schema(t: &Type) -> Value {
         }
 
         Type::Option(opt_handle) => {
-            let inner_schema = type_to_json_schema(&opt_handle.ty());
+            let inner_schema = type_to_json_schema(&opt_handle.ty(), defs.as_deref_mut(), options);
             json!({
                 "anyOf": [
                     { "type": "null" },
@@ -297,12 +654,12 @@ fn type_to_json_schema(t: &Type) -> Value {
         Type::Result(res_handle) => {
             let ok_schema = res_handle
                 .ok()
-                .map(|ok_ty| type_to_json_schema(&ok_ty))
+                .map(|ok_ty| type_to_json_schema(&ok_ty, defs.as_deref_mut(), options))
                 .unwrap_or(json!({ "type": "null" }));
 
             let err_schema = res_handle
                 .err()
-                .map(|err_ty| type_to_json_schema(&err_ty))
+                .map(|err_ty| type_to_json_schema(&err_ty, defs, options))
                 .unwrap_or(json!({ "type": "null" }));
 
             json!({
@@ -325,16 +682,26 @@ fn type_to_json_schema(t: &Type) -> Value {
             })
         }
 
-        Type::Flags(flags_handle) => {
-            let mut props = serde_json::Map::new();
-            for name in flags_handle.names() {
-                props.insert(name.to_string(), json!({"type":"boolean"}));
+        Type::Flags(flags_handle) => match options.flags {
+            FlagsRepr::Array => {
+                let names: Vec<&str> = flags_handle.names().collect();
+                json!({
+                    "type": "array",
+                    "items": { "type": "string", "enum": names },
+                    "uniqueItems": true
+                })
             }
-            json!({
-                "type": "object",
-                "properties": props
-            })
-        }
+            FlagsRepr::Object => {
+                let mut props = serde_json::Map::new();
+                for name in flags_handle.names() {
+                    props.insert(name.to_string(), json!({"type":"boolean"}));
+                }
+                json!({
+                    "type": "object",
+                    "properties": props
+                })
+            }
+        },
 
         Type::Own(r) => {
             json!({
@@ -351,57 +718,307 @@ fn type_to_json_schema(t: &Type) -> Value {
     }
 }
 
-fn component_func_to_schema(name: &str, func: &ComponentFunc, output: bool) -> serde_json::Value {
+/// The records, variants, enums and flags of a component, used to emit types that are used more
+/// than once as shared `$defs` instead of repeating them.
+///
+/// Types are compared with their [`PartialEq`] implementation, which compares them structurally.
+#[derive(Default)]
+struct TypeDefs {
+    entries: Vec<TypeDef>,
+}
+
+struct TypeDef {
+    ty: Type,
+    name: String,
+    uses: usize,
+    schema: Option<Value>,
+}
+
+impl TypeDefs {
+    /// Records the use of every type in the signature of `func`
+    fn count_function(&mut self, func: &ComponentFunc, output: bool) {
+        for (_, ty) in func.params() {
+            self.count(&ty);
+        }
+        if output {
+            for ty in func.results() {
+                self.count(&ty);
+            }
+        }
+    }
+
+    /// Records a use of `t` and, the first time it is seen, of the types nested in it. Nested types
+    /// are only visited once, so that a type referring to itself can't cause infinite recursion.
+    fn count(&mut self, t: &Type) {
+        if let Some(kind) = definition_kind(t) {
+            if let Some(def) = self.entries.iter_mut().find(|def| def.ty == *t) {
+                def.uses += 1;
+                return;
+            }
+            let index = self
+                .entries
+                .iter()
+                .filter(|def| definition_kind(&def.ty) == Some(kind))
+                .count();
+            self.entries.push(TypeDef {
+                ty: t.clone(),
+                name: format!("{kind}{index}"),
+                uses: 1,
+                schema: None,
+            });
+        }
+        for nested in nested_types(t) {
+            self.count(&nested);
+        }
+    }
+
+    /// Returns a `$ref` to the definition of `t` if it is used more than once, generating the
+    /// definition the first time
+    fn reference(&mut self, t: &Type, options: SchemaOptions) -> Option<Value> {
+        definition_kind(t)?;
+        let index = self
+            .entries
+            .iter()
+            .position(|def| def.uses > 1 && def.ty == *t)?;
+        if self.entries[index].schema.is_none() {
+            // Mark the definition as generated before recursing, so that nested uses of the same
+            // type become references
+            self.entries[index].schema = Some(Value::Null);
+            let schema = inline_type_schema(t, Some(self), options);
+            self.entries[index].schema = Some(schema);
+        }
+        Some(json!({ "$ref": format!("#/$defs/{}", self.entries[index].name) }))
+    }
+
+    /// Takes the generated definition that `reference` points to, so that it can be modified
+    /// while the other definitions are used. Returns `None` if it is already taken.
+    fn take_schema(&mut self, reference: &str) -> Option<Value> {
+        let def = self
+            .entries
+            .iter_mut()
+            .find(|def| reference == format!("#/$defs/{}", def.name))?;
+        match def.schema.replace(Value::Null) {
+            Some(Value::Null) | None => None,
+            schema => schema,
+        }
+    }
+
+    /// Puts back a definition taken with [`TypeDefs::take_schema`]
+    fn restore_schema(&mut self, reference: &str, schema: Value) {
+        if let Some(def) = self
+            .entries
+            .iter_mut()
+            .find(|def| reference == format!("#/$defs/{}", def.name))
+        {
+            def.schema = Some(schema);
+        }
+    }
+
+    /// Returns the generated definitions, keyed by name
+    fn into_defs(self) -> Map<String, Value> {
+        self.entries
+            .into_iter()
+            .filter_map(|def| Some((def.name, def.schema?)))
+            .collect()
+    }
+}
+
+/// Returns the kind of `t` if it is a type that gets its own definition when shared
+fn definition_kind(t: &Type) -> Option<&'static str> {
+    match t {
+        Type::Record(_) => Some("record"),
+        Type::Variant(_) => Some("variant"),
+        Type::Enum(_) => Some("enum"),
+        Type::Flags(_) => Some("flags"),
+        _ => None,
+    }
+}
+
+/// Returns the types directly nested in `t`
+fn nested_types(t: &Type) -> Vec<Type> {
+    match t {
+        Type::List(list) => vec![list.ty()],
+        Type::Record(record) => record.fields().map(|field| field.ty).collect(),
+        Type::Tuple(tuple) => tuple.types().collect(),
+        Type::Variant(variant) => variant.cases().filter_map(|case| case.ty).collect(),
+        Type::Option(option) => vec![option.ty()],
+        Type::Result(result) => result.ok().into_iter().chain(result.err()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn component_func_to_schema(
+    name: &str,
+    func: &ComponentFunc,
+    output: bool,
+    mut defs: Option<&mut TypeDefs>,
+    docs: Option<FunctionDocs<'_>>,
+    options: SchemaOptions,
+) -> serde_json::Value {
     let mut properties = serde_json::Map::new();
     let mut required = Vec::new();
 
     for (param_name, param_type) in func.params() {
-        required.push(param_name.to_string());
-        properties.insert(param_name.to_string(), type_to_json_schema(&param_type));
+        let mut schema = type_to_json_schema(&param_type, defs.as_deref_mut(), options);
+        // Like optional record fields, `option` parameters may be left out and default to `none`
+        if matches!(param_type, Type::Option(_)) {
+            if let Some(schema) = schema.as_object_mut() {
+                schema.insert("default".to_string(), Value::Null);
+            }
+        } else {
+            required.push(param_name.to_string());
+        }
+        properties.insert(param_name.to_string(), schema);
     }
 
+    let mut properties = Value::Object(properties);
+    if let Some(docs) = docs {
+        docs.annotate_params(&mut properties, defs.as_deref_mut());
+    }
     let input_schema = json!({
         "type": "object",
         "properties": properties,
         "required": required
     });
 
+    let description = docs
+        .as_ref()
+        .and_then(|docs| docs.description())
+        .map(|description| description.to_string())
+        .unwrap_or_else(|| format!("Auto-generated schema for function '{name}'"));
+
     let mut tool_obj = serde_json::Map::new();
     tool_obj.insert("name".to_string(), json!(name));
-    tool_obj.insert(
-        "description".to_string(),
-        json!(format!("Auto-generated schema for function '{name}'")),
-    );
+    tool_obj.insert("description".to_string(), json!(description));
     tool_obj.insert("inputSchema".to_string(), input_schema);
 
     if output {
         let mut results_iter = func.results();
         let output_schema = match results_iter.len() {
             0 => None,
-            1 => Some(type_to_json_schema(&results_iter.next().unwrap())),
+            1 => {
+                let mut schema = type_to_json_schema(
+                    &results_iter.next().unwrap(),
+                    defs.as_deref_mut(),
+                    options,
+                );
+                if let Some(docs) = docs {
+                    docs.annotate_result(&mut schema, defs.as_deref_mut());
+                }
+                Some(schema)
+            }
             _ => {
-                let schemas: Vec<_> = results_iter.map(|ty| type_to_json_schema(&ty)).collect();
-                Some(json!({
-                    "type": "array",
-                    "items": schemas
-                }))
+                Some(multi_result_schema(results_iter.map(|ty| {
+                    type_to_json_schema(&ty, defs.as_deref_mut(), options)
+                })))
             }
         };
         if let Some(o) = output_schema {
             tool_obj.insert("outputSchema".to_string(), o);
         }
     }
+    // The hash is always computed over the self-contained schema, so that it doesn't depend on
+    // which types are shared with other functions
+    let hash = if defs.is_some() {
+        component_func_to_schema(name, func, output, None, docs, options)[TOOL_HASH_FIELD].clone()
+    } else {
+        json!(tool_schema_hash(&Value::Object(tool_obj.clone())))
+    };
+    tool_obj.insert(TOOL_HASH_FIELD.to_string(), hash);
     json!(tool_obj)
 }
 
-fn gather_exported_functions_with_metadata(
+/// The schema of the results of a function with more than one result, an object with a `val{i}`
+/// property per result, matching what [`vals_to_json`] produces
+fn multi_result_schema(schemas: impl Iterator<Item = Value>) -> Value {
+    let mut properties = Map::new();
+    for (i, schema) in schemas.enumerate() {
+        properties.insert(format!("val{i}"), schema);
+    }
+    let required: Vec<String> = properties.keys().cloned().collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// The field of a generated tool schema that holds its [`tool_schema_hash`]
+pub const TOOL_HASH_FIELD: &str = "x-wassette-hash";
+
+/// Computes a stable content hash over a tool's description, input schema and output schema.
+///
+/// Object keys are hashed in sorted order, so the hash doesn't depend on how the schema was
+/// built and is deterministic across runs. Clients can compare hashes to find out whether a tool
+/// definition changed.
+pub fn tool_schema_hash(tool: &Value) -> String {
+    let mut canonical = String::new();
+    for field in ["description", "inputSchema", "outputSchema"] {
+        canonical.push_str(field);
+        canonical.push('=');
+        write_canonical_json(tool.get(field).unwrap_or(&Value::Null), &mut canonical);
+        canonical.push('\n');
+    }
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+}
+
+fn write_canonical_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical_json(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical_json(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// Returns the identifier and type of every function exported by the component, including the
+/// functions of exported instances
+fn exported_functions(
+    component: &Component,
+    engine: &Engine,
+) -> Vec<(FunctionIdentifier, ComponentFunc)> {
+    let mut functions = Vec::new();
+    for (export_name, export_item) in component.component_type().exports(engine) {
+        gather_functions(
+            export_name,
+            None,
+            None,
+            &export_item,
+            engine,
+            &mut functions,
+        );
+    }
+    functions
+}
+
+fn gather_functions(
     export_name: &str,
     previous_name: Option<String>,
     package_name: Option<String>,
     item: &ComponentItem,
     engine: &Engine,
-    results: &mut Vec<ToolMetadata>,
-    output: bool,
+    results: &mut Vec<(FunctionIdentifier, ComponentFunc)>,
 ) {
     match item {
         ComponentItem::ComponentFunc(func) => {
@@ -410,41 +1027,31 @@ fn gather_exported_functions_with_metadata(
                 interface_name: previous_name,
                 function_name: export_name.to_string(),
             };
-
-            let normalized_name = normalize_tool_name(&function_id);
-            let schema = component_func_to_schema(&normalized_name, func, output);
-
-            results.push(ToolMetadata {
-                identifier: function_id,
-                normalized_name,
-                schema,
-            });
+            results.push((function_id, func.clone()));
         }
         ComponentItem::Component(sub_component) => {
             let previous_name = Some(export_name.to_string());
             for (export_name, export_item) in sub_component.exports(engine) {
-                gather_exported_functions_with_metadata(
+                gather_functions(
                     export_name,
                     previous_name.clone(),
                     package_name.clone(),
                     &export_item,
                     engine,
                     results,
-                    output,
                 );
             }
         }
         ComponentItem::ComponentInstance(instance) => {
             let previous_name = Some(export_name.to_string());
             for (export_name, export_item) in instance.exports(engine) {
-                gather_exported_functions_with_metadata(
+                gather_functions(
                     export_name,
                     previous_name.clone(),
                     package_name.clone(),
                     &export_item,
                     engine,
                     results,
-                    output,
                 );
             }
         }
@@ -455,7 +1062,20 @@ fn gather_exported_functions_with_metadata(
     }
 }
 
+#[cfg(test)]
 fn val_to_json(val: &Val) -> Value {
+    val_to_json_with(
+        val,
+        &mut |res| Value::String(format!("resource: {res:?}")),
+        ValToJsonOptions::default(),
+    )
+}
+
+fn val_to_json_with(
+    val: &Val,
+    resource_to_json: &mut dyn FnMut(&ResourceAny) -> Value,
+    options: ValToJsonOptions,
+) -> Value {
     match val {
         Val::Bool(b) => Value::Bool(*b),
         Val::S8(n) => Value::Number((*n as i64).into()),
@@ -464,46 +1084,60 @@ fn val_to_json(val: &Val) -> Value {
         Val::U16(n) => Value::Number((*n as u64).into()),
         Val::S32(n) => Value::Number((*n as i64).into()),
         Val::U32(n) => Value::Number((*n as u64).into()),
+        Val::S64(n) if options.large_integers_as_strings && n.unsigned_abs() > MAX_SAFE_INTEGER => {
+            Value::String(n.to_string())
+        }
         Val::S64(n) => Value::Number((*n).into()),
+        Val::U64(n) if options.large_integers_as_strings && *n > MAX_SAFE_INTEGER => {
+            Value::String(n.to_string())
+        }
         Val::U64(n) => Value::Number((*n).into()),
-        Val::Float32(f) => serde_json::Number::from_f64(*f as f64)
-            .map(Value::Number)
-            .unwrap_or_else(|| Value::String(f.to_string())),
-        Val::Float64(f) => serde_json::Number::from_f64(*f)
-            .map(Value::Number)
-            .unwrap_or_else(|| Value::String(f.to_string())),
+        Val::Float32(f) => float_to_json(*f as f64),
+        Val::Float64(f) => float_to_json(*f),
         Val::Char(c) => Value::String(c.to_string()),
         Val::String(s) => Value::String(s.clone()),
 
-        Val::List(list) => Value::Array(list.iter().map(val_to_json).collect()),
+        Val::List(list) => Value::Array(
+            list.iter()
+                .map(|v| val_to_json_with(v, resource_to_json, options))
+                .collect(),
+        ),
         Val::Record(fields) => {
             let mut map = Map::new();
             for (k, v) in fields {
-                map.insert(k.clone(), val_to_json(v));
+                map.insert(k.clone(), val_to_json_with(v, resource_to_json, options));
             }
             Value::Object(map)
         }
-        Val::Tuple(items) => Value::Array(items.iter().map(val_to_json).collect()),
+        Val::Tuple(items) => Value::Array(
+            items
+                .iter()
+                .map(|v| val_to_json_with(v, resource_to_json, options))
+                .collect(),
+        ),
 
         Val::Variant(tag, payload) => {
             let mut obj = Map::new();
             obj.insert("tag".to_string(), Value::String(tag.clone()));
             if let Some(val_box) = payload {
-                obj.insert("val".to_string(), val_to_json(val_box));
+                obj.insert(
+                    "val".to_string(),
+                    val_to_json_with(val_box, resource_to_json, options),
+                );
             }
             Value::Object(obj)
         }
         Val::Enum(s) => Value::String(s.clone()),
 
         Val::Option(None) => Value::Null,
-        Val::Option(Some(val_box)) => val_to_json(val_box),
+        Val::Option(Some(val_box)) => val_to_json_with(val_box, resource_to_json, options),
 
         Val::Result(Ok(opt_box)) => {
             let mut obj = Map::new();
             obj.insert(
                 "ok".to_string(),
                 match opt_box {
-                    Some(v) => val_to_json(v),
+                    Some(v) => val_to_json_with(v, resource_to_json, options),
                     None => Value::Null,
                 },
             );
@@ -514,19 +1148,41 @@ fn val_to_json(val: &Val) -> Value {
             obj.insert(
                 "err".to_string(),
                 match opt_box {
-                    Some(v) => val_to_json(v),
+                    Some(v) => val_to_json_with(v, resource_to_json, options),
                     None => Value::Null,
                 },
             );
             Value::Object(obj)
         }
 
-        Val::Flags(flags) => Value::Array(flags.iter().map(|f| Value::String(f.clone())).collect()),
-        Val::Resource(res) => Value::String(format!("resource: {res:?}")),
+        Val::Flags(flags) => match options.flags {
+            FlagsRepr::Array => {
+                Value::Array(flags.iter().map(|f| Value::String(f.clone())).collect())
+            }
+            FlagsRepr::Object => Value::Object(
+                flags
+                    .iter()
+                    .map(|f| (f.clone(), Value::Bool(true)))
+                    .collect(),
+            ),
+        },
+        Val::Resource(res) => resource_to_json(res),
     }
 }
 
+#[cfg(test)]
 fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
+    json_to_val_with(value, ty, None, JsonToValOptions::default())
+}
+
+/// Converts `value` to a value of type `ty`. In [`JsonToValOptions::strict`] mode, objects
+/// representing records must not have keys that aren't fields of the record.
+fn json_to_val_with(
+    value: &Value,
+    ty: &Type,
+    resources: Option<&dyn ResourceResolver>,
+    options: JsonToValOptions,
+) -> Result<Val, ValError> {
     match ty {
         Type::Bool => match value {
             Value::Bool(b) => Ok(Val::Bool(*b)),
@@ -538,6 +1194,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .and_then(|i| i8::try_from(i).ok())
                 .map(Val::S8)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<i8>(s)
+                .map(Val::S8)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("s8", format!("{value:?}"))),
         },
         Type::S16 => match value {
@@ -546,6 +1205,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .and_then(|i| i16::try_from(i).ok())
                 .map(Val::S16)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<i16>(s)
+                .map(Val::S16)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("s16", format!("{value:?}"))),
         },
         Type::S32 => match value {
@@ -554,6 +1216,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .and_then(|i| i32::try_from(i).ok())
                 .map(Val::S32)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<i32>(s)
+                .map(Val::S32)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("s32", format!("{value:?}"))),
         },
         Type::S64 => match value {
@@ -561,6 +1226,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .as_i64()
                 .map(Val::S64)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<i64>(s)
+                .map(Val::S64)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("s64", format!("{value:?}"))),
         },
         Type::U8 => match value {
@@ -569,6 +1237,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .and_then(|i| u8::try_from(i).ok())
                 .map(Val::U8)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<u8>(s)
+                .map(Val::U8)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("u8", format!("{value:?}"))),
         },
         Type::U16 => match value {
@@ -577,6 +1248,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .and_then(|i| u16::try_from(i).ok())
                 .map(Val::U16)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<u16>(s)
+                .map(Val::U16)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("u16", format!("{value:?}"))),
         },
         Type::U32 => match value {
@@ -585,6 +1259,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .and_then(|i| u32::try_from(i).ok())
                 .map(Val::U32)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<u32>(s)
+                .map(Val::U32)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("u32", format!("{value:?}"))),
         },
         Type::U64 => match value {
@@ -592,30 +1269,44 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 .as_u64()
                 .map(Val::U64)
                 .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+            Value::String(s) => parse_integer_string::<u64>(s)
+                .map(Val::U64)
+                .ok_or_else(|| ValError::NumberError(s.clone())),
             _ => Err(ValError::ShapeError("u64", format!("{value:?}"))),
         },
-        Type::Float32 => match value {
-            Value::Number(n) => n
-                .as_f64()
-                .map(|f| Val::Float32(f as f32))
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("float32", format!("{value:?}"))),
-        },
-        Type::Float64 => match value {
-            Value::Number(n) => n
-                .as_f64()
-                .map(Val::Float64)
-                .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
-            _ => Err(ValError::ShapeError("float64", format!("{value:?}"))),
-        },
+        Type::Float32 => json_to_float(value, "float32").map(|f| Val::Float32(f as f32)),
+        Type::Float64 => json_to_float(value, "float64").map(Val::Float64),
         Type::Char => match value {
             Value::String(s) => {
-                if s.chars().count() == 1 {
-                    Ok(Val::Char(s.chars().next().unwrap()))
-                } else {
-                    Err(ValError::InvalidChar(s.clone()))
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Val::Char(c)),
+                    (None, _) => Err(ValError::InvalidChar {
+                        value: format!("{s:?}"),
+                        reason: "expected a single unicode scalar value, found an empty string"
+                            .to_string(),
+                    }),
+                    _ => Err(ValError::InvalidChar {
+                        value: format!("{s:?}"),
+                        reason: format!(
+                            "expected a single unicode scalar value, found {}. What displays as \
+                             one character (a grapheme) can be made of several scalar values, \
+                             like a letter followed by a combining accent or an emoji with a \
+                             modifier; use a string for those",
+                            s.chars().count()
+                        ),
+                    }),
                 }
             }
+            Value::Number(n) if options.char_codepoints => n
+                .as_u64()
+                .and_then(|n| u32::try_from(n).ok())
+                .and_then(char::from_u32)
+                .map(Val::Char)
+                .ok_or_else(|| ValError::InvalidChar {
+                    value: n.to_string(),
+                    reason: "not a unicode scalar value".to_string(),
+                }),
             _ => Err(ValError::ShapeError("char", format!("{value:?}"))),
         },
         Type::String => match value {
@@ -626,7 +1317,12 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
             Value::Array(arr) => {
                 let mut vals = Vec::new();
                 for item in arr {
-                    vals.push(json_to_val(item, &list_handle.ty())?);
+                    vals.push(json_to_val_with(
+                        item,
+                        &list_handle.ty(),
+                        resources,
+                        options,
+                    )?);
                 }
                 Ok(Val::List(vals))
             }
@@ -634,12 +1330,23 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
         },
         Type::Record(r) => match value {
             Value::Object(obj) => {
+                if options.strict {
+                    let known: Vec<&str> = r.fields().map(|field| field.name).collect();
+                    check_unknown_fields(obj, &known)?;
+                }
                 let mut fields = Vec::<(String, Val)>::new();
                 for field in r.fields() {
-                    let value = obj.get(field.name).ok_or_else(|| {
-                        ValError::ShapeError("record", format!("missing field {}", field.name))
-                    })?;
-                    fields.push((field.name.to_string(), json_to_val(value, &field.ty)?));
+                    let val = match (obj.get(field.name), &field.ty) {
+                        (Some(value), ty) => json_to_val_with(value, ty, resources, options)?,
+                        (None, Type::Option(_)) => Val::Option(None),
+                        (None, _) => {
+                            return Err(ValError::ShapeError(
+                                "record",
+                                format!("missing field {}", field.name),
+                            ))
+                        }
+                    };
+                    fields.push((field.name.to_string(), val));
                 }
                 Ok(Val::Record(fields))
             }
@@ -656,7 +1363,7 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 }
                 let mut items = Vec::new();
                 for (value, ty) in arr.iter().zip(types) {
-                    items.push(json_to_val(value, &ty)?);
+                    items.push(json_to_val_with(value, &ty, resources, options)?);
                 }
                 Ok(Val::Tuple(items))
             }
@@ -678,7 +1385,9 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                     let val = obj.get("val").ok_or_else(|| {
                         ValError::ShapeError("variant", "missing val".to_string())
                     })?;
-                    Some(Box::new(json_to_val(val, payload_ty)?))
+                    Some(Box::new(json_to_val_with(
+                        val, payload_ty, resources, options,
+                    )?))
                 } else {
                     None
                 };
@@ -702,22 +1411,24 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
         },
         Type::Option(opt_handle) => match value {
             Value::Null => Ok(Val::Option(None)),
-            v => Ok(Val::Option(Some(Box::new(json_to_val(
+            v => Ok(Val::Option(Some(Box::new(json_to_val_with(
                 v,
                 &opt_handle.ty(),
+                resources,
+                options,
             )?)))),
         },
         Type::Result(res_handle) => match value {
             Value::Object(obj) => {
                 if let Some(ok_val) = obj.get("ok") {
                     let ok_ty = res_handle.ok().unwrap_or(Type::Bool);
-                    Ok(Val::Result(Ok(Some(Box::new(json_to_val(
-                        ok_val, &ok_ty,
+                    Ok(Val::Result(Ok(Some(Box::new(json_to_val_with(
+                        ok_val, &ok_ty, resources, options,
                     )?)))))
                 } else if let Some(err_val) = obj.get("err") {
                     let err_ty = res_handle.err().unwrap_or(Type::Bool);
-                    Ok(Val::Result(Err(Some(Box::new(json_to_val(
-                        err_val, &err_ty,
+                    Ok(Val::Result(Err(Some(Box::new(json_to_val_with(
+                        err_val, &err_ty, resources, options,
                     )?)))))
                 } else {
                     Err(ValError::ShapeError("result", format!("{value:?}")))
@@ -735,12 +1446,82 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
                 }
                 Ok(Val::Flags(flags))
             }
+            Value::Object(obj) => {
+                let mut flags = Vec::new();
+                for name in flags_handle.names() {
+                    match obj.get(name) {
+                        Some(Value::Bool(true)) => flags.push(name.to_string()),
+                        Some(Value::Bool(false)) | None => {}
+                        Some(other) => {
+                            return Err(ValError::ShapeError(
+                                "flags",
+                                format!("expected boolean for flag {name}, found: {other:?}"),
+                            ))
+                        }
+                    }
+                }
+                Ok(Val::Flags(flags))
+            }
             _ => Err(ValError::ShapeError("flags", format!("{value:?}"))),
         },
-        Type::Own(_) | Type::Borrow(_) => Err(ValError::ResourceError),
+        Type::Own(resource_ty) | Type::Borrow(resource_ty) => {
+            let resource = resources
+                .and_then(|resolver| {
+                    let id = value.get(RESOURCE_HANDLE_FIELD)?.as_u64()?;
+                    resolver.resolve(u32::try_from(id).ok()?)
+                })
+                .ok_or(ValError::ResourceError)?;
+            if resource.ty() != *resource_ty {
+                return Err(ValError::ResourceError);
+            }
+            Ok(Val::Resource(resource))
+        }
     }
 }
 
+/// Converts a float to a JSON number, or to a [`FLOAT_FIELD`] object if it is NaN or infinite
+fn float_to_json(f: f64) -> Value {
+    match serde_json::Number::from_f64(f) {
+        Some(n) => Value::Number(n),
+        None => {
+            let repr = if f.is_nan() {
+                "nan"
+            } else if f > 0.0 {
+                "inf"
+            } else {
+                "-inf"
+            };
+            json!({ FLOAT_FIELD: repr })
+        }
+    }
+}
+
+/// Converts a JSON number or [`FLOAT_FIELD`] object to a float, the inverse of [`float_to_json`]
+fn json_to_float(value: &Value, kind: &'static str) -> Result<f64, ValError> {
+    match value {
+        Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| ValError::NumberError(format!("{n:?}"))),
+        Value::Object(obj) => match obj.get(FLOAT_FIELD).and_then(Value::as_str) {
+            Some("nan") if obj.len() == 1 => Ok(f64::NAN),
+            Some("inf") if obj.len() == 1 => Ok(f64::INFINITY),
+            Some("-inf") if obj.len() == 1 => Ok(f64::NEG_INFINITY),
+            _ => Err(ValError::ShapeError(kind, format!("{value:?}"))),
+        },
+        _ => Err(ValError::ShapeError(kind, format!("{value:?}"))),
+    }
+}
+
+/// Parses an integer sent as a JSON string, which clients use to avoid losing precision on large
+/// values. Only plain decimal integers are accepted, so `"12.5"`, `"0x10"` or `"+1"` are rejected.
+fn parse_integer_string<T: std::str::FromStr>(s: &str) -> Option<T> {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
 fn default_val_for_type(ty: &Type) -> Val {
     match ty {
         Type::Bool => Val::Bool(false),
@@ -822,6 +1603,28 @@ mod tests {
         assert_eq!(obj.get("val1").unwrap(), &json!(42));
     }
 
+    #[test]
+    fn test_multi_result_schema_matches_vals_to_json() {
+        // Current component binaries can't declare more than one result, so the schema is checked
+        // against what `vals_to_json` produces for the same values directly
+        let schema =
+            multi_result_schema([json!({"type": "string"}), json!({"type": "number"})].into_iter());
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["required"], json!(["val0", "val1"]));
+
+        let output = vals_to_json(&[Val::String("example".to_string()), Val::S64(42)]);
+        let properties = schema["properties"].as_object().unwrap();
+        let output = output.as_object().unwrap();
+        assert_eq!(
+            properties.keys().collect::<Vec<_>>(),
+            output.keys().collect::<Vec<_>>()
+        );
+        assert!(output["val0"].is_string());
+        assert_eq!(properties["val0"]["type"], "string");
+        assert!(output["val1"].is_number());
+        assert_eq!(properties["val1"]["type"], "number");
+    }
+
     #[test]
     fn test_val_to_json_bool() {
         let val = Val::Bool(false);
@@ -873,6 +1676,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_non_finite_floats_roundtrip() {
+        let cases = [
+            (f64::NAN, json!({ "$float": "nan" })),
+            (f64::INFINITY, json!({ "$float": "inf" })),
+            (f64::NEG_INFINITY, json!({ "$float": "-inf" })),
+        ];
+        for (f, expected) in cases {
+            let json = val_to_json(&Val::Float64(f));
+            assert_eq!(json, expected);
+            match json_to_val(&json, &Type::Float64).unwrap() {
+                Val::Float64(back) if f.is_nan() => assert!(back.is_nan()),
+                Val::Float64(back) => assert_eq!(back, f),
+                other => panic!("Expected a Float64, got {other:?}"),
+            }
+
+            let json = val_to_json(&Val::Float32(f as f32));
+            assert_eq!(json, expected);
+            match json_to_val(&json, &Type::Float32).unwrap() {
+                Val::Float32(back) if f.is_nan() => assert!(back.is_nan()),
+                Val::Float32(back) => assert_eq!(back, f as f32),
+                other => panic!("Expected a Float32, got {other:?}"),
+            }
+        }
+
+        for invalid in [
+            json!({ "$float": "NaN" }),
+            json!({ "$float": 1 }),
+            json!({ "$float": "inf", "extra": true }),
+            json!("NaN"),
+        ] {
+            assert!(json_to_val(&invalid, &Type::Float64).is_err(), "{invalid}");
+        }
+    }
+
     #[test]
     fn test_val_to_json_char() {
         let val = Val::Char('A');
@@ -968,13 +1806,216 @@ mod tests {
         assert_eq!(val_to_json(&val), json!(["f1", "f2"]));
     }
 
+    #[test]
+    fn test_char_conversion() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (func (param "c" char)))
+                    (export "put" (func (type 0)))
+                ))
+                (export "test:chars/chars" (component (type 0)))
+            ))
+            (export "chars" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let (_, func) = exported_functions(&component, &engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        let (_, char_ty) = func.params().next().unwrap();
+
+        // "é" as a single precomposed scalar value
+        assert_eq!(
+            json_to_val(&json!("\u{e9}"), &char_ty).unwrap(),
+            Val::Char('\u{e9}')
+        );
+
+        // "é" as "e" followed by a combining acute accent looks the same, but is two scalars
+        let err = json_to_val(&json!("e\u{301}"), &char_ty).unwrap_err();
+        assert!(matches!(err, ValError::InvalidChar { .. }), "{err}");
+        let message = err.to_string();
+        assert!(message.contains("found 2"), "{message}");
+        assert!(message.contains("grapheme"), "{message}");
+
+        // An emoji with a skin tone modifier
+        assert!(matches!(
+            json_to_val(&json!("\u{1f44d}\u{1f3fd}"), &char_ty),
+            Err(ValError::InvalidChar { .. })
+        ));
+        assert!(matches!(
+            json_to_val(&json!(""), &char_ty),
+            Err(ValError::InvalidChar { .. })
+        ));
+
+        // Codepoints are only accepted when enabled
+        assert!(matches!(
+            json_to_val(&json!(65), &char_ty),
+            Err(ValError::ShapeError("char", _))
+        ));
+        let types = vec![("c".to_string(), char_ty)];
+        let options = JsonToValOptions {
+            char_codepoints: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            json_to_vals_with_options(&json!({"c": 65}), &types, options).unwrap(),
+            vec![Val::Char('A')]
+        );
+        assert_eq!(
+            json_to_vals_with_options(&json!({"c": 0xe9}), &types, options).unwrap(),
+            vec![Val::Char('\u{e9}')]
+        );
+        for invalid in [json!(0xd800), json!(0x110000), json!(-1), json!(65.5)] {
+            assert!(matches!(
+                json_to_vals_with_options(&json!({"c": invalid}), &types, options),
+                Err(ValError::InvalidChar { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_strict_json_to_vals_rejects_unknown_keys() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (record (field "name" string) (field "age" u32)))
+                    (export "person" (type (eq 0)))
+                    (type (func (param "person" 1) (param "greeting" string)))
+                    (export "greet" (func (type 2)))
+                ))
+                (export "test:people/people" (component (type 0)))
+            ))
+            (export "people" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let (_, func) = exported_functions(&component, &engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        let types: Vec<(String, Type)> = func
+            .params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect();
+
+        let valid = json!({"person": {"name": "Ada", "age": 36}, "greeting": "Hello"});
+        let extra_param = json!({
+            "person": {"name": "Ada", "age": 36},
+            "greeting": "Hello",
+            "gretting": "Hi",
+            "loud": true
+        });
+        let extra_field = json!({
+            "person": {"name": "Ada", "age": 36, "nickname": "Countess"},
+            "greeting": "Hello"
+        });
+
+        // Lenient mode drops the unknown keys
+        let expected = json_to_vals(&valid, &types).unwrap();
+        assert_eq!(json_to_vals(&extra_param, &types).unwrap(), expected);
+        assert_eq!(json_to_vals(&extra_field, &types).unwrap(), expected);
+
+        // Strict mode names them
+        assert_eq!(json_to_vals_strict(&valid, &types).unwrap(), expected);
+        let err = json_to_vals_strict(&extra_param, &types).unwrap_err();
+        assert!(
+            matches!(&err, ValError::UnknownFields(keys) if keys == &["gretting", "loud"]),
+            "{err}"
+        );
+        assert_eq!(err.to_string(), "unknown fields: gretting, loud");
+        let err = json_to_vals_strict(&extra_field, &types).unwrap_err();
+        assert_eq!(err.to_string(), "unknown fields: nickname");
+    }
+
+    #[test]
+    fn test_flags_representations() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (flags "read" "write" "exec"))
+                    (export "mode" (type (eq 0)))
+                    (type (func (param "mode" 1) (result 1)))
+                    (export "chmod" (func (type 2)))
+                ))
+                (export "test:files/files" (component (type 0)))
+            ))
+            (export "files" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let (_, func) = exported_functions(&component, &engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        let (_, mode_ty) = func.params().next().unwrap();
+        let val = Val::Flags(vec!["read".to_string(), "exec".to_string()]);
+
+        // Both forms are accepted
+        let from_array = json_to_val(&json!(["read", "exec"]), &mode_ty).unwrap();
+        let from_object = json_to_val(
+            &json!({"read": true, "write": false, "exec": true}),
+            &mode_ty,
+        )
+        .unwrap();
+        assert_eq!(from_array, val);
+        assert_eq!(from_object, val);
+        assert!(json_to_val(&json!({"read": "yes"}), &mode_ty).is_err());
+
+        // Both forms round-trip
+        for flags in [FlagsRepr::Array, FlagsRepr::Object] {
+            let options = ValToJsonOptions {
+                flags,
+                ..Default::default()
+            };
+            let json = vals_to_json_with_options(std::slice::from_ref(&val), options);
+            assert_eq!(json_to_val(&json, &mode_ty).unwrap(), val, "{flags:?}");
+        }
+        let object_options = ValToJsonOptions {
+            flags: FlagsRepr::Object,
+            ..Default::default()
+        };
+        assert_eq!(
+            vals_to_json_with_options(std::slice::from_ref(&val), object_options),
+            json!({"read": true, "exec": true})
+        );
+
+        // The schemas match the chosen representation
+        let schema_for = |flags| {
+            let tools = component_exports_to_tools_with_options(
+                &component,
+                &engine,
+                true,
+                None,
+                SchemaOptions { flags },
+            );
+            tools[0].schema["outputSchema"].clone()
+        };
+        let array_schema = schema_for(FlagsRepr::Array);
+        assert_eq!(array_schema["type"], "array");
+        assert_eq!(
+            array_schema["items"]["enum"],
+            json!(["read", "write", "exec"])
+        );
+        assert_eq!(
+            component_exports_to_tools(&component, &engine, wat.as_bytes(), true, false)[0].schema
+                ["outputSchema"],
+            array_schema
+        );
+        let object_schema = schema_for(FlagsRepr::Object);
+        assert_eq!(object_schema["type"], "object");
+        assert_eq!(object_schema["properties"]["write"]["type"], "boolean");
+    }
+
     #[test]
     fn test_component_exports_empty() {
         let engine = Engine::default();
         // A minimal component with no exports
         let wat = r#"(component)"#;
         let component = Component::new(&engine, wat).unwrap();
-        let schema = component_exports_to_json_schema(&component, &engine, false);
+        let schema =
+            component_exports_to_json_schema(&component, &engine, wat.as_bytes(), false, false);
         let tools = schema.get("tools").unwrap().as_array().unwrap();
         assert_eq!(tools.len(), 0);
     }
@@ -985,8 +2026,9 @@ mod tests {
         config.wasm_component_model(true);
         config.async_support(true);
         let engine = Engine::new(&config).unwrap();
-        let component = Component::from_file(&engine, "testdata/filesystem.wasm").unwrap();
-        let schema = component_exports_to_json_schema(&component, &engine, true);
+        let wasm = std::fs::read("testdata/filesystem.wasm").unwrap();
+        let component = Component::new(&engine, &wasm).unwrap();
+        let schema = component_exports_to_json_schema(&component, &engine, &wasm, true, false);
 
         let tools = schema.get("tools").unwrap().as_array().unwrap();
         assert_eq!(tools.len(), 4);
@@ -1056,7 +2098,8 @@ mod tests {
             (@producers (processed-by "wit-component" "0.223.0"))
         )"#;
         let component = Component::new(&engine, wat).unwrap();
-        let schema = component_exports_to_json_schema(&component, &engine, true);
+        let schema =
+            component_exports_to_json_schema(&component, &engine, wat.as_bytes(), true, false);
 
         let tools = schema.get("tools").unwrap().as_array().unwrap();
         assert_eq!(tools.len(), 1);
@@ -1073,6 +2116,264 @@ mod tests {
         assert!(output_schema.get("oneOf").is_some());
     }
 
+    /// Follows a `$ref` into the `$defs` of a component schema document
+    fn resolve_ref<'a>(document: &'a Value, schema: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => &document["$defs"][reference.trim_start_matches("#/$defs/")],
+            None => schema,
+        }
+    }
+
+    #[test]
+    fn test_shared_types_use_defs() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (record (field "x" s32) (field "y" s32)))
+                    (export "point" (type (eq 0)))
+                    (type (record (field "start" 1) (field "end" 1)))
+                    (export "line" (type (eq 2)))
+                    (type (list 3))
+                    (type (record (field "name" string) (field "lines" 4) (field "origin" 1)))
+                    (export "shape" (type (eq 5)))
+                    (type (func (param "shape" 6) (result 6)))
+                    (export "transform" (func (type 7)))
+                    (type (func (param "a" 1) (param "b" 1) (result s32)))
+                    (export "distance" (func (type 8)))
+                ))
+                (export "geo:geo/shapes" (component (type 0)))
+            ))
+            (export "geo" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let schema =
+            component_exports_to_json_schema(&component, &engine, wat.as_bytes(), true, false);
+
+        // `shape` and `point` are used more than once, `line` only inside of `shape`
+        let defs = schema["$defs"].as_object().unwrap();
+        assert_eq!(defs.len(), 2);
+        let shape_ref = json!({ "$ref": "#/$defs/record0" });
+        let point_ref = json!({ "$ref": "#/$defs/record2" });
+
+        let tools = schema["tools"].as_array().unwrap();
+        let transform = &tools[0];
+        assert_eq!(transform["inputSchema"]["properties"]["shape"], shape_ref);
+        assert_eq!(transform["outputSchema"], shape_ref);
+        let distance = &tools[1];
+        assert_eq!(distance["inputSchema"]["properties"]["a"], point_ref);
+        assert_eq!(distance["inputSchema"]["properties"]["b"], point_ref);
+
+        let shape = resolve_ref(&schema, &shape_ref);
+        assert_eq!(shape["properties"]["origin"], point_ref);
+        let line = &shape["properties"]["lines"]["items"];
+        assert_eq!(line["type"], "object");
+        assert_eq!(line["properties"]["start"], point_ref);
+        assert_eq!(line["properties"]["end"], point_ref);
+
+        let point = resolve_ref(&schema, &point_ref);
+        assert_eq!(point["required"], json!(["x", "y"]));
+
+        // The tool schemas used by the server are self-contained and hash the same way
+        let tool_list =
+            component_exports_to_tools(&component, &engine, wat.as_bytes(), true, false);
+        let self_contained = &tool_list[0].schema;
+        assert_eq!(
+            self_contained["inputSchema"]["properties"]["shape"]["properties"]["origin"]["type"],
+            "object"
+        );
+        assert!(!self_contained.to_string().contains("$ref"));
+        assert_eq!(self_contained[TOOL_HASH_FIELD], transform[TOOL_HASH_FIELD]);
+
+        // Components without shared types get an empty `$defs` map
+        let component = Component::new(&engine, "(component)").unwrap();
+        let schema =
+            component_exports_to_json_schema(&component, &engine, b"(component)", true, false);
+        assert_eq!(schema["$defs"], json!({}));
+    }
+
+    #[test]
+    fn test_record_shared_by_three_functions() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (record (field "id" u32) (field "name" string)))
+                    (export "user" (type (eq 0)))
+                    (type (func (param "user" 1) (result bool)))
+                    (export "save" (func (type 2)))
+                    (type (func (param "id" u32) (result 1)))
+                    (export "load" (func (type 3)))
+                    (type (list 1))
+                    (type (func (param "users" 4) (result u32)))
+                    (export "count" (func (type 5)))
+                ))
+                (export "app:users/store" (component (type 0)))
+            ))
+            (export "users" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let schema =
+            component_exports_to_json_schema(&component, &engine, wat.as_bytes(), true, false);
+
+        let defs = schema["$defs"].as_object().unwrap();
+        assert_eq!(defs.len(), 1);
+        let user_ref = json!({ "$ref": "#/$defs/record0" });
+        let user = resolve_ref(&schema, &user_ref);
+        assert_eq!(user["required"], json!(["id", "name"]));
+
+        let tools = schema["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 3);
+        assert_eq!(tools[0]["inputSchema"]["properties"]["user"], user_ref);
+        assert_eq!(tools[1]["outputSchema"], user_ref);
+        assert_eq!(
+            tools[2]["inputSchema"]["properties"]["users"]["items"],
+            user_ref
+        );
+        // The record is spelled out exactly once in the whole document
+        assert_eq!(schema.to_string().matches("\"name\":{").count(), 1);
+    }
+
+    #[test]
+    fn test_optional_record_fields() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (option string))
+                    (type (record (field "name" string) (field "nickname" 0)))
+                    (export "person" (type (eq 1)))
+                    (type (func (param "person" 2)))
+                    (export "greet" (func (type 3)))
+                ))
+                (export "test:people/people" (component (type 0)))
+            ))
+            (export "people" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let (_, func) = exported_functions(&component, &engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        let (_, person_ty) = func.params().next().unwrap();
+
+        // The optional field is described but not required
+        let schema = type_to_json_schema(&person_ty, None, SchemaOptions::default());
+        assert_eq!(schema["required"], json!(["name"]));
+        assert!(schema["properties"].get("nickname").is_some());
+
+        // A missing optional field is `none`
+        let val = json_to_val(&json!({"name": "Ada"}), &person_ty).unwrap();
+        assert_eq!(
+            val,
+            Val::Record(vec![
+                ("name".to_string(), Val::String("Ada".to_string())),
+                ("nickname".to_string(), Val::Option(None)),
+            ])
+        );
+
+        let val = json_to_val(&json!({"name": "Ada", "nickname": "Countess"}), &person_ty).unwrap();
+        assert_eq!(
+            val,
+            Val::Record(vec![
+                ("name".to_string(), Val::String("Ada".to_string())),
+                (
+                    "nickname".to_string(),
+                    Val::Option(Some(Box::new(Val::String("Countess".to_string()))))
+                ),
+            ])
+        );
+
+        // Required fields still have to be provided
+        let err = json_to_val(&json!({"nickname": "Countess"}), &person_ty).unwrap_err();
+        assert!(err.to_string().contains("missing field name"), "{err}");
+    }
+
+    #[test]
+    fn test_optional_params() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (core module $m
+                (memory (export "mem") 1)
+                (func (export "search") (param i32 i32 i32 i32 i32 i32 i32 i32))
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 0)
+            )
+            (core instance $i (instantiate $m))
+            (func (export "search")
+                (param "query" string)
+                (param "limit" (option u32))
+                (param "offset" u32)
+                (param "tag" (option string))
+                (canon lift
+                    (core func $i "search")
+                    (memory $i "mem")
+                    (realloc (func $i "realloc"))
+                )
+            )
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let schema =
+            component_exports_to_json_schema(&component, &engine, wat.as_bytes(), true, false);
+        let input = &schema["tools"][0]["inputSchema"];
+        assert_eq!(input["required"], json!(["query", "offset"]));
+        assert_eq!(input["properties"]["limit"]["default"], Value::Null);
+        assert!(input["properties"]["limit"]["anyOf"].is_array());
+        assert!(input["properties"]["query"].get("default").is_none());
+
+        // Optional parameters that are left out are `none`
+        let (_, func) = exported_functions(&component, &engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        let types: Vec<_> = func
+            .params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect();
+        let vals = json_to_vals(&json!({"query": "wasm", "offset": 0}), &types).unwrap();
+        assert_eq!(vals[1], Val::Option(None));
+        assert_eq!(vals[3], Val::Option(None));
+        let err = json_to_vals(&json!({"query": "wasm"}), &types).unwrap_err();
+        assert!(err.to_string().contains("missing field offset"), "{err}");
+    }
+
+    #[test]
+    fn test_tool_schema_hash() {
+        let engine = Engine::default();
+        let tool_for = |param_type: &str| {
+            let wat = format!(
+                r#"(component
+                    (core module $m (func (export "f") (param i32)))
+                    (core instance $i (instantiate $m))
+                    (func (export "greet") (param "x" {param_type})
+                        (canon lift (core func $i "f")))
+                )"#
+            );
+            let component = Component::new(&engine, &wat).unwrap();
+            let schema =
+                component_exports_to_json_schema(&component, &engine, wat.as_bytes(), true, false);
+            schema["tools"][0].clone()
+        };
+
+        let tool = tool_for("u32");
+        let hash = tool[TOOL_HASH_FIELD].as_str().unwrap().to_string();
+        assert_eq!(hash, tool_schema_hash(&tool));
+
+        // Generating the schema again yields the same hash
+        assert_eq!(tool_for("u32")[TOOL_HASH_FIELD], hash.as_str());
+
+        // Changing the schema changes the hash
+        assert_ne!(tool_for("bool")[TOOL_HASH_FIELD], hash.as_str());
+
+        // The hash doesn't depend on key order
+        let a = json!({"description": "d", "inputSchema": {"type": "object", "required": []}});
+        let b = json!({"inputSchema": {"required": [], "type": "object"}, "description": "d"});
+        assert_eq!(tool_schema_hash(&a), tool_schema_hash(&b));
+        assert_ne!(
+            tool_schema_hash(&a),
+            tool_schema_hash(&json!({"description": "other", "inputSchema": a["inputSchema"]}))
+        );
+    }
+
     #[test]
     fn test_component_exports_schema() {
         let mut config = wasmtime::Config::new();
@@ -1220,7 +2521,8 @@ mod tests {
             )
             )"#;
         let component = Component::new(&engine, wat).unwrap();
-        let schema = component_exports_to_json_schema(&component, &engine, true);
+        let schema =
+            component_exports_to_json_schema(&component, &engine, wat.as_bytes(), true, false);
 
         let tools = schema.get("tools").unwrap().as_array().unwrap();
         assert_eq!(tools.len(), 7);
@@ -1289,7 +2591,8 @@ mod tests {
             assert_eq!(input_props.len(), 1);
             assert!(input_props.contains_key("x")); // string
 
-            let output_schema = foo_b.get("outputSchema").unwrap();
+            // The variant is shared with `foo_c`, so it is defined once in `$defs`
+            let output_schema = resolve_ref(&schema, foo_b.get("outputSchema").unwrap());
             let cases = output_schema.get("oneOf").unwrap().as_array().unwrap();
             assert_eq!(cases.len(), 3);
 
@@ -1361,6 +2664,7 @@ mod tests {
                 .unwrap();
             assert_eq!(input_props.len(), 1);
             assert!(input_props.contains_key("x")); // variant type
+            assert_eq!(input_props["x"], foo_b["outputSchema"]);
 
             let output_schema = foo_c.get("outputSchema").unwrap();
             assert_eq!(output_schema.get("type").unwrap(), "string");
@@ -1470,7 +2774,7 @@ mod tests {
 
         let invalid_type = json!({
             "name": "John",
-            "age": "30"
+            "age": "thirty"
         });
         assert!(json_to_vals(&invalid_type, &types).is_err());
     }
@@ -1820,7 +3124,8 @@ mod tests {
         )"#;
 
         let component = wasmtime::component::Component::new(&engine, wat)?;
-        let tool_metadata = component_exports_to_tools(&component, &engine, true);
+        let tool_metadata =
+            component_exports_to_tools(&component, &engine, wat.as_bytes(), true, false);
 
         // Should have exactly one tool
         assert_eq!(tool_metadata.len(), 1);
@@ -1848,4 +3153,134 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_resource_handle_round_trip() -> anyhow::Result<()> {
+        use wasmtime::component::{Resource, ResourceType};
+
+        struct Counter;
+        struct Other;
+
+        let engine = Engine::default();
+        let mut store = wasmtime::Store::new(&engine, ());
+        let first = ResourceAny::try_from_resource(Resource::<Counter>::new_own(1), &mut store)?;
+        let second = ResourceAny::try_from_resource(Resource::<Counter>::new_own(2), &mut store)?;
+
+        let mut handles = ResourceHandles::new();
+        let json = vals_to_json_with_resources(
+            &[Val::Resource(first), Val::List(vec![Val::Resource(second)])],
+            &mut handles,
+        );
+        assert_eq!(
+            json,
+            json!({"val0": {"$resource": 0}, "val1": [{"$resource": 1}]})
+        );
+        // Handle IDs are stable for the same resource
+        assert_eq!(handles.insert(first), 0);
+        assert_eq!(handles.len(), 2);
+
+        let types = vec![
+            ("a".to_string(), Type::Own(ResourceType::host::<Counter>())),
+            (
+                "b".to_string(),
+                Type::Borrow(ResourceType::host::<Counter>()),
+            ),
+        ];
+        let params = json!({"a": json["val0"], "b": json["val1"][0]});
+        let vals = json_to_vals_with_resources(&params, &types, &handles)?;
+        assert!(matches!(&vals[0], Val::Resource(r) if *r == first));
+        assert!(matches!(&vals[1], Val::Resource(r) if *r == second));
+
+        // A lookup closure works as well
+        let resolver = |id: u32| (id == 7).then_some(second);
+        let vals =
+            json_to_vals_with_resources(&json!({"a": {"$resource": 7}}), &types[..1], &resolver)?;
+        assert!(matches!(&vals[0], Val::Resource(r) if *r == second));
+
+        // Unknown handles and resources of the wrong type are rejected
+        assert!(matches!(
+            json_to_vals_with_resources(&json!({"a": {"$resource": 9}}), &types[..1], &handles),
+            Err(ValError::ResourceError)
+        ));
+        let other_types = vec![("a".to_string(), Type::Own(ResourceType::host::<Other>()))];
+        assert!(matches!(
+            json_to_vals_with_resources(&json!({"a": {"$resource": 0}}), &other_types, &handles),
+            Err(ValError::ResourceError)
+        ));
+
+        // Without a resolver, resources are still rejected
+        assert!(matches!(
+            json_to_vals(&params, &types),
+            Err(ValError::ResourceError)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_integers_as_strings() {
+        // Strings are accepted for every integer type, as long as they are plain decimals
+        assert!(matches!(
+            json_to_val(&json!(u64::MAX.to_string()), &Type::U64).unwrap(),
+            Val::U64(u64::MAX)
+        ));
+        assert!(matches!(
+            json_to_val(&json!(i64::MIN.to_string()), &Type::S64).unwrap(),
+            Val::S64(i64::MIN)
+        ));
+        assert!(matches!(
+            json_to_val(&json!("42"), &Type::U32).unwrap(),
+            Val::U32(42)
+        ));
+        assert!(matches!(
+            json_to_val(&json!("-7"), &Type::S8).unwrap(),
+            Val::S8(-7)
+        ));
+        for invalid in ["12.5", "0x10", "+1", "", "-", " 1", "1e3"] {
+            assert!(
+                json_to_val(&json!(invalid), &Type::U64).is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+        assert!(json_to_val(&json!("256"), &Type::U8).is_err());
+        assert!(json_to_val(&json!("-1"), &Type::U64).is_err());
+        assert!(json_to_val(&json!("18446744073709551616"), &Type::U64).is_err());
+    }
+
+    #[test]
+    fn test_large_integers_as_strings_round_trip() {
+        let vals = [Val::U64(u64::MAX), Val::S64(i64::MIN), Val::U64(42)];
+
+        // By default integers are always numbers
+        let json = vals_to_json(&vals);
+        assert_eq!(json["val0"], json!(u64::MAX));
+        assert_eq!(json["val1"], json!(i64::MIN));
+
+        let options = ValToJsonOptions {
+            large_integers_as_strings: true,
+            ..Default::default()
+        };
+        let json = vals_to_json_with_options(&vals, options);
+        assert_eq!(json["val0"], json!("18446744073709551615"));
+        assert_eq!(json["val1"], json!("-9223372036854775808"));
+        assert_eq!(json["val2"], json!(42));
+
+        // Values at the edge of the safe range stay numbers
+        let edge = [
+            Val::U64(MAX_SAFE_INTEGER),
+            Val::S64(-(MAX_SAFE_INTEGER as i64)),
+        ];
+        let json = vals_to_json_with_options(&edge, options);
+        assert_eq!(json["val0"], json!(MAX_SAFE_INTEGER));
+        assert_eq!(json["val1"], json!(-(MAX_SAFE_INTEGER as i64)));
+
+        // The strings convert back to the same values
+        let back = json_to_vals(
+            &json!({"a": json!("18446744073709551615"), "b": json!("-9223372036854775808")}),
+            &[("a".to_string(), Type::U64), ("b".to_string(), Type::S64)],
+        )
+        .unwrap();
+        assert!(matches!(back[0], Val::U64(u64::MAX)));
+        assert!(matches!(back[1], Val::S64(i64::MIN)));
+    }
 }
@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! OpenAPI documents describing component exports as a REST API
+
+use serde_json::{json, Map, Value};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::{exported_json_schema, ComponentDocs, SchemaOptions, TOOL_HASH_FIELD};
+
+/// The OpenAPI version of the generated documents
+pub const OPENAPI_VERSION: &str = "3.1.0";
+
+/// Given a component and a wasmtime engine, return an OpenAPI 3.1 document that exposes every
+/// exported function as a `POST /{tool-name}` operation served from `base_url`.
+///
+/// The request body of an operation is the JSON object of its arguments and the `200` response is
+/// its result, with the schemas of [`crate::component_exports_to_json_schema`]. Shared types are
+/// defined once in `#/components/schemas`.
+pub fn component_exports_to_openapi(
+    component: &Component,
+    engine: &Engine,
+    base_url: &str,
+) -> Value {
+    component_exports_to_openapi_with_options(
+        component,
+        engine,
+        base_url,
+        None,
+        SchemaOptions::default(),
+    )
+}
+
+/// Same as [`component_exports_to_openapi`], with optional `docs` (see
+/// [`crate::component_exports_to_tools`]) and the given [`SchemaOptions`]
+pub fn component_exports_to_openapi_with_options(
+    component: &Component,
+    engine: &Engine,
+    base_url: &str,
+    docs: Option<&ComponentDocs>,
+    options: SchemaOptions,
+) -> Value {
+    let mut document = exported_json_schema(component, engine, true, docs, options);
+    rewrite_refs(&mut document);
+
+    let mut paths = Map::new();
+    for tool in document["tools"].as_array().into_iter().flatten() {
+        let Some(name) = tool["name"].as_str() else {
+            continue;
+        };
+        let mut response = json!({ "description": "The result of the function" });
+        if let Some(output_schema) = tool.get("outputSchema") {
+            response["content"] = json!({ "application/json": { "schema": output_schema } });
+        }
+        paths.insert(
+            format!("/{name}"),
+            json!({
+                "post": {
+                    "operationId": name,
+                    "description": tool["description"],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": { "schema": tool["inputSchema"] }
+                        }
+                    },
+                    "responses": { "200": response },
+                    TOOL_HASH_FIELD: tool[TOOL_HASH_FIELD],
+                }
+            }),
+        );
+    }
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": "WebAssembly component",
+            "version": "1.0.0",
+        },
+        "servers": [{ "url": base_url }],
+        "paths": paths,
+        "components": { "schemas": document["$defs"].take() },
+    })
+}
+
+/// Points the `$ref`s of a component schema document at `#/components/schemas` instead of `$defs`
+fn rewrite_refs(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    Value::String(reference) if key == "$ref" => {
+                        if let Some(name) = reference.strip_prefix("#/$defs/") {
+                            *reference = format!("#/components/schemas/{name}");
+                        }
+                    }
+                    _ => rewrite_refs(value),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(rewrite_refs),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the parts of the OpenAPI 3.1 structure the generated documents rely on
+    fn assert_valid_openapi(document: &Value) {
+        assert_eq!(document["openapi"], "3.1.0");
+        assert!(document["info"]["title"].is_string());
+        assert!(document["info"]["version"].is_string());
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        let paths = document["paths"].as_object().unwrap();
+        for (path, item) in paths {
+            assert!(path.starts_with('/'), "{path}");
+            let operation = item["post"].as_object().unwrap();
+            assert!(operation["operationId"].is_string());
+            assert!(operation["requestBody"]["content"]["application/json"]["schema"].is_object());
+            assert!(operation["responses"]["200"]["description"].is_string());
+        }
+        // Every reference resolves
+        let text = document.to_string();
+        for reference in text.split("\"$ref\":\"").skip(1) {
+            let reference = &reference[..reference.find('"').unwrap()];
+            let name = reference
+                .strip_prefix("#/components/schemas/")
+                .unwrap_or_else(|| panic!("Unexpected reference {reference}"));
+            assert!(schemas.contains_key(name), "{reference}");
+        }
+    }
+
+    #[test]
+    fn test_openapi_document() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (record (field "x" s32) (field "y" s32)))
+                    (export "point" (type (eq 0)))
+                    (type (func (param "a" 1) (param "b" 1) (result s32)))
+                    (export "distance" (func (type 2)))
+                    (type (func (param "p" 1) (result 1)))
+                    (export "mirror" (func (type 3)))
+                    (type (func (param "p" 1)))
+                    (export "draw" (func (type 4)))
+                ))
+                (export "geo:geo/points" (component (type 0)))
+            ))
+            (export "points" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let document = component_exports_to_openapi(&component, &engine, "https://api.example.com");
+        assert_valid_openapi(&document);
+
+        assert_eq!(document["servers"][0]["url"], "https://api.example.com");
+        let point_ref = json!({ "$ref": "#/components/schemas/record0" });
+        assert_eq!(
+            document["components"]["schemas"]["record0"]["type"],
+            "object"
+        );
+
+        let distance = &document["paths"]["/geo_geo_points_distance"]["post"];
+        assert_eq!(distance["operationId"], "geo_geo_points_distance");
+        let input = &distance["requestBody"]["content"]["application/json"]["schema"];
+        assert_eq!(input["properties"]["a"], point_ref);
+        assert_eq!(input["required"], json!(["a", "b"]));
+        assert_eq!(
+            distance["responses"]["200"]["content"]["application/json"]["schema"],
+            json!({ "type": "number" })
+        );
+
+        let mirror = &document["paths"]["/geo_geo_points_mirror"]["post"];
+        assert_eq!(
+            mirror["responses"]["200"]["content"]["application/json"]["schema"],
+            point_ref
+        );
+
+        // Functions without results have a response without content
+        let draw = &document["paths"]["/geo_geo_points_draw"]["post"];
+        assert!(draw["responses"]["200"].get("content").is_none());
+    }
+
+    #[test]
+    fn test_openapi_empty_component() {
+        let engine = Engine::default();
+        let component = Component::new(&engine, "(component)").unwrap();
+        let document = component_exports_to_openapi(&component, &engine, "http://localhost:8080");
+        assert_valid_openapi(&document);
+        assert_eq!(document["paths"], json!({}));
+        assert_eq!(document["components"]["schemas"], json!({}));
+    }
+}
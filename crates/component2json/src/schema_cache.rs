@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Caching of component schemas by the contents of the component
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::component_exports_to_json_schema;
+
+/// The SHA-256 hash of a component binary
+pub type ContentHash = [u8; 32];
+
+/// Caches the schemas returned by [`component_exports_to_json_schema`], keyed by the SHA-256
+/// hash of the component binary, so that identical components don't need their types walked
+/// again.
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    schemas: HashMap<(ContentHash, bool), Value>,
+    computed: usize,
+}
+
+impl SchemaCache {
+    /// Returns an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the SHA-256 hash of a component binary, as used to key the cache
+    pub fn hash(wasm_bytes: &[u8]) -> ContentHash {
+        Sha256::digest(wasm_bytes).into()
+    }
+
+    /// Returns the schema of the component in `wasm_bytes`, compiling it and generating the schema
+    /// only if it isn't cached yet
+    pub fn get_or_compute(
+        &mut self,
+        wasm_bytes: &[u8],
+        engine: &Engine,
+        output: bool,
+    ) -> anyhow::Result<Value> {
+        let hash = Self::hash(wasm_bytes);
+        if let Some(schema) = self.schemas.get(&(hash, output)) {
+            return Ok(schema.clone());
+        }
+        let component = Component::new(engine, wasm_bytes)?;
+        Ok(self.get_or_insert_with(hash, output, || {
+            component_exports_to_json_schema(&component, engine, wasm_bytes, output, false)
+        }))
+    }
+
+    /// Returns the cached schema for the component with the given hash, generating it with
+    /// `compute` if it isn't cached yet. Used when the component is already compiled.
+    pub fn get_or_insert_with(
+        &mut self,
+        hash: ContentHash,
+        output: bool,
+        compute: impl FnOnce() -> Value,
+    ) -> Value {
+        self.schemas
+            .entry((hash, output))
+            .or_insert_with(|| {
+                self.computed += 1;
+                compute()
+            })
+            .clone()
+    }
+
+    /// Drops the cached schemas of the component with the given hash
+    pub fn remove(&mut self, hash: &ContentHash) {
+        self.schemas.retain(|(cached, _), _| cached != hash);
+    }
+
+    /// The number of schemas generated so far, i.e. the number of cache misses
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+
+    /// The number of cached schemas
+    pub fn len(&self) -> usize {
+        self.schemas.len()
+    }
+
+    /// Whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAT: &str = r#"(component
+        (core module $m (func (export "f") (param i32) (result i32) local.get 0))
+        (core instance $i (instantiate $m))
+        (func $f (param "x" u32) (result u32) (canon lift (core func $i "f")))
+        (export "echo" (func $f))
+    )"#;
+
+    #[test]
+    fn test_second_lookup_hits_cache() {
+        let engine = Engine::default();
+        let bytes = wat::parse_str(WAT).unwrap();
+        let mut cache = SchemaCache::new();
+
+        let first = cache.get_or_compute(&bytes, &engine, true).unwrap();
+        let second = cache.get_or_compute(&bytes, &engine, true).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.computed(), 1);
+        assert_eq!(first["tools"][0]["name"], "echo");
+
+        // Schemas with and without output are cached separately
+        let without_output = cache.get_or_compute(&bytes, &engine, false).unwrap();
+        assert!(without_output["tools"][0].get("outputSchema").is_none());
+        assert_eq!(cache.computed(), 2);
+
+        cache.remove(&SchemaCache::hash(&bytes));
+        assert!(cache.is_empty());
+        cache.get_or_compute(&bytes, &engine, true).unwrap();
+        assert_eq!(cache.computed(), 3);
+    }
+
+    #[test]
+    fn test_invalid_component_is_not_cached() {
+        let engine = Engine::default();
+        let mut cache = SchemaCache::new();
+        assert!(cache.get_or_compute(b"not wasm", &engine, true).is_err());
+        assert_eq!(cache.computed(), 0);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! TypeScript declarations for the JSON arguments and results of component exports
+
+use std::fmt::Write;
+
+use wasmtime::component::types::ComponentFunc;
+use wasmtime::component::{Component, Type};
+use wasmtime::Engine;
+
+use crate::{exported_functions, normalize_tool_name, FlagsRepr, SchemaOptions};
+
+/// Given a component and a wasmtime engine, return a `.d.ts` module declaring the JSON types of
+/// the arguments and results of every exported function.
+///
+/// For every tool, an `<Name>Input` interface with the parameters and an `<Name>Output` type with
+/// the result are emitted, where `<Name>` is the [`normalize_tool_name`] of the function in
+/// PascalCase. A `Tools` interface maps every tool name to both. The types describe the JSON that
+/// [`crate::json_to_vals`] accepts and [`crate::vals_to_json`] produces, e.g. options are
+/// `T | null`, results are `{ ok: T } | { err: E }` and variants are `{ tag: "case"; val: T }`.
+pub fn component_exports_to_typescript(component: &Component, engine: &Engine) -> String {
+    component_exports_to_typescript_with_options(component, engine, SchemaOptions::default())
+}
+
+/// Same as [`component_exports_to_typescript`], with the given [`SchemaOptions`]
+pub fn component_exports_to_typescript_with_options(
+    component: &Component,
+    engine: &Engine,
+    options: SchemaOptions,
+) -> String {
+    // Writing to a `String` can't fail
+    let mut out = String::from("// Generated from the exports of a WebAssembly component\n");
+    let mut tools = Vec::new();
+    for (identifier, func) in exported_functions(component, engine) {
+        let tool_name = normalize_tool_name(&identifier);
+        let type_name = pascal_case(&tool_name);
+        write_function(&mut out, &type_name, &func, options);
+        tools.push((tool_name, type_name));
+    }
+
+    out.push_str("\nexport interface Tools {\n");
+    for (tool_name, type_name) in &tools {
+        writeln!(
+            out,
+            "  {}: {{ input: {type_name}Input; output: {type_name}Output }};",
+            property_name(tool_name)
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn write_function(out: &mut String, type_name: &str, func: &ComponentFunc, options: SchemaOptions) {
+    writeln!(out, "\nexport interface {type_name}Input {{").unwrap();
+    for (name, ty) in func.params() {
+        writeln!(out, "  {}: {};", property_name(name), ts_type(&ty, options)).unwrap();
+    }
+    out.push_str("}\n");
+
+    // Matches the shapes produced by `vals_to_json`
+    let results: Vec<Type> = func.results().collect();
+    let output = match results.as_slice() {
+        [] => "null".to_string(),
+        [ty] => ts_type(ty, options),
+        _ => {
+            let fields: Vec<String> = results
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| format!("val{i}: {}", ts_type(ty, options)))
+                .collect();
+            object_type(&fields)
+        }
+    };
+    writeln!(out, "\nexport type {type_name}Output = {output};").unwrap();
+}
+
+/// Returns the TypeScript type of the JSON representation of `ty`, on a single line
+fn ts_type(ty: &Type, options: SchemaOptions) -> String {
+    match ty {
+        Type::Bool => "boolean".to_string(),
+        Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64
+        | Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::Float32
+        | Type::Float64 => "number".to_string(),
+        Type::Char | Type::String => "string".to_string(),
+        Type::List(list) => format!("Array<{}>", ts_type(&list.ty(), options)),
+        Type::Record(record) => {
+            let fields: Vec<String> = record
+                .fields()
+                .map(|field| {
+                    // Optional fields can be left out, see `json_to_val_with`
+                    let optional = if matches!(field.ty, Type::Option(_)) {
+                        "?"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "{}{optional}: {}",
+                        property_name(field.name),
+                        ts_type(&field.ty, options)
+                    )
+                })
+                .collect();
+            object_type(&fields)
+        }
+        Type::Tuple(tuple) => {
+            let items: Vec<String> = tuple.types().map(|ty| ts_type(&ty, options)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Type::Variant(variant) => {
+            let cases: Vec<String> = variant
+                .cases()
+                .map(|case| match case.ty {
+                    Some(payload) => format!(
+                        "{{ tag: {}; val: {} }}",
+                        string_literal(case.name),
+                        ts_type(&payload, options)
+                    ),
+                    None => format!("{{ tag: {} }}", string_literal(case.name)),
+                })
+                .collect();
+            union(cases)
+        }
+        Type::Enum(enum_) => union(enum_.names().map(string_literal).collect()),
+        Type::Option(option) => format!("{} | null", ts_type(&option.ty(), options)),
+        Type::Result(result) => {
+            let ok = result
+                .ok()
+                .map_or("null".to_string(), |ty| ts_type(&ty, options));
+            let err = result
+                .err()
+                .map_or("null".to_string(), |ty| ts_type(&ty, options));
+            format!("{{ ok: {ok} }} | {{ err: {err} }}")
+        }
+        Type::Flags(flags) => match options.flags {
+            FlagsRepr::Array => format!(
+                "Array<{}>",
+                union(flags.names().map(string_literal).collect())
+            ),
+            FlagsRepr::Object => {
+                let fields: Vec<String> = flags
+                    .names()
+                    .map(|name| format!("{}?: boolean", property_name(name)))
+                    .collect();
+                object_type(&fields)
+            }
+        },
+        Type::Own(_) | Type::Borrow(_) => {
+            format!("{{ {}: number }}", crate::RESOURCE_HANDLE_FIELD)
+        }
+    }
+}
+
+fn object_type(fields: &[String]) -> String {
+    if fields.is_empty() {
+        return "{}".to_string();
+    }
+    format!("{{ {} }}", fields.join("; "))
+}
+
+fn union(members: Vec<String>) -> String {
+    if members.is_empty() {
+        return "never".to_string();
+    }
+    members.join(" | ")
+}
+
+fn string_literal(value: &str) -> String {
+    serde_json::to_string(value).expect("Strings can always be serialized")
+}
+
+/// Returns `name` as a property name, quoted if it isn't a valid identifier (WIT names are
+/// kebab-case)
+fn property_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_identifier {
+        name.to_string()
+    } else {
+        string_literal(name)
+    }
+}
+
+/// Converts a normalized tool name like `foo_bar-baz` to `FooBarBaz`
+fn pascal_case(name: &str) -> String {
+    let mut out = String::new();
+    for part in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.push(first.to_ascii_uppercase());
+            out.extend(chars);
+        }
+    }
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert(0, 'T');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::*;
+
+    /// Checks that all brackets, braces, parentheses and angle brackets are balanced outside of
+    /// string literals, which catches most syntax errors in the generated declarations
+    fn assert_balanced(source: &str) {
+        let mut stack = Vec::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in source.chars() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' | '[' | '(' | '<' => stack.push(c),
+                '}' | ']' | ')' | '>' => {
+                    let open = stack.pop();
+                    let expected = match c {
+                        '}' => '{',
+                        ']' => '[',
+                        ')' => '(',
+                        _ => '<',
+                    };
+                    assert_eq!(open, Some(expected), "unbalanced '{c}' in:\n{source}");
+                }
+                _ => {}
+            }
+        }
+        assert!(!in_string, "unterminated string in:\n{source}");
+        assert!(stack.is_empty(), "unclosed {stack:?} in:\n{source}");
+    }
+
+    #[test]
+    fn test_typescript_types() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (option string))
+                    (type (record (field "max-results" u32) (field "label" 0)))
+                    (export "query" (type (eq 1)))
+                    (type (variant (case "circle" float64) (case "empty")))
+                    (export "shape" (type (eq 3)))
+                    (type (enum "red" "green"))
+                    (export "color" (type (eq 5)))
+                    (type (flags "read" "write"))
+                    (export "mode" (type (eq 7)))
+                    (type (tuple 4 6))
+                    (type (list 9))
+                    (type (result 10 (error string)))
+                    (type (func (param "q" 2) (param "m" 8) (result 11)))
+                    (export "search" (func (type 12)))
+                    (type (func (param "s" 4)))
+                    (export "clear" (func (type 13)))
+                ))
+                (export "test:ts/shapes" (component (type 0)))
+            ))
+            (export "shapes" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let ts = component_exports_to_typescript(&component, &engine);
+        assert_balanced(&ts);
+
+        assert!(ts.contains(
+            "export interface TestTsShapesSearchInput {\n  q: { \"max-results\": number; label?: string | null };\n  m: Array<\"read\" | \"write\">;\n}"
+        ), "{ts}");
+        assert!(ts.contains(
+            "export type TestTsShapesSearchOutput = { ok: Array<[{ tag: \"circle\"; val: number } | { tag: \"empty\" }, \"red\" | \"green\"]> } | { err: string };"
+        ), "{ts}");
+        assert!(
+            ts.contains("export type TestTsShapesClearOutput = null;"),
+            "{ts}"
+        );
+        assert!(ts.contains(
+            "  test_ts_shapes_search: { input: TestTsShapesSearchInput; output: TestTsShapesSearchOutput };"
+        ), "{ts}");
+
+        let options = SchemaOptions {
+            flags: FlagsRepr::Object,
+        };
+        let ts = component_exports_to_typescript_with_options(&component, &engine, options);
+        assert!(
+            ts.contains("m: { read?: boolean; write?: boolean };"),
+            "{ts}"
+        );
+    }
+
+    #[test]
+    fn test_property_names() {
+        assert_eq!(property_name("path"), "path");
+        assert_eq!(property_name("max-results"), "\"max-results\"");
+        assert_eq!(property_name("1st"), "\"1st\"");
+        assert_eq!(
+            pascal_case("component_filesystem2_read-file"),
+            "ComponentFilesystem2ReadFile"
+        );
+        assert_eq!(pascal_case("3d_render"), "T3dRender");
+    }
+
+    fn build_filesystem_component() -> PathBuf {
+        let example_dir = std::env::current_dir()
+            .unwrap()
+            .join("../../examples/filesystem-rs");
+        let component_path = example_dir.join("target/wasm32-wasip2/release/filesystem.wasm");
+        if !component_path.exists() {
+            let status = Command::new("cargo")
+                .current_dir(&example_dir)
+                .args(["build", "--release", "--target", "wasm32-wasip2"])
+                .status()
+                .expect("Failed to execute cargo build");
+            assert!(
+                status.success(),
+                "Failed to compile filesystem-rs component"
+            );
+        }
+        component_path
+    }
+
+    #[test]
+    fn test_filesystem_component_typescript() {
+        let engine = Engine::default();
+        let component = Component::from_file(&engine, build_filesystem_component()).unwrap();
+        let ts = component_exports_to_typescript(&component, &engine);
+        assert_balanced(&ts);
+
+        for function in [
+            "list-directory",
+            "read-file",
+            "search-file",
+            "get-file-info",
+        ] {
+            let tool = crate::exported_functions(&component, &engine)
+                .into_iter()
+                .map(|(identifier, _)| normalize_tool_name(&identifier))
+                .find(|name| name.ends_with(function))
+                .unwrap_or_else(|| panic!("No tool for {function}"));
+            let type_name = pascal_case(&tool);
+            assert!(
+                ts.contains(&format!("export interface {type_name}Input {{")),
+                "{ts}"
+            );
+            assert!(
+                ts.contains(&format!("export type {type_name}Output = ")),
+                "{ts}"
+            );
+        }
+        assert!(ts.contains("path: string;"), "{ts}");
+        assert!(ts.contains("= { ok: string } | { err: string };"), "{ts}");
+    }
+}
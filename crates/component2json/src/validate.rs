@@ -0,0 +1,385 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Checking JSON values against component types without converting them
+
+use std::str::FromStr;
+
+use serde_json::Value;
+use wasmtime::component::Type;
+
+use crate::{json_to_float, parse_integer_string, ValError};
+
+/// Checks that `value` would be accepted by [`crate::json_to_vals`] as a value of type `ty`,
+/// without allocating any [`wasmtime::component::Val`]. Returns the error the conversion would
+/// return for the first problem found.
+///
+/// Like [`crate::json_to_vals`], resource handles are always rejected with a
+/// [`ValError::ResourceError`], since they can only be resolved by a [`crate::ResourceResolver`].
+pub fn validate_json_for_type(value: &Value, ty: &Type) -> Result<(), ValError> {
+    match ty {
+        Type::Bool => match value {
+            Value::Bool(_) => Ok(()),
+            _ => Err(ValError::ShapeError("bool", format!("{value:?}"))),
+        },
+        Type::S8 => validate_integer::<i8>(value, "s8"),
+        Type::S16 => validate_integer::<i16>(value, "s16"),
+        Type::S32 => validate_integer::<i32>(value, "s32"),
+        Type::S64 => validate_integer::<i64>(value, "s64"),
+        Type::U8 => validate_integer::<u8>(value, "u8"),
+        Type::U16 => validate_integer::<u16>(value, "u16"),
+        Type::U32 => validate_integer::<u32>(value, "u32"),
+        Type::U64 => validate_integer::<u64>(value, "u64"),
+        Type::Float32 => json_to_float(value, "float32").map(|_| ()),
+        Type::Float64 => json_to_float(value, "float64").map(|_| ()),
+        Type::Char => match value {
+            Value::String(s) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(_), None) => Ok(()),
+                    _ => Err(ValError::InvalidChar {
+                        value: format!("{s:?}"),
+                        reason: "expected a single unicode scalar value".to_string(),
+                    }),
+                }
+            }
+            _ => Err(ValError::ShapeError("char", format!("{value:?}"))),
+        },
+        Type::String => match value {
+            Value::String(_) => Ok(()),
+            _ => Err(ValError::ShapeError("string", format!("{value:?}"))),
+        },
+        Type::List(list) => match value {
+            Value::Array(items) => {
+                let item_ty = list.ty();
+                items
+                    .iter()
+                    .try_for_each(|item| validate_json_for_type(item, &item_ty))
+            }
+            _ => Err(ValError::ShapeError("list", format!("{value:?}"))),
+        },
+        Type::Record(record) => match value {
+            Value::Object(obj) => {
+                record
+                    .fields()
+                    .try_for_each(|field| match (obj.get(field.name), &field.ty) {
+                        (Some(value), ty) => validate_json_for_type(value, ty),
+                        (None, Type::Option(_)) => Ok(()),
+                        (None, _) => Err(ValError::ShapeError(
+                            "record",
+                            format!("missing field {}", field.name),
+                        )),
+                    })
+            }
+            _ => Err(ValError::ShapeError("record", format!("{value:?}"))),
+        },
+        Type::Tuple(tuple) => match value {
+            Value::Array(items) => {
+                let expected = tuple.types().count();
+                if items.len() != expected {
+                    return Err(ValError::ShapeError(
+                        "tuple",
+                        format!("expected {expected} items, got {}", items.len()),
+                    ));
+                }
+                items
+                    .iter()
+                    .zip(tuple.types())
+                    .try_for_each(|(value, ty)| validate_json_for_type(value, &ty))
+            }
+            _ => Err(ValError::ShapeError("tuple", format!("{value:?}"))),
+        },
+        Type::Variant(variant) => match value {
+            Value::Object(obj) => {
+                let tag = obj
+                    .get("tag")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| ValError::ShapeError("variant", "missing tag".to_string()))?;
+                let case = variant
+                    .cases()
+                    .find(|case| case.name == tag)
+                    .ok_or_else(|| ValError::UnknownShape(obj.clone()))?;
+                match &case.ty {
+                    Some(payload_ty) => {
+                        let payload = obj.get("val").ok_or_else(|| {
+                            ValError::ShapeError("variant", "missing val".to_string())
+                        })?;
+                        validate_json_for_type(payload, payload_ty)
+                    }
+                    None => Ok(()),
+                }
+            }
+            _ => Err(ValError::ShapeError("variant", format!("{value:?}"))),
+        },
+        Type::Enum(enum_ty) => match value {
+            Value::String(s) if enum_ty.names().any(|name| name == s) => Ok(()),
+            Value::String(s) => Err(ValError::ShapeError(
+                "enum",
+                format!("invalid enum value: {s}"),
+            )),
+            _ => Err(ValError::ShapeError("enum", format!("{value:?}"))),
+        },
+        Type::Option(option) => match value {
+            Value::Null => Ok(()),
+            value => validate_json_for_type(value, &option.ty()),
+        },
+        Type::Result(result) => match value {
+            Value::Object(obj) => {
+                if let Some(ok) = obj.get("ok") {
+                    validate_json_for_type(ok, &result.ok().unwrap_or(Type::Bool))
+                } else if let Some(err) = obj.get("err") {
+                    validate_json_for_type(err, &result.err().unwrap_or(Type::Bool))
+                } else {
+                    Err(ValError::ShapeError("result", format!("{value:?}")))
+                }
+            }
+            _ => Err(ValError::ShapeError("result", format!("{value:?}"))),
+        },
+        Type::Flags(flags) => match value {
+            Value::Array(_) => Ok(()),
+            Value::Object(obj) => flags.names().try_for_each(|name| match obj.get(name) {
+                Some(Value::Bool(_)) | None => Ok(()),
+                Some(other) => Err(ValError::ShapeError(
+                    "flags",
+                    format!("expected boolean for flag {name}, found: {other:?}"),
+                )),
+            }),
+            _ => Err(ValError::ShapeError("flags", format!("{value:?}"))),
+        },
+        Type::Own(_) | Type::Borrow(_) => Err(ValError::ResourceError),
+    }
+}
+
+/// Checks that `value` is an integer, or a string holding one, that fits in `T`
+fn validate_integer<T>(value: &Value, kind: &'static str) -> Result<(), ValError>
+where
+    T: TryFrom<i64> + TryFrom<u64> + FromStr,
+{
+    match value {
+        Value::Number(n) => {
+            let fits = n
+                .as_i64()
+                .is_some_and(|i| <T as TryFrom<i64>>::try_from(i).is_ok())
+                || n.as_u64()
+                    .is_some_and(|u| <T as TryFrom<u64>>::try_from(u).is_ok());
+            if fits {
+                Ok(())
+            } else {
+                Err(ValError::NumberError(format!("{n:?}")))
+            }
+        }
+        Value::String(s) => parse_integer_string::<T>(s)
+            .map(|_| ())
+            .ok_or_else(|| ValError::NumberError(s.clone())),
+        _ => Err(ValError::ShapeError(kind, format!("{value:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wasmtime::component::Component;
+    use wasmtime::Engine;
+
+    use super::*;
+    use crate::{exported_functions, json_to_val};
+
+    /// Returns the parameter types of the single function of a component, which takes a
+    /// parameter per type
+    fn param_types(engine: &Engine) -> Vec<(String, Type)> {
+        let wat = r#"(component
+            (core module $m
+                (memory (export "mem") 1)
+                (func (export "everything") (param i32))
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 0)
+            )
+            (core instance $i (instantiate $m))
+            (type $person' (record (field "name" string) (field "nick" (option string))))
+            (export $person "person" (type $person'))
+            (type $maybe' (variant (case "none") (case "some" u32)))
+            (export $maybe "maybe" (type $maybe'))
+            (type $color' (enum "red" "green"))
+            (export $color "color" (type $color'))
+            (type $mode' (flags "read" "write"))
+            (export $mode "mode" (type $mode'))
+            (func (export "everything")
+                (param "b" bool)
+                (param "s8" s8)
+                (param "u8" u8)
+                (param "s64" s64)
+                (param "u64" u64)
+                (param "f" float64)
+                (param "c" char)
+                (param "str" string)
+                (param "list" (list u16))
+                (param "record" $person)
+                (param "tuple" (tuple u8 string))
+                (param "variant" $maybe)
+                (param "enum" $color)
+                (param "option" (option u32))
+                (param "result" (result u8 (error string)))
+                (param "flags" $mode)
+                (canon lift
+                    (core func $i "everything")
+                    (memory $i "mem")
+                    (realloc (func $i "realloc"))
+                )
+            )
+        )"#;
+        let component = Component::new(engine, wat).unwrap();
+        let (_, func) = exported_functions(&component, engine)
+            .into_iter()
+            .next()
+            .unwrap();
+        func.params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_agrees_with_json_to_val() {
+        let engine = Engine::default();
+        let types = param_types(&engine);
+        let cases: &[(&str, &[Value])] = &[
+            ("b", &[json!(true), json!("true"), json!(1)]),
+            (
+                "s8",
+                &[
+                    json!(-128),
+                    json!(128),
+                    json!("-5"),
+                    json!("1.5"),
+                    json!(1.5),
+                    json!(null),
+                ],
+            ),
+            (
+                "u8",
+                &[json!(255), json!(-1), json!(256), json!("7"), json!("+7")],
+            ),
+            (
+                "s64",
+                &[
+                    json!(i64::MIN),
+                    json!(u64::MAX),
+                    json!("-9223372036854775808"),
+                ],
+            ),
+            (
+                "u64",
+                &[
+                    json!(u64::MAX),
+                    json!(-1),
+                    json!("18446744073709551615"),
+                    json!("18446744073709551616"),
+                ],
+            ),
+            (
+                "f",
+                &[
+                    json!(1.5),
+                    json!(3),
+                    json!("1.5"),
+                    json!({ "$float": "-inf" }),
+                    json!({ "$float": "big" }),
+                ],
+            ),
+            ("c", &[json!("x"), json!(""), json!("ab"), json!(65)]),
+            ("str", &[json!("hello"), json!(1), json!(null)]),
+            (
+                "list",
+                &[json!([1, 2]), json!([1, -2]), json!([]), json!({})],
+            ),
+            (
+                "record",
+                &[
+                    json!({ "name": "a" }),
+                    json!({ "name": "a", "nick": null, "extra": 1 }),
+                    json!({ "nick": "b" }),
+                    json!({ "name": 1 }),
+                    json!([]),
+                ],
+            ),
+            (
+                "tuple",
+                &[json!([1, "a"]), json!([1]), json!([1, 2]), json!("a")],
+            ),
+            (
+                "variant",
+                &[
+                    json!({ "tag": "none" }),
+                    json!({ "tag": "some", "val": 1 }),
+                    json!({ "tag": "some" }),
+                    json!({ "tag": "other" }),
+                    json!({ "val": 1 }),
+                    json!("none"),
+                ],
+            ),
+            ("enum", &[json!("red"), json!("blue"), json!(0)]),
+            ("option", &[json!(null), json!(1), json!("x")]),
+            (
+                "result",
+                &[
+                    json!({ "ok": 1 }),
+                    json!({ "err": "bad" }),
+                    json!({ "ok": "1.5" }),
+                    json!({ "err": 1 }),
+                    json!({}),
+                ],
+            ),
+            (
+                "flags",
+                &[
+                    json!(["read"]),
+                    json!({ "read": true, "write": false }),
+                    json!({ "read": "yes" }),
+                    json!("read"),
+                ],
+            ),
+        ];
+
+        for (name, values) in cases {
+            let (_, ty) = types.iter().find(|(param, _)| param == name).unwrap();
+            for value in *values {
+                let validated = validate_json_for_type(value, ty);
+                let converted = json_to_val(value, ty);
+                assert_eq!(
+                    validated.is_ok(),
+                    converted.is_ok(),
+                    "{name} = {value}: validate {validated:?}, json_to_val {converted:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let engine = Engine::default();
+        let types = param_types(&engine);
+        let ty = |name: &str| {
+            types
+                .iter()
+                .find(|(param, _)| param == name)
+                .unwrap()
+                .1
+                .clone()
+        };
+
+        assert!(matches!(
+            validate_json_for_type(&json!(300), &ty("u8")),
+            Err(ValError::NumberError(_))
+        ));
+        assert!(matches!(
+            validate_json_for_type(&json!({ "name": "a", "nick": 1 }), &ty("record")),
+            Err(ValError::ShapeError("string", _))
+        ));
+        assert!(matches!(
+            validate_json_for_type(&json!({ "tag": "other" }), &ty("variant")),
+            Err(ValError::UnknownShape(_))
+        ));
+        assert!(matches!(
+            validate_json_for_type(&json!("ab"), &ty("c")),
+            Err(ValError::InvalidChar { .. })
+        ));
+    }
+}
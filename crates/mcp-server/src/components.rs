@@ -7,33 +7,24 @@ use std::sync::Arc;
 use anyhow::Result;
 use futures::stream::{self, StreamExt};
 use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
-use rmcp::{Peer, RoleServer};
 use serde_json::{json, Value};
 use tracing::{debug, error, info, instrument};
-use wassette::LifecycleManager;
+use wassette::{LifecycleManager, LoadResult, PolicyInfo, WassetteError, TOOL_NAME_SEPARATOR};
+
+use crate::media::{is_media, media_content};
+use crate::streaming::{is_chunked, send_chunks, ChunkSink};
 
 #[instrument(skip(lifecycle_manager))]
 pub(crate) async fn get_component_tools(lifecycle_manager: &LifecycleManager) -> Result<Vec<Tool>> {
-    debug!("Listing components");
-    let component_ids = lifecycle_manager.list_components().await;
-
-    info!(count = component_ids.len(), "Found components");
-    let mut tools = Vec::new();
-
-    for id in component_ids {
-        debug!(component_id = %id, "Getting component details");
-        if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
-            if let Some(arr) = schema.get("tools").and_then(|v| v.as_array()) {
-                let tool_count = arr.len();
-                debug!(component_id = %id, tool_count, "Found tools in component");
-                for tool_json in arr {
-                    if let Some(tool) = parse_tool_schema(tool_json) {
-                        tools.push(tool);
-                    }
-                }
-            }
-        }
-    }
+    debug!("Listing component tools");
+    // The registered tool schemas are used instead of the component schema documents, because
+    // the documents may refer to shared type definitions at their root with `$ref`
+    let tools: Vec<Tool> = lifecycle_manager
+        .list_tools()
+        .await
+        .iter()
+        .filter_map(parse_tool_schema)
+        .collect();
     info!(total_tools = tools.len(), "Total tools collected");
     Ok(tools)
 }
@@ -42,7 +33,6 @@ pub(crate) async fn get_component_tools(lifecycle_manager: &LifecycleManager) ->
 pub(crate) async fn handle_load_component(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
     let path = args
@@ -53,9 +43,10 @@ pub(crate) async fn handle_load_component(
     info!(path, "Loading component");
 
     match lifecycle_manager.load_component(path).await {
-        Ok((id, _load_result)) => {
-            handle_tool_list_notification(Some(server_peer), &id, "load").await;
-            create_component_success_result("load", &id)
+        Ok((id, load_result)) => {
+            // Clients are told about the new tools by the subscribers of the lifecycle manager
+            info!(component_id = %id, ?load_result, "Component loaded");
+            create_load_component_result(lifecycle_manager, &id, load_result).await
         }
         Err(e) => {
             error!(error = %e, path, "Failed to load component");
@@ -72,7 +63,6 @@ pub(crate) async fn handle_load_component(
 pub(crate) async fn handle_unload_component(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
     let id = args
@@ -84,7 +74,7 @@ pub(crate) async fn handle_unload_component(
 
     match lifecycle_manager.unload_component(id).await {
         Ok(()) => {
-            handle_tool_list_notification(Some(server_peer), id, "unload").await;
+            info!(component_id = %id, "Component unloaded");
             create_component_success_result("unload", id)
         }
         Err(e) => {
@@ -94,10 +84,11 @@ pub(crate) async fn handle_unload_component(
     }
 }
 
-#[instrument(skip(lifecycle_manager))]
+#[instrument(skip(lifecycle_manager, chunk_sink))]
 pub(crate) async fn handle_component_call(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
+    chunk_sink: Option<&dyn ChunkSink>,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
 
@@ -112,17 +103,42 @@ pub(crate) async fn handle_component_call(
         })?;
 
     let result = lifecycle_manager
-        .execute_component_call(&component_id, &method_name, &serde_json::to_string(&args)?)
+        .execute_component_call_json(&component_id, &method_name, &serde_json::to_string(&args)?)
         .await;
 
     match result {
-        Ok(result_str) => {
+        Ok(result) => {
             debug!("Component call successful");
-            let contents = vec![Content::text(result_str)];
+            let output_schema =
+                tool_output_schema(lifecycle_manager, &component_id, &method_name).await;
+            if let (Some(chunk_sink), Value::Array(chunks)) = (chunk_sink, &result) {
+                if is_chunked(output_schema.as_ref()) {
+                    debug!(chunks = chunks.len(), "Streaming result");
+                    send_chunks(chunk_sink, chunks).await?;
+                    return Ok(CallToolResult {
+                        content: Some(vec![]),
+                        structured_content: Some(structured_result(
+                            json!([]),
+                            output_schema.as_ref(),
+                        )),
+                        is_error: None,
+                    });
+                }
+            }
+
+            let contents = if is_media(output_schema.as_ref()) {
+                let uri = format!("component://{component_id}/{method_name}");
+                vec![media_content(&result, &uri)?]
+            } else {
+                let result_str = lifecycle_manager
+                    .render_call_result(&component_id, &method_name, &result)
+                    .await?;
+                vec![Content::text(result_str)]
+            };
 
             Ok(CallToolResult {
                 content: Some(contents),
-                structured_content: None,
+                structured_content: Some(structured_result(result, output_schema.as_ref())),
                 is_error: None,
             })
         }
@@ -133,6 +149,38 @@ pub(crate) async fn handle_component_call(
     }
 }
 
+/// Returns the output schema a component declares for a tool, if it declares one
+async fn tool_output_schema(
+    lifecycle_manager: &LifecycleManager,
+    component_id: &str,
+    tool_name: &str,
+) -> Option<Value> {
+    let tool_name = tool_name
+        .split_once(TOOL_NAME_SEPARATOR)
+        .map_or(tool_name, |(_, tool_name)| tool_name);
+    lifecycle_manager
+        .list_tools_detailed()
+        .await
+        .into_iter()
+        .find(|tool| tool.component_id == component_id && tool.normalized_name == tool_name)
+        .and_then(|tool| tool.schema.get("outputSchema").cloned())
+}
+
+/// Returns the structured content for the `result` of a tool. Tools whose output schema isn't an
+/// object have it wrapped in a `result` property by [`parse_tool_schema`], so their results are
+/// wrapped the same way.
+fn structured_result(result: Value, output_schema: Option<&Value>) -> Value {
+    let is_object = match output_schema {
+        Some(schema) => schema.get("type").and_then(Value::as_str) == Some("object"),
+        None => result.is_object(),
+    };
+    if is_object {
+        result
+    } else {
+        json!({ "result": result })
+    }
+}
+
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_list_components(
     lifecycle_manager: &LifecycleManager,
@@ -144,25 +192,37 @@ pub async fn handle_list_components(
     let components_info = stream::iter(component_ids)
         .map(|id| async move {
             debug!(component_id = %id, "Getting component details");
-            if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
-                let tools_count = schema
-                    .get("tools")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.len())
-                    .unwrap_or(0);
-
-                json!({
-                    "id": id,
-                    "tools_count": tools_count,
-                    "schema": schema
-                })
-            } else {
-                json!({
-                    "id": id,
-                    "tools_count": 0,
-                    "schema": null
-                })
+            let mut component =
+                if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
+                    let tools_count = schema
+                        .get("tools")
+                        .and_then(|v| v.as_array())
+                        .map(|arr| arr.len())
+                        .unwrap_or(0);
+
+                    json!({
+                        "id": id,
+                        "tools_count": tools_count,
+                        "schema": schema
+                    })
+                } else {
+                    json!({
+                        "id": id,
+                        "tools_count": 0,
+                        "schema": null
+                    })
+                };
+            if let Some(info) = lifecycle_manager.component_info(&id).await {
+                let sha256: String = info.sha256.iter().map(|b| format!("{b:02x}")).collect();
+                component["wasm_size_bytes"] = json!(info.wasm_size_bytes);
+                component["sha256"] = json!(sha256);
+                component["loaded_at"] = json!(info
+                    .loaded_at
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs());
             }
+            component
         })
         .buffer_unordered(50)
         .collect::<Vec<_>>()
@@ -218,11 +278,74 @@ fn create_component_success_result(
     })
 }
 
+/// Create the result for a successful load, with both a text block and structured content
+/// describing the loaded component
+async fn create_load_component_result(
+    lifecycle_manager: &LifecycleManager,
+    component_id: &str,
+    load_result: LoadResult,
+) -> Result<CallToolResult> {
+    let tools = lifecycle_manager
+        .get_component_tool_names(component_id)
+        .await
+        .unwrap_or_default();
+    let policy_info = lifecycle_manager.get_policy_info(component_id).await;
+
+    build_load_component_result(component_id, load_result, tools, policy_info.as_ref())
+}
+
+fn build_load_component_result(
+    component_id: &str,
+    load_result: LoadResult,
+    mut tools: Vec<String>,
+    policy_info: Option<&PolicyInfo>,
+) -> Result<CallToolResult> {
+    tools.sort();
+
+    let load_result = match load_result {
+        LoadResult::New => "new",
+        LoadResult::Replaced => "replaced",
+        LoadResult::Unchanged => "unchanged",
+    };
+
+    let policy = policy_info.map(|info| {
+        json!({
+            "policy_id": info.policy_id,
+            "source_uri": info.source_uri,
+            "local_path": info.local_path,
+            "created_at": info.created_at.duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default().as_secs()
+        })
+    });
+
+    let structured = json!({
+        "status": "component loaded successfully",
+        "id": component_id,
+        "load_result": load_result,
+        "tools": tools,
+        "policy": policy,
+    });
+
+    let mut text = format!("Loaded component `{component_id}` ({load_result})");
+    if !tools.is_empty() {
+        text.push_str(&format!(": tools {}", tools.join(", ")));
+    }
+    if let Some(info) = policy_info {
+        text.push_str(&format!(" with policy {}", info.source_uri));
+    }
+
+    Ok(CallToolResult {
+        content: Some(vec![Content::text(text)]),
+        structured_content: Some(structured),
+        is_error: None,
+    })
+}
+
 /// Create error result for component operations
 fn create_component_error_result(
     operation_name: &str,
     operation_arg: &str,
-    error: &anyhow::Error,
+    error: &WassetteError,
 ) -> CallToolResult {
     let error_text = serde_json::to_string(&json!({
         "status": "error",
@@ -242,26 +365,6 @@ fn create_component_error_result(
     }
 }
 
-/// Handle tool list change notification
-async fn handle_tool_list_notification(
-    server_peer: Option<Peer<RoleServer>>,
-    component_id: &str,
-    operation_name: &str,
-) {
-    if let Some(peer) = server_peer {
-        if let Err(e) = peer.notify_tool_list_changed().await {
-            error!(error = %e, "Failed to send tool list change notification");
-        } else {
-            info!(
-                component_id = %component_id,
-                "Sent tool list changed notification after {}ing component", operation_name
-            );
-        }
-    } else {
-        info!(component_id = %component_id, "Component {}ed successfully in CLI mode", operation_name);
-    }
-}
-
 /// CLI-specific version of handle_load_component that doesn't require server peer notifications
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_load_component_cli(
@@ -277,9 +380,9 @@ pub async fn handle_load_component_cli(
     info!(path, "Loading component (CLI mode)");
 
     match lifecycle_manager.load_component(path).await {
-        Ok((id, _load_result)) => {
-            handle_tool_list_notification(None, &id, "load").await;
-            create_component_success_result("load", &id)
+        Ok((id, load_result)) => {
+            info!(component_id = %id, "Component loaded successfully in CLI mode");
+            create_load_component_result(lifecycle_manager, &id, load_result).await
         }
         Err(e) => {
             error!(error = %e, path, "Failed to load component");
@@ -308,7 +411,7 @@ pub async fn handle_unload_component_cli(
 
     match lifecycle_manager.unload_component(id).await {
         Ok(()) => {
-            handle_tool_list_notification(None, id, "unload").await;
+            info!(component_id = %id, "Component unloaded successfully in CLI mode");
             create_component_success_result("unload", id)
         }
         Err(e) => {
@@ -387,6 +490,7 @@ pub(crate) fn parse_tool_schema(tool_json: &Value) -> Option<Tool> {
 #[cfg(test)]
 mod tests {
     use serde_json::json;
+    use wassette::test_fixtures::*;
 
     use super::*;
 
@@ -435,6 +539,110 @@ mod tests {
         assert_eq!(args.get("id").unwrap(), "test-id");
     }
 
+    #[test]
+    fn test_build_load_component_result_structured_fields() {
+        let result = build_load_component_result(
+            "fetch_rs",
+            LoadResult::Replaced,
+            vec!["fetch".to_string(), "another-tool".to_string()],
+            None,
+        )
+        .unwrap();
+
+        let structured = result.structured_content.expect("structured content");
+        assert_eq!(structured["id"], "fetch_rs");
+        assert_eq!(structured["load_result"], "replaced");
+        assert_eq!(structured["tools"], json!(["another-tool", "fetch"]));
+        assert!(structured["policy"].is_null());
+
+        let contents = result.content.expect("text content");
+        let text = &contents[0].as_text().expect("text content").text;
+        assert_eq!(
+            text,
+            "Loaded component `fetch_rs` (replaced): tools another-tool, fetch"
+        );
+    }
+
+    #[test]
+    fn test_build_load_component_result_with_policy() {
+        let policy_info = PolicyInfo {
+            policy_id: "fetch_rs-policy".to_string(),
+            source_uri: "file:///tmp/policy.yaml".to_string(),
+            local_path: std::path::PathBuf::from("/tmp/fetch_rs.policy.yaml"),
+            component_id: "fetch_rs".to_string(),
+            created_at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(42),
+        };
+
+        let result = build_load_component_result(
+            "fetch_rs",
+            LoadResult::Unchanged,
+            vec![],
+            Some(&policy_info),
+        )
+        .unwrap();
+
+        let structured = result.structured_content.unwrap();
+        assert_eq!(structured["load_result"], "unchanged");
+        assert_eq!(
+            structured["policy"]["source_uri"],
+            "file:///tmp/policy.yaml"
+        );
+        assert_eq!(structured["policy"]["created_at"], 42);
+
+        let contents = result.content.unwrap();
+        assert_eq!(
+            contents[0].as_text().unwrap().text,
+            "Loaded component `fetch_rs` (unchanged) with policy file:///tmp/policy.yaml"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_component_call_structured_content() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(tempdir.path()).await?;
+        load_wat_component(&lifecycle_manager, "answer", ANSWER_WAT).await?;
+
+        let req = CallToolRequestParam {
+            name: "answer".into(),
+            arguments: None,
+        };
+        let result = handle_component_call(&req, &lifecycle_manager, None).await?;
+        assert_eq!(result.is_error, None);
+        let contents = result.content.expect("text content");
+        assert_eq!(contents[0].as_text().expect("text content").text, "42");
+        // The result isn't an object, so it is wrapped like the output schema of the tool
+        assert_eq!(result.structured_content, Some(json!({"result": 42})));
+
+        let req = CallToolRequestParam {
+            name: "missing".into(),
+            arguments: None,
+        };
+        assert!(handle_component_call(&req, &lifecycle_manager, None)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_structured_result_follows_output_schema() {
+        let object_schema = json!({"type": "object", "properties": {}});
+        let string_schema = json!({"type": "string"});
+        assert_eq!(
+            structured_result(json!({"a": 1}), Some(&object_schema)),
+            json!({"a": 1})
+        );
+        assert_eq!(
+            structured_result(json!({"a": 1}), Some(&string_schema)),
+            json!({"result": {"a": 1}})
+        );
+        assert_eq!(
+            structured_result(json!("text"), None),
+            json!({"result": "text"})
+        );
+        assert_eq!(structured_result(json!({"a": 1}), None), json!({"a": 1}));
+    }
+
     #[test]
     fn test_extract_args_from_request_none() {
         let req = CallToolRequestParam {
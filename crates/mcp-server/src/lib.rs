@@ -4,10 +4,15 @@
 pub use wassette::LifecycleManager;
 
 pub mod components;
+mod media;
+pub mod middleware;
 pub mod prompts;
 pub mod resources;
+pub mod streaming;
 pub mod tools;
 
+pub use middleware::{AuditLogMiddleware, ToolCallMiddleware};
 pub use prompts::handle_prompts_list;
-pub use resources::handle_resources_list;
+pub use resources::{handle_resources_list, handle_resources_read};
+pub use streaming::{ChunkSink, ProgressNotifier};
 pub use tools::{handle_tools_call, handle_tools_list};
@@ -0,0 +1,198 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Returning the results of tools as images and blobs rather than text
+//!
+//! A tool opts in through its signature, by returning a record with exactly a `mime-type` string
+//! and the `data`, either as a base64 string or as the bytes themselves:
+//!
+//! ```wit
+//! record media {
+//!     mime-type: string,
+//!     data: list<u8>,
+//! }
+//! ```
+//!
+//! Results with an `image/*` MIME type are returned as image content, the others as an embedded
+//! blob resource.
+
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use rmcp::model::{Content, ResourceContents};
+use serde_json::Value;
+
+/// The names the MIME type field may have. Component records can only have the first, results
+/// changed by a result transformer may have either
+const MIME_TYPE_FIELDS: [&str; 2] = ["mime-type", "mime_type"];
+const DATA_FIELD: &str = "data";
+
+/// Returns whether a tool with `output_schema` returns media, see the module documentation
+pub(crate) fn is_media(output_schema: Option<&Value>) -> bool {
+    let Some(properties) = output_schema
+        .filter(|schema| schema.get("type").and_then(Value::as_str) == Some("object"))
+        .and_then(|schema| schema.get("properties"))
+        .and_then(Value::as_object)
+    else {
+        return false;
+    };
+    let schema_type = |name: &str| {
+        properties
+            .get(name)
+            .and_then(|schema| schema.get("type"))
+            .and_then(Value::as_str)
+    };
+    let has_mime_type = MIME_TYPE_FIELDS
+        .iter()
+        .any(|field| schema_type(field) == Some("string"));
+    properties.len() == 2
+        && has_mime_type
+        && matches!(schema_type(DATA_FIELD), Some("string" | "array"))
+}
+
+/// Returns the content of a media `result`. Blobs are embedded as a resource with `uri`.
+pub(crate) fn media_content(result: &Value, uri: &str) -> Result<Content> {
+    let mime_type = MIME_TYPE_FIELDS
+        .iter()
+        .find_map(|field| result.get(field))
+        .and_then(Value::as_str)
+        .context("The result has no MIME type")?;
+    let data = match result.get(DATA_FIELD) {
+        Some(Value::String(data)) => {
+            BASE64
+                .decode(data)
+                .context("The data of the result isn't valid base64")?;
+            data.clone()
+        }
+        Some(Value::Array(bytes)) => {
+            let bytes = bytes
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .context("The data of the result isn't a list of bytes")?;
+            BASE64.encode(bytes)
+        }
+        _ => bail!("The result has no data"),
+    };
+
+    if mime_type.starts_with("image/") {
+        Ok(Content::image(data, mime_type))
+    } else {
+        Ok(Content::resource(ResourceContents::BlobResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some(mime_type.to_string()),
+            blob: data,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::{CallToolRequestParam, CallToolResult, RawContent};
+    use serde_json::json;
+
+    use super::*;
+    use crate::handle_tools_call;
+
+    /// Returns the first bytes of a PNG file, as base64
+    const IMAGE_WAT: &str = r#"(component
+        (core module $m
+            (memory (export "mem") 1)
+            (data (i32.const 16) "image/png")
+            (data (i32.const 32) "iVBORw0KGgo=")
+            (data (i32.const 64) "\10\00\00\00\09\00\00\00\20\00\00\00\0c\00\00\00")
+            (func (export "f") (result i32) i32.const 64)
+        )
+        (core instance $i (instantiate $m))
+        (type $media' (record (field "mime-type" string) (field "data" string)))
+        (export $media "media" (type $media'))
+        (func (export "image") (result $media)
+            (canon lift (core func $i "f") (memory $i "mem"))
+        )
+    )"#;
+
+    #[test]
+    fn test_media_schemas() {
+        let media = |data: Value| {
+            json!({
+                "type": "object",
+                "properties": {"mime-type": {"type": "string"}, "data": data},
+                "required": ["mime-type", "data"],
+            })
+        };
+        assert!(is_media(Some(&media(json!({"type": "string"})))));
+        assert!(is_media(Some(&media(
+            json!({"type": "array", "items": {"type": "number"}})
+        ))));
+        assert!(!is_media(Some(&media(json!({"type": "number"})))));
+
+        let mut extra_field = media(json!({"type": "string"}));
+        extra_field["properties"]["name"] = json!({"type": "string"});
+        assert!(!is_media(Some(&extra_field)));
+        assert!(!is_media(Some(&json!({"type": "string"}))));
+        assert!(!is_media(None));
+    }
+
+    #[test]
+    fn test_media_content() -> Result<()> {
+        let content = media_content(
+            &json!({"mime_type": "image/png", "data": "iVBORw0KGgo="}),
+            "component://c/image",
+        )?;
+        let image = content.as_image().expect("image content");
+        assert_eq!(image.data, "iVBORw0KGgo=");
+        assert_eq!(image.mime_type, "image/png");
+
+        // Bytes are encoded, and media that isn't an image is a blob
+        let content = media_content(
+            &json!({"mime-type": "application/pdf", "data": [37, 80, 68, 70]}),
+            "component://c/document",
+        )?;
+        match &content.raw {
+            RawContent::Resource(resource) => assert_eq!(
+                resource.resource,
+                ResourceContents::BlobResourceContents {
+                    uri: "component://c/document".to_string(),
+                    mime_type: Some("application/pdf".to_string()),
+                    blob: BASE64.encode(b"%PDF"),
+                }
+            ),
+            other => panic!("expected a blob, got {other:?}"),
+        }
+
+        assert!(media_content(
+            &json!({"mime-type": "image/png", "data": "not base64!"}),
+            ""
+        )
+        .is_err());
+        assert!(media_content(&json!({"mime-type": "image/png", "data": [256]}), "").is_err());
+        assert!(media_content(&json!({"data": "iVBORw0KGgo="}), "").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_component_returns_image() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(tempdir.path()).await?;
+        let component_path = tempdir.path().join("image.wasm");
+        std::fs::write(&component_path, IMAGE_WAT)?;
+        lifecycle_manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        let request = CallToolRequestParam {
+            name: "image".into(),
+            arguments: None,
+        };
+        let result = handle_tools_call(request, &lifecycle_manager, &[], None).await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+        assert_eq!(result.is_error, None);
+        let contents = result.content.expect("content");
+        assert_eq!(contents.len(), 1);
+        let image = contents[0].as_image().expect("image content");
+        assert_eq!(image.data, "iVBORw0KGgo=");
+        assert_eq!(image.mime_type, "image/png");
+
+        Ok(())
+    }
+}
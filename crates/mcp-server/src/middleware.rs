@@ -0,0 +1,363 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Hooks that run before and after every tool call
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use rmcp::model::CallToolResult;
+use serde_json::{json, Value};
+
+/// Intercepts tool calls, e.g. to audit, rate limit or reject them.
+///
+/// Every middleware passed to [`crate::handle_tools_call`] is called in order before the tool
+/// runs, and again in order after it ran.
+pub trait ToolCallMiddleware: Send + Sync {
+    /// Called before `tool` runs with `args`. Returning an error fails the call with that error
+    /// without running the tool or the `before_call` of the following middleware.
+    fn before_call<'a>(&'a self, tool: &'a str, args: &'a Value) -> BoxFuture<'a, Result<()>>;
+
+    /// Called with the `result` of `tool`, including failed calls and calls rejected by a
+    /// `before_call`. Returning an error replaces the result with that error, but the tool has
+    /// already run.
+    fn after_call<'a>(
+        &'a self,
+        tool: &'a str,
+        result: &'a CallToolResult,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Calls the [`ToolCallMiddleware::before_call`] of every middleware in order, stopping at the
+/// first error
+pub(crate) async fn before_call(
+    middleware: &[Box<dyn ToolCallMiddleware + Send + Sync>],
+    tool: &str,
+    args: &Value,
+) -> Result<()> {
+    for middleware in middleware {
+        middleware.before_call(tool, args).await?;
+    }
+    Ok(())
+}
+
+/// Calls the [`ToolCallMiddleware::after_call`] of every middleware in order, stopping at the
+/// first error
+pub(crate) async fn after_call(
+    middleware: &[Box<dyn ToolCallMiddleware + Send + Sync>],
+    tool: &str,
+    result: &CallToolResult,
+) -> Result<()> {
+    for middleware in middleware {
+        middleware.after_call(tool, result).await?;
+    }
+    Ok(())
+}
+
+/// The size at which the audit log is rotated, unless set with
+/// [`AuditLogMiddleware::with_rotation`]
+const DEFAULT_MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+/// The number of rotated audit logs kept, unless set with [`AuditLogMiddleware::with_rotation`]
+const DEFAULT_MAX_ROTATED_LOGS: usize = 5;
+
+/// Appends a line of JSON to a log file for every tool call and result, with the time it
+/// happened.
+///
+/// When the log would grow past its maximum size, it is renamed to `<path>.1`, moving older logs
+/// to `<path>.2` and so on, and the oldest log is removed.
+pub struct AuditLogMiddleware {
+    path: PathBuf,
+    max_bytes: u64,
+    max_rotated: usize,
+    file: Mutex<AuditLog>,
+}
+
+struct AuditLog {
+    file: File,
+    len: u64,
+}
+
+impl AuditLogMiddleware {
+    /// Logs to the file at `path`, appending to it if it exists
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = AuditLog::open(&path)?;
+        Ok(Self {
+            path,
+            max_bytes: DEFAULT_MAX_LOG_BYTES,
+            max_rotated: DEFAULT_MAX_ROTATED_LOGS,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Rotates the log when it would grow past `max_bytes`, keeping `max_rotated` older logs
+    pub fn with_rotation(mut self, max_bytes: u64, max_rotated: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.max_rotated = max_rotated;
+        self
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&self) -> Result<()> {
+        if self.max_rotated == 0 {
+            std::fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+        for index in (1..self.max_rotated).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+        Ok(())
+    }
+
+    fn log(&self, entry: Value) -> Result<()> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut log = self.file.lock().unwrap();
+        if log.len > 0 && log.len + line.len() as u64 > self.max_bytes {
+            self.rotate()
+                .with_context(|| format!("Failed to rotate audit log {}", self.path.display()))?;
+            *log = AuditLog::open(&self.path)?;
+        }
+        log.file
+            .write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write audit log {}", self.path.display()))?;
+        log.len += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl AuditLog {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+        let len = file.metadata()?.len();
+        Ok(Self { file, len })
+    }
+}
+
+impl ToolCallMiddleware for AuditLogMiddleware {
+    fn before_call<'a>(&'a self, tool: &'a str, args: &'a Value) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.log(json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "event": "call",
+                "tool": tool,
+                "arguments": args,
+            }))
+        })
+    }
+
+    fn after_call<'a>(
+        &'a self,
+        tool: &'a str,
+        result: &'a CallToolResult,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.log(json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "event": "result",
+                "tool": tool,
+                "is_error": result.is_error.unwrap_or(false),
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rmcp::model::CallToolRequestParam;
+
+    use wassette::test_fixtures::ANSWER_WAT;
+
+    use super::*;
+    use crate::handle_tools_call;
+
+    /// Records the calls it sees, and fails the calls of `reject`
+    struct Recorder {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+        reject: Option<&'static str>,
+    }
+
+    impl ToolCallMiddleware for Recorder {
+        fn before_call<'a>(&'a self, tool: &'a str, _args: &'a Value) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                self.calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} before {tool}", self.name));
+                if self.reject == Some(tool) {
+                    anyhow::bail!("{tool} is not allowed");
+                }
+                Ok(())
+            })
+        }
+
+        fn after_call<'a>(
+            &'a self,
+            tool: &'a str,
+            result: &'a CallToolResult,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                self.calls.lock().unwrap().push(format!(
+                    "{} after {tool} error={}",
+                    self.name,
+                    result.is_error.unwrap_or(false)
+                ));
+                Ok(())
+            })
+        }
+    }
+
+    fn recorders(
+        calls: &Arc<Mutex<Vec<String>>>,
+        reject: Option<&'static str>,
+    ) -> Vec<Box<dyn ToolCallMiddleware + Send + Sync>> {
+        vec![
+            Box::new(Recorder {
+                name: "first",
+                calls: calls.clone(),
+                reject: None,
+            }),
+            Box::new(Recorder {
+                name: "second",
+                calls: calls.clone(),
+                reject,
+            }),
+            Box::new(Recorder {
+                name: "third",
+                calls: calls.clone(),
+                reject: None,
+            }),
+        ]
+    }
+
+    fn request(name: &'static str) -> CallToolRequestParam {
+        CallToolRequestParam {
+            name: name.into(),
+            arguments: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_called_in_order() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(&tempdir).await?;
+        let calls = Arc::default();
+
+        let result = handle_tools_call(
+            request("list-components"),
+            &lifecycle_manager,
+            &recorders(&calls, None),
+            None,
+        )
+        .await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+        assert_eq!(result.is_error, None);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "first before list-components",
+                "second before list-components",
+                "third before list-components",
+                "first after list-components error=false",
+                "second after list-components error=false",
+                "third after list-components error=false",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_before_call_error_prevents_execution() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(tempdir.path()).await?;
+        let component_path = tempdir.path().join("answer.wasm");
+        std::fs::write(&component_path, ANSWER_WAT)?;
+        let mut args = serde_json::Map::new();
+        args.insert(
+            "path".to_string(),
+            json!(format!("file://{}", component_path.display())),
+        );
+        let calls = Arc::default();
+
+        let result = handle_tools_call(
+            CallToolRequestParam {
+                name: "load-component".into(),
+                arguments: Some(args),
+            },
+            &lifecycle_manager,
+            &recorders(&calls, Some("load-component")),
+            None,
+        )
+        .await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+        assert_eq!(result.is_error, Some(true));
+        assert!(format!("{:?}", result.content).contains("load-component is not allowed"));
+
+        // The component wasn't loaded, and the later middleware never saw the call
+        assert!(lifecycle_manager.list_components().await.is_empty());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "first before load-component",
+                "second before load-component",
+                "first after load-component error=true",
+                "second after load-component error=true",
+                "third after load-component error=true",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_rotates() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("audit.log");
+        let audit = AuditLogMiddleware::new(&path)?.with_rotation(300, 2);
+
+        for i in 0..10 {
+            audit
+                .before_call("get-policy", &json!({"component_id": format!("c{i}")}))
+                .await?;
+        }
+
+        let log = std::fs::read_to_string(&path)?;
+        let entry: Value = serde_json::from_str(log.lines().last().unwrap())?;
+        assert_eq!(entry["event"], "call");
+        assert_eq!(entry["tool"], "get-policy");
+        assert_eq!(entry["arguments"]["component_id"], "c9");
+        assert!(entry["timestamp"]
+            .as_str()
+            .is_some_and(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).is_ok()));
+
+        // Every log stays below the maximum size, and only two rotated logs are kept
+        assert!(log.len() <= 300);
+        assert!(std::fs::metadata(tempdir.path().join("audit.log.1"))?.len() <= 300);
+        assert!(tempdir.path().join("audit.log.2").exists());
+        assert!(!tempdir.path().join("audit.log.3").exists());
+
+        Ok(())
+    }
+}
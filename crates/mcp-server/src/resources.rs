@@ -1,14 +1,325 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
-use anyhow::Result;
-use rmcp::model::{ListResourcesRequest, ListResourcesResult};
+//! MCP resources: the JSON schemas of the loaded components, and the resources components provide
+//!
+//! A component provides resources by exporting these functions, the fields of `resource` other
+//! than `uri` and `name` being optional:
+//!
+//! ```wit
+//! record resource {
+//!     uri: string,
+//!     name: string,
+//!     description: option<string>,
+//!     mime-type: option<string>,
+//! }
+//!
+//! list-resources: func() -> list<resource>;
+//! read-resource: func(uri: string) -> result<string, string>;
+//! ```
+//!
+//! Reading a resource calls `read-resource` of the component that lists it, and returns the text.
 
-pub async fn handle_resources_list(req: serde_json::Value) -> Result<serde_json::Value> {
-    let _parsed_req: ListResourcesRequest = serde_json::from_value(req)?;
+use anyhow::{anyhow, bail, Context, Result};
+use rmcp::model::{
+    ListResourcesResult, RawResource, ReadResourceResult, Resource, ResourceContents,
+};
+use serde_json::{json, Value};
+use tracing::{debug, instrument, warn};
+use wassette::LifecycleManager;
+
+const SCHEMA_MIME_TYPE: &str = "application/json";
+const LIST_RESOURCES: &str = "list-resources";
+const READ_RESOURCE: &str = "read-resource";
+
+/// The URI of the schema resource of a component
+fn schema_uri(component_id: &str) -> String {
+    format!("component://{component_id}/schema")
+}
+
+/// Returns the IDs of the loaded components that export both `list-resources` and
+/// `read-resource`, sorted
+async fn resource_providers(lifecycle_manager: &LifecycleManager) -> Vec<String> {
+    let tools = lifecycle_manager.list_tools_detailed().await;
+    let exporting = |name: &str| -> Vec<String> {
+        tools
+            .iter()
+            .filter(|tool| tool.normalized_name == name)
+            .map(|tool| tool.component_id.clone())
+            .collect()
+    };
+    let readers = exporting(READ_RESOURCE);
+    let mut providers: Vec<String> = exporting(LIST_RESOURCES)
+        .into_iter()
+        .filter(|id| readers.contains(id))
+        .collect();
+    providers.sort();
+    providers
+}
+
+/// Returns the resources a component lists with its `list-resources` export
+async fn component_resources(
+    lifecycle_manager: &LifecycleManager,
+    component_id: &str,
+) -> Result<Vec<RawResource>> {
+    let result = lifecycle_manager
+        .execute_component_call_json(component_id, LIST_RESOURCES, "{}")
+        .await?;
+    let entries = result
+        .as_array()
+        .with_context(|| format!("{LIST_RESOURCES} of {component_id} didn't return a list"))?;
+    entries
+        .iter()
+        .map(|entry| {
+            let field = |name: &str| entry.get(name).and_then(Value::as_str).map(str::to_string);
+            let (Some(uri), Some(name)) = (field("uri"), field("name")) else {
+                bail!(
+                    "{LIST_RESOURCES} of {component_id} returned a resource without a URI or name"
+                );
+            };
+            Ok(RawResource {
+                description: field("description"),
+                mime_type: field("mime-type"),
+                ..RawResource::new(uri, name)
+            })
+        })
+        .collect()
+}
+
+/// Handles a request to list resources, with a schema resource per loaded component and the
+/// resources of the components providing some
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_resources_list(
+    lifecycle_manager: &LifecycleManager,
+) -> Result<serde_json::Value> {
+    debug!("Handling resources list request");
+    let mut component_ids = lifecycle_manager.list_components().await;
+    component_ids.sort();
+
+    let mut resources: Vec<Resource> = component_ids
+        .iter()
+        .map(|id| {
+            Resource::new(
+                RawResource {
+                    mime_type: Some(SCHEMA_MIME_TYPE.to_string()),
+                    ..RawResource::new(schema_uri(id), format!("{id} schema"))
+                },
+                None,
+            )
+        })
+        .collect();
+    for component_id in resource_providers(lifecycle_manager).await {
+        // A component failing to list its resources shouldn't hide the others
+        match component_resources(lifecycle_manager, &component_id).await {
+            Ok(provided) => resources.extend(
+                provided
+                    .into_iter()
+                    .map(|resource| Resource::new(resource, None)),
+            ),
+            Err(e) => warn!(component_id, error = %e, "Failed to list the resources of component"),
+        }
+    }
     let response = ListResourcesResult {
-        resources: vec![],
+        resources,
         next_cursor: None,
     };
     Ok(serde_json::to_value(response)?)
 }
+
+/// Handles a request to read a resource, returning the JSON schema of the component's exports for
+/// a `component://{id}/schema` URI, and calling the component listing the resource otherwise
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_resources_read(
+    uri: &str,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<serde_json::Value> {
+    debug!("Handling resource read request");
+    let Some(component_id) = uri
+        .strip_prefix("component://")
+        .and_then(|rest| rest.strip_suffix("/schema"))
+    else {
+        return read_component_resource(uri, lifecycle_manager).await;
+    };
+    let schema = lifecycle_manager
+        .get_component_schema(component_id)
+        .await
+        .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+
+    let response = ReadResourceResult {
+        contents: vec![ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some(SCHEMA_MIME_TYPE.to_string()),
+            text: serde_json::to_string_pretty(&schema)?,
+        }],
+    };
+    Ok(serde_json::to_value(response)?)
+}
+
+/// Reads a resource by calling `read-resource` of the component that lists it
+async fn read_component_resource(
+    uri: &str,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<serde_json::Value> {
+    for component_id in resource_providers(lifecycle_manager).await {
+        let resources = match component_resources(lifecycle_manager, &component_id).await {
+            Ok(resources) => resources,
+            Err(e) => {
+                warn!(component_id, error = %e, "Failed to list the resources of component");
+                continue;
+            }
+        };
+        let Some(resource) = resources.into_iter().find(|resource| resource.uri == uri) else {
+            continue;
+        };
+
+        debug!(component_id, "Reading resource of component");
+        let result = lifecycle_manager
+            .execute_component_call_json(
+                &component_id,
+                READ_RESOURCE,
+                &json!({ "uri": uri }).to_string(),
+            )
+            .await?;
+        let text = match &result {
+            Value::Object(result) if result.contains_key("err") => {
+                bail!("Failed to read resource {}: {}", uri, result["err"])
+            }
+            Value::Object(result) => result.get("ok").and_then(Value::as_str),
+            result => result.as_str(),
+        }
+        .with_context(|| format!("{READ_RESOURCE} of {component_id} didn't return a string"))?;
+
+        let response = ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: resource.mime_type,
+                text: text.to_string(),
+            }],
+        };
+        return Ok(serde_json::to_value(response)?);
+    }
+    Err(anyhow!("Unknown resource: {}", uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use wassette::test_fixtures::*;
+
+    use super::*;
+
+    /// Lists a `memo://greeting` resource, and reads every resource as "Hello!"
+    const RESOURCES_WAT: &str = r#"(component
+        (core module $m
+            (memory (export "mem") 1)
+            (data (i32.const 16) "memo://greeting")
+            (data (i32.const 32) "greeting")
+            (data (i32.const 48) "text/plain")
+            (data (i32.const 64) "\10\00\00\00\0f\00\00\00\20\00\00\00\08\00\00\00\30\00\00\00\0a\00\00\00")
+            (data (i32.const 96) "Hello!")
+            (data (i32.const 128) "\40\00\00\00\01\00\00\00")
+            (data (i32.const 160) "\00\00\00\00\60\00\00\00\06\00\00\00")
+            (func (export "list") (result i32) i32.const 128)
+            (func (export "read") (param i32 i32) (result i32) i32.const 160)
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 1024)
+        )
+        (core instance $i (instantiate $m))
+        (type $resource' (record
+            (field "uri" string)
+            (field "name" string)
+            (field "mime-type" string)
+        ))
+        (export $resource "resource" (type $resource'))
+        (func (export "list-resources") (result (list $resource))
+            (canon lift (core func $i "list") (memory $i "mem"))
+        )
+        (func (export "read-resource") (param "uri" string) (result (result string (error string)))
+            (canon lift (core func $i "read") (memory $i "mem") (realloc (func $i "realloc")))
+        )
+    )"#;
+
+    #[tokio::test]
+    async fn test_component_schema_resources() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        let list: ListResourcesResult =
+            serde_json::from_value(handle_resources_list(&lifecycle_manager).await?)?;
+        assert!(list.resources.is_empty());
+
+        load_wat_component(&lifecycle_manager, "answer", ANSWER_WAT).await?;
+
+        let list: ListResourcesResult =
+            serde_json::from_value(handle_resources_list(&lifecycle_manager).await?)?;
+        assert_eq!(list.resources.len(), 1);
+        let resource = &list.resources[0];
+        assert_eq!(resource.uri, "component://answer/schema");
+        assert_eq!(resource.name, "answer schema");
+        assert_eq!(resource.mime_type.as_deref(), Some("application/json"));
+
+        let read: ReadResourceResult = serde_json::from_value(
+            handle_resources_read(&resource.uri, &lifecycle_manager).await?,
+        )?;
+        let ResourceContents::TextResourceContents { text, .. } = &read.contents[0] else {
+            panic!("schema should be text: {read:?}");
+        };
+        let schema: serde_json::Value = serde_json::from_str(text)?;
+        assert_eq!(schema["tools"][0]["name"], "answer");
+
+        assert!(
+            handle_resources_read("component://missing/schema", &lifecycle_manager)
+                .await
+                .is_err()
+        );
+        assert!(
+            handle_resources_read("file:///etc/passwd", &lifecycle_manager)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_component_provided_resources() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        for (name, wat) in [("answer", ANSWER_WAT), ("resources", RESOURCES_WAT)] {
+            load_wat_component(&lifecycle_manager, name, wat).await?;
+        }
+
+        // Only the component exporting the resource functions provides resources
+        let list: ListResourcesResult =
+            serde_json::from_value(handle_resources_list(&lifecycle_manager).await?)?;
+        let uris: Vec<&str> = list.resources.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            [
+                "component://answer/schema",
+                "component://resources/schema",
+                "memo://greeting",
+            ]
+        );
+        let greeting = &list.resources[2];
+        assert_eq!(greeting.name, "greeting");
+        assert_eq!(greeting.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(greeting.description, None);
+
+        let read: ReadResourceResult = serde_json::from_value(
+            handle_resources_read("memo://greeting", &lifecycle_manager).await?,
+        )?;
+        assert_eq!(
+            read.contents,
+            [ResourceContents::TextResourceContents {
+                uri: "memo://greeting".to_string(),
+                mime_type: Some("text/plain".to_string()),
+                text: "Hello!".to_string(),
+            }]
+        );
+
+        // Resources the component doesn't list aren't read from it
+        assert!(handle_resources_read("memo://missing", &lifecycle_manager)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Sending the results of tools that return a list of strings as a stream of chunks
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use rmcp::{Peer, RoleServer};
+use serde_json::Value;
+
+/// Receives the chunks of a streamed tool result, see [`crate::handle_tools_call`]
+pub trait ChunkSink: Send + Sync {
+    /// Sends `chunk`, the `index`th of `total` chunks
+    fn send_chunk<'a>(
+        &'a self,
+        chunk: &'a str,
+        index: usize,
+        total: usize,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Sends chunks to an MCP client as `notifications/progress`, with the chunk as the message. The
+/// progress is the number of chunks sent so far.
+pub struct ProgressNotifier {
+    peer: Peer<RoleServer>,
+    progress_token: ProgressToken,
+}
+
+impl ProgressNotifier {
+    /// Sends notifications to `peer` for the request with `progress_token` in its `_meta`
+    pub fn new(peer: Peer<RoleServer>, progress_token: ProgressToken) -> Self {
+        Self {
+            peer,
+            progress_token,
+        }
+    }
+}
+
+impl ChunkSink for ProgressNotifier {
+    fn send_chunk<'a>(
+        &'a self,
+        chunk: &'a str,
+        index: usize,
+        total: usize,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.peer
+                .notify_progress(ProgressNotificationParam {
+                    progress_token: self.progress_token.clone(),
+                    progress: (index + 1) as f64,
+                    total: Some(total as f64),
+                    message: Some(chunk.to_string()),
+                })
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+/// Returns whether a tool with `output_schema` returns a `list<string>`, whose items can be
+/// streamed as chunks
+pub(crate) fn is_chunked(output_schema: Option<&Value>) -> bool {
+    output_schema.is_some_and(|schema| {
+        schema.get("type").and_then(Value::as_str) == Some("array")
+            && schema
+                .get("items")
+                .and_then(|items| items.get("type"))
+                .and_then(Value::as_str)
+                == Some("string")
+    })
+}
+
+/// Sends every string of `chunks` to `sink`, in order
+pub(crate) async fn send_chunks(sink: &dyn ChunkSink, chunks: &[Value]) -> Result<()> {
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk = chunk
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Chunk {index} of the result isn't a string"))?;
+        sink.send_chunk(chunk, index, chunks.len()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use rmcp::model::{CallToolRequestParam, CallToolResult};
+    use serde_json::json;
+
+    use super::*;
+    use crate::handle_tools_call;
+
+    /// The chunks of a `list<string>` result: "one", "two" and "three"
+    const CHUNKS_WAT: &str = r#"(component
+        (core module $m
+            (memory (export "mem") 1)
+            (data (i32.const 16) "onetwothree")
+            (data (i32.const 64) "\10\00\00\00\03\00\00\00\13\00\00\00\03\00\00\00\16\00\00\00\05\00\00\00")
+            (data (i32.const 128) "\40\00\00\00\03\00\00\00")
+            (func (export "f") (result i32) i32.const 128)
+        )
+        (core instance $i (instantiate $m))
+        (func (export "chunks") (result (list string))
+            (canon lift (core func $i "f") (memory $i "mem"))
+        )
+    )"#;
+
+    /// Collects the chunks it receives
+    #[derive(Default)]
+    struct Collector {
+        chunks: Mutex<Vec<(String, usize, usize)>>,
+    }
+
+    impl ChunkSink for Collector {
+        fn send_chunk<'a>(
+            &'a self,
+            chunk: &'a str,
+            index: usize,
+            total: usize,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                self.chunks
+                    .lock()
+                    .unwrap()
+                    .push((chunk.to_string(), index, total));
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn test_only_lists_of_strings_are_chunked() {
+        assert!(is_chunked(Some(
+            &json!({"type": "array", "items": {"type": "string"}})
+        )));
+        assert!(!is_chunked(Some(
+            &json!({"type": "array", "items": {"type": "integer"}})
+        )));
+        assert!(!is_chunked(Some(&json!({"type": "string"}))));
+        assert!(!is_chunked(None));
+    }
+
+    #[tokio::test]
+    async fn test_list_results_streamed_as_chunks() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = wassette::LifecycleManager::new(tempdir.path()).await?;
+        let component_path = tempdir.path().join("chunks.wasm");
+        std::fs::write(&component_path, CHUNKS_WAT)?;
+        lifecycle_manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        let request = || CallToolRequestParam {
+            name: "chunks".into(),
+            arguments: None,
+        };
+
+        let collector = Collector::default();
+        let result =
+            handle_tools_call(request(), &lifecycle_manager, &[], Some(&collector)).await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+        assert_eq!(result.is_error, None);
+        assert_eq!(result.content, Some(vec![]));
+        assert_eq!(
+            *collector.chunks.lock().unwrap(),
+            [
+                ("one".to_string(), 0, 3),
+                ("two".to_string(), 1, 3),
+                ("three".to_string(), 2, 3),
+            ]
+        );
+
+        // Without a sink, the chunks are returned as the result
+        let result = handle_tools_call(request(), &lifecycle_manager, &[], None).await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+        assert_eq!(
+            result.structured_content,
+            Some(json!({"result": ["one", "two", "three"]}))
+        );
+
+        Ok(())
+    }
+}
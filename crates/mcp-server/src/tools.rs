@@ -5,87 +5,137 @@ use std::borrow::Cow;
 use std::sync::Arc;
 
 use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
-use rmcp::{Peer, RoleServer};
 use serde_json::{json, Value};
 use tracing::{debug, error, info, instrument};
-use wassette::LifecycleManager;
+use wassette::{LifecycleManager, RevokeResult};
 
 use crate::components::{
     extract_args_from_request, get_component_tools, handle_component_call, handle_list_components,
     handle_load_component, handle_unload_component,
 };
+use crate::middleware::{after_call, before_call, ToolCallMiddleware};
+use crate::streaming::ChunkSink;
+
+/// The number of tools returned per page of a tools list request
+const TOOLS_PAGE_SIZE: usize = 100;
 
 /// Handles a request to list available tools.
+///
+/// Tools are sorted by name and returned in pages. When more tools remain, the result has a
+/// `next_cursor` to pass as `cursor` to get the next page.
 #[instrument(skip(lifecycle_manager))]
-pub async fn handle_tools_list(lifecycle_manager: &LifecycleManager) -> Result<Value> {
+pub async fn handle_tools_list(
+    lifecycle_manager: &LifecycleManager,
+    cursor: Option<String>,
+) -> Result<Value> {
     debug!("Handling tools list request");
 
     let mut tools = get_component_tools(lifecycle_manager).await?;
     tools.extend(get_builtin_tools());
     debug!(num_tools = %tools.len(), "Retrieved tools");
 
-    let response = rmcp::model::ListToolsResult {
-        tools,
-        next_cursor: None,
-    };
-
+    let response = paginate_tools(tools, cursor.as_deref(), TOOLS_PAGE_SIZE)?;
     Ok(serde_json::to_value(response)?)
 }
 
+/// Returns the page of `tools` after `cursor`. The cursor is the base64-encoded name of the last
+/// tool of the previous page, so pages stay stable when tools are added or removed in between.
+fn paginate_tools(
+    mut tools: Vec<Tool>,
+    cursor: Option<&str>,
+    page_size: usize,
+) -> Result<rmcp::model::ListToolsResult> {
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    if let Some(cursor) = cursor {
+        let after = BASE64
+            .decode(cursor)
+            .ok()
+            .and_then(|name| String::from_utf8(name).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid cursor: {}", cursor))?;
+        tools.retain(|tool| tool.name.as_ref() > after.as_str());
+    }
+
+    let next_cursor =
+        (tools.len() > page_size).then(|| BASE64.encode(tools[page_size - 1].name.as_bytes()));
+    tools.truncate(page_size);
+    Ok(rmcp::model::ListToolsResult { tools, next_cursor })
+}
+
 /// Handles a tool call request.
+///
+/// The `before_call` of every middleware runs in order before the tool, and their `after_call`
+/// in order after it.
+///
+/// With a `chunk_sink`, the strings returned by component tools with a `list<string>` result are
+/// sent to it one by one, and the result itself is empty.
 #[instrument(skip_all, fields(method_name = %req.name))]
 pub async fn handle_tools_call(
     req: CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
+    middleware: &[Box<dyn ToolCallMiddleware + Send + Sync>],
+    chunk_sink: Option<&dyn ChunkSink>,
 ) -> Result<Value> {
     info!("Handling tool call");
 
-    let result = match req.name.as_ref() {
-        "load-component" => handle_load_component(&req, lifecycle_manager, server_peer).await,
-        "unload-component" => handle_unload_component(&req, lifecycle_manager, server_peer).await,
+    let arguments = Value::Object(req.arguments.clone().unwrap_or_default());
+    let result = async {
+        before_call(middleware, &req.name, &arguments).await?;
+        dispatch_tool_call(&req, lifecycle_manager, chunk_sink).await
+    }
+    .await;
+
+    if let Err(ref e) = result {
+        error!(error = ?e, "Tool call failed");
+    }
+
+    let mut result = result.unwrap_or_else(error_result);
+    if let Err(e) = after_call(middleware, &req.name, &result).await {
+        error!(error = ?e, "Tool call middleware failed");
+        result = error_result(e);
+    }
+    Ok(serde_json::to_value(result)?)
+}
+
+fn error_result(e: anyhow::Error) -> CallToolResult {
+    CallToolResult {
+        content: Some(vec![Content::text(format!("Error: {e}"))]),
+        structured_content: None,
+        is_error: Some(true),
+    }
+}
+
+async fn dispatch_tool_call(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    chunk_sink: Option<&dyn ChunkSink>,
+) -> Result<CallToolResult> {
+    match req.name.as_ref() {
+        "load-component" => handle_load_component(req, lifecycle_manager).await,
+        "unload-component" => handle_unload_component(req, lifecycle_manager).await,
         "list-components" => handle_list_components(lifecycle_manager).await,
-        "get-policy" => handle_get_policy(&req, lifecycle_manager).await,
-        "grant-storage-permission" => {
-            handle_grant_storage_permission(&req, lifecycle_manager).await
-        }
-        "grant-network-permission" => {
-            handle_grant_network_permission(&req, lifecycle_manager).await
-        }
+        "get-policy" => handle_get_policy(req, lifecycle_manager).await,
+        "list-permissions" => handle_list_permissions(req, lifecycle_manager).await,
+        "get-metrics" => handle_get_metrics(req, lifecycle_manager).await,
+        "grant-storage-permission" => handle_grant_storage_permission(req, lifecycle_manager).await,
+        "grant-network-permission" => handle_grant_network_permission(req, lifecycle_manager).await,
         "grant-environment-variable-permission" => {
-            handle_grant_environment_variable_permission(&req, lifecycle_manager).await
+            handle_grant_environment_variable_permission(req, lifecycle_manager).await
         }
         "revoke-storage-permission" => {
-            handle_revoke_storage_permission(&req, lifecycle_manager).await
+            handle_revoke_storage_permission(req, lifecycle_manager).await
         }
         "revoke-network-permission" => {
-            handle_revoke_network_permission(&req, lifecycle_manager).await
+            handle_revoke_network_permission(req, lifecycle_manager).await
         }
         "revoke-environment-variable-permission" => {
-            handle_revoke_environment_variable_permission(&req, lifecycle_manager).await
-        }
-        "reset-permission" => handle_reset_permission(&req, lifecycle_manager).await,
-        _ => handle_component_call(&req, lifecycle_manager).await,
-    };
-
-    if let Err(ref e) = result {
-        error!(error = ?e, "Tool call failed");
-    }
-
-    match result {
-        Ok(result) => Ok(serde_json::to_value(result)?),
-        Err(e) => {
-            let error_text = format!("Error: {e}");
-            let contents = vec![Content::text(error_text)];
-
-            let error_result = CallToolResult {
-                content: Some(contents),
-                structured_content: None,
-                is_error: Some(true),
-            };
-            Ok(serde_json::to_value(error_result)?)
+            handle_revoke_environment_variable_permission(req, lifecycle_manager).await
         }
+        "grant-permissions" => handle_grant_permissions(req, lifecycle_manager).await,
+        "reset-permission" => handle_reset_permission(req, lifecycle_manager).await,
+        _ => handle_component_call(req, lifecycle_manager, chunk_sink).await,
     }
 }
 
@@ -165,6 +215,48 @@ fn get_builtin_tools() -> Vec<Tool> {
             output_schema: None,
             annotations: None,
         },
+        Tool {
+            name: Cow::Borrowed("list-permissions"),
+            description: Some(Cow::Borrowed(
+                "Lists the storage, network, environment variable and resource permissions currently in force for a component, including those of the base policy",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "component_id": {
+                            "type": "string",
+                            "description": "ID of the component to list the permissions of"
+                        }
+                    },
+                    "required": ["component_id"]
+                }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
+        Tool {
+            name: Cow::Borrowed("get-metrics"),
+            description: Some(Cow::Borrowed(
+                "Gets the number of calls, failed calls and time spent in calls of a component, or of every called component if no component ID is given",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "component_id": {
+                            "type": "string",
+                            "description": "ID of the component to get metrics for"
+                        }
+                    },
+                    "required": []
+                }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
         Tool {
             name: Cow::Borrowed("grant-storage-permission"),
             description: Some(Cow::Borrowed(
@@ -196,6 +288,10 @@ fn get_builtin_tools() -> Vec<Tool> {
                         },
                         "required": ["uri", "access"],
                         "additionalProperties": false
+                      },
+                      "dry_run": {
+                        "type": "boolean",
+                        "description": "If true, return the change to the component's policy as a diff without granting the permission"
                       }
                     },
                     "required": ["component_id", "details"]
@@ -228,6 +324,10 @@ fn get_builtin_tools() -> Vec<Tool> {
                         },
                         "required": ["host"],
                         "additionalProperties": false
+                      },
+                      "dry_run": {
+                        "type": "boolean",
+                        "description": "If true, return the change to the component's policy as a diff without granting the permission"
                       }
                     },
                     "required": ["component_id", "details"]
@@ -260,6 +360,10 @@ fn get_builtin_tools() -> Vec<Tool> {
                         },
                         "required": ["key"],
                         "additionalProperties": false
+                      },
+                      "dry_run": {
+                        "type": "boolean",
+                        "description": "If true, return the change to the component's policy as a diff without granting the permission"
                       }
                     },
                     "required": ["component_id", "details"]
@@ -269,6 +373,46 @@ fn get_builtin_tools() -> Vec<Tool> {
             output_schema: None,
             annotations: None,
         },
+        Tool {
+            name: Cow::Borrowed("grant-permissions"),
+            description: Some(Cow::Borrowed(
+                "Grants several storage, network and environment variable permissions to a component at once. If any of them is invalid, none is granted."
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                      "component_id": {
+                        "type": "string",
+                        "description": "ID of the component to grant the permissions to"
+                      },
+                      "permissions": {
+                        "type": "array",
+                        "items": {
+                          "type": "object",
+                          "properties": {
+                            "permission_type": {
+                              "type": "string",
+                              "enum": ["storage", "network", "environment"],
+                              "description": "Type of the permission"
+                            },
+                            "details": {
+                              "type": "object",
+                              "description": "Details of the permission, as given to the grant-storage-permission, grant-network-permission or grant-environment-variable-permission tool"
+                            }
+                          },
+                          "required": ["permission_type", "details"],
+                          "additionalProperties": false
+                        }
+                      }
+                    },
+                    "required": ["component_id", "permissions"]
+                  }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
         Tool {
             name: Cow::Borrowed("revoke-storage-permission"),
             description: Some(Cow::Borrowed(
@@ -442,6 +586,134 @@ pub async fn handle_get_policy(
     })
 }
 
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_list_permissions(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let component_id = args
+        .get("component_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+
+    info!("Listing permissions of component {}", component_id);
+
+    let permissions = lifecycle_manager
+        .get_effective_permissions(component_id)
+        .await?;
+    let status_text = serde_json::to_string(&json!({
+        "component_id": component_id,
+        "permissions": permissions
+    }))?;
+
+    let contents = vec![Content::text(status_text)];
+
+    Ok(CallToolResult {
+        content: Some(contents),
+        structured_content: None,
+        is_error: None,
+    })
+}
+
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_get_metrics(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let component_id = req
+        .arguments
+        .as_ref()
+        .and_then(|args| args.get("component_id"))
+        .and_then(|v| v.as_str());
+
+    let metrics = match component_id {
+        Some(component_id) => {
+            info!("Getting metrics for component {}", component_id);
+            if lifecycle_manager
+                .get_component(component_id)
+                .await
+                .is_none()
+            {
+                return Err(anyhow::anyhow!("Component not found: {}", component_id));
+            }
+            let metrics = lifecycle_manager
+                .get_metrics(component_id)
+                .await
+                .unwrap_or_default();
+            vec![(component_id.to_string(), metrics)]
+        }
+        None => {
+            info!("Getting metrics for all components");
+            lifecycle_manager.list_metrics().await
+        }
+    };
+
+    let metrics: Vec<Value> = metrics
+        .into_iter()
+        .map(|(component_id, metrics)| {
+            json!({
+                "component_id": component_id,
+                "call_count": metrics.call_count,
+                "error_count": metrics.error_count,
+                "error_rate": metrics.error_rate(),
+                "total_duration_ms": metrics.total_duration_ms,
+                "last_called_at": metrics.last_called_at.map(|at| {
+                    at.duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs()
+                })
+            })
+        })
+        .collect();
+
+    let contents = vec![Content::text(serde_json::to_string(
+        &json!({ "metrics": metrics }),
+    )?)];
+
+    Ok(CallToolResult {
+        content: Some(contents),
+        structured_content: None,
+        is_error: None,
+    })
+}
+
+/// Answers a grant tool called with `dry_run`, with the change the grant would make to the
+/// component's policy
+async fn handle_grant_dry_run(
+    lifecycle_manager: &LifecycleManager,
+    component_id: &str,
+    permission_type: &str,
+    details: &Value,
+) -> Result<CallToolResult> {
+    let diff = lifecycle_manager
+        .grant_permission_dry_run(component_id, permission_type, details)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to check {} permission for component {}: {}",
+                permission_type,
+                component_id,
+                e
+            )
+        })?;
+
+    let status_text = serde_json::to_string(&json!({
+        "status": "dry run, no permission was granted",
+        "component_id": component_id,
+        "permission_type": permission_type,
+        "details": details,
+        "diff": diff
+    }))?;
+
+    Ok(CallToolResult {
+        content: Some(vec![Content::text(status_text)]),
+        structured_content: None,
+        is_error: None,
+    })
+}
+
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_grant_storage_permission(
     req: &CallToolRequestParam,
@@ -458,6 +730,10 @@ pub async fn handle_grant_storage_permission(
         .get("details")
         .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'details'"))?;
 
+    if args.get("dry_run").and_then(|v| v.as_bool()) == Some(true) {
+        return handle_grant_dry_run(lifecycle_manager, component_id, "storage", details).await;
+    }
+
     info!("Granting storage permission to component {}", component_id);
 
     let result = lifecycle_manager
@@ -508,6 +784,10 @@ pub async fn handle_grant_network_permission(
         .get("details")
         .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'details'"))?;
 
+    if args.get("dry_run").and_then(|v| v.as_bool()) == Some(true) {
+        return handle_grant_dry_run(lifecycle_manager, component_id, "network", details).await;
+    }
+
     info!("Granting network permission to component {}", component_id);
 
     let result = lifecycle_manager
@@ -558,6 +838,10 @@ pub async fn handle_grant_environment_variable_permission(
         .get("details")
         .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'details'"))?;
 
+    if args.get("dry_run").and_then(|v| v.as_bool()) == Some(true) {
+        return handle_grant_dry_run(lifecycle_manager, component_id, "environment", details).await;
+    }
+
     info!(
         "Granting environment variable permission to component {}",
         component_id
@@ -595,6 +879,73 @@ pub async fn handle_grant_environment_variable_permission(
     }
 }
 
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_grant_permissions(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let component_id = args
+        .get("component_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+
+    let permissions = args
+        .get("permissions")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'permissions'"))?;
+
+    let rules = permissions
+        .iter()
+        .map(|permission| {
+            let permission_type = permission
+                .get("permission_type")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'permission_type' of a permission"))?;
+            let details = permission
+                .get("details")
+                .ok_or_else(|| anyhow::anyhow!("Missing 'details' of a permission"))?;
+            lifecycle_manager.parse_permission_rule(permission_type, details)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    info!(
+        "Granting {} permissions to component {}",
+        rules.len(),
+        component_id
+    );
+
+    match lifecycle_manager
+        .grant_permissions(component_id, rules)
+        .await
+    {
+        Ok(()) => {
+            let status_text = serde_json::to_string(&json!({
+                "status": "permissions granted successfully",
+                "component_id": component_id,
+                "permissions": permissions
+            }))?;
+
+            let contents = vec![Content::text(status_text)];
+
+            Ok(CallToolResult {
+                content: Some(contents),
+                structured_content: None,
+                is_error: None,
+            })
+        }
+        Err(e) => {
+            error!("Failed to grant permissions: {}", e);
+            Err(anyhow::anyhow!(
+                "Failed to grant permissions to component {}: {}",
+                component_id,
+                e
+            ))
+        }
+    }
+}
+
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_grant_memory_permission(
     req: &CallToolRequestParam,
@@ -645,6 +996,15 @@ pub async fn handle_grant_memory_permission(
     }
 }
 
+/// The status reported by the revoke tools
+fn revoke_status(result: &RevokeResult) -> &'static str {
+    if result.changed {
+        "permission revoked"
+    } else {
+        "permission was not granted, nothing changed"
+    }
+}
+
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_revoke_storage_permission(
     req: &CallToolRequestParam,
@@ -676,12 +1036,14 @@ pub async fn handle_revoke_storage_permission(
         .await;
 
     match result {
-        Ok(()) => {
+        Ok(result) => {
             let status_text = serde_json::to_string(&json!({
-                "status": "permission revoked successfully",
+                "status": revoke_status(&result),
                 "component_id": component_id,
                 "uri": uri,
-                "message": "All access (read and write) to the specified URI has been revoked"
+                "message": "All access (read and write) to the specified URI has been revoked",
+                "removed": result.removed,
+                "changed": result.changed
             }))?;
 
             let contents = vec![Content::text(status_text)];
@@ -729,12 +1091,14 @@ pub async fn handle_revoke_network_permission(
         .await;
 
     match result {
-        Ok(()) => {
+        Ok(result) => {
             let status_text = serde_json::to_string(&json!({
-                "status": "permission revoked",
+                "status": revoke_status(&result),
                 "component_id": component_id,
                 "permission_type": "network",
-                "details": details
+                "details": details,
+                "removed": result.removed,
+                "changed": result.changed
             }))?;
 
             let contents = vec![Content::text(status_text)];
@@ -782,12 +1146,14 @@ pub async fn handle_revoke_environment_variable_permission(
         .await;
 
     match result {
-        Ok(()) => {
+        Ok(result) => {
             let status_text = serde_json::to_string(&json!({
-                "status": "permission revoked",
+                "status": revoke_status(&result),
                 "component_id": component_id,
                 "permission_type": "environment",
-                "details": details
+                "details": details,
+                "removed": result.removed,
+                "changed": result.changed
             }))?;
 
             let contents = vec![Content::text(status_text)];
@@ -858,11 +1224,13 @@ mod tests {
     #[test]
     fn test_get_builtin_tools() {
         let tools = get_builtin_tools();
-        assert_eq!(tools.len(), 11);
+        assert_eq!(tools.len(), 14);
         assert!(tools.iter().any(|t| t.name == "load-component"));
         assert!(tools.iter().any(|t| t.name == "unload-component"));
         assert!(tools.iter().any(|t| t.name == "list-components"));
         assert!(tools.iter().any(|t| t.name == "get-policy"));
+        assert!(tools.iter().any(|t| t.name == "list-permissions"));
+        assert!(tools.iter().any(|t| t.name == "get-metrics"));
         assert!(tools.iter().any(|t| t.name == "grant-storage-permission"));
         assert!(tools.iter().any(|t| t.name == "grant-network-permission"));
         assert!(tools
@@ -873,9 +1241,99 @@ mod tests {
         assert!(tools
             .iter()
             .any(|t| t.name == "revoke-environment-variable-permission"));
+        assert!(tools.iter().any(|t| t.name == "grant-permissions"));
         assert!(tools.iter().any(|t| t.name == "reset-permission"));
     }
 
+    #[test]
+    fn test_grant_tools_accept_dry_run() {
+        for tool in get_builtin_tools()
+            .iter()
+            .filter(|t| t.name.ends_with("-permission") && t.name.starts_with("grant-"))
+        {
+            assert_eq!(
+                tool.input_schema["properties"]["dry_run"]["type"], "boolean",
+                "{} should accept dry_run",
+                tool.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_tools_list_pages() -> Result<()> {
+        // 30 tools of 3 components, in no particular order
+        let tools: Vec<Tool> = (0..30)
+            .map(|i| Tool {
+                name: Cow::Owned(format!("component-{}-tool-{:02}", i % 3, (i * 7) % 30)),
+                description: None,
+                input_schema: Arc::new(serde_json::Map::new()),
+                output_schema: None,
+                annotations: None,
+            })
+            .collect();
+
+        let mut names = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let page = paginate_tools(tools.clone(), cursor.as_deref(), 8)?;
+            assert!(page.tools.len() <= 8);
+            names.extend(page.tools.into_iter().map(|tool| tool.name.to_string()));
+            pages += 1;
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 4);
+
+        let mut expected: Vec<String> = tools.iter().map(|tool| tool.name.to_string()).collect();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        // A page that ends exactly at the last tool has no next cursor
+        assert!(paginate_tools(tools.clone(), None, 30)?
+            .next_cursor
+            .is_none());
+        assert!(paginate_tools(tools, Some("not base64!"), 8).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tools_list_pages_when_tools_change() -> Result<()> {
+        let tool = |name: &str| Tool {
+            name: Cow::Owned(name.to_string()),
+            description: None,
+            input_schema: Arc::new(serde_json::Map::new()),
+            output_schema: None,
+            annotations: None,
+        };
+        let names = |page: &rmcp::model::ListToolsResult| {
+            page.tools
+                .iter()
+                .map(|tool| tool.name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let tools = ["a", "c", "e", "g"].map(tool).to_vec();
+        let first = paginate_tools(tools, None, 2)?;
+        assert_eq!(names(&first), ["a", "c"]);
+
+        // The last tool of the previous page was removed, and tools were added before and after
+        // it. The next page continues after it without repeating or skipping any remaining tool.
+        let tools = ["a", "b", "d", "e", "g"].map(tool).to_vec();
+        let second = paginate_tools(tools, first.next_cursor.as_deref(), 2)?;
+        assert_eq!(names(&second), ["d", "e"]);
+
+        let tools = ["a", "b", "d", "e", "f", "g"].map(tool).to_vec();
+        let third = paginate_tools(tools, second.next_cursor.as_deref(), 2)?;
+        assert_eq!(names(&third), ["f", "g"]);
+        assert!(third.next_cursor.is_none());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_grant_network_permission_integration() -> Result<()> {
         // Create a test lifecycle manager
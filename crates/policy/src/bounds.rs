@@ -0,0 +1,636 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Checking whether a set of permissions stays within the bounds of another one, e.g. whether the
+//! permissions a component requests are covered by an approved policy
+
+use std::fmt::Display;
+use std::net::IpAddr;
+
+use serde::Serialize;
+
+use crate::{
+    AccessType, NetworkHostPermission, NetworkPermission, Permissions, PolicyDocument, PolicyError,
+    PolicyResult, ResourceLimits,
+};
+
+/// A permission rule that isn't covered by the permissions it was compared to, see
+/// [`Permissions::exceeds`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PermissionViolation {
+    /// The permission category of the rule, as used in the `permissions` section of a policy
+    /// (e.g. `network`)
+    pub permission_type: String,
+    /// The offending rule, e.g. `api.example.com` or `fs://work/** (read, write)`
+    pub rule: String,
+    /// Why the rule isn't covered
+    pub reason: String,
+}
+
+impl PermissionViolation {
+    fn new(permission_type: &str, rule: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            permission_type: permission_type.to_string(),
+            rule: rule.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for PermissionViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} `{}`: {}",
+            self.permission_type, self.rule, self.reason
+        )
+    }
+}
+
+impl Permissions {
+    /// Returns true if everything these permissions allow is also allowed by `superset`
+    pub fn is_subset_of(&self, superset: &Permissions) -> bool {
+        self.exceeds(superset).is_empty()
+    }
+
+    /// Returns the allow rules and resource limits of these permissions that go beyond `limit`.
+    ///
+    /// An allow rule is within bounds if it is covered by an allow rule of `limit` (for example
+    /// `fs://work/agent/**` is covered by `fs://work/**` and `api.example.com` by `*.example.com`)
+    /// and doesn't overlap with any of its deny rules. Resource limits are within bounds if they
+    /// are at most the ones of `limit`. Deny rules and runtime settings are not compared.
+    pub fn exceeds(&self, limit: &Permissions) -> Vec<PermissionViolation> {
+        let mut violations = Vec::new();
+        self.storage_violations(limit, &mut violations);
+        self.network_violations(limit, &mut violations);
+        self.environment_violations(limit, &mut violations);
+        self.ipc_violations(limit, &mut violations);
+        if let Some(resources) = &limit.resources {
+            resource_violations(self.resources.as_ref(), resources, &mut violations);
+        }
+        violations
+    }
+
+    fn storage_violations(&self, limit: &Permissions, violations: &mut Vec<PermissionViolation>) {
+        let requested = self.storage.as_ref().and_then(|s| s.allow.as_deref());
+        let limit_storage = limit.storage.as_ref();
+        let allowed = limit_storage
+            .and_then(|s| s.allow.as_deref())
+            .unwrap_or_default();
+        let denied = limit_storage
+            .and_then(|s| s.deny.as_deref())
+            .unwrap_or_default();
+        let own_denied = self
+            .storage
+            .as_ref()
+            .and_then(|s| s.deny.as_deref())
+            .unwrap_or_default();
+
+        for rule in requested.unwrap_or_default() {
+            let display = format!("{} ({})", rule.uri, access_list(&rule.access));
+            for access in &rule.access {
+                let is_allowed = allowed.iter().any(|allow| {
+                    allow.access.contains(access) && uri_pattern_covers(&allow.uri, &rule.uri)
+                });
+                // A rule that contains a denied path is fine if it denies that path itself
+                let is_denied = denied.iter().any(|deny| {
+                    let denied_here = |uri: &str| {
+                        own_denied.iter().any(|own| {
+                            own.access.contains(access) && uri_pattern_covers(&own.uri, uri)
+                        })
+                    };
+                    deny.access.contains(access)
+                        && (uri_pattern_covers(&deny.uri, &rule.uri)
+                            || (uri_pattern_covers(&rule.uri, &deny.uri)
+                                && !denied_here(&deny.uri)))
+                });
+                if !is_allowed {
+                    violations.push(PermissionViolation::new(
+                        "storage",
+                        &display,
+                        format!(
+                            "{} access is not allowed",
+                            access_list(std::slice::from_ref(access))
+                        ),
+                    ));
+                } else if is_denied {
+                    violations.push(PermissionViolation::new(
+                        "storage",
+                        &display,
+                        format!(
+                            "{} access is denied",
+                            access_list(std::slice::from_ref(access))
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn network_violations(&self, limit: &Permissions, violations: &mut Vec<PermissionViolation>) {
+        let requested = self.network.as_ref().and_then(|n| n.allow.as_deref());
+        let limit_network = limit.network.as_ref();
+        let allowed = limit_network
+            .and_then(|n| n.allow.as_deref())
+            .unwrap_or_default();
+        let denied = limit_network
+            .and_then(|n| n.deny.as_deref())
+            .unwrap_or_default();
+        let own_denied = self
+            .network
+            .as_ref()
+            .and_then(|n| n.deny.as_deref())
+            .unwrap_or_default();
+        let denied_here = |denied_host: &str| {
+            own_denied.iter().any(|own| match own {
+                NetworkPermission::Host(own) => host_pattern_covers(&own.host, denied_host),
+                NetworkPermission::Cidr(_) => false,
+            })
+        };
+
+        for rule in requested.unwrap_or_default() {
+            let reason = match rule {
+                NetworkPermission::Host(host) => {
+                    let is_denied = denied.iter().any(|deny| match deny {
+                        NetworkPermission::Host(deny) => {
+                            host_pattern_covers(&deny.host, &host.host)
+                                || (host_pattern_covers(&host.host, &deny.host)
+                                    && !denied_here(&deny.host))
+                        }
+                        NetworkPermission::Cidr(_) => false,
+                    });
+                    let allowed_hosts: Vec<&NetworkHostPermission> = allowed
+                        .iter()
+                        .filter_map(|allow| match allow {
+                            NetworkPermission::Host(allow) => Some(allow),
+                            NetworkPermission::Cidr(_) => None,
+                        })
+                        .filter(|allow| host_pattern_covers(&allow.host, &host.host))
+                        .collect();
+                    if is_denied {
+                        Some("host is denied")
+                    } else if allowed_hosts.is_empty() {
+                        Some("host is not allowed")
+                    } else if !allowed_hosts.iter().any(|allow| ports_covered(allow, host)) {
+                        Some("host is not allowed on the requested ports")
+                    } else {
+                        None
+                    }
+                }
+                NetworkPermission::Cidr(cidr) => {
+                    let is_denied = denied.iter().any(|deny| match deny {
+                        NetworkPermission::Cidr(deny) => {
+                            cidr_covers(&deny.cidr, &cidr.cidr)
+                                || cidr_covers(&cidr.cidr, &deny.cidr)
+                        }
+                        NetworkPermission::Host(_) => false,
+                    });
+                    let is_allowed = allowed.iter().any(|allow| match allow {
+                        NetworkPermission::Cidr(allow) => cidr_covers(&allow.cidr, &cidr.cidr),
+                        NetworkPermission::Host(_) => false,
+                    });
+                    if is_denied {
+                        Some("network range is denied")
+                    } else if !is_allowed {
+                        Some("network range is not allowed")
+                    } else {
+                        None
+                    }
+                }
+            };
+            if let Some(reason) = reason {
+                violations.push(PermissionViolation::new(
+                    "network",
+                    network_rule(rule),
+                    reason,
+                ));
+            }
+        }
+    }
+
+    fn environment_violations(
+        &self,
+        limit: &Permissions,
+        violations: &mut Vec<PermissionViolation>,
+    ) {
+        let requested = self.environment.as_ref().and_then(|e| e.allow.as_deref());
+        let limit_env = limit.environment.as_ref();
+        let allowed = limit_env
+            .and_then(|e| e.allow.as_deref())
+            .unwrap_or_default();
+        let denied = limit_env
+            .and_then(|e| e.deny.as_deref())
+            .unwrap_or_default();
+
+        for rule in requested.unwrap_or_default() {
+            let reason = if denied.iter().any(|deny| deny.matches(&rule.key)) {
+                "variable is denied"
+            } else if !allowed.iter().any(|allow| allow.matches(&rule.key)) {
+                "variable is not allowed"
+            } else {
+                continue;
+            };
+            violations.push(PermissionViolation::new("environment", &rule.key, reason));
+        }
+    }
+
+    fn ipc_violations(&self, limit: &Permissions, violations: &mut Vec<PermissionViolation>) {
+        let requested = self.ipc.as_ref().and_then(|i| i.allow.as_deref());
+        let allowed = limit
+            .ipc
+            .as_ref()
+            .and_then(|i| i.allow.as_deref())
+            .unwrap_or_default();
+
+        for rule in requested.unwrap_or_default() {
+            if !allowed.iter().any(|allow| allow.uri == rule.uri) {
+                violations.push(PermissionViolation::new(
+                    "ipc",
+                    &rule.uri,
+                    "endpoint is not allowed",
+                ));
+            }
+        }
+    }
+}
+
+impl PolicyDocument {
+    /// Validates the policy like [`PolicyDocument::validate`], and additionally checks that its
+    /// permissions stay within `limit`, e.g. the permissions of an approved policy. The error
+    /// lists every violation, see [`Permissions::exceeds`].
+    pub fn validate_within(&self, limit: &Permissions) -> PolicyResult<()> {
+        self.validate()?;
+        let violations = self.permissions.exceeds(limit);
+        if !violations.is_empty() {
+            let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+            return Err(PolicyError::validation(
+                "permissions",
+                format!(
+                    "Policy exceeds the allowed permissions:\n- {}",
+                    violations.join("\n- ")
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn resource_violations(
+    requested: Option<&ResourceLimits>,
+    limit: &ResourceLimits,
+    violations: &mut Vec<PermissionViolation>,
+) {
+    let memory_bytes = |resources: &ResourceLimits| -> Option<u64> {
+        let limits = resources.limits.as_ref();
+        match limits.and_then(|limits| limits.memory_bytes().ok().flatten()) {
+            Some(bytes) => Some(bytes),
+            // Legacy values are in MB
+            None => resources.memory.map(|mb| mb.saturating_mul(1024 * 1024)),
+        }
+    };
+    let cpu_cores = |resources: &ResourceLimits| -> Option<f64> {
+        let limits = resources.limits.as_ref();
+        limits
+            .and_then(|limits| limits.cpu_cores().ok().flatten())
+            .or(resources.cpu)
+    };
+
+    if let Some(max) = memory_bytes(limit) {
+        match requested.and_then(memory_bytes) {
+            Some(bytes) if bytes <= max => {}
+            Some(bytes) => violations.push(PermissionViolation::new(
+                "resources",
+                format!("memory: {bytes} bytes"),
+                format!("memory is limited to {max} bytes"),
+            )),
+            None => violations.push(PermissionViolation::new(
+                "resources",
+                "memory: unlimited",
+                format!("memory is limited to {max} bytes"),
+            )),
+        }
+    }
+    if let Some(max) = cpu_cores(limit) {
+        match requested.and_then(cpu_cores) {
+            Some(cores) if cores <= max => {}
+            Some(cores) => violations.push(PermissionViolation::new(
+                "resources",
+                format!("cpu: {cores} cores"),
+                format!("cpu is limited to {max} cores"),
+            )),
+            None => violations.push(PermissionViolation::new(
+                "resources",
+                "cpu: unlimited",
+                format!("cpu is limited to {max} cores"),
+            )),
+        }
+    }
+}
+
+fn access_list(access: &[AccessType]) -> String {
+    access
+        .iter()
+        .map(|access| match access {
+            AccessType::Read => "read",
+            AccessType::Write => "write",
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn network_rule(rule: &NetworkPermission) -> String {
+    match rule {
+        NetworkPermission::Host(host) => match &host.ports {
+            Some(ports) => {
+                let ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
+                format!("{} (ports {})", host.host, ports.join(", "))
+            }
+            None => host.host.clone(),
+        },
+        NetworkPermission::Cidr(cidr) => cidr.cidr.clone(),
+    }
+}
+
+/// Returns true if every URI matched by `uri` is also matched by `pattern`. In both, `*` matches a
+/// single path segment (or part of one) and `**` matches any number of segments.
+fn uri_pattern_covers(pattern: &str, uri: &str) -> bool {
+    fn segments_covered(pattern: &[&str], uri: &[&str]) -> bool {
+        match (pattern.split_first(), uri.split_first()) {
+            (None, None) => true,
+            (Some((&"**", rest)), _) => {
+                segments_covered(rest, uri)
+                    || (!uri.is_empty() && segments_covered(pattern, &uri[1..]))
+            }
+            // Any number of segments is only covered by `**`
+            (Some(_), Some((&"**", _))) => false,
+            (Some((p, pattern_rest)), Some((u, uri_rest))) => {
+                segment_covered(p, u) && segments_covered(pattern_rest, uri_rest)
+            }
+            _ => false,
+        }
+    }
+
+    // A segment with a wildcard is covered if the pattern matches it literally, e.g. `log*` is
+    // covered by `lo*` but not by `log`
+    fn segment_covered(pattern: &str, segment: &str) -> bool {
+        match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                segment.len() >= prefix.len() + suffix.len()
+                    && segment.starts_with(prefix)
+                    && segment.ends_with(suffix)
+            }
+            None => pattern == segment,
+        }
+    }
+
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let uri: Vec<&str> = uri.trim_end_matches('/').split('/').collect();
+    segments_covered(&pattern, &uri)
+}
+
+/// Returns true if every host matched by `host` is also matched by `pattern`. Hosts can be
+/// prefixed with a scheme, and be wildcards like `*.example.com` or `*`.
+fn host_pattern_covers(pattern: &str, host: &str) -> bool {
+    let split_scheme = |host: &str| -> (Option<String>, String) {
+        match host.split_once("://") {
+            Some((scheme, host)) => (Some(scheme.to_ascii_lowercase()), host.to_ascii_lowercase()),
+            None => (None, host.to_ascii_lowercase()),
+        }
+    };
+    let (pattern_scheme, pattern) = split_scheme(pattern);
+    let (host_scheme, host) = split_scheme(host);
+    if pattern_scheme.is_some() && pattern_scheme != host_scheme {
+        return false;
+    }
+
+    if pattern == "*" || pattern == host {
+        return true;
+    }
+    match pattern.strip_prefix("*.") {
+        Some(domain) => {
+            let host = host.strip_prefix("*.").unwrap_or(&host);
+            host.ends_with(&format!(".{domain}"))
+        }
+        None => false,
+    }
+}
+
+/// Returns true if the ports of `allow` include all the ports `requested` asks for
+fn ports_covered(allow: &NetworkHostPermission, requested: &NetworkHostPermission) -> bool {
+    match (&allow.ports, &requested.ports) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(allowed), Some(ports)) => ports.iter().all(|range| {
+            allowed
+                .iter()
+                .any(|allowed| allowed.start <= range.start && range.end <= allowed.end)
+        }),
+    }
+}
+
+/// Returns true if the network range `cidr` is part of the network range `range`. Ranges that
+/// can't be parsed only cover themselves.
+fn cidr_covers(range: &str, cidr: &str) -> bool {
+    fn parse(cidr: &str) -> Option<(u128, u32, u32)> {
+        let (address, prefix) = cidr.split_once('/')?;
+        let prefix: u32 = prefix.parse().ok()?;
+        let (bits, width) = match address.parse::<IpAddr>().ok()? {
+            IpAddr::V4(address) => (u128::from(u32::from(address)), 32),
+            IpAddr::V6(address) => (u128::from(address), 128),
+        };
+        (prefix <= width).then_some((bits, prefix, width))
+    }
+
+    match (parse(range), parse(cidr)) {
+        (Some((range_bits, range_prefix, range_width)), Some((bits, prefix, width))) => {
+            let host_bits = range_width - range_prefix;
+            let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0);
+            range_width == width && range_prefix <= prefix && range_bits & mask == bits & mask
+        }
+        _ => range == cidr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PolicyParser;
+
+    fn permissions(yaml: &str) -> Permissions {
+        let yaml = format!("version: \"1.0\"\npermissions:\n{yaml}");
+        PolicyParser::parse_str(yaml).unwrap().permissions
+    }
+
+    const APPROVED: &str = r#"
+  storage:
+    allow:
+      - uri: "fs://work/**"
+        access: ["read", "write"]
+      - uri: "fs://data/**"
+        access: ["read"]
+    deny:
+      - uri: "fs://work/secrets/**"
+        access: ["read", "write"]
+  network:
+    allow:
+      - host: "*.example.com"
+      - host: "api.partner.com"
+        ports: [443]
+      - cidr: "10.0.0.0/8"
+    deny:
+      - host: "admin.example.com"
+  environment:
+    allow:
+      - key: "API_KEY"
+      - key: "HOME"
+  resources:
+    limits:
+      memory: "512Mi"
+"#;
+
+    #[test]
+    fn test_subset_within_bounds() {
+        let approved = permissions(APPROVED);
+        let requested = permissions(
+            r#"
+  storage:
+    allow:
+      - uri: "fs://work/agent/**"
+        access: ["read", "write"]
+      - uri: "fs://data/reports"
+        access: ["read"]
+      - uri: "fs://work/**"
+        access: ["read"]
+    deny:
+      - uri: "fs://work/secrets/**"
+        access: ["read"]
+  network:
+    allow:
+      - host: "api.example.com"
+      - host: "api.partner.com"
+        ports: [443]
+      - cidr: "10.1.0.0/16"
+  environment:
+    allow:
+      - key: "API_KEY"
+  resources:
+    limits:
+      memory: "256Mi"
+"#,
+        );
+
+        assert!(requested.is_subset_of(&approved));
+        assert!(requested.exceeds(&approved).is_empty());
+        assert!(approved.is_subset_of(&approved));
+        // Not setting a memory limit asks for unlimited memory
+        assert!(!Permissions::default().is_subset_of(&approved));
+    }
+
+    #[test]
+    fn test_exceeds_lists_violations() {
+        let approved = permissions(APPROVED);
+        let requested = permissions(
+            r#"
+  storage:
+    allow:
+      - uri: "fs://data/**"
+        access: ["read", "write"]
+      - uri: "fs://work/**"
+        access: ["read"]
+  network:
+    allow:
+      - host: "example.com"
+      - host: "admin.example.com"
+      - host: "api.partner.com"
+      - cidr: "192.168.0.0/16"
+  environment:
+    allow:
+      - key: "AWS_SECRET_ACCESS_KEY"
+  resources:
+    limits:
+      memory: "1Gi"
+"#,
+        );
+
+        let violations = requested.exceeds(&approved);
+        let summary: Vec<(&str, &str)> = violations
+            .iter()
+            .map(|v| (v.permission_type.as_str(), v.rule.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("storage", "fs://data/** (read, write)"),
+                ("storage", "fs://work/** (read)"),
+                ("network", "example.com"),
+                ("network", "admin.example.com"),
+                ("network", "api.partner.com"),
+                ("network", "192.168.0.0/16"),
+                ("environment", "AWS_SECRET_ACCESS_KEY"),
+                ("resources", "memory: 1073741824 bytes"),
+            ]
+        );
+        assert_eq!(violations[0].reason, "write access is not allowed");
+        // `fs://work/**` overlaps with the denied secrets directory
+        assert_eq!(violations[1].reason, "read access is denied");
+        assert_eq!(violations[2].reason, "host is not allowed");
+        assert_eq!(violations[3].reason, "host is denied");
+        assert_eq!(
+            violations[4].to_string(),
+            "network `api.partner.com`: host is not allowed on the requested ports"
+        );
+        assert!(!requested.is_subset_of(&approved));
+    }
+
+    #[test]
+    fn test_validate_within() {
+        let approved = permissions(APPROVED);
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  environment:
+    allow:
+      - key: "HOME"
+      - key: "PATH"
+"#,
+        )
+        .unwrap();
+
+        let err = policy.validate_within(&approved).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("environment `PATH`: variable is not allowed"),
+            "{err}"
+        );
+        assert!(policy.validate_within(&policy.permissions).is_ok());
+    }
+
+    #[test]
+    fn test_pattern_coverage() {
+        assert!(uri_pattern_covers("fs://work/**", "fs://work/a/b"));
+        assert!(uri_pattern_covers("fs://work/**", "fs://work/**"));
+        assert!(uri_pattern_covers("fs://work/lo*", "fs://work/log*"));
+        assert!(!uri_pattern_covers("fs://work/*", "fs://work/**"));
+        assert!(!uri_pattern_covers("fs://work/log", "fs://work/log*"));
+
+        assert!(host_pattern_covers("*", "*.example.com"));
+        assert!(host_pattern_covers("*.example.com", "*.api.example.com"));
+        assert!(!host_pattern_covers("*.example.com", "example.com"));
+        assert!(!host_pattern_covers("api.example.com", "*.example.com"));
+        assert!(host_pattern_covers(
+            "api.example.com",
+            "https://API.example.com"
+        ));
+        assert!(!host_pattern_covers(
+            "https://api.example.com",
+            "api.example.com"
+        ));
+
+        assert!(cidr_covers("10.0.0.0/8", "10.20.0.0/16"));
+        assert!(!cidr_covers("10.0.0.0/16", "10.0.0.0/8"));
+        assert!(cidr_covers("0.0.0.0/0", "192.168.1.0/24"));
+        assert!(!cidr_covers("10.0.0.0/8", "::1/128"));
+        assert!(cidr_covers("fd00::/8", "fd12::/16"));
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Building policies in code
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    AccessType, EnvironmentPermission, NetworkCidrPermission, NetworkHostPermission,
+    NetworkPermission, PermissionList, PolicyDocument, PolicyResult, ResourceLimits, Runtime,
+    StoragePermission,
+};
+
+/// A builder for a [`PolicyDocument`], created with [`PolicyDocument::builder`].
+///
+/// Rules are added in the order the methods are called, so a built policy is equal to a parsed
+/// one listing the same rules in the same order.
+///
+/// ```
+/// use policy::{AccessType, PolicyDocument};
+///
+/// let policy = PolicyDocument::builder("1.0")
+///     .description("Fetches weather data")
+///     .allow_network_host("api.openai.com")
+///     .allow_storage("fs:///tmp", [AccessType::Read, AccessType::Write])
+///     .allow_env("PATH")
+///     .build()
+///     .unwrap();
+/// assert_eq!(policy.description.as_deref(), Some("Fetches weather data"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PolicyDocumentBuilder {
+    document: PolicyDocument,
+}
+
+impl PolicyDocument {
+    /// Returns a [`PolicyDocumentBuilder`] for a policy with the given format version and no
+    /// permissions
+    pub fn builder(version: impl Into<String>) -> PolicyDocumentBuilder {
+        PolicyDocumentBuilder {
+            document: PolicyDocument::new(version, None),
+        }
+    }
+}
+
+impl PolicyDocumentBuilder {
+    /// Sets the human-readable description of the policy
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.document.description = Some(description.into());
+        self
+    }
+
+    /// Sets the time after which the policy no longer grants anything
+    pub fn expires_at(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.document.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Allows the given kinds of access to a storage URI
+    pub fn allow_storage(
+        mut self,
+        uri: impl Into<String>,
+        access: impl IntoIterator<Item = AccessType>,
+    ) -> Self {
+        let storage = self.document.permissions.storage.get_or_insert_default();
+        push(&mut storage.allow, storage_permission(uri, access));
+        self
+    }
+
+    /// Denies the given kinds of access to a storage URI
+    pub fn deny_storage(
+        mut self,
+        uri: impl Into<String>,
+        access: impl IntoIterator<Item = AccessType>,
+    ) -> Self {
+        let storage = self.document.permissions.storage.get_or_insert_default();
+        push(&mut storage.deny, storage_permission(uri, access));
+        self
+    }
+
+    /// Allows outgoing requests to a host on any port
+    pub fn allow_network_host(mut self, host: impl Into<String>) -> Self {
+        let network = self.network();
+        push(&mut network.allow, host_permission(host));
+        self
+    }
+
+    /// Denies outgoing requests to a host
+    pub fn deny_network_host(mut self, host: impl Into<String>) -> Self {
+        let network = self.network();
+        push(&mut network.deny, host_permission(host));
+        self
+    }
+
+    /// Allows outgoing requests to a network range
+    pub fn allow_network_cidr(mut self, cidr: impl Into<String>) -> Self {
+        let network = self.network();
+        push(&mut network.allow, cidr_permission(cidr));
+        self
+    }
+
+    /// Denies outgoing requests to a network range
+    pub fn deny_network_cidr(mut self, cidr: impl Into<String>) -> Self {
+        let network = self.network();
+        push(&mut network.deny, cidr_permission(cidr));
+        self
+    }
+
+    /// Exposes an environment variable
+    pub fn allow_env(mut self, key: impl Into<String>) -> Self {
+        let environment = self
+            .document
+            .permissions
+            .environment
+            .get_or_insert_default();
+        push(
+            &mut environment.allow,
+            EnvironmentPermission { key: key.into() },
+        );
+        self
+    }
+
+    /// Never exposes an environment variable, even if it is also allowed
+    pub fn deny_env(mut self, key: impl Into<String>) -> Self {
+        let environment = self
+            .document
+            .permissions
+            .environment
+            .get_or_insert_default();
+        push(
+            &mut environment.deny,
+            EnvironmentPermission { key: key.into() },
+        );
+        self
+    }
+
+    /// Sets the resource limits
+    pub fn resources(mut self, resources: ResourceLimits) -> Self {
+        self.document.permissions.resources = Some(resources);
+        self
+    }
+
+    /// Sets the runtime configuration
+    pub fn runtime(mut self, runtime: Runtime) -> Self {
+        self.document.permissions.runtime = Some(runtime);
+        self
+    }
+
+    /// Validates the policy and returns it
+    pub fn build(self) -> PolicyResult<PolicyDocument> {
+        self.document.validate()?;
+        Ok(self.document)
+    }
+
+    fn network(&mut self) -> &mut PermissionList<NetworkPermission> {
+        self.document.permissions.network.get_or_insert_default()
+    }
+}
+
+fn push<T>(list: &mut Option<Vec<T>>, item: T) {
+    list.get_or_insert_default().push(item);
+}
+
+fn storage_permission(
+    uri: impl Into<String>,
+    access: impl IntoIterator<Item = AccessType>,
+) -> StoragePermission {
+    StoragePermission {
+        uri: uri.into(),
+        access: access.into_iter().collect(),
+    }
+}
+
+fn host_permission(host: impl Into<String>) -> NetworkPermission {
+    NetworkPermission::Host(NetworkHostPermission {
+        host: host.into(),
+        ports: None,
+    })
+}
+
+fn cidr_permission(cidr: impl Into<String>) -> NetworkPermission {
+    NetworkPermission::Cidr(NetworkCidrPermission { cidr: cidr.into() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        CapabilityAction, DockerCapabilities, DockerRuntime, DockerSecurity, PolicyError,
+        PolicyParser,
+    };
+
+    #[test]
+    fn test_build_docker_policy() {
+        let built = PolicyDocument::builder("1.0")
+            .description("Permission policy for docker container")
+            .allow_storage("fs://work/agent/**", [AccessType::Read, AccessType::Write])
+            .allow_storage("fs://work/agent/config.yaml", [AccessType::Read])
+            .allow_network_host("api.openai.com")
+            .allow_network_host("*.internal.myorg.com")
+            .allow_network_cidr("10.0.0.0/8")
+            .allow_env("PATH")
+            .allow_env("HOME")
+            .runtime(Runtime {
+                docker: Some(DockerRuntime {
+                    security: Some(DockerSecurity {
+                        privileged: Some(false),
+                        no_new_privileges: Some(true),
+                        capabilities: Some(DockerCapabilities {
+                            drop: Some(vec![CapabilityAction::All]),
+                            add: Some(vec![CapabilityAction::NetBindService]),
+                        }),
+                    }),
+                }),
+                hyperlight: None,
+                memory_limit_mb: None,
+                execution_timeout_ms: None,
+                cache: None,
+            })
+            .build()
+            .unwrap();
+
+        let parsed = PolicyParser::parse_file("testdata/docker.yaml").unwrap();
+        assert_eq!(built, parsed);
+    }
+
+    #[test]
+    fn test_build_deny_rules() {
+        let policy = PolicyDocument::builder("1.0")
+            .allow_network_host("*.example.com")
+            .deny_network_host("internal.example.com")
+            .deny_network_cidr("192.168.0.0/16")
+            .allow_env("HOME")
+            .deny_env("AWS_SECRET_ACCESS_KEY")
+            .deny_storage("fs://secrets/**", [AccessType::Read])
+            .build()
+            .unwrap();
+
+        let network = policy.permissions.network.as_ref().unwrap();
+        assert_eq!(network.allow.as_ref().unwrap().len(), 1);
+        assert_eq!(network.deny.as_ref().unwrap().len(), 2);
+        let environment = policy.permissions.environment.as_ref().unwrap();
+        assert_eq!(
+            environment.deny.as_ref().unwrap(),
+            &vec![EnvironmentPermission {
+                key: "AWS_SECRET_ACCESS_KEY".to_string()
+            }]
+        );
+        let storage = policy.permissions.storage.as_ref().unwrap();
+        assert!(storage.allow.is_none());
+        assert_eq!(storage.deny.as_ref().unwrap()[0].uri, "fs://secrets/**");
+    }
+
+    #[test]
+    fn test_build_validates() {
+        let err = PolicyDocument::builder("2.0").build().unwrap_err();
+        assert!(matches!(err, PolicyError::UnsupportedVersion { .. }));
+
+        let err = PolicyDocument::builder("1.0")
+            .allow_network_cidr("10.0.0.0")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, PolicyError::InvalidCidr { .. }));
+
+        let err = PolicyDocument::builder("1.0")
+            .allow_storage("http://example.com", [AccessType::Read])
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, PolicyError::InvalidUri { .. }));
+    }
+}
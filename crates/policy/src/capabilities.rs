@@ -0,0 +1,197 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Descriptions of the capabilities a policy can grant, for building permission UIs
+
+use schemars::generate::SchemaSettings;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::types::{
+    EnvironmentPermission, NetworkCidrPermission, NetworkHostPermission, StoragePermission,
+};
+
+/// Describes a single kind of entry that can be added to the `allow` list of a permission
+/// category
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapabilityDescriptor {
+    /// The permission category, as used in the `permissions` section of a policy (e.g. `network`)
+    pub category: String,
+    /// Human-readable description of the entry
+    pub description: Option<String>,
+    /// The fields of the entry
+    pub fields: Vec<FieldDescriptor>,
+    /// The JSON schema of the entry, with all subschemas inlined
+    pub schema: Value,
+}
+
+/// Describes a single field of a permission entry
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDescriptor {
+    /// The field name, as used in a policy document
+    pub name: String,
+    /// The JSON type of the field (`string`, `array`, ...)
+    pub field_type: String,
+    /// The JSON type of the elements, if the field is an array
+    pub item_type: Option<String>,
+    /// The only values the field (or its elements, for arrays) can take, if restricted
+    pub allowed_values: Option<Vec<String>>,
+    /// Whether the field must be present
+    pub required: bool,
+    /// Human-readable description of the field
+    pub description: Option<String>,
+    /// Minimum length of the field, in characters for strings or elements for arrays
+    pub min_length: Option<u64>,
+    /// Additional validation rules that can't be expressed by the other properties
+    pub validation: Option<String>,
+}
+
+/// Returns a descriptor for every kind of permission entry that can be granted to a component.
+///
+/// Categories that accept several kinds of entries have one descriptor per kind. For example
+/// `network` accepts either a host or a CIDR range, so there are two `network` descriptors.
+pub fn capability_descriptors() -> Vec<CapabilityDescriptor> {
+    vec![
+        describe::<StoragePermission>("storage"),
+        describe::<NetworkHostPermission>("network"),
+        describe::<NetworkCidrPermission>("network"),
+        describe::<EnvironmentPermission>("environment"),
+    ]
+}
+
+fn describe<T: JsonSchema>(category: &str) -> CapabilityDescriptor {
+    let generator = SchemaSettings::draft2020_12()
+        .with(|s| s.inline_subschemas = true)
+        .into_generator();
+    let schema = serde_json::to_value(generator.into_root_schema_for::<T>())
+        .expect("JSON schemas are always serializable");
+
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let fields = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, field)| {
+                    let is_required = required.contains(&name.as_str());
+                    describe_field(name, field, is_required)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    CapabilityDescriptor {
+        category: category.to_string(),
+        description: string_property(&schema, "description"),
+        fields,
+        schema,
+    }
+}
+
+fn describe_field(name: &str, field: &Value, required: bool) -> FieldDescriptor {
+    let items = field.get("items");
+    let allowed_values = items.unwrap_or(field).get("enum").and_then(|values| {
+        values.as_array().map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+    });
+
+    FieldDescriptor {
+        name: name.to_string(),
+        field_type: string_property(field, "type").unwrap_or_default(),
+        item_type: items.and_then(|items| string_property(items, "type")),
+        allowed_values,
+        required,
+        description: string_property(field, "description"),
+        min_length: field
+            .get("minLength")
+            .or_else(|| field.get("minItems"))
+            .and_then(Value::as_u64),
+        validation: string_property(field, "x-validation"),
+    }
+}
+
+fn string_property(schema: &Value, key: &str) -> Option<String> {
+    schema.get(key).and_then(Value::as_str).map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(
+        descriptors: &'a [CapabilityDescriptor],
+        category: &str,
+        field: &str,
+    ) -> &'a FieldDescriptor {
+        descriptors
+            .iter()
+            .filter(|d| d.category == category)
+            .flat_map(|d| d.fields.iter())
+            .find(|f| f.name == field)
+            .unwrap_or_else(|| panic!("No field {field} in category {category}"))
+    }
+
+    #[test]
+    fn test_capability_descriptors_cover_grantable_categories() {
+        let descriptors = capability_descriptors();
+        let mut categories: Vec<_> = descriptors.iter().map(|d| d.category.as_str()).collect();
+        categories.dedup();
+        assert_eq!(categories, vec!["storage", "network", "environment"]);
+    }
+
+    #[test]
+    fn test_storage_descriptor() {
+        let descriptors = capability_descriptors();
+
+        let uri = find(&descriptors, "storage", "uri");
+        assert_eq!(uri.field_type, "string");
+        assert!(uri.required);
+        assert_eq!(uri.min_length, Some(1));
+        assert_eq!(
+            uri.description.as_deref(),
+            Some("URI pattern for the resource")
+        );
+        assert!(uri.validation.as_deref().unwrap().contains("**"));
+
+        let access = find(&descriptors, "storage", "access");
+        assert_eq!(access.field_type, "array");
+        assert_eq!(access.item_type.as_deref(), Some("string"));
+        assert_eq!(
+            access.allowed_values,
+            Some(vec!["read".to_string(), "write".to_string()])
+        );
+        assert_eq!(access.min_length, Some(1));
+    }
+
+    #[test]
+    fn test_network_and_environment_descriptors() {
+        let descriptors = capability_descriptors();
+
+        let host = find(&descriptors, "network", "host");
+        assert_eq!(host.field_type, "string");
+        assert!(host.required);
+        assert!(host.validation.is_some());
+
+        let ports = find(&descriptors, "network", "ports");
+        assert_eq!(ports.field_type, "array");
+        assert!(!ports.required);
+
+        let cidr = find(&descriptors, "network", "cidr");
+        assert!(cidr.validation.as_deref().unwrap().contains('/'));
+
+        let key = find(&descriptors, "environment", "key");
+        assert_eq!(key.field_type, "string");
+        assert_eq!(key.allowed_values, None);
+        assert!(key.required);
+    }
+}
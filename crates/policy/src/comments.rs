@@ -0,0 +1,238 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Best-effort preservation of the comments in YAML policies.
+//!
+//! Comments are attached to the path of the line that follows them, e.g. `permissions.network` or
+//! `permissions.network.allow[0]`, so that they can be put back in front of the same line when
+//! the policy is serialized again. Comments after the last line are stored under an empty path.
+//! Only full-line comments in block-style YAML are tracked.
+
+use std::collections::HashMap;
+
+/// Returns the comments of a YAML document, keyed by the path of the line following them
+pub(crate) fn extract(source: &str) -> HashMap<String, String> {
+    let mut comments = HashMap::new();
+    let mut tracker = PathTracker::default();
+    let mut pending: Vec<&str> = Vec::new();
+
+    for line in source.lines() {
+        match tracker.classify(line) {
+            Line::Blank => {}
+            Line::Comment(comment) => pending.push(comment),
+            Line::Content(path) => {
+                if let Some(path) = path.filter(|_| !pending.is_empty()) {
+                    comments.insert(path, pending.join("\n"));
+                }
+                pending.clear();
+            }
+        }
+    }
+    if !pending.is_empty() {
+        comments.insert(String::new(), pending.join("\n"));
+    }
+    comments
+}
+
+/// Puts comments returned by [`extract`] back into a YAML document. Comments whose path doesn't
+/// exist in the document anymore are dropped.
+pub(crate) fn insert(yaml: &str, comments: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(yaml.len());
+    let mut tracker = PathTracker::default();
+
+    for line in yaml.lines() {
+        if let Line::Content(Some(path)) = tracker.classify(line) {
+            if let Some(comment) = comments.get(&path) {
+                let indent = &line[..line.len() - line.trim_start().len()];
+                for comment_line in comment.lines() {
+                    out.push_str(indent);
+                    out.push_str(comment_line);
+                    out.push('\n');
+                }
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    if let Some(comment) = comments.get("") {
+        out.push_str(comment);
+        out.push('\n');
+    }
+    out
+}
+
+enum Line<'a> {
+    Blank,
+    Comment(&'a str),
+    /// A line of content, with its path if it starts a mapping entry or sequence item
+    Content(Option<String>),
+}
+
+/// Follows the nesting of the mapping keys and sequence items of a block-style YAML document
+#[derive(Default)]
+struct PathTracker {
+    /// The indentation and path segment of every open mapping entry and sequence item
+    stack: Vec<(usize, String)>,
+    /// The number of items seen so far in every sequence, keyed by the path of the sequence
+    item_counts: HashMap<String, usize>,
+    /// The indentation of the key whose value is a block scalar (`|` or `>`) being read
+    block_scalar: Option<usize>,
+}
+
+impl PathTracker {
+    fn classify<'a>(&mut self, line: &'a str) -> Line<'a> {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if let Some(key_indent) = self.block_scalar {
+            if trimmed.is_empty() || indent > key_indent {
+                return Line::Content(None);
+            }
+            self.block_scalar = None;
+        }
+        if trimmed.is_empty() {
+            return Line::Blank;
+        }
+        if trimmed.starts_with('#') {
+            return Line::Comment(trimmed);
+        }
+
+        if let Some(rest) = trimmed
+            .strip_prefix("- ")
+            .or((trimmed == "-").then_some(""))
+        {
+            // Sequences can be indented at the same level as the key they belong to
+            while self.stack.last().is_some_and(|(i, segment)| {
+                *i > indent || (*i == indent && segment.starts_with('['))
+            }) {
+                self.stack.pop();
+            }
+            let count = self.item_counts.entry(self.path()).or_default();
+            let segment = format!("[{count}]");
+            *count += 1;
+            self.stack.push((indent, segment));
+            let path = self.path();
+            if let Some((key, value)) = mapping_entry(rest) {
+                self.stack.push((indent + 2, key));
+                self.check_block_scalar(indent + 2, value);
+            }
+            return Line::Content(Some(path));
+        }
+
+        while self.stack.last().is_some_and(|(i, _)| *i >= indent) {
+            self.stack.pop();
+        }
+        match mapping_entry(trimmed) {
+            Some((key, value)) => {
+                self.stack.push((indent, key));
+                self.check_block_scalar(indent, value);
+                Line::Content(Some(self.path()))
+            }
+            None => Line::Content(None),
+        }
+    }
+
+    fn check_block_scalar(&mut self, indent: usize, value: &str) {
+        if value.starts_with('|') || value.starts_with('>') {
+            self.block_scalar = Some(indent);
+        }
+    }
+
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for (_, segment) in &self.stack {
+            if !path.is_empty() && !segment.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+}
+
+/// Splits a `key: value` line into the unquoted key and the value
+fn mapping_entry(line: &str) -> Option<(String, &str)> {
+    let (key, rest) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = line[1..].find(quote)? + 1;
+            (&line[1..end], &line[end + 1..])
+        }
+        _ => {
+            let end = line
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| line[i + 1..].is_empty() || line[i + 1..].starts_with(' '))?;
+            (&line[..end], &line[end..])
+        }
+    };
+    let value = rest.strip_prefix(':')?;
+    if !(value.is_empty() || value.starts_with(' ')) {
+        return None;
+    }
+    Some((key.to_string(), value.trim_start()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"# Owned by the platform team
+version: "1.0"
+description: |
+  Multi-line description
+  # not a comment
+permissions:
+  network:
+    allow:
+    # Production only
+    - host: "api.example.com"
+      # Only HTTPS
+      ports: [443]
+    - host: "cdn.example.com"
+  environment:
+    allow:
+      - key: "PATH"
+# ipc:
+#   allow: []
+"#;
+
+    #[test]
+    fn test_extract_comments() {
+        let comments = extract(SOURCE);
+        assert_eq!(comments.len(), 4);
+        assert_eq!(comments["version"], "# Owned by the platform team");
+        assert_eq!(
+            comments["permissions.network.allow[0]"],
+            "# Production only"
+        );
+        assert_eq!(
+            comments["permissions.network.allow[0].ports"],
+            "# Only HTTPS"
+        );
+        assert_eq!(comments[""], "# ipc:\n#   allow: []");
+    }
+
+    #[test]
+    fn test_insert_comments() {
+        let comments = extract(SOURCE);
+        // Laid out like the output of serde_yaml
+        let yaml = "version: '1.0'\ndescription: |\n  Multi-line description\n  # not a comment\npermissions:\n  network:\n    allow:\n    - host: api.example.com\n      ports:\n      - 443\n    - host: cdn.example.com\n  environment:\n    allow:\n    - key: PATH\n";
+        let expected = "# Owned by the platform team\nversion: '1.0'\ndescription: |\n  Multi-line description\n  # not a comment\npermissions:\n  network:\n    allow:\n    # Production only\n    - host: api.example.com\n      # Only HTTPS\n      ports:\n      - 443\n    - host: cdn.example.com\n  environment:\n    allow:\n    - key: PATH\n# ipc:\n#   allow: []\n";
+        assert_eq!(insert(yaml, &comments), expected);
+    }
+
+    #[test]
+    fn test_mapping_entry() {
+        assert_eq!(
+            mapping_entry("host: \"a:b\""),
+            Some(("host".to_string(), "\"a:b\""))
+        );
+        assert_eq!(mapping_entry("allow:"), Some(("allow".to_string(), "")));
+        assert_eq!(
+            mapping_entry("'quoted key': 1"),
+            Some(("quoted key".to_string(), "1"))
+        );
+        assert_eq!(mapping_entry("fs://work/agent"), None);
+        assert_eq!(mapping_entry("plain scalar"), None);
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Errors returned while parsing, validating and combining policies
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+/// An error returned while parsing, validating or combining policies
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    /// The policy uses a format version that isn't supported
+    #[error("Unsupported version: {version}")]
+    UnsupportedVersion { version: String },
+    /// The policy has an expiry time and it has passed
+    #[error("Policy expired at {}", expires_at.to_rfc3339())]
+    Expired { expires_at: DateTime<Utc> },
+    /// A storage URI is malformed
+    #[error("Invalid storage URI '{uri}': {reason}")]
+    InvalidUri { uri: String, reason: String },
+    /// A CIDR range is malformed
+    #[error("Invalid CIDR '{cidr}': {reason}")]
+    InvalidCidr { cidr: String, reason: String },
+    /// An access type other than `read` or `write`
+    #[error("Invalid access type '{value}': expected read or write")]
+    InvalidAccessType { value: String },
+    /// A part of the policy has an invalid value. `field` names the part of the policy, e.g.
+    /// `permissions.network`
+    #[error("Validation failed for {field}: {reason}")]
+    ValidationFailed { field: String, reason: String },
+    /// The policy couldn't be parsed or serialized
+    #[error("Failed to parse policy: {0}")]
+    ParseError(String),
+    /// A policy file couldn't be read or written
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Validating the policy found several problems
+    #[error("{} problems found in the policy:{}", .0.len(), list_errors(.0))]
+    Multiple(Vec<PolicyError>),
+}
+
+fn list_errors(errors: &[PolicyError]) -> String {
+    errors.iter().map(|e| format!("\n  - {e}")).collect()
+}
+
+impl PolicyError {
+    /// Returns the individual problems, which are several for [`PolicyError::Multiple`]
+    pub fn errors(&self) -> &[PolicyError] {
+        match self {
+            Self::Multiple(errors) => errors,
+            error => std::slice::from_ref(error),
+        }
+    }
+
+    /// Turns the problems found while validating into a result, which is an error if there are
+    /// any
+    pub(crate) fn combine(mut errors: Vec<PolicyError>) -> Result<(), PolicyError> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Self::Multiple(errors)),
+        }
+    }
+
+    pub(crate) fn validation(field: &str, reason: impl Into<String>) -> Self {
+        Self::ValidationFailed {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn invalid_uri(uri: &str, reason: impl Into<String>) -> Self {
+        Self::InvalidUri {
+            uri: uri.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn invalid_cidr(cidr: &str, reason: impl Into<String>) -> Self {
+        Self::InvalidCidr {
+            cidr: cidr.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for PolicyError {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::ParseError(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for PolicyError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::ParseError(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for PolicyError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::ParseError(e.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for PolicyError {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::ParseError(e.to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for PolicyError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Self::ParseError(format!("Not valid UTF-8: {e}"))
+    }
+}
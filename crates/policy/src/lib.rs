@@ -6,17 +6,30 @@
 //! Parser for MCP server policy files. Supports storage, network, environment
 //! and runtime permissions.
 
-use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+pub mod bounds;
+pub mod builder;
+pub mod capabilities;
+mod comments;
+pub mod error;
+pub mod merge;
 pub mod parser;
 pub mod types;
 
-pub use parser::PolicyParser;
+pub use bounds::PermissionViolation;
+pub use builder::PolicyDocumentBuilder;
+pub use capabilities::{capability_descriptors, CapabilityDescriptor, FieldDescriptor};
+pub use error::PolicyError;
+pub use merge::MergeStrategy;
+pub use parser::{PolicyParser, SerializerOptions};
 pub use types::*;
 
 /// Policy document structure
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PolicyDocument {
     /// Policy format version
     pub version: String,
@@ -26,21 +39,59 @@ pub struct PolicyDocument {
 
     /// Permission definitions
     pub permissions: Permissions,
+
+    /// The time after which the policy no longer grants anything, as an RFC 3339 timestamp.
+    /// Policies without an expiry never expire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// The comments of a policy parsed from YAML, keyed by the path of the line following them,
+    /// e.g. `permissions.network.allow[0]`. Comments after the last line are stored under an
+    /// empty path. They are written back by [`PolicyParser::to_yaml_with_options`] when
+    /// [`SerializerOptions::preserve_comments`] is set.
+    #[serde(skip)]
+    pub comments: HashMap<String, String>,
+}
+
+/// Comments are formatting metadata, so they are ignored when comparing policies
+impl PartialEq for PolicyDocument {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.description == other.description
+            && self.permissions == other.permissions
+            && self.expires_at == other.expires_at
+    }
 }
 
 impl PolicyDocument {
     /// Validate the policy document
-    pub fn validate(&self) -> Result<()> {
+    pub fn validate(&self) -> PolicyResult<()> {
+        let mut errors = Vec::new();
         // Only supporting v1.x for now - will add v2 when we know what it looks like
         if !self.version.starts_with("1.") {
-            bail!("Unsupported version: {}", self.version);
+            errors.push(PolicyError::UnsupportedVersion {
+                version: self.version.clone(),
+            });
+        }
+        if let Err(e) = self.permissions.validate() {
+            errors.extend(match e {
+                PolicyError::Multiple(errors) => errors,
+                e => vec![e],
+            });
+        }
+        PolicyError::combine(errors)?;
+        if let Some(expires_at) = self.expires_at.filter(|_| self.is_expired()) {
+            return Err(PolicyError::Expired { expires_at });
         }
-        self.permissions
-            .validate()
-            .context("Permission validation failed")?;
         Ok(())
     }
 
+    /// Returns true if the policy has an expiry time and it has passed
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
     /// Create a new policy document with default permissions
     pub fn new(version: impl Into<String>, description: Option<String>) -> Self {
         Self {
@@ -51,7 +102,7 @@ impl PolicyDocument {
     }
 }
 
-pub type PolicyResult<T> = Result<T>;
+pub type PolicyResult<T> = Result<T, PolicyError>;
 
 #[cfg(test)]
 mod tests {
@@ -63,6 +114,7 @@ mod tests {
             version: "1.0".to_string(),
             description: Some("Test policy".to_string()),
             permissions: Permissions::default(),
+            ..Default::default()
         };
 
         assert!(policy.validate().is_ok());
@@ -86,12 +138,63 @@ mod tests {
             version: "2.0".to_string(),
             description: None,
             permissions: Permissions::default(),
+            ..Default::default()
         };
 
-        let result = policy.validate();
-        assert!(result.is_err());
-        let error_message = result.unwrap_err().to_string();
-        assert!(error_message.contains("Unsupported version: 2.0"));
+        let err = policy.validate().unwrap_err();
+        assert!(
+            matches!(&err, PolicyError::UnsupportedVersion { version } if version == "2.0"),
+            "{err}"
+        );
+        assert!(err.to_string().contains("Unsupported version: 2.0"));
+    }
+
+    #[test]
+    fn test_policy_expiry() {
+        let mut policy = PolicyDocument::new("1.0", None);
+        policy.expires_at = Some(Utc::now() + chrono::Duration::hours(24));
+        assert!(!policy.is_expired());
+        policy.validate().unwrap();
+
+        policy.expires_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert!(policy.is_expired());
+        let err = policy.validate().unwrap_err();
+        assert!(matches!(err, PolicyError::Expired { .. }), "{err}");
+        assert!(err.to_string().contains("Policy expired at"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_expires_at() {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+expires_at: "2999-01-01T00:00:00Z"
+permissions:
+  network:
+    allow:
+    - host: "api.example.com"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.expires_at.unwrap().to_rfc3339(),
+            "2999-01-01T00:00:00+00:00"
+        );
+
+        // Expired policies are rejected when parsed
+        let err = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+expires_at: "2000-01-01T00:00:00Z"
+permissions: {}
+"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Policy expired at"), "{err}");
+
+        // Policies without expiry don't serialize the field
+        let yaml = PolicyParser::to_yaml(&PolicyDocument::new("1.0", None)).unwrap();
+        assert!(!yaml.contains("expires_at"));
     }
 
     #[test]
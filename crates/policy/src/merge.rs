@@ -0,0 +1,672 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Combining a base policy with an overlay
+
+use crate::{
+    EnvironmentPermission, EnvironmentPermissions, IpcPermission, NetworkPermission,
+    PermissionList, Permissions, PolicyDocument, PolicyError, PolicyResult, StoragePermission,
+};
+
+/// How conflicts between the allow and deny rules of two policies are resolved when merging them
+/// with [`PolicyDocument::merge`].
+///
+/// Two rules conflict when they are about the same resource: the same storage URI (and access
+/// type), network host or CIDR, or environment variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Only resources denied by both policies stay denied, and allowing a resource in either
+    /// policy removes the deny rules for it
+    AllowWins,
+    /// Resources denied by either policy are denied, and allow rules for them are removed
+    DenyWins,
+    /// Resources denied by either policy are denied, but when the policies disagree about a
+    /// resource the overlay's rule is kept and the base's is removed
+    OverlayWins,
+}
+
+impl PolicyDocument {
+    /// Combines a base policy with an overlay, e.g. an organization-wide policy with the policy of
+    /// a single component.
+    ///
+    /// The allow lists are combined, and the deny lists are combined according to `strategy`. The
+    /// merged policy has the overlay's version and description, the earliest expiry of the two
+    /// and the overlay's runtime and resource settings if it has any. Fails if the policies have
+    /// different major versions or if the merged policy is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::{MergeStrategy, PolicyDocument, PolicyParser};
+    ///
+    /// let base = PolicyParser::parse_str(r#"
+    /// version: "1.0"
+    /// permissions:
+    ///   network:
+    ///     allow:
+    ///       - host: "api.example.com"
+    /// "#).unwrap();
+    /// let overlay = PolicyParser::parse_str(r#"
+    /// version: "1.0"
+    /// permissions:
+    ///   network:
+    ///     deny:
+    ///       - host: "api.example.com"
+    /// "#).unwrap();
+    ///
+    /// let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::DenyWins).unwrap();
+    /// let network = merged.permissions.network.unwrap();
+    /// assert!(network.allow.is_none());
+    /// assert_eq!(network.deny.unwrap().len(), 1);
+    /// ```
+    pub fn merge(
+        base: &PolicyDocument,
+        overlay: &PolicyDocument,
+        strategy: MergeStrategy,
+    ) -> PolicyResult<PolicyDocument> {
+        let major_version = |version: &str| version.split('.').next().unwrap_or("").to_string();
+        if major_version(&base.version) != major_version(&overlay.version) {
+            return Err(PolicyError::validation(
+                "version",
+                format!(
+                    "Can't merge policies with different major versions: {} and {}",
+                    base.version, overlay.version
+                ),
+            ));
+        }
+
+        let (base_perms, overlay_perms) = (&base.permissions, &overlay.permissions);
+        let environment = match (&base_perms.environment, &overlay_perms.environment) {
+            (None, None) => None,
+            (base_env, overlay_env) => {
+                let base_env = base_env.clone().unwrap_or_default();
+                let overlay_env = overlay_env.clone().unwrap_or_default();
+                let (allow, deny) = merge_rules(
+                    (base_env.allow.as_deref(), base_env.deny.as_deref()),
+                    (overlay_env.allow.as_deref(), overlay_env.deny.as_deref()),
+                    strategy,
+                );
+                Some(EnvironmentPermissions {
+                    allow,
+                    deny,
+                    allow_all: base_env.allow_all || overlay_env.allow_all,
+                })
+            }
+        };
+
+        let merged = PolicyDocument {
+            version: overlay.version.clone(),
+            description: overlay.description.clone(),
+            expires_at: match (base.expires_at, overlay.expires_at) {
+                (Some(base), Some(overlay)) => Some(base.min(overlay)),
+                (base, overlay) => base.or(overlay),
+            },
+            permissions: Permissions {
+                storage: merge_lists(&base_perms.storage, &overlay_perms.storage, strategy),
+                network: merge_lists(&base_perms.network, &overlay_perms.network, strategy),
+                environment,
+                runtime: overlay_perms
+                    .runtime
+                    .clone()
+                    .or_else(|| base_perms.runtime.clone()),
+                resources: overlay_perms
+                    .resources
+                    .clone()
+                    .or_else(|| base_perms.resources.clone()),
+                ipc: merge_lists(&base_perms.ipc, &overlay_perms.ipc, strategy),
+            },
+            comments: Default::default(),
+        };
+        merged.validate()?;
+        Ok(merged)
+    }
+
+    /// Merges this policy as a base under `other`, e.g. a policy shared by every component under
+    /// the policy of a single component.
+    ///
+    /// Unlike [`PolicyDocument::merge`], neither policy's settings are preferred: the allow and
+    /// deny lists are combined with [`MergeStrategy::DenyWins`], the merged policy has the higher
+    /// version of the two, and it fails if both policies have runtime or resource settings that
+    /// differ. Identical entries are kept once: storage URIs and network hosts listed by both
+    /// policies get the access types and ports of both, and environment variables are matched by
+    /// key.
+    pub fn merge_with(&self, other: &PolicyDocument) -> PolicyResult<PolicyDocument> {
+        let (perms, other_perms) = (&self.permissions, &other.permissions);
+        if let (Some(runtime), Some(other_runtime)) = (&perms.runtime, &other_perms.runtime) {
+            if runtime != other_runtime {
+                return Err(PolicyError::validation(
+                    "permissions.runtime",
+                    "The policies have conflicting runtime settings",
+                ));
+            }
+        }
+        if let (Some(resources), Some(other_resources)) = (&perms.resources, &other_perms.resources)
+        {
+            if resources != other_resources {
+                return Err(PolicyError::validation(
+                    "permissions.resources",
+                    "The policies have conflicting resource settings",
+                ));
+            }
+        }
+
+        let mut merged = PolicyDocument::merge(self, other, MergeStrategy::DenyWins)?;
+        let version_parts = |version: &str| -> Vec<u64> {
+            version
+                .split('.')
+                .map(|part| part.parse().unwrap_or(0))
+                .collect()
+        };
+        if version_parts(&self.version) > version_parts(&other.version) {
+            merged.version = self.version.clone();
+        }
+        if merged.description.is_none() {
+            merged.description = self.description.clone();
+        }
+        Ok(merged)
+    }
+}
+
+/// A permission entry that can be merged with the entries of another policy
+trait MergeRule: Clone {
+    /// Identifies the resource the entry is about. Entries with the same key conflict
+    fn key(&self) -> String;
+
+    /// Adds what `other`, which has the same key, covers to this entry
+    fn extend(&mut self, _other: &Self) {}
+
+    /// Removes what `other`, which has the same key, covers from this entry. Returns false if
+    /// nothing is left
+    fn subtract(&mut self, _other: &Self) -> bool {
+        false
+    }
+
+    /// Keeps only what both this entry and `other`, which has the same key, cover. Returns false
+    /// if nothing is left
+    fn intersect(&mut self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl MergeRule for StoragePermission {
+    fn key(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn extend(&mut self, other: &Self) {
+        for access in &other.access {
+            if !self.access.contains(access) {
+                self.access.push(access.clone());
+            }
+        }
+    }
+
+    fn subtract(&mut self, other: &Self) -> bool {
+        self.access.retain(|access| !other.access.contains(access));
+        !self.access.is_empty()
+    }
+
+    fn intersect(&mut self, other: &Self) -> bool {
+        self.access.retain(|access| other.access.contains(access));
+        !self.access.is_empty()
+    }
+}
+
+impl MergeRule for NetworkPermission {
+    fn key(&self) -> String {
+        match self {
+            NetworkPermission::Host(host) => format!("host:{}", host.host),
+            NetworkPermission::Cidr(cidr) => format!("cidr:{}", cidr.cidr),
+        }
+    }
+
+    fn extend(&mut self, other: &Self) {
+        // A host that is unrestricted in either entry stays unrestricted
+        if let (NetworkPermission::Host(host), NetworkPermission::Host(other)) = (self, other) {
+            match (&mut host.ports, &other.ports) {
+                (Some(ports), Some(other_ports)) => {
+                    for range in other_ports {
+                        if !ports.contains(range) {
+                            ports.push(*range);
+                        }
+                    }
+                }
+                (ports, _) => *ports = None,
+            }
+        }
+    }
+}
+
+impl MergeRule for EnvironmentPermission {
+    fn key(&self) -> String {
+        self.key.clone()
+    }
+}
+
+impl MergeRule for IpcPermission {
+    fn key(&self) -> String {
+        self.uri.clone()
+    }
+}
+
+fn merge_lists<T: MergeRule>(
+    base: &Option<PermissionList<T>>,
+    overlay: &Option<PermissionList<T>>,
+    strategy: MergeStrategy,
+) -> Option<PermissionList<T>> {
+    if base.is_none() && overlay.is_none() {
+        return None;
+    }
+    let (allow, deny) = merge_rules(rules(base), rules(overlay), strategy);
+    Some(PermissionList { allow, deny })
+}
+
+type Rules<'a, T> = (Option<&'a [T]>, Option<&'a [T]>);
+
+/// Returns the allow and deny rules of `list`
+fn rules<T>(list: &Option<PermissionList<T>>) -> Rules<'_, T> {
+    list.as_ref().map_or((None, None), |list| {
+        (list.allow.as_deref(), list.deny.as_deref())
+    })
+}
+
+/// Merges the allow and deny rules of a base and an overlay, returning the merged allow and deny
+/// rules
+fn merge_rules<T: MergeRule>(
+    (base_allow, base_deny): Rules<'_, T>,
+    (overlay_allow, overlay_deny): Rules<'_, T>,
+    strategy: MergeStrategy,
+) -> (Option<Vec<T>>, Option<Vec<T>>) {
+    let (base_allow, base_deny) = (
+        base_allow.unwrap_or_default(),
+        base_deny.unwrap_or_default(),
+    );
+    let (overlay_allow, overlay_deny) = (
+        overlay_allow.unwrap_or_default(),
+        overlay_deny.unwrap_or_default(),
+    );
+
+    let (allow, deny) = match strategy {
+        MergeStrategy::AllowWins => {
+            let allow = union(base_allow, overlay_allow);
+            let deny = subtract(intersection(base_deny, overlay_deny), &allow);
+            (allow, deny)
+        }
+        MergeStrategy::DenyWins => {
+            let deny = union(base_deny, overlay_deny);
+            let allow = subtract(union(base_allow, overlay_allow), &deny);
+            (allow, deny)
+        }
+        MergeStrategy::OverlayWins => {
+            let allow = union(&subtract(base_allow.to_vec(), overlay_deny), overlay_allow);
+            let deny = union(&subtract(base_deny.to_vec(), overlay_allow), overlay_deny);
+            (allow, deny)
+        }
+    };
+    let non_empty = |rules: Vec<T>| (!rules.is_empty()).then_some(rules);
+    (non_empty(allow), non_empty(deny))
+}
+
+/// Returns the entries of both lists, with the entries that have the same key combined
+fn union<T: MergeRule>(first: &[T], second: &[T]) -> Vec<T> {
+    let mut merged: Vec<T> = Vec::new();
+    for rule in first.iter().chain(second) {
+        match merged
+            .iter_mut()
+            .find(|existing| existing.key() == rule.key())
+        {
+            Some(existing) => existing.extend(rule),
+            None => merged.push(rule.clone()),
+        }
+    }
+    merged
+}
+
+/// Returns what is covered by both lists
+fn intersection<T: MergeRule>(first: &[T], second: &[T]) -> Vec<T> {
+    first
+        .iter()
+        .filter_map(|rule| {
+            let mut rule = rule.clone();
+            let others: Vec<&T> = second.iter().filter(|o| o.key() == rule.key()).collect();
+            let covered = !others.is_empty() && others.iter().all(|other| rule.intersect(other));
+            covered.then_some(rule)
+        })
+        .collect()
+}
+
+/// Removes what the entries of `removed` cover from `rules`
+fn subtract<T: MergeRule>(rules: Vec<T>, removed: &[T]) -> Vec<T> {
+    rules
+        .into_iter()
+        .filter_map(|mut rule| {
+            let key = rule.key();
+            let kept = removed
+                .iter()
+                .filter(|other| other.key() == key)
+                .all(|other| rule.subtract(other));
+            kept.then_some(rule)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccessType, PolicyParser};
+
+    fn policy(yaml: &str) -> PolicyDocument {
+        PolicyParser::parse_str(yaml).unwrap()
+    }
+
+    fn storage_rules(rules: &Option<Vec<StoragePermission>>) -> Vec<(String, Vec<AccessType>)> {
+        rules
+            .iter()
+            .flatten()
+            .map(|rule| (rule.uri.clone(), rule.access.clone()))
+            .collect()
+    }
+
+    fn hosts(rules: &Option<Vec<NetworkPermission>>) -> Vec<String> {
+        rules.iter().flatten().map(MergeRule::key).collect()
+    }
+
+    #[test]
+    fn test_merge_overlapping_storage_uris() {
+        let base = policy(
+            r#"
+version: "1.0"
+description: "Org policy"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://workspace/**"
+        access: ["read"]
+      - uri: "fs://cache/**"
+        access: ["read", "write"]
+    deny:
+      - uri: "fs://workspace/secrets/**"
+        access: ["read", "write"]
+"#,
+        );
+        let overlay = policy(
+            r#"
+version: "1.1"
+description: "Component policy"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://workspace/**"
+        access: ["write"]
+      - uri: "fs://workspace/secrets/**"
+        access: ["read"]
+"#,
+        );
+
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::DenyWins).unwrap();
+        assert_eq!(merged.version, "1.1");
+        assert_eq!(merged.description.as_deref(), Some("Component policy"));
+        let storage = merged.permissions.storage.as_ref().unwrap();
+        assert_eq!(
+            storage_rules(&storage.allow),
+            vec![
+                (
+                    "fs://workspace/**".to_string(),
+                    vec![AccessType::Read, AccessType::Write]
+                ),
+                (
+                    "fs://cache/**".to_string(),
+                    vec![AccessType::Read, AccessType::Write]
+                ),
+            ]
+        );
+        assert_eq!(
+            storage_rules(&storage.deny),
+            vec![(
+                "fs://workspace/secrets/**".to_string(),
+                vec![AccessType::Read, AccessType::Write]
+            )]
+        );
+
+        // Allowing read access lifts that part of the deny rule
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::AllowWins).unwrap();
+        let storage = merged.permissions.storage.as_ref().unwrap();
+        assert_eq!(storage.allow.as_ref().unwrap().len(), 3);
+        assert_eq!(storage.deny, None);
+
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::OverlayWins).unwrap();
+        let storage = merged.permissions.storage.as_ref().unwrap();
+        assert_eq!(
+            storage_rules(&storage.deny),
+            vec![(
+                "fs://workspace/secrets/**".to_string(),
+                vec![AccessType::Write]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_merge_conflicting_network_grants() {
+        let base = policy(
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "api.example.com"
+      - host: "cdn.example.com"
+    deny:
+      - host: "tracker.example.com"
+      - host: "uploads.example.com"
+"#,
+        );
+        let overlay = policy(
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "tracker.example.com"
+    deny:
+      - host: "cdn.example.com"
+      - host: "uploads.example.com"
+"#,
+        );
+
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::DenyWins).unwrap();
+        let network = merged.permissions.network.unwrap();
+        assert_eq!(hosts(&network.allow), vec!["host:api.example.com"]);
+        assert_eq!(
+            hosts(&network.deny),
+            vec![
+                "host:tracker.example.com",
+                "host:uploads.example.com",
+                "host:cdn.example.com"
+            ]
+        );
+
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::AllowWins).unwrap();
+        let network = merged.permissions.network.unwrap();
+        assert_eq!(
+            hosts(&network.allow),
+            vec![
+                "host:api.example.com",
+                "host:cdn.example.com",
+                "host:tracker.example.com"
+            ]
+        );
+        assert_eq!(hosts(&network.deny), vec!["host:uploads.example.com"]);
+
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::OverlayWins).unwrap();
+        let network = merged.permissions.network.unwrap();
+        assert_eq!(
+            hosts(&network.allow),
+            vec!["host:api.example.com", "host:tracker.example.com"]
+        );
+        assert_eq!(
+            hosts(&network.deny),
+            vec!["host:uploads.example.com", "host:cdn.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_merge_version_mismatch() {
+        let base = policy("version: \"1.0\"\npermissions: {}\n");
+        let mut overlay = base.clone();
+        overlay.version = "2.0".to_string();
+
+        let err = PolicyDocument::merge(&base, &overlay, MergeStrategy::OverlayWins).unwrap_err();
+        assert!(
+            err.to_string().contains("different major versions"),
+            "{err}"
+        );
+
+        // Minor versions can differ, the overlay's is kept
+        overlay.version = "1.3".to_string();
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::OverlayWins).unwrap();
+        assert_eq!(merged.version, "1.3");
+        assert_eq!(merged.permissions, Permissions::default());
+    }
+
+    #[test]
+    fn test_merge_keeps_earliest_expiry_and_environment() {
+        let base = policy(
+            r#"
+version: "1.0"
+expires_at: "2999-01-01T00:00:00Z"
+permissions:
+  environment:
+    allow:
+      - key: "API_KEY"
+"#,
+        );
+        let overlay = policy(
+            r#"
+version: "1.0"
+expires_at: "2998-01-01T00:00:00Z"
+permissions:
+  environment:
+    deny:
+      - key: "API_KEY"
+"#,
+        );
+
+        let merged = PolicyDocument::merge(&base, &overlay, MergeStrategy::DenyWins).unwrap();
+        assert_eq!(merged.expires_at, overlay.expires_at);
+        let environment = merged.permissions.environment.unwrap();
+        assert_eq!(environment.allow, None);
+        assert_eq!(environment.deny.unwrap()[0].key, "API_KEY");
+    }
+
+    #[test]
+    fn test_merge_with_dedups_entries() {
+        let base = policy(
+            r#"
+version: "1.2"
+description: "Shared policy"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://cache/**"
+        access: ["read"]
+  network:
+    allow:
+      - host: "api.example.com"
+        ports: [443]
+      - host: "cdn.example.com"
+  environment:
+    allow:
+      - key: "LOG_LEVEL"
+      - key: "API_KEY"
+"#,
+        );
+        let component = policy(
+            r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://cache/**"
+        access: ["read", "write"]
+  network:
+    allow:
+      - host: "api.example.com"
+        ports: [443, 8443]
+      - host: "cdn.example.com"
+  environment:
+    allow:
+      - key: "API_KEY"
+    deny:
+      - key: "LOG_LEVEL"
+"#,
+        );
+
+        let merged = base.merge_with(&component).unwrap();
+        assert_eq!(merged.version, "1.2");
+        assert_eq!(merged.description.as_deref(), Some("Shared policy"));
+        let storage = merged.permissions.storage.as_ref().unwrap();
+        assert_eq!(
+            storage_rules(&storage.allow),
+            vec![(
+                "fs://cache/**".to_string(),
+                vec![AccessType::Read, AccessType::Write]
+            )]
+        );
+        let network = merged.permissions.network.as_ref().unwrap();
+        assert_eq!(
+            hosts(&network.allow),
+            vec!["host:api.example.com", "host:cdn.example.com"]
+        );
+        let Some(NetworkPermission::Host(api)) = network.allow.as_ref().unwrap().first() else {
+            panic!("expected a host rule");
+        };
+        assert_eq!(api.ports.as_ref().unwrap().len(), 2);
+
+        // A variable denied by either policy isn't allowed
+        let environment = merged.permissions.environment.as_ref().unwrap();
+        let keys = |rules: &Option<Vec<EnvironmentPermission>>| -> Vec<String> {
+            rules
+                .iter()
+                .flatten()
+                .map(|rule| rule.key.clone())
+                .collect()
+        };
+        assert_eq!(keys(&environment.allow), vec!["API_KEY"]);
+        assert_eq!(keys(&environment.deny), vec!["LOG_LEVEL"]);
+
+        // Merging a policy with itself changes nothing
+        assert_eq!(
+            component.merge_with(&component).unwrap().permissions,
+            component.permissions
+        );
+    }
+
+    #[test]
+    fn test_merge_with_conflicting_runtime() {
+        let base = policy(
+            r#"
+version: "1.0"
+permissions:
+  runtime:
+    memory_limit_mb: 64
+"#,
+        );
+        let mut component = base.clone();
+        assert_eq!(
+            base.merge_with(&component).unwrap().permissions.runtime,
+            base.permissions.runtime
+        );
+
+        // Settings of only one of the policies are kept
+        component.permissions.runtime = None;
+        assert_eq!(
+            component.merge_with(&base).unwrap().permissions.runtime,
+            base.permissions.runtime
+        );
+
+        let mut other = base.clone();
+        other.permissions.runtime.as_mut().unwrap().memory_limit_mb = Some(128);
+        let err = base.merge_with(&other).unwrap_err();
+        assert!(err.to_string().contains("runtime"), "{err}");
+    }
+}
@@ -4,12 +4,52 @@
 use std::fs;
 use std::path::Path;
 
-use anyhow::Context;
+use serde::Serialize;
+use serde_json::{json, Value};
 
-use crate::{PolicyDocument, PolicyResult};
+use crate::{MergeStrategy, NetworkPermission, PolicyDocument, PolicyError, PolicyResult};
 
 pub struct PolicyParser;
 
+/// Options for serializing a policy with [`PolicyParser::to_yaml_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct SerializerOptions {
+    /// Writes the comments of a policy parsed from YAML back in front of the same entries, see
+    /// [`PolicyDocument::comments`]. This is best-effort: comments of entries that were removed
+    /// are dropped.
+    pub preserve_comments: bool,
+}
+
+/// The serialization format of a policy file, detected from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PolicyFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl PolicyFormat {
+    /// Returns the format for the given path. Unrecognized extensions are treated as YAML
+    fn from_path(path: &Path) -> Self {
+        // JSON is checked first: it is also valid YAML, but the JSON parser gives better errors
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Yaml,
+        }
+    }
+}
+
+impl std::fmt::Display for PolicyFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Yaml => write!(f, "YAML"),
+            Self::Toml => write!(f, "TOML"),
+            Self::Json => write!(f, "JSON"),
+        }
+    }
+}
+
 impl PolicyParser {
     /// Parse a policy document from a YAML string
     ///
@@ -32,12 +72,66 @@ impl PolicyParser {
     /// assert_eq!(policy.version, "1.0");
     /// ```
     pub fn parse_str(content: impl AsRef<str>) -> PolicyResult<PolicyDocument> {
-        let document: PolicyDocument = serde_yaml::from_str(content.as_ref())?;
+        let mut document: PolicyDocument = serde_yaml::from_str(content.as_ref())?;
+        document.validate()?;
+        document.comments = crate::comments::extract(content.as_ref());
+        Ok(document)
+    }
+
+    /// Parse a policy document from a TOML string
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::PolicyParser;
+    ///
+    /// let toml_content = r#"
+    /// version = "1.0"
+    /// description = "Test policy"
+    ///
+    /// [[permissions.storage.allow]]
+    /// uri = "fs://work/agent/**"
+    /// access = ["read", "write"]
+    /// "#;
+    ///
+    /// let policy = PolicyParser::parse_toml_str(toml_content).unwrap();
+    /// assert_eq!(policy.version, "1.0");
+    /// ```
+    pub fn parse_toml_str(content: impl AsRef<str>) -> PolicyResult<PolicyDocument> {
+        let document: PolicyDocument = toml::from_str(content.as_ref())?;
+        document.validate()?;
+        Ok(document)
+    }
+
+    /// Parse a policy document from a JSON string
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::PolicyParser;
+    ///
+    /// let json_content = r#"{
+    ///     "version": "1.0",
+    ///     "description": "Test policy",
+    ///     "permissions": {
+    ///         "storage": {
+    ///             "allow": [{ "uri": "fs://work/agent/**", "access": ["read", "write"] }]
+    ///         }
+    ///     }
+    /// }"#;
+    ///
+    /// let policy = PolicyParser::parse_json_str(json_content).unwrap();
+    /// assert_eq!(policy.version, "1.0");
+    /// ```
+    pub fn parse_json_str(content: impl AsRef<str>) -> PolicyResult<PolicyDocument> {
+        let document: PolicyDocument = serde_json::from_str(content.as_ref())?;
         document.validate()?;
         Ok(document)
     }
 
-    /// Parse a policy document from a file path
+    /// Parse a policy document from a file path. Files with a `.json` extension are parsed as
+    /// JSON, files with a `.toml` extension as TOML, and everything else as YAML. Parse errors
+    /// name the format the file was parsed as.
     ///
     /// # Example
     ///
@@ -48,8 +142,47 @@ impl PolicyParser {
     /// println!("Loaded policy: {}", policy.description.unwrap_or_default());
     /// ```
     pub fn parse_file<P: AsRef<Path>>(path: P) -> PolicyResult<PolicyDocument> {
+        let path = path.as_ref();
         let content = fs::read_to_string(path)?;
-        Self::parse_str(&content)
+        let format = PolicyFormat::from_path(path);
+        let result = match format {
+            PolicyFormat::Yaml => Self::parse_str(&content),
+            PolicyFormat::Toml => Self::parse_toml_str(&content),
+            PolicyFormat::Json => Self::parse_json_str(&content),
+        };
+        result.map_err(|e| match e {
+            PolicyError::ParseError(message) => PolicyError::ParseError(format!(
+                "invalid {format} in {}: {message}",
+                path.display()
+            )),
+            e => e,
+        })
+    }
+
+    /// Parse a base policy and an overlay from files and merge them, see [`PolicyDocument::merge`]
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use policy::{MergeStrategy, PolicyParser};
+    ///
+    /// let policy =
+    ///     PolicyParser::merge_files("org.yaml", "component.yaml", MergeStrategy::DenyWins).unwrap();
+    /// ```
+    pub fn merge_files<P: AsRef<Path>, Q: AsRef<Path>>(
+        base: P,
+        overlay: Q,
+        strategy: MergeStrategy,
+    ) -> PolicyResult<PolicyDocument> {
+        let base_path = base.as_ref();
+        let overlay_path = overlay.as_ref();
+        let base = Self::parse_file(base_path).map_err(|e| {
+            PolicyError::ParseError(format!("base policy {}: {e}", base_path.display()))
+        })?;
+        let overlay = Self::parse_file(overlay_path).map_err(|e| {
+            PolicyError::ParseError(format!("overlay policy {}: {e}", overlay_path.display()))
+        })?;
+        PolicyDocument::merge(&base, &overlay, strategy)
     }
 
     /// Parse a policy document from bytes
@@ -63,7 +196,7 @@ impl PolicyParser {
     /// assert_eq!(policy.version, "1.0");
     /// ```
     pub fn parse_bytes(bytes: &[u8]) -> PolicyResult<PolicyDocument> {
-        let content = std::str::from_utf8(bytes).context("Not valid UTF-8")?;
+        let content = std::str::from_utf8(bytes)?;
         Self::parse_str(content)
     }
 
@@ -78,18 +211,192 @@ impl PolicyParser {
     ///     version: "1.0".to_string(),
     ///     description: Some("Test policy".to_string()),
     ///     permissions: Permissions::default(),
+    ///     ..Default::default()
     /// };
     ///
     /// let yaml = PolicyParser::to_yaml(&policy).unwrap();
     /// assert!(yaml.contains("version: '1.0'"));
     /// ```
     pub fn to_yaml(document: &PolicyDocument) -> PolicyResult<String> {
+        Self::to_yaml_with_options(document, &SerializerOptions::default())
+    }
+
+    /// Serialize a policy document to YAML string with the given options
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::{PolicyParser, SerializerOptions};
+    ///
+    /// let policy = PolicyParser::parse_str(
+    ///     "version: '1.0'\npermissions:\n  network:\n    allow:\n    # Production only\n    - host: api.example.com\n",
+    /// )
+    /// .unwrap();
+    ///
+    /// let options = SerializerOptions {
+    ///     preserve_comments: true,
+    /// };
+    /// let yaml = PolicyParser::to_yaml_with_options(&policy, &options).unwrap();
+    /// assert!(yaml.contains("# Production only"));
+    /// ```
+    pub fn to_yaml_with_options(
+        document: &PolicyDocument,
+        options: &SerializerOptions,
+    ) -> PolicyResult<String> {
         document.validate()?;
         let yaml = serde_yaml::to_string(document)?;
+        if options.preserve_comments && !document.comments.is_empty() {
+            return Ok(crate::comments::insert(&yaml, &document.comments));
+        }
         Ok(yaml)
     }
 
-    /// Write a policy document to a file
+    /// Serialize a policy document to TOML string
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::{PolicyParser, PolicyDocument, Permissions};
+    ///
+    /// let policy = PolicyDocument {
+    ///     version: "1.0".to_string(),
+    ///     description: Some("Test policy".to_string()),
+    ///     permissions: Permissions::default(),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let toml = PolicyParser::to_toml(&policy).unwrap();
+    /// assert!(toml.contains("version = \"1.0\""));
+    /// ```
+    pub fn to_toml(document: &PolicyDocument) -> PolicyResult<String> {
+        document.validate()?;
+        let toml = toml::to_string(document)?;
+        Ok(toml)
+    }
+
+    /// Serialize a policy document to a pretty-printed JSON string
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::{PolicyParser, PolicyDocument, Permissions};
+    ///
+    /// let policy = PolicyDocument {
+    ///     version: "1.0".to_string(),
+    ///     description: Some("Test policy".to_string()),
+    ///     permissions: Permissions::default(),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let json = PolicyParser::to_json(&policy).unwrap();
+    /// assert!(json.contains("\"version\": \"1.0\""));
+    /// ```
+    pub fn to_json(document: &PolicyDocument) -> PolicyResult<String> {
+        document.validate()?;
+        let json = serde_json::to_string_pretty(document)?;
+        Ok(json)
+    }
+
+    /// Translate a policy document into an [OPA](https://www.openpolicyagent.org/) Rego module
+    ///
+    /// The module lives in the `wassette.policy` package and defines an `allow` rule per
+    /// permission category, evaluated against an `input` describing a single access:
+    ///
+    /// - `allow_storage`: `{"storage": {"uri": "fs://work/file.txt", "access": "read"}}`
+    /// - `allow_network`: `{"network": {"host": "api.example.com", "scheme": "https", "port": 443}}`
+    ///   or `{"network": {"ip": "10.1.2.3"}}`
+    /// - `allow_environment`: `{"environment": {"key": "HOME"}}`
+    /// - `allow_ipc`: `{"ipc": {"uri": "pipe://myservice"}}`
+    ///
+    /// The rules follow what the runtime enforces: deny rules win over allow rules, `*` and `**`
+    /// in storage URIs match one and any number of path segments, `*.domain` hosts match
+    /// subdomains only and host ports default to allowing every port. `allow` is true if any of
+    /// the category rules is. Resource limits and runtime settings have no Rego equivalent and are
+    /// left out.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::PolicyParser;
+    ///
+    /// let policy = PolicyParser::parse_file("./testdata/docker.yaml").unwrap();
+    /// let rego = PolicyParser::to_rego(&policy).unwrap();
+    /// assert!(rego.contains("package wassette.policy"));
+    /// ```
+    pub fn to_rego(document: &PolicyDocument) -> PolicyResult<String> {
+        document.validate()?;
+        let permissions = &document.permissions;
+
+        let mut rego = String::new();
+        rego.push_str(&format!(
+            "# Generated from a wassette policy (version {})\n",
+            document.version
+        ));
+        if let Some(description) = &document.description {
+            for line in description.lines() {
+                rego.push_str(&format!("# {line}\n"));
+            }
+        }
+        rego.push_str("package wassette.policy\n\nimport rego.v1\n\n");
+
+        let storage = permissions.storage.as_ref();
+        push_rego_rules(
+            &mut rego,
+            "storage_allow",
+            storage.and_then(|s| s.allow.as_deref()),
+        )?;
+        push_rego_rules(
+            &mut rego,
+            "storage_deny",
+            storage.and_then(|s| s.deny.as_deref()),
+        )?;
+
+        let network = permissions.network.as_ref();
+        let network_rules = |list: Option<&[NetworkPermission]>| {
+            let (hosts, cidrs): (Vec<_>, Vec<_>) = list
+                .unwrap_or_default()
+                .iter()
+                .partition(|rule| matches!(rule, NetworkPermission::Host(_)));
+            let hosts: Vec<Value> = hosts.into_iter().map(rego_host_rule).collect();
+            let cidrs: Vec<Value> = cidrs
+                .into_iter()
+                .filter_map(|rule| match rule {
+                    NetworkPermission::Cidr(cidr) => Some(json!({ "cidr": cidr.cidr })),
+                    NetworkPermission::Host(_) => None,
+                })
+                .collect();
+            (hosts, cidrs)
+        };
+        let (allowed_hosts, allowed_cidrs) =
+            network_rules(network.and_then(|n| n.allow.as_deref()));
+        let (denied_hosts, denied_cidrs) = network_rules(network.and_then(|n| n.deny.as_deref()));
+        push_rego_rules(&mut rego, "network_host_allow", Some(&allowed_hosts))?;
+        push_rego_rules(&mut rego, "network_host_deny", Some(&denied_hosts))?;
+        push_rego_rules(&mut rego, "network_cidr_allow", Some(&allowed_cidrs))?;
+        push_rego_rules(&mut rego, "network_cidr_deny", Some(&denied_cidrs))?;
+
+        let environment = permissions.environment.as_ref();
+        push_rego_rules(
+            &mut rego,
+            "environment_allow",
+            environment.and_then(|e| e.allow.as_deref()),
+        )?;
+        push_rego_rules(
+            &mut rego,
+            "environment_deny",
+            environment.and_then(|e| e.deny.as_deref()),
+        )?;
+
+        let ipc = permissions.ipc.as_ref();
+        push_rego_rules(&mut rego, "ipc_allow", ipc.and_then(|i| i.allow.as_deref()))?;
+        push_rego_rules(&mut rego, "ipc_deny", ipc.and_then(|i| i.deny.as_deref()))?;
+
+        rego.push_str(REGO_RULES);
+        Ok(rego)
+    }
+
+    /// Write a policy document to a file, in the format matching its extension (see
+    /// [`PolicyParser::parse_file`])
     ///
     /// # Example
     ///
@@ -100,12 +407,163 @@ impl PolicyParser {
     /// PolicyParser::write_file(&policy, "./testdata/docker.yaml").unwrap();
     /// ```
     pub fn write_file<P: AsRef<Path>>(document: &PolicyDocument, path: P) -> PolicyResult<()> {
-        let yaml = Self::to_yaml(document)?;
-        fs::write(path, yaml)?;
+        let path = path.as_ref();
+        let content = match PolicyFormat::from_path(path) {
+            PolicyFormat::Yaml => Self::to_yaml(document)?,
+            PolicyFormat::Toml => Self::to_toml(document)?,
+            PolicyFormat::Json => Self::to_json(document)?,
+        };
+        fs::write(path, content)?;
         Ok(())
     }
 }
 
+/// Writes `name := [...]` with one JSON object per rule, which is valid Rego
+fn push_rego_rules<T: Serialize>(
+    rego: &mut String,
+    name: &str,
+    rules: Option<&[T]>,
+) -> PolicyResult<()> {
+    let rules = rules.unwrap_or_default();
+    if rules.is_empty() {
+        rego.push_str(&format!("{name} := []\n\n"));
+        return Ok(());
+    }
+    rego.push_str(&format!("{name} := [\n"));
+    for rule in rules {
+        rego.push_str(&format!("\t{},\n", serde_json::to_string(rule)?));
+    }
+    rego.push_str("]\n\n");
+    Ok(())
+}
+
+/// Splits a host rule into the parts the Rego rules match on, mirroring how the runtime parses
+/// hosts: an optional `scheme://` prefix, the host pattern and the allowed port ranges
+fn rego_host_rule(rule: &NetworkPermission) -> Value {
+    let NetworkPermission::Host(host) = rule else {
+        unreachable!("only called for host rules");
+    };
+    let mut value = match host.host.split_once("://") {
+        Some((scheme, pattern)) => json!({ "host": pattern, "scheme": scheme }),
+        None => json!({ "host": host.host }),
+    };
+    if let Some(ports) = &host.ports {
+        let ranges: Vec<Value> = ports
+            .iter()
+            .map(|range| json!({ "start": range.start, "end": range.end }))
+            .collect();
+        value["ports"] = Value::Array(ranges);
+    }
+    value
+}
+
+/// The rules evaluating `input` against the rule lists written by [`PolicyParser::to_rego`]
+const REGO_RULES: &str = r#"default allow := false
+
+allow if allow_storage
+
+allow if allow_network
+
+allow if allow_environment
+
+allow if allow_ipc
+
+# Storage: `*` matches within a path segment and `**` across segments
+default allow_storage := false
+
+allow_storage if {
+	some rule in storage_allow
+	glob.match(rule.uri, ["/"], input.storage.uri)
+	input.storage.access in rule.access
+	not storage_denied
+}
+
+storage_denied if {
+	some rule in storage_deny
+	glob.match(rule.uri, ["/"], input.storage.uri)
+	input.storage.access in rule.access
+}
+
+# Network: hosts are matched exactly, `*` matches every host and `*.domain` any subdomain
+default allow_network := false
+
+allow_network if {
+	some rule in network_host_allow
+	host_matches(rule.host, input.network.host)
+	scheme_allowed(rule)
+	port_allowed(rule)
+	not network_host_denied
+}
+
+allow_network if {
+	some rule in network_cidr_allow
+	net.cidr_contains(rule.cidr, input.network.ip)
+	not network_cidr_denied
+}
+
+network_host_denied if {
+	some rule in network_host_deny
+	host_matches(rule.host, input.network.host)
+}
+
+network_cidr_denied if {
+	some rule in network_cidr_deny
+	net.cidr_contains(rule.cidr, input.network.ip)
+}
+
+host_matches(pattern, _) if pattern == "*"
+
+host_matches(pattern, host) if {
+	startswith(pattern, "*.")
+	endswith(host, substring(pattern, 1, -1))
+	count(host) >= count(pattern)
+}
+
+host_matches(pattern, host) if pattern == host
+
+scheme_allowed(rule) if not rule.scheme
+
+scheme_allowed(_) if not input.network.scheme
+
+scheme_allowed(rule) if rule.scheme == input.network.scheme
+
+port_allowed(rule) if not rule.ports
+
+port_allowed(rule) if {
+	some range in rule.ports
+	input.network.port >= range.start
+	input.network.port <= range.end
+}
+
+# Environment: keys are patterns where * matches any number of characters
+default allow_environment := false
+
+allow_environment if {
+	some rule in environment_allow
+	glob.match(rule.key, [], input.environment.key)
+	not environment_denied
+}
+
+environment_denied if {
+	some rule in environment_deny
+	glob.match(rule.key, [], input.environment.key)
+}
+
+# IPC: URIs are matched exactly
+default allow_ipc := false
+
+allow_ipc if {
+	some rule in ipc_allow
+	rule.uri == input.ipc.uri
+	not ipc_denied
+}
+
+ipc_denied if {
+	some rule in ipc_deny
+	rule.uri == input.ipc.uri
+}
+"#;
+
 #[cfg(test)]
 mod tests {
     use std::io::Write;
@@ -114,8 +572,8 @@ mod tests {
 
     use super::*;
     use crate::{
-        AccessType, CapabilityAction, CpuLimit, MemoryLimit, PermissionList, Permissions,
-        StoragePermission,
+        AccessType, CapabilityAction, CpuLimit, MemoryLimit, NetworkPermission, PermissionList,
+        Permissions, StoragePermission,
     };
 
     #[test]
@@ -185,6 +643,7 @@ invalid: yaml: content
             version: "1.0".to_string(),
             description: Some("Test policy".to_string()),
             permissions,
+            ..Default::default()
         };
 
         let yaml = PolicyParser::to_yaml(&original).unwrap();
@@ -193,6 +652,129 @@ invalid: yaml: content
         assert_eq!(original, parsed);
     }
 
+    #[test]
+    fn test_round_trip_serialization_toml() {
+        let permissions = Permissions {
+            storage: Some(PermissionList {
+                allow: Some(vec![StoragePermission {
+                    uri: "fs://work/agent/**".to_string(),
+                    access: vec![AccessType::Read, AccessType::Write],
+                }]),
+                deny: None,
+            }),
+            ..Default::default()
+        };
+        let original = PolicyDocument {
+            version: "1.0".to_string(),
+            description: Some("Test policy".to_string()),
+            permissions,
+            ..Default::default()
+        };
+
+        let toml = PolicyParser::to_toml(&original).unwrap();
+        let parsed = PolicyParser::parse_toml_str(&toml).unwrap();
+
+        assert_eq!(original, parsed);
+    }
+
+    #[test]
+    fn test_parse_toml_file() {
+        let mut temp_file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        let toml_content = r#"
+version = "1.0"
+description = "File test policy"
+
+[[permissions.environment.allow]]
+key = "PATH"
+
+[[permissions.environment.allow]]
+key = "HOME"
+"#;
+
+        temp_file.write_all(toml_content.as_bytes()).unwrap();
+        temp_file.flush().unwrap();
+
+        let policy = PolicyParser::parse_file(temp_file.path()).unwrap();
+        assert_eq!(policy.description, Some("File test policy".to_string()));
+        let environment = policy.permissions.environment.as_ref().unwrap();
+        let allow_list = environment.allow.as_ref().unwrap();
+        assert_eq!(allow_list.len(), 2);
+        assert_eq!(allow_list[1].key, "HOME");
+
+        // Writing to a .toml path keeps the format, so the file can be read back
+        PolicyParser::write_file(&policy, temp_file.path()).unwrap();
+        let written = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(written.contains("version = \"1.0\""));
+        assert_eq!(PolicyParser::parse_file(temp_file.path()).unwrap(), policy);
+    }
+
+    #[test]
+    fn test_round_trip_serialization_json() {
+        let original = PolicyParser::parse_file("testdata/docker.yaml").unwrap();
+
+        let json = PolicyParser::to_json(&original).unwrap();
+        let parsed = PolicyParser::parse_json_str(&json).unwrap();
+
+        assert_eq!(original, parsed);
+    }
+
+    #[test]
+    fn test_parse_testdata_docker_json_matches_yaml() {
+        let from_json = PolicyParser::parse_file("testdata/docker.json").unwrap();
+        let from_yaml = PolicyParser::parse_file("testdata/docker.yaml").unwrap();
+        assert_eq!(from_json, from_yaml);
+    }
+
+    #[test]
+    fn test_parse_json_str_invalid() {
+        let err =
+            PolicyParser::parse_json_str(r#"{"version": "1.0", "permissions": "#).unwrap_err();
+        assert!(!err.to_string().is_empty());
+    }
+
+    #[test]
+    fn test_parse_file_error_names_format() {
+        let mut temp_file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        temp_file
+            .write_all(br#"{"version": "1.0", "permissions": "#)
+            .unwrap();
+        let err = PolicyParser::parse_file(temp_file.path()).unwrap_err();
+        assert!(err.to_string().contains("invalid JSON"), "{err}");
+
+        let mut temp_file = tempfile::Builder::new().suffix(".yml").tempfile().unwrap();
+        temp_file.write_all(b"version: [").unwrap();
+        let err = PolicyParser::parse_file(temp_file.path()).unwrap_err();
+        assert!(err.to_string().contains("invalid YAML"), "{err}");
+    }
+
+    #[test]
+    fn test_policy_format_from_path() {
+        assert_eq!(
+            PolicyFormat::from_path(Path::new("policy.json")),
+            PolicyFormat::Json
+        );
+        assert_eq!(
+            PolicyFormat::from_path(Path::new("policy.toml")),
+            PolicyFormat::Toml
+        );
+        assert_eq!(
+            PolicyFormat::from_path(Path::new("policy.TOML")),
+            PolicyFormat::Toml
+        );
+        assert_eq!(
+            PolicyFormat::from_path(Path::new("policy.yaml")),
+            PolicyFormat::Yaml
+        );
+        assert_eq!(
+            PolicyFormat::from_path(Path::new("policy.policy")),
+            PolicyFormat::Yaml
+        );
+        assert_eq!(
+            PolicyFormat::from_path(Path::new("policy")),
+            PolicyFormat::Yaml
+        );
+    }
+
     #[test]
     fn test_parse_file() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -220,6 +802,81 @@ permissions:
         assert_eq!(allow_list[1].key, "HOME");
     }
 
+    #[test]
+    fn test_parse_v1_1_deny_rules() {
+        let yaml_content = r#"
+version: "1.1"
+permissions:
+  network:
+    allow:
+    - host: "*.example.com"
+    deny:
+    - host: "secret.example.com"
+  storage:
+    allow:
+    - uri: "fs://data"
+      access: ["read", "write"]
+    deny:
+    - uri: "fs://data"
+      access: ["write"]
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        policy.validate().unwrap();
+        assert_eq!(policy.version, "1.1");
+
+        let network = policy.permissions.network.unwrap();
+        let hosts = |list: Option<Vec<NetworkPermission>>| -> Vec<String> {
+            list.unwrap()
+                .into_iter()
+                .filter_map(|perm| match perm {
+                    NetworkPermission::Host(host) => Some(host.host),
+                    NetworkPermission::Cidr(_) => None,
+                })
+                .collect()
+        };
+        assert_eq!(hosts(network.allow), ["*.example.com"]);
+        assert_eq!(hosts(network.deny), ["secret.example.com"]);
+
+        let storage = policy.permissions.storage.unwrap();
+        assert_eq!(storage.deny.unwrap()[0].access, [AccessType::Write]);
+    }
+
+    #[test]
+    fn test_merge_files() {
+        let mut base_file = NamedTempFile::new().unwrap();
+        base_file
+            .write_all(
+                b"version: \"1.0\"\npermissions:\n  environment:\n    allow:\n    - key: \"PATH\"\n",
+            )
+            .unwrap();
+        let mut overlay_file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        overlay_file
+            .write_all(
+                br#"{"version": "1.0", "description": "Overlay", "permissions": {"environment": {"allow": [{"key": "HOME"}]}}}"#,
+            )
+            .unwrap();
+
+        let policy = PolicyParser::merge_files(
+            base_file.path(),
+            overlay_file.path(),
+            MergeStrategy::DenyWins,
+        )
+        .unwrap();
+        assert_eq!(policy.description.as_deref(), Some("Overlay"));
+        let allow = policy.permissions.environment.unwrap().allow.unwrap();
+        let keys: Vec<_> = allow.iter().map(|env| env.key.as_str()).collect();
+        assert_eq!(keys, vec!["PATH", "HOME"]);
+
+        let err = PolicyParser::merge_files(
+            base_file.path(),
+            "does-not-exist.yaml",
+            MergeStrategy::DenyWins,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("overlay policy"), "{err}");
+    }
+
     #[test]
     fn test_write_file() {
         let permissions = Permissions::default();
@@ -227,6 +884,7 @@ permissions:
             version: "1.0".to_string(),
             description: Some("Write test policy".to_string()),
             permissions,
+            ..Default::default()
         };
 
         let temp_file = NamedTempFile::new().unwrap();
@@ -527,6 +1185,7 @@ permissions: {}
             "testdata/web-service.yaml",
             "testdata/docker.yaml",
             "testdata/resource-limits.yaml",
+            "testdata/docker.json",
         ];
 
         for file_path in &test_files {
@@ -537,9 +1196,64 @@ permissions: {}
                 original_policy, reparsed_policy,
                 "Round trip failed for {file_path}",
             );
+
+            let json_string = PolicyParser::to_json(&original_policy).unwrap();
+            let reparsed_policy = PolicyParser::parse_json_str(&json_string).unwrap();
+            assert_eq!(
+                original_policy, reparsed_policy,
+                "JSON round trip failed for {file_path}",
+            );
+
+            let toml_string = PolicyParser::to_toml(&original_policy).unwrap();
+            let reparsed_policy = PolicyParser::parse_toml_str(&toml_string).unwrap();
+            assert_eq!(
+                original_policy, reparsed_policy,
+                "TOML round trip failed for {file_path}",
+            );
         }
     }
 
+    #[test]
+    fn test_round_trip_comments() {
+        let test_files = [
+            "testdata/comprehensive.yaml",
+            "testdata/docker.yaml",
+            "testdata/minimal.yaml",
+        ];
+        let preserve = SerializerOptions {
+            preserve_comments: true,
+        };
+
+        for file_path in &test_files {
+            let original_policy = PolicyParser::parse_file(file_path).unwrap();
+
+            // Without comments the output is the same as before and nothing is dropped
+            let yaml_string =
+                PolicyParser::to_yaml_with_options(&original_policy, &SerializerOptions::default())
+                    .unwrap();
+            assert_eq!(
+                yaml_string,
+                serde_yaml::to_string(&original_policy).unwrap()
+            );
+            let reparsed_policy = PolicyParser::parse_str(&yaml_string).unwrap();
+            assert_eq!(original_policy, reparsed_policy, "{file_path}");
+            assert!(reparsed_policy.comments.is_empty(), "{file_path}");
+
+            let yaml_string =
+                PolicyParser::to_yaml_with_options(&original_policy, &preserve).unwrap();
+            let reparsed_policy = PolicyParser::parse_str(&yaml_string).unwrap();
+            assert_eq!(original_policy, reparsed_policy, "{file_path}");
+            assert_eq!(
+                original_policy.comments, reparsed_policy.comments,
+                "Comments were lost for {file_path}"
+            );
+        }
+
+        let docker = PolicyParser::parse_file("testdata/docker.yaml").unwrap();
+        let yaml_string = PolicyParser::to_yaml_with_options(&docker, &preserve).unwrap();
+        assert!(yaml_string.ends_with("# ipc:\n#   allow:\n#   - uri: \"pipe://myservice\"\n"));
+    }
+
     #[test]
     fn test_validation_all_testdata() {
         let test_files = [
@@ -563,4 +1277,172 @@ permissions: {}
             });
         }
     }
+
+    /// Returns the rule lists of a generated Rego module, checking that brackets are balanced
+    /// outside of strings and comments
+    fn parse_rego_rule_lists(rego: &str) -> std::collections::HashMap<String, Vec<Value>> {
+        let mut stack = Vec::new();
+        for line in rego.lines() {
+            let mut in_string = false;
+            let mut escaped = false;
+            for c in line.chars() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if in_string => escaped = true,
+                    '"' => in_string = !in_string,
+                    '#' if !in_string => break,
+                    '(' | '[' | '{' if !in_string => stack.push(c),
+                    ')' | ']' | '}' if !in_string => {
+                        let open = stack.pop().expect("unbalanced closing bracket");
+                        assert_eq!(open, matching_open(c), "mismatched bracket");
+                    }
+                    _ => {}
+                }
+            }
+            assert!(!in_string, "unterminated string in {line}");
+        }
+        assert!(stack.is_empty(), "unclosed brackets: {stack:?}");
+
+        let mut lists = std::collections::HashMap::new();
+        let mut lines = rego.lines();
+        while let Some(line) = lines.next() {
+            let Some((name, start)) = line.split_once(" := [") else {
+                continue;
+            };
+            let mut list = format!("[{start}");
+            if start != "]" {
+                for line in lines.by_ref() {
+                    list.push_str(line);
+                    if line == "]" {
+                        break;
+                    }
+                }
+            }
+            // Rego allows a trailing comma, JSON doesn't
+            let list = list.replace(",]", "]");
+            let rules: Vec<Value> = serde_json::from_str(&list)
+                .unwrap_or_else(|e| panic!("rule list {name} is not valid: {e}"));
+            lists.insert(name.to_string(), rules);
+        }
+        lists
+    }
+
+    fn matching_open(close: char) -> char {
+        match close {
+            ')' => '(',
+            ']' => '[',
+            _ => '{',
+        }
+    }
+
+    #[test]
+    fn test_to_rego_testdata_docker() {
+        let policy = PolicyParser::parse_file("testdata/docker.yaml").unwrap();
+        let rego = PolicyParser::to_rego(&policy).unwrap();
+
+        assert!(rego.starts_with("# Generated from a wassette policy (version 1.0)\n"));
+        assert!(rego.contains("\npackage wassette.policy\n"));
+        assert!(rego.contains("\nimport rego.v1\n"));
+        for rule in [
+            "allow_storage",
+            "allow_network",
+            "allow_environment",
+            "allow_ipc",
+        ] {
+            assert!(rego.contains(&format!("default {rule} := false")));
+            assert!(rego.contains(&format!("allow if {rule}\n")));
+        }
+
+        let lists = parse_rego_rule_lists(&rego);
+        let permissions = &policy.permissions;
+
+        let storage = permissions
+            .storage
+            .as_ref()
+            .unwrap()
+            .allow
+            .as_ref()
+            .unwrap();
+        assert_eq!(lists["storage_allow"].len(), storage.len());
+        for rule in storage {
+            assert!(lists["storage_allow"].contains(&serde_json::to_value(rule).unwrap()));
+        }
+
+        let network = permissions
+            .network
+            .as_ref()
+            .unwrap()
+            .allow
+            .as_ref()
+            .unwrap();
+        for rule in network {
+            match rule {
+                NetworkPermission::Host(host) => {
+                    assert!(lists["network_host_allow"].contains(&json!({ "host": host.host })))
+                }
+                NetworkPermission::Cidr(cidr) => {
+                    assert!(lists["network_cidr_allow"].contains(&json!({ "cidr": cidr.cidr })))
+                }
+            }
+        }
+        assert_eq!(
+            lists["network_host_allow"].len() + lists["network_cidr_allow"].len(),
+            network.len()
+        );
+
+        let environment = permissions
+            .environment
+            .as_ref()
+            .unwrap()
+            .allow
+            .as_ref()
+            .unwrap();
+        assert_eq!(lists["environment_allow"].len(), environment.len());
+        for rule in environment {
+            assert!(lists["environment_allow"].contains(&json!({ "key": rule.key })));
+        }
+
+        for empty in [
+            "storage_deny",
+            "network_host_deny",
+            "environment_deny",
+            "ipc_allow",
+        ] {
+            assert!(lists[empty].is_empty(), "{empty} should be empty");
+        }
+    }
+
+    #[test]
+    fn test_to_rego_host_schemes_and_ports() {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+description: "Hosts with \"quotes\"\nand several lines"
+permissions:
+  network:
+    allow:
+      - host: "https://api.example.com"
+        ports: [443, "8000-8080"]
+    deny:
+      - host: "admin.example.com"
+"#,
+        )
+        .unwrap();
+        let rego = PolicyParser::to_rego(&policy).unwrap();
+        assert!(rego.contains("# Hosts with \"quotes\"\n# and several lines\n"));
+
+        let lists = parse_rego_rule_lists(&rego);
+        assert_eq!(
+            lists["network_host_allow"],
+            vec![json!({
+                "host": "api.example.com",
+                "scheme": "https",
+                "ports": [{ "start": 443, "end": 443 }, { "start": 8000, "end": 8080 }],
+            })]
+        );
+        assert_eq!(
+            lists["network_host_deny"],
+            vec![json!({ "host": "admin.example.com" })]
+        );
+    }
 }
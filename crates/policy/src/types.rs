@@ -3,49 +3,193 @@
 
 //! Type definitions
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
 use std::sync::OnceLock;
 
-use anyhow::bail;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Serialize};
 
-use crate::PolicyResult;
+use crate::{PolicyError, PolicyResult};
+
+/// The schemes a storage URI can start with. Both refer to paths relative to the plugin directory
+pub const STORAGE_URI_SCHEMES: &[&str] = &["fs://", "file://"];
 
 /// read: read access
 /// write: write access
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AccessType {
     Read,
     Write,
 }
 
+impl FromStr for AccessType {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            other => Err(PolicyError::InvalidAccessType {
+                value: other.to_string(),
+            }),
+        }
+    }
+}
+
 /// uri: URI pattern for the resource (e.g. fs://work/agent/**)
 /// access: Access types allowed (read, write)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct StoragePermission {
     /// URI pattern for the resource
+    #[schemars(
+        length(min = 1),
+        extend("x-validation" = "`**` must be a whole path segment, other segments allow one `*`")
+    )]
     pub uri: String,
     /// Access types allowed
+    #[schemars(length(min = 1))]
     pub access: Vec<AccessType>,
 }
 
 /// Network host permission
 ///
 /// host: Hostname or pattern (supports wildcards like *.domain.com)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct NetworkHostPermission {
     /// Hostname or pattern (supports wildcards like *.domain.com)
+    #[schemars(
+        length(min = 1),
+        extend("x-validation" = "Wildcards are only allowed as `*` or a leading `*.`")
+    )]
     pub host: String,
+    /// Ports the host can be reached on. Every port is allowed if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Vec<PortRange>")]
+    pub ports: Option<Vec<PortRange>>,
+}
+
+/// A single port (`443`) or an inclusive range of ports (`8000-8080`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "RawPortRange", into = "RawPortRange")]
+pub struct PortRange {
+    /// The first port of the range
+    pub start: u16,
+    /// The last port of the range, equal to `start` for a single port
+    pub end: u16,
+}
+
+impl PortRange {
+    /// Creates a range containing a single port
+    pub fn single(port: u16) -> Self {
+        Self {
+            start: port,
+            end: port,
+        }
+    }
+
+    /// Returns true if `port` is part of the range
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_port = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .map_err(|_| PolicyError::validation("ports", format!("Invalid port: {s}")))
+        };
+        let range = match s.split_once('-') {
+            Some((start, end)) => Self {
+                start: parse_port(start)?,
+                end: parse_port(end)?,
+            },
+            None => Self::single(parse_port(s)?),
+        };
+        if range.start > range.end {
+            return Err(PolicyError::validation(
+                "ports",
+                format!("Port range start is greater than its end: {s}"),
+            ));
+        }
+        Ok(range)
+    }
+}
+
+impl Display for PortRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// How a [`PortRange`] is written in a policy document: a port number or a string holding either
+/// a port or a range
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawPortRange {
+    Port(u16),
+    Range(String),
+}
+
+impl TryFrom<RawPortRange> for PortRange {
+    type Error = PolicyError;
+
+    fn try_from(raw: RawPortRange) -> Result<Self, Self::Error> {
+        match raw {
+            RawPortRange::Port(port) => Ok(Self::single(port)),
+            RawPortRange::Range(range) => range.parse(),
+        }
+    }
+}
+
+impl From<PortRange> for RawPortRange {
+    fn from(range: PortRange) -> Self {
+        if range.start == range.end {
+            RawPortRange::Port(range.start)
+        } else {
+            RawPortRange::Range(range.to_string())
+        }
+    }
+}
+
+impl JsonSchema for PortRange {
+    fn schema_name() -> Cow<'static, str> {
+        "PortRange".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A single port or an inclusive range of ports",
+            "oneOf": [
+                { "type": "integer", "minimum": 0, "maximum": 65535 },
+                { "type": "string", "pattern": "^[0-9]+(-[0-9]+)?$" }
+            ]
+        })
+    }
 }
 
 /// Network CIDR permission
 ///
 /// cidr: CIDR notation for network range (e.g. 10.0.0.0/8)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct NetworkCidrPermission {
     /// CIDR notation for network range
+    #[schemars(
+        length(min = 1),
+        extend("x-validation" = "Must contain a `/` separating the address and the prefix length")
+    )]
     pub cidr: String,
 }
 
@@ -58,11 +202,56 @@ pub enum NetworkPermission {
 }
 
 /// Environment variable permission
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct EnvironmentPermission {
+    /// Name of the environment variable, or a pattern like `AWS_*` where `*` matches any number
+    /// of characters
+    #[schemars(
+        length(min = 1),
+        extend("x-validation" = "A key matching every variable, like `*`, needs `allow_all: true`")
+    )]
     pub key: String,
 }
 
+impl EnvironmentPermission {
+    /// Returns true if the key of this rule, which may contain `*` wildcards, matches the name
+    /// of an environment variable
+    pub fn matches(&self, name: &str) -> bool {
+        let (pattern, name) = (self.key.as_bytes(), name.as_bytes());
+        let (mut p, mut n) = (0, 0);
+        // The last `*` seen and where its match in the name ends for now. Only that `*` ever needs
+        // to match more, which keeps this O(n·m) even for keys like `*A*A*A*B` that agents can
+        // pass through the grant tools.
+        let mut backtrack = None;
+        while n < name.len() {
+            match pattern.get(p) {
+                Some(b'*') => {
+                    backtrack = Some((p, n));
+                    p += 1;
+                }
+                Some(&c) if c == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                _ => match backtrack {
+                    Some((star, matched)) => {
+                        p = star + 1;
+                        n = matched + 1;
+                        backtrack = Some((star, n));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|&c| c == b'*')
+    }
+
+    /// Returns true if the key matches every environment variable
+    pub fn matches_all(&self) -> bool {
+        !self.key.is_empty() && self.key.chars().all(|c| c == '*')
+    }
+}
+
 /// Docker capability action
 ///
 /// TODO: Add more capabilities
@@ -182,6 +371,43 @@ pub struct IpcPermission {
 pub struct Runtime {
     pub docker: Option<DockerRuntime>,
     pub hyperlight: Option<HyperlightRuntime>,
+    /// The most memory a single call of the component may use, in MiB. Calls going over it fail
+    /// instead of the allocation failing inside the component
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
+    /// How long a single call of the component may run, in milliseconds, before it is aborted.
+    /// Overrides the call timeout of the host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_timeout_ms: Option<u64>,
+    /// Whether the results of the component's calls are cached, for components whose tools
+    /// always return the same result for the same arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CachePolicy>,
+}
+
+/// Caching of the results of a component's calls. A call with the same tool and arguments as an
+/// earlier successful call returns the earlier result without running the component, until the
+/// result is older than `ttl_ms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachePolicy {
+    #[serde(default = "CachePolicy::default_enabled")]
+    pub enabled: bool,
+    /// How long a result is reused, in milliseconds
+    pub ttl_ms: u64,
+    /// The most results kept for the component. The least recently used result is dropped to
+    /// make room for a new one
+    #[serde(default = "CachePolicy::default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl CachePolicy {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_max_entries() -> usize {
+        128
+    }
 }
 
 /// Permission list with allow/deny rules
@@ -200,10 +426,17 @@ impl<T> Default for PermissionList<T> {
     }
 }
 
-/// Environment permissions (allow-only for security)
+/// Environment permissions. Keys listed in `deny` are never exposed, even if they are also
+/// allowed
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct EnvironmentPermissions {
     pub allow: Option<Vec<EnvironmentPermission>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deny: Option<Vec<EnvironmentPermission>>,
+    /// Must be set for an allow rule like `key: "*"` to be accepted, which exposes every
+    /// environment variable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_all: bool,
 }
 
 /// Complete permissions structure
@@ -223,29 +456,44 @@ impl CpuLimit {
         match self {
             CpuLimit::String(s) => {
                 if s.is_empty() {
-                    bail!("CPU limit string cannot be empty");
+                    return Err(PolicyError::validation(
+                        "permissions.resources.cpu",
+                        "CPU limit string cannot be empty",
+                    ));
                 }
 
                 if s.ends_with('m') {
                     // Millicores format like "500m"
                     let millicores_str = &s[..s.len() - 1];
-                    let millicores: f64 = millicores_str
-                        .parse()
-                        .map_err(|_| anyhow::anyhow!("Invalid millicores value: {}", s))?;
+                    let millicores: f64 = millicores_str.parse().map_err(|_| {
+                        PolicyError::validation(
+                            "permissions.resources.cpu",
+                            format!("Invalid millicores value: {s}"),
+                        )
+                    })?;
 
                     if millicores < 0.0 {
-                        bail!("CPU millicores cannot be negative: {}", s);
+                        return Err(PolicyError::validation(
+                            "permissions.resources.cpu",
+                            format!("CPU millicores cannot be negative: {s}"),
+                        ));
                     }
 
                     Ok(millicores / 1000.0)
                 } else {
                     // Cores format like "1", "2", "0.5"
-                    let cores: f64 = s
-                        .parse()
-                        .map_err(|_| anyhow::anyhow!("Invalid cores value: {}", s))?;
+                    let cores: f64 = s.parse().map_err(|_| {
+                        PolicyError::validation(
+                            "permissions.resources.cpu",
+                            format!("Invalid cores value: {s}"),
+                        )
+                    })?;
 
                     if cores < 0.0 {
-                        bail!("CPU cores cannot be negative: {}", s);
+                        return Err(PolicyError::validation(
+                            "permissions.resources.cpu",
+                            format!("CPU cores cannot be negative: {s}"),
+                        ));
                     }
 
                     Ok(cores)
@@ -253,7 +501,10 @@ impl CpuLimit {
             }
             CpuLimit::Number(n) => {
                 if *n < 0.0 {
-                    bail!("CPU cores cannot be negative: {}", n);
+                    return Err(PolicyError::validation(
+                        "permissions.resources.cpu",
+                        format!("CPU cores cannot be negative: {n}"),
+                    ));
                 }
                 Ok(*n)
             }
@@ -267,7 +518,10 @@ impl MemoryLimit {
         let bytes = match self {
             MemoryLimit::String(s) => {
                 if s.is_empty() {
-                    bail!("Memory limit string cannot be empty");
+                    return Err(PolicyError::validation(
+                        "permissions.resources.memory",
+                        "Memory limit string cannot be empty",
+                    ));
                 }
 
                 let (value_str, multiplier) = if s.ends_with("Ki") {
@@ -283,25 +537,41 @@ impl MemoryLimit {
                     (s.as_str(), 1u64)
                 };
 
-                let value: u64 = value_str
-                    .parse()
-                    .map_err(|_| anyhow::anyhow!("Invalid memory value: {}", s))?;
+                let value: u64 = value_str.parse().map_err(|_| {
+                    PolicyError::validation(
+                        "permissions.resources.memory",
+                        format!("Invalid memory value: {s}"),
+                    )
+                })?;
 
                 if value == 0 {
-                    bail!("Memory limit cannot be zero: {}", s);
+                    return Err(PolicyError::validation(
+                        "permissions.resources.memory",
+                        format!("Memory limit cannot be zero: {s}"),
+                    ));
                 }
 
-                value
-                    .checked_mul(multiplier)
-                    .ok_or_else(|| anyhow::anyhow!("Memory value too large: {}", s))?
+                value.checked_mul(multiplier).ok_or_else(|| {
+                    PolicyError::validation(
+                        "permissions.resources.memory",
+                        format!("Memory value too large: {s}"),
+                    )
+                })?
             }
             MemoryLimit::Number(n) => {
                 if *n == 0 {
-                    bail!("Memory limit cannot be zero");
+                    return Err(PolicyError::validation(
+                        "permissions.resources.memory",
+                        "Memory limit cannot be zero",
+                    ));
                 }
                 // Assume legacy numeric values are in MB
-                n.checked_mul(1024 * 1024)
-                    .ok_or_else(|| anyhow::anyhow!("Memory value too large: {}", n))?
+                n.checked_mul(1024 * 1024).ok_or_else(|| {
+                    PolicyError::validation(
+                        "permissions.resources.memory",
+                        format!("Memory value too large: {n}"),
+                    )
+                })?
             }
         };
 
@@ -373,7 +643,10 @@ impl ResourceLimits {
         // Validate legacy fields
         if let Some(cpu) = self.cpu {
             if cpu < 0.0 {
-                bail!("Legacy CPU value cannot be negative: {}", cpu);
+                return Err(PolicyError::validation(
+                    "permissions.resources.cpu",
+                    format!("Legacy CPU value cannot be negative: {cpu}"),
+                ));
             }
         }
 
@@ -392,25 +665,44 @@ impl ResourceLimits {
 impl Permissions {
     fn validate_storage_uri(uri: &str) -> PolicyResult<()> {
         if uri.is_empty() {
-            bail!("Storage URI can't be empty");
+            return Err(PolicyError::invalid_uri(uri, "Storage URI can't be empty"));
+        }
+
+        if !STORAGE_URI_SCHEMES
+            .iter()
+            .any(|scheme| uri.starts_with(scheme))
+        {
+            return Err(PolicyError::invalid_uri(
+                uri,
+                format!(
+                    "Storage URIs must start with one of {}",
+                    STORAGE_URI_SCHEMES.join(", ")
+                ),
+            ));
         }
 
         if uri.contains("***") {
-            bail!("Too many wildcards in: {}", uri);
+            return Err(PolicyError::invalid_uri(uri, "Too many wildcards"));
         }
 
         // Make sure ** is used properly (learned this the hard way)
         let parts: Vec<&str> = uri.split('/').collect();
         for part in parts.iter() {
             if part.contains("**") && *part != "**" {
-                bail!("Wildcard ** needs to be its own path segment in: {}", uri);
+                return Err(PolicyError::invalid_uri(
+                    uri,
+                    "Wildcard ** needs to be its own path segment",
+                ));
             }
 
             // println!("DEBUG: checking part: {}", part); // TODO: remove this
             if part.contains('*') && *part != "*" && *part != "**" {
                 let star_count = part.matches('*').count();
                 if star_count > 1 && !part.contains("**") {
-                    bail!("Multiple * in path segment '{}' - that's weird", part);
+                    return Err(PolicyError::invalid_uri(
+                        uri,
+                        format!("Multiple * in path segment '{part}'"),
+                    ));
                 }
             }
         }
@@ -420,110 +712,206 @@ impl Permissions {
 
     fn validate_network_host(host: &str) -> PolicyResult<()> {
         if host.is_empty() {
-            bail!("Host can't be empty");
+            return Err(PolicyError::validation(
+                "permissions.network",
+                "Host can't be empty",
+            ));
         }
 
         if host.matches('*').count() > 1 {
-            bail!("Too many wildcards in host: {}", host);
+            return Err(PolicyError::validation(
+                "permissions.network",
+                format!("Too many wildcards in host: {host}"),
+            ));
         }
 
         if host.contains('*') && !host.starts_with("*.") && host != "*" {
-            bail!("Wildcard should be at start like *.domain.com in: {}", host);
+            return Err(PolicyError::validation(
+                "permissions.network",
+                format!("Wildcard should be at start like *.domain.com in: {host}"),
+            ));
         }
 
         if let Some(domain_part) = host.strip_prefix("*.") {
             if domain_part.is_empty() || domain_part.ends_with('.') {
-                bail!("Domain part looks wrong in: {}", host);
+                return Err(PolicyError::validation(
+                    "permissions.network",
+                    format!("Domain part looks wrong in: {host}"),
+                ));
             }
         }
 
         Ok(())
     }
 
-    fn validate_environment_key(key: &str) -> PolicyResult<()> {
-        if key.is_empty() {
-            bail!("Environment key can't be empty");
+    fn validate_cidr(cidr: &str) -> PolicyResult<()> {
+        let Some((address, prefix)) = cidr.split_once('/') else {
+            return Err(PolicyError::invalid_cidr(
+                cidr,
+                "expected an address and a prefix length like 10.0.0.0/8",
+            ));
+        };
+        let address: IpAddr = address.parse().map_err(|_| {
+            PolicyError::invalid_cidr(cidr, format!("'{address}' is not an IPv4 or IPv6 address"))
+        })?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= max_prefix => Ok(()),
+            _ => Err(PolicyError::invalid_cidr(
+                cidr,
+                format!("the prefix length must be a number from 0 to {max_prefix}"),
+            )),
         }
+    }
 
-        // No wildcards in env vars - too risky
-        if key.contains('*') {
-            bail!("No wildcards allowed in environment keys: {}", key);
+    fn validate_environment_key(key: &str) -> PolicyResult<()> {
+        if key.is_empty() {
+            return Err(PolicyError::validation(
+                "permissions.environment",
+                "Environment key can't be empty",
+            ));
         }
-
         Ok(())
     }
 
-    /// Validate the permissions structure
+    /// Validate the permissions structure. Every problem is reported, combined into
+    /// [`PolicyError::Multiple`] if there are several
     pub fn validate(&self) -> PolicyResult<()> {
-        if let Some(storage) = &self.storage {
-            if let Some(allow_list) = &storage.allow {
-                for perm in allow_list {
-                    Self::validate_storage_uri(&perm.uri)?;
-                    if perm.access.is_empty() {
-                        bail!("Storage needs some access permissions");
-                    }
-                }
+        let mut errors = Vec::new();
+        let mut check = |result: PolicyResult<()>| {
+            if let Err(e) = result {
+                errors.push(e);
             }
-            if let Some(deny_list) = &storage.deny {
-                for perm in deny_list {
-                    Self::validate_storage_uri(&perm.uri)?;
-                    if perm.access.is_empty() {
-                        bail!("Storage needs some access permissions");
-                    }
+        };
+
+        if let Some(storage) = &self.storage {
+            for perm in storage.allow.iter().chain(&storage.deny).flatten() {
+                check(Self::validate_storage_uri(&perm.uri));
+                if perm.access.is_empty() {
+                    check(Err(PolicyError::validation(
+                        "permissions.storage",
+                        format!("Storage needs some access permissions: {}", perm.uri),
+                    )));
                 }
             }
         }
 
         if let Some(network) = &self.network {
-            if let Some(allow_list) = &network.allow {
-                for perm in allow_list {
-                    match perm {
-                        NetworkPermission::Host(host_perm) => {
-                            Self::validate_network_host(&host_perm.host)?;
-                        }
-                        NetworkPermission::Cidr(cidr_perm) => {
-                            if cidr_perm.cidr.is_empty() {
-                                bail!("CIDR can't be empty");
-                            }
-                            if !cidr_perm.cidr.contains('/') {
-                                bail!("CIDR needs a slash: {}", cidr_perm.cidr);
-                            }
+            for perm in network.allow.iter().flatten() {
+                match perm {
+                    NetworkPermission::Host(host_perm) => {
+                        check(Self::validate_network_host(&host_perm.host));
+                        if host_perm.ports.as_ref().is_some_and(Vec::is_empty) {
+                            check(Err(PolicyError::validation(
+                                "permissions.network",
+                                format!("Port list can't be empty for host: {}", host_perm.host),
+                            )));
                         }
                     }
+                    NetworkPermission::Cidr(cidr_perm) => {
+                        check(Self::validate_cidr(&cidr_perm.cidr));
+                    }
                 }
             }
-            if let Some(deny_list) = &network.deny {
-                for perm in deny_list {
-                    match perm {
-                        NetworkPermission::Host(host_perm) => {
-                            Self::validate_network_host(&host_perm.host)?;
-                        }
-                        NetworkPermission::Cidr(cidr_perm) => {
-                            if cidr_perm.cidr.is_empty() {
-                                bail!("CIDR can't be empty");
-                            }
-                            if !cidr_perm.cidr.contains('/') {
-                                bail!("CIDR needs a slash: {}", cidr_perm.cidr);
-                            }
+            for perm in network.deny.iter().flatten() {
+                match perm {
+                    NetworkPermission::Host(host_perm) => {
+                        check(Self::validate_network_host(&host_perm.host));
+                        // Denied hosts are blocked on every port
+                        if host_perm.ports.is_some() {
+                            check(Err(PolicyError::validation(
+                                "permissions.network",
+                                format!(
+                                    "Ports can only be restricted for allowed hosts: {}",
+                                    host_perm.host
+                                ),
+                            )));
                         }
                     }
+                    NetworkPermission::Cidr(cidr_perm) => {
+                        check(Self::validate_cidr(&cidr_perm.cidr));
+                    }
                 }
             }
         }
 
         if let Some(env) = &self.environment {
-            if let Some(allow_list) = &env.allow {
-                for perm in allow_list {
-                    Self::validate_environment_key(&perm.key)?;
+            for perm in env.allow.iter().flatten() {
+                check(Self::validate_environment_key(&perm.key));
+                // Easy to write by accident, and would leak every secret in the environment
+                if perm.matches_all() && !env.allow_all {
+                    check(Err(PolicyError::validation(
+                        "permissions.environment",
+                        format!(
+                            "Environment key '{}' exposes every environment variable, set `allow_all: true` if that is intended",
+                            perm.key
+                        ),
+                    )));
+                }
+            }
+            for perm in env.deny.iter().flatten() {
+                check(Self::validate_environment_key(&perm.key));
+            }
+            for (list, rules) in [("allow", &env.allow), ("deny", &env.deny)] {
+                let mut seen = HashSet::new();
+                for perm in rules.iter().flatten() {
+                    if !seen.insert(perm.key.as_str()) {
+                        check(Err(PolicyError::validation(
+                            "permissions.environment",
+                            format!("Environment key '{}' is listed twice in {list}", perm.key),
+                        )));
+                    }
                 }
             }
         }
 
         if let Some(resources) = &self.resources {
-            resources.validate()?;
+            check(resources.validate());
         }
 
-        Ok(())
+        if self
+            .runtime
+            .as_ref()
+            .is_some_and(|runtime| runtime.memory_limit_mb == Some(0))
+        {
+            check(Err(PolicyError::validation(
+                "permissions.runtime.memory_limit_mb",
+                "Memory limit must be greater than zero",
+            )));
+        }
+
+        if self
+            .runtime
+            .as_ref()
+            .is_some_and(|runtime| runtime.execution_timeout_ms == Some(0))
+        {
+            check(Err(PolicyError::validation(
+                "permissions.runtime.execution_timeout_ms",
+                "Execution timeout must be greater than zero",
+            )));
+        }
+
+        if let Some(cache) = self
+            .runtime
+            .as_ref()
+            .and_then(|runtime| runtime.cache.as_ref())
+            .filter(|cache| cache.enabled)
+        {
+            if cache.ttl_ms == 0 {
+                check(Err(PolicyError::validation(
+                    "permissions.runtime.cache.ttl_ms",
+                    "Cache TTL must be greater than zero",
+                )));
+            }
+            if cache.max_entries == 0 {
+                check(Err(PolicyError::validation(
+                    "permissions.runtime.cache.max_entries",
+                    "Cache must be able to hold at least one entry",
+                )));
+            }
+        }
+
+        PolicyError::combine(errors)
     }
 }
 
@@ -544,7 +932,91 @@ mod tests {
             ..Default::default()
         };
 
-        assert!(permissions.validate().is_err());
+        let err = permissions.validate().unwrap_err();
+        assert!(
+            matches!(&err, PolicyError::InvalidUri { uri, .. } if uri.is_empty()),
+            "{err}"
+        );
+    }
+
+    fn validate_yaml(permissions: &str) -> PolicyResult<()> {
+        serde_yaml::from_str::<Permissions>(permissions)
+            .unwrap()
+            .validate()
+    }
+
+    #[test]
+    fn test_semantic_validation_messages() {
+        let cases = [
+            (
+                "network: {allow: [{host: \"\"}]}",
+                "Validation failed for permissions.network: Host can't be empty",
+            ),
+            (
+                "network: {allow: [{cidr: \"10.0.0.0/40\"}]}",
+                "Invalid CIDR '10.0.0.0/40': the prefix length must be a number from 0 to 32",
+            ),
+            (
+                "storage: {allow: [{uri: \"/tmp/data\", access: [read]}]}",
+                "Invalid storage URI '/tmp/data': Storage URIs must start with one of fs://, file://",
+            ),
+            (
+                "environment: {allow: [{key: API_KEY}, {key: API_KEY}]}",
+                "Validation failed for permissions.environment: Environment key 'API_KEY' is listed twice in allow",
+            ),
+        ];
+        for (permissions, message) in cases {
+            let err = validate_yaml(permissions).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+
+        // The same key may be both allowed and denied
+        validate_yaml("environment: {allow: [{key: API_KEY}], deny: [{key: API_KEY}]}").unwrap();
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let err = validate_yaml(
+            r#"
+storage:
+  allow:
+    - uri: "/tmp/data"
+      access: [read]
+network:
+  allow:
+    - host: ""
+    - cidr: "10.0.0.0/40"
+environment:
+  deny:
+    - key: "TOKEN"
+    - key: "TOKEN"
+"#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, PolicyError::Multiple(errors) if errors.len() == 4),
+            "{err}"
+        );
+        assert_eq!(
+            err.to_string(),
+            "4 problems found in the policy:\n  \
+             - Invalid storage URI '/tmp/data': Storage URIs must start with one of fs://, file://\n  \
+             - Validation failed for permissions.network: Host can't be empty\n  \
+             - Invalid CIDR '10.0.0.0/40': the prefix length must be a number from 0 to 32\n  \
+             - Validation failed for permissions.environment: Environment key 'TOKEN' is listed twice in deny"
+        );
+        assert_eq!(err.errors().len(), 4);
+    }
+
+    #[test]
+    fn test_access_type_from_str() {
+        assert_eq!("read".parse::<AccessType>().unwrap(), AccessType::Read);
+        assert_eq!("write".parse::<AccessType>().unwrap(), AccessType::Write);
+        let err = "execute".parse::<AccessType>().unwrap_err();
+        assert!(
+            matches!(&err, PolicyError::InvalidAccessType { value } if value == "execute"),
+            "{err}"
+        );
     }
 
     #[test]
@@ -559,7 +1031,38 @@ mod tests {
             ..Default::default()
         };
 
-        assert!(permissions.validate().is_err());
+        let err = permissions.validate().unwrap_err();
+        assert!(
+            matches!(&err, PolicyError::InvalidCidr { cidr, .. } if cidr == "invalid-cidr"),
+            "{err}"
+        );
+
+        for cidr in [
+            "10.0.0.0/8",
+            "0.0.0.0/0",
+            "192.168.1.1/32",
+            "fd00::/8",
+            "::1/128",
+        ] {
+            assert!(Permissions::validate_cidr(cidr).is_ok(), "{cidr}");
+        }
+        for (cidr, reason) in [
+            ("", "prefix length like"),
+            ("10.0.0.0", "prefix length like"),
+            ("10.0.0/8", "not an IPv4 or IPv6 address"),
+            ("example.com/8", "not an IPv4 or IPv6 address"),
+            ("10.0.0.0/33", "from 0 to 32"),
+            ("10.0.0.0/", "from 0 to 32"),
+            ("fd00::/129", "from 0 to 128"),
+            ("10.0.0.0/-1", "from 0 to 32"),
+        ] {
+            let err = Permissions::validate_cidr(cidr).unwrap_err();
+            assert!(
+                matches!(&err, PolicyError::InvalidCidr { cidr: invalid, .. } if invalid == cidr),
+                "{err}"
+            );
+            assert!(err.to_string().contains(reason), "{err}");
+        }
     }
 
     #[test]
@@ -592,6 +1095,27 @@ mod tests {
         assert!(Permissions::validate_storage_uri("fs://work/agent/**/**.txt").is_err());
     }
 
+    #[test]
+    fn test_storage_uri_scheme_validation() {
+        assert!(Permissions::validate_storage_uri("fs:///tmp/data").is_ok());
+        assert!(Permissions::validate_storage_uri("file:///tmp/data").is_ok());
+
+        for uri in [
+            "fss://typo",
+            "http://not-fs",
+            "/tmp/data",
+            "work/agent/**",
+            "fs:/tmp",
+        ] {
+            let err = Permissions::validate_storage_uri(uri).unwrap_err();
+            assert!(
+                matches!(&err, PolicyError::InvalidUri { uri: invalid, reason }
+                    if invalid == uri && reason.contains("fs://, file://")),
+                "{err}"
+            );
+        }
+    }
+
     #[test]
     fn test_network_host_wildcard_validation() {
         assert!(Permissions::validate_network_host("example.com").is_ok());
@@ -614,14 +1138,68 @@ mod tests {
         assert!(Permissions::validate_environment_key("MY_VAR").is_ok());
         assert!(Permissions::validate_environment_key("HOME").is_ok());
 
+        assert!(Permissions::validate_environment_key("PATH_*").is_ok());
+        assert!(Permissions::validate_environment_key("*_DEBUG").is_ok());
+        assert!(Permissions::validate_environment_key("PA*TH").is_ok());
+
         assert!(Permissions::validate_environment_key("").is_err());
-        assert!(Permissions::validate_environment_key("PATH_*").is_err());
-        assert!(Permissions::validate_environment_key("*_DEBUG").is_err());
-        assert!(Permissions::validate_environment_key("*").is_err());
-        assert!(Permissions::validate_environment_key("PA*TH").is_err());
-        assert!(Permissions::validate_environment_key("*PATH*").is_err());
-        assert!(Permissions::validate_environment_key("**PATH").is_err());
-        assert!(Permissions::validate_environment_key("PATH**").is_err());
+    }
+
+    #[test]
+    fn test_environment_key_patterns() {
+        let rule = |key: &str| EnvironmentPermission {
+            key: key.to_string(),
+        };
+        assert!(rule("AWS_*").matches("AWS_REGION"));
+        assert!(rule("AWS_*").matches("AWS_"));
+        assert!(!rule("AWS_*").matches("MY_AWS_REGION"));
+        assert!(rule("*_DEBUG").matches("APP_DEBUG"));
+        assert!(rule("A*_*Y").matches("AWS_SECRET_KEY"));
+        assert!(rule("PATH").matches("PATH"));
+        assert!(!rule("PATH").matches("PATHS"));
+        assert!(!rule("PATH").matches("PAT"));
+        assert!(rule("*A*B").matches("XAYAB"));
+        assert!(!rule("*A*B").matches("XAYBA"));
+        assert!(rule("").matches(""));
+        assert!(!rule("").matches("A"));
+
+        // Would take exponential time with a matcher that backtracks to every `*`
+        let key = format!("{}B", "*A".repeat(20));
+        assert!(!rule(&key).matches(&"A".repeat(100)));
+
+        assert!(rule("*").matches_all());
+        assert!(rule("**").matches_all());
+        assert!(!rule("AWS_*").matches_all());
+    }
+
+    #[test]
+    fn test_environment_allow_all() {
+        let mut permissions = Permissions {
+            environment: Some(EnvironmentPermissions {
+                allow: Some(vec![EnvironmentPermission {
+                    key: "*".to_string(),
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let err = permissions.validate().unwrap_err().to_string();
+        assert!(err.contains("allow_all"), "unexpected error: {err}");
+
+        permissions.environment.as_mut().unwrap().allow_all = true;
+        permissions.validate().unwrap();
+
+        // Denying every variable is harmless
+        let permissions = Permissions {
+            environment: Some(EnvironmentPermissions {
+                deny: Some(vec![EnvironmentPermission {
+                    key: "*".to_string(),
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        permissions.validate().unwrap();
     }
 
     #[test]
@@ -647,16 +1225,19 @@ mod tests {
                 allow: Some(vec![
                     NetworkPermission::Host(NetworkHostPermission {
                         host: "*.example.com".to_string(),
+                        ports: None,
                     }),
                     NetworkPermission::Host(NetworkHostPermission {
                         host: "api.service.com".to_string(),
+                        ports: None,
                     }),
                 ]),
                 deny: Some(vec![NetworkPermission::Host(NetworkHostPermission {
                     host: "*.malicious.com".to_string(),
+                    ports: None,
                 })]),
             }),
-            // Test environment with valid keys (no wildcards allowed)
+            // Test environment with valid keys
             environment: Some(EnvironmentPermissions {
                 allow: Some(vec![
                     EnvironmentPermission {
@@ -669,6 +1250,10 @@ mod tests {
                         key: "MY_DEBUG_VAR".to_string(),
                     },
                 ]),
+                deny: Some(vec![EnvironmentPermission {
+                    key: "AWS_SECRET_ACCESS_KEY".to_string(),
+                }]),
+                ..Default::default()
             }),
             ..Default::default()
         };
@@ -778,6 +1363,71 @@ mod tests {
         assert!(invalid_memory.validate().is_err());
     }
 
+    #[test]
+    fn test_runtime_memory_limit_validation() {
+        let mut permissions = Permissions {
+            runtime: Some(Runtime {
+                docker: None,
+                hyperlight: None,
+                memory_limit_mb: Some(64),
+                execution_timeout_ms: None,
+                cache: None,
+            }),
+            ..Default::default()
+        };
+        assert!(permissions.validate().is_ok());
+
+        permissions.runtime.as_mut().unwrap().memory_limit_mb = Some(0);
+        assert!(permissions.validate().is_err());
+    }
+
+    #[test]
+    fn test_runtime_execution_timeout_validation() {
+        let mut permissions = Permissions {
+            runtime: Some(Runtime {
+                docker: None,
+                hyperlight: None,
+                memory_limit_mb: None,
+                execution_timeout_ms: Some(5000),
+                cache: None,
+            }),
+            ..Default::default()
+        };
+        assert!(permissions.validate().is_ok());
+
+        permissions.runtime.as_mut().unwrap().execution_timeout_ms = Some(0);
+        let err = permissions.validate().unwrap_err();
+        assert!(err.to_string().contains("execution_timeout_ms"), "{err}");
+    }
+
+    #[test]
+    fn test_runtime_cache_validation() {
+        let parse = |cache: &str| {
+            serde_yaml::from_str::<Permissions>(&format!("runtime:\n  cache: {cache}\n")).unwrap()
+        };
+
+        let permissions = parse("{ttl_ms: 1000}");
+        assert!(permissions.validate().is_ok());
+        assert_eq!(
+            permissions.runtime.unwrap().cache,
+            Some(CachePolicy {
+                enabled: true,
+                ttl_ms: 1000,
+                max_entries: 128
+            })
+        );
+
+        let err = parse("{ttl_ms: 0}").validate().unwrap_err();
+        assert!(err.to_string().contains("ttl_ms"), "{err}");
+        let err = parse("{ttl_ms: 1000, max_entries: 0}")
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("max_entries"), "{err}");
+
+        // Settings of a disabled cache don't matter
+        assert!(parse("{enabled: false, ttl_ms: 0}").validate().is_ok());
+    }
+
     #[test]
     fn test_resource_limit_values_caching() {
         // Test that parsing is cached for CPU
@@ -900,6 +1550,7 @@ mod tests {
         permissions.network = Some(PermissionList {
             allow: Some(vec![NetworkPermission::Host(NetworkHostPermission {
                 host: "example*.com".to_string(), // Invalid: * in middle
+                ports: None,
             })]),
             deny: None,
         });
@@ -908,8 +1559,112 @@ mod tests {
         permissions = Permissions::default();
         permissions.environment = Some(EnvironmentPermissions {
             allow: Some(vec![EnvironmentPermission {
-                key: "PATH_WITH_WILDCARD_*".to_string(),
+                key: "*".to_string(),
             }]),
+            ..Default::default()
+        });
+        assert!(permissions.validate().is_err());
+
+        permissions = Permissions::default();
+        permissions.storage = Some(PermissionList {
+            allow: None,
+            deny: Some(vec![StoragePermission {
+                uri: "fs://secrets/**/keys**".to_string(),
+                access: vec![AccessType::Read],
+            }]),
+        });
+        assert!(permissions.validate().is_err());
+    }
+
+    #[test]
+    fn test_port_range_parsing() {
+        assert_eq!("443".parse::<PortRange>().unwrap(), PortRange::single(443));
+        assert_eq!(
+            "8000-8080".parse::<PortRange>().unwrap(),
+            PortRange {
+                start: 8000,
+                end: 8080
+            }
+        );
+        assert!("8080-8000".parse::<PortRange>().is_err());
+        assert!("70000".parse::<PortRange>().is_err());
+        assert!("http".parse::<PortRange>().is_err());
+
+        let range: PortRange = "8000-8080".parse().unwrap();
+        assert!(range.contains(8000));
+        assert!(range.contains(8080));
+        assert!(!range.contains(8081));
+        assert_eq!(range.to_string(), "8000-8080");
+    }
+
+    #[test]
+    fn test_network_host_ports_yaml() {
+        let yaml = r#"
+allow:
+  - host: "api.example.com"
+    ports: [443, "8000-8080"]
+  - host: "cdn.example.com"
+"#;
+        let network: PermissionList<NetworkPermission> = serde_yaml::from_str(yaml).unwrap();
+        let allow = network.allow.as_ref().unwrap();
+        assert_eq!(
+            allow[0],
+            NetworkPermission::Host(NetworkHostPermission {
+                host: "api.example.com".to_string(),
+                ports: Some(vec![
+                    PortRange::single(443),
+                    PortRange {
+                        start: 8000,
+                        end: 8080
+                    }
+                ]),
+            })
+        );
+        assert_eq!(
+            allow[1],
+            NetworkPermission::Host(NetworkHostPermission {
+                host: "cdn.example.com".to_string(),
+                ports: None,
+            })
+        );
+
+        // Single ports are written back as numbers, and unrestricted hosts without `ports`
+        let serialized = serde_yaml::to_string(&network).unwrap();
+        assert!(serialized.contains("- 443"), "{serialized}");
+        assert!(serialized.contains("8000-8080"), "{serialized}");
+        assert_eq!(serialized.matches("ports").count(), 1, "{serialized}");
+
+        let invalid = "allow:\n  - host: \"api.example.com\"\n    ports: [\"9000-8000\"]\n";
+        assert!(serde_yaml::from_str::<PermissionList<NetworkPermission>>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_network_host_ports_validation() {
+        let host_with_ports = |ports| {
+            NetworkPermission::Host(NetworkHostPermission {
+                host: "api.example.com".to_string(),
+                ports,
+            })
+        };
+
+        let mut permissions = Permissions {
+            network: Some(PermissionList {
+                allow: Some(vec![host_with_ports(Some(vec![PortRange::single(443)]))]),
+                deny: None,
+            }),
+            ..Default::default()
+        };
+        assert!(permissions.validate().is_ok());
+
+        permissions.network = Some(PermissionList {
+            allow: Some(vec![host_with_ports(Some(vec![]))]),
+            deny: None,
+        });
+        assert!(permissions.validate().is_err());
+
+        permissions.network = Some(PermissionList {
+            allow: None,
+            deny: Some(vec![host_with_ports(Some(vec![PortRange::single(22)]))]),
         });
         assert!(permissions.validate().is_err());
     }
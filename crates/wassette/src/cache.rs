@@ -0,0 +1,490 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! An on-disk cache of compiled components, so that unchanged components don't have to be
+//! recompiled every time the plugin directory is loaded, and the precompiled components written
+//! next to their `.wasm` file by [`LifecycleManager::precompile_component`]
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{debug, warn};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::{LifecycleManager, WassetteError};
+
+/// The directory inside the plugin directory the compiled components are stored in, unless
+/// another one is set with [`crate::LifecycleManagerBuilder::with_cache_dir`]
+pub(crate) const CACHE_DIR: &str = ".cache";
+
+/// The file in the cache directory holding the key that authenticates its entries
+const KEY_FILE: &str = "key";
+
+/// The length of the key authenticating cache entries, and of the MAC every entry starts with
+const KEY_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The extension of precompiled components in the plugin directory
+pub(crate) const PRECOMPILED_EXTENSION: &str = "cwasm";
+
+impl LifecycleManager {
+    /// Writes the compiled code of a loaded component to `{id}.cwasm` next to its `.wasm` file in
+    /// the plugin directory. When the plugin directory is loaded, the precompiled component is used
+    /// instead of compiling the `.wasm` file, as long as it was compiled from the same contents.
+    /// Requires [`crate::LifecycleManagerBuilder::with_precompiled_components`].
+    ///
+    /// Precompiled components are authenticated with the key of the compile cache, so they only
+    /// load on the host that wrote them, and only in the same wasmtime version and configuration.
+    /// Otherwise the `.wasm` file is compiled as usual.
+    pub async fn precompile_component(&self, id: &str) -> Result<(), WassetteError> {
+        if !self.precompiled_components {
+            return Err(anyhow!("Precompiled components are not enabled").into());
+        }
+        let instance = self
+            .loaded_component(id)
+            .await
+            .ok_or_else(|| WassetteError::ComponentNotFound(id.to_string()))?;
+        let path = precompiled_path(&self.plugin_dir, id);
+        let cache_dir = self.cache_dir.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let key = cache_key(&cache_dir)?;
+            write_entry(&path, &instance.component, &key, &instance.content_hash)
+        })
+        .await
+        .map_err(anyhow::Error::from)??;
+        Ok(())
+    }
+}
+
+/// Returns the compiled component for the given bytes, from the cache in `cache_dir` if it has
+/// been compiled before.
+///
+/// Cache entries are authenticated with a key only readable by the current user, and bound to the
+/// content hash of `wasm_bytes`, so that a tampered or swapped entry is never deserialized. Any
+/// problem with the cache, like a corrupt or outdated entry, is logged and the component is
+/// compiled instead. Only compilation errors are returned.
+pub(crate) fn load_or_compile(
+    engine: &Engine,
+    cache_dir: &Path,
+    wasm_bytes: &[u8],
+) -> Result<Component> {
+    let content_hash = crate::reconcile::content_hash(wasm_bytes);
+    let cache_path = cache_path(engine, cache_dir, &content_hash);
+    let key = match cache_key(cache_dir) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            warn!(dir = %cache_dir.display(), error = %e, "Compile cache is unavailable");
+            None
+        }
+    };
+    let Some(key) = key else {
+        return Component::new(engine, wasm_bytes);
+    };
+
+    if cache_path.exists() {
+        match load_entry(engine, &key, &content_hash, &cache_path) {
+            Ok(component) => {
+                debug!(path = %cache_path.display(), "Loaded compiled component from cache");
+                return Ok(component);
+            }
+            Err(e) => {
+                warn!(path = %cache_path.display(), error = %e, "Ignoring unusable cache entry");
+            }
+        }
+    }
+
+    let component = Component::new(engine, wasm_bytes)?;
+    if let Err(e) = write_entry(&cache_path, &component, &key, &content_hash) {
+        warn!(path = %cache_path.display(), error = %e, "Failed to cache compiled component");
+    }
+    Ok(component)
+}
+
+/// Reads a cache entry or precompiled component and deserializes it, if its MAC matches the
+/// content hash and serialized component
+fn load_entry(
+    engine: &Engine,
+    key: &[u8; KEY_LEN],
+    content_hash: &str,
+    path: &Path,
+) -> Result<Component> {
+    let entry = std::fs::read(path)?;
+    if entry.len() < KEY_LEN {
+        bail!("Cache entry is truncated");
+    }
+    let (tag, serialized) = entry.split_at(KEY_LEN);
+    entry_mac(key, content_hash, serialized)
+        .verify_slice(tag)
+        .map_err(|_| anyhow!("Cache entry failed authentication"))?;
+    // SAFETY: the bytes were written by `write_entry` with the output of `Component::serialize`,
+    // which the MAC proves since only the current user can read the key. wasmtime also checks that they
+    // were compiled by the same version and configuration before using them.
+    unsafe { Component::deserialize(engine, serialized) }
+}
+
+/// Returns the precompiled component with the given ID if it was compiled from the contents with
+/// the given hash on this host. Problems loading it are logged, and `None` is returned so that the
+/// component is compiled instead.
+pub(crate) fn load_precompiled(
+    engine: &Engine,
+    plugin_dir: &Path,
+    cache_dir: &Path,
+    component_id: &str,
+    content_hash: &str,
+) -> Option<Component> {
+    let path = precompiled_path(plugin_dir, component_id);
+    if !path.exists() {
+        return None;
+    }
+    let loaded = cache_key(cache_dir).and_then(|key| load_entry(engine, &key, content_hash, &path));
+    match loaded {
+        Ok(component) => {
+            debug!(path = %path.display(), "Loaded precompiled component");
+            Some(component)
+        }
+        Err(e) => {
+            // Also the case for components precompiled before their `.wasm` file was updated
+            debug!(path = %path.display(), error = %e, "Ignoring precompiled component");
+            None
+        }
+    }
+}
+
+/// Returns the path of the precompiled component with the given ID
+pub(crate) fn precompiled_path(plugin_dir: &Path, component_id: &str) -> PathBuf {
+    plugin_dir.join(crate::component_file_name(
+        component_id,
+        PRECOMPILED_EXTENSION,
+    ))
+}
+
+/// Removes the cache entries compiled from the contents with the given hash
+pub(crate) fn remove(cache_dir: &Path, content_hash: &str) {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_entry = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(content_hash));
+        if is_entry {
+            if let Err(e) = std::fs::remove_file(entry.path()) {
+                warn!(path = %entry.path().display(), error = %e, "Failed to remove cache entry");
+            }
+        }
+    }
+}
+
+/// Returns the number of compiled components in the cache
+#[cfg(test)]
+pub(crate) fn entry_count(cache_dir: &Path) -> usize {
+    std::fs::read_dir(cache_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "cwasm"))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// The cache entry of a component depends on its contents as well as the wasmtime version and
+/// engine configuration, since compiled components can only be loaded by a matching engine
+fn cache_path(engine: &Engine, cache_dir: &Path, content_hash: &str) -> PathBuf {
+    cache_dir.join(format!(
+        "{content_hash}-{}.cwasm",
+        engine_fingerprint(engine)
+    ))
+}
+
+/// Returns a string that only matches between engines of the same wasmtime version and
+/// configuration
+pub(crate) fn engine_fingerprint(engine: &Engine) -> String {
+    let mut hasher = DefaultHasher::new();
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Returns the key authenticating the entries of the cache, generating it the first time. The
+/// cache directory and key are only accessible by the current user, and a key that others can
+/// read is refused.
+fn cache_key(cache_dir: &Path) -> Result<[u8; KEY_LEN]> {
+    create_private_dir(cache_dir)?;
+    let path = cache_dir.join(KEY_FILE);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    match options.open(&path) {
+        Ok(mut file) => {
+            let key: [u8; KEY_LEN] = rand::random();
+            file.write_all(&key)?;
+            return Ok(key);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {}", path.display())),
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if std::fs::metadata(&path)?.permissions().mode() & 0o077 != 0 {
+            bail!("{} must only be accessible to its owner", path.display());
+        }
+    }
+    std::fs::read(&path)?
+        .try_into()
+        .map_err(|_| anyhow!("{} is not a valid cache key", path.display()))
+}
+
+/// Creates a directory only accessible by the current user, if it doesn't exist yet
+fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))
+}
+
+/// Returns the MAC of a cache entry, which covers the content hash of the `.wasm` file it was
+/// compiled from as well as the serialized component
+fn entry_mac(key: &[u8; KEY_LEN], content_hash: &str, serialized: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(content_hash.as_bytes());
+    mac.update(serialized);
+    mac
+}
+
+/// Writes the compiled component prefixed with its MAC to a temporary file that is then moved into
+/// place, so that concurrent loads never see a partially written entry
+fn write_entry(
+    path: &Path,
+    component: &Component,
+    key: &[u8; KEY_LEN],
+    content_hash: &str,
+) -> Result<()> {
+    let serialized = component.serialize()?;
+    let tag = entry_mac(key, content_hash, &serialized)
+        .finalize()
+        .into_bytes();
+    let mut entry = Vec::with_capacity(tag.len() + serialized.len());
+    entry.extend_from_slice(&tag);
+    entry.extend_from_slice(&serialized);
+    write_atomically(path, &entry)
+}
+
+/// Writes `contents` to a temporary file in the directory of `path` that is then moved to `path`
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().context("Path has no parent directory")?;
+    std::fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+
+    #[test]
+    fn test_cache_round_trip() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let engine = Engine::default();
+        let hash = crate::reconcile::content_hash(ANSWER_WAT.as_bytes());
+
+        load_or_compile(&engine, tempdir.path(), ANSWER_WAT.as_bytes())?;
+        let cache_path = cache_path(&engine, tempdir.path(), &hash);
+        assert!(cache_path.exists());
+        assert_eq!(entry_count(tempdir.path()), 1);
+
+        let component = load_or_compile(&engine, tempdir.path(), ANSWER_WAT.as_bytes())?;
+        assert!(component.get_export_index(None, "answer").is_some());
+
+        // An entry is only ever used for the bytes it was compiled from
+        assert!(load_or_compile(&engine, tempdir.path(), b"not a component").is_err());
+
+        remove(tempdir.path(), &hash);
+        assert!(!cache_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_cache_entry_is_recompiled() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let engine = Engine::default();
+        let hash = crate::reconcile::content_hash(ANSWER_WAT.as_bytes());
+
+        let cache_path = cache_path(&engine, tempdir.path(), &hash);
+        std::fs::create_dir_all(cache_path.parent().unwrap())?;
+        std::fs::write(&cache_path, b"garbage")?;
+
+        let component = load_or_compile(&engine, tempdir.path(), ANSWER_WAT.as_bytes())?;
+        assert!(component.get_export_index(None, "answer").is_some());
+        // The corrupt entry was replaced
+        assert_ne!(std::fs::read(&cache_path)?, b"garbage");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unauthenticated_cache_entries_are_ignored() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let engine = Engine::default();
+        let hash = crate::reconcile::content_hash(ANSWER_WAT.as_bytes());
+        let other_hash = crate::reconcile::content_hash(OTHER_WAT.as_bytes());
+        load_or_compile(&engine, tempdir.path(), OTHER_WAT.as_bytes())?;
+        let cache_path = cache_path(&engine, tempdir.path(), &hash);
+        let other_path = super::cache_path(&engine, tempdir.path(), &other_hash);
+
+        // An entry of other contents moved in place of this one isn't used
+        std::fs::copy(&other_path, &cache_path)?;
+        let component = load_or_compile(&engine, tempdir.path(), ANSWER_WAT.as_bytes())?;
+        assert!(component.get_export_index(None, "answer").is_some());
+
+        // Neither is a serialized component written without the key
+        let mut forged = vec![0; KEY_LEN];
+        forged.extend(Component::new(&engine, OTHER_WAT)?.serialize()?);
+        std::fs::write(&cache_path, forged)?;
+        let component = load_or_compile(&engine, tempdir.path(), ANSWER_WAT.as_bytes())?;
+        assert!(component.get_export_index(None, "answer").is_some());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cache_is_private() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = tempfile::tempdir()?;
+        let cache_dir = tempdir.path().join(CACHE_DIR);
+        let engine = Engine::default();
+        load_or_compile(&engine, &cache_dir, ANSWER_WAT.as_bytes())?;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&cache_dir), 0o700);
+        assert_eq!(mode(&cache_dir.join(KEY_FILE)), 0o600);
+
+        // A key others can read isn't trusted, and components are compiled without the cache
+        std::fs::set_permissions(
+            cache_dir.join(KEY_FILE),
+            std::fs::Permissions::from_mode(0o644),
+        )?;
+        assert!(cache_key(&cache_dir).is_err());
+        let component = load_or_compile(&engine, &cache_dir, ANSWER_WAT.as_bytes())?;
+        assert!(component.get_export_index(None, "answer").is_some());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_cache_entries_follow_loaded_components() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        let cache_dir = tempdir.path().join(CACHE_DIR);
+
+        // Two IDs loaded from the same contents share an entry
+        manager
+            .load_component_bytes("first", ANSWER_WAT.as_bytes())
+            .await?;
+        manager
+            .load_component_bytes("second", ANSWER_WAT.as_bytes())
+            .await?;
+        assert_eq!(entry_count(&cache_dir), 1);
+        manager.unload_component("first").await?;
+        assert_eq!(entry_count(&cache_dir), 1);
+
+        // Replacing the component drops the entry of its old contents
+        manager
+            .load_component_bytes("second", OTHER_WAT.as_bytes())
+            .await?;
+        let other_hash = crate::reconcile::content_hash(OTHER_WAT.as_bytes());
+        assert_eq!(entry_count(&cache_dir), 1);
+        assert!(cache_path(&manager.engine, &cache_dir, &other_hash).exists());
+
+        manager.unload_component("second").await?;
+        assert_eq!(entry_count(&cache_dir), 0);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_precompiled_component_is_used_instead_of_compiling() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let cache_dir = tempdir.path().join(CACHE_DIR);
+        let hash = crate::reconcile::content_hash(ANSWER_WAT.as_bytes());
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_precompiled_components(true)
+            .build()
+            .await?;
+        manager
+            .load_component_bytes("answer", ANSWER_WAT.as_bytes())
+            .await?;
+        manager.precompile_component("answer").await?;
+        assert!(tempdir.path().join("answer.cwasm").exists());
+        assert!(matches!(
+            manager.precompile_component("missing").await,
+            Err(WassetteError::ComponentNotFound(_))
+        ));
+        drop(manager);
+
+        // Without the compile cache entry, the precompiled component is loaded instead of
+        // compiling, which would have added the entry back
+        remove(&cache_dir, &hash);
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_precompiled_components(true)
+            .build()
+            .await?;
+        assert_eq!(manager.list_components().await, vec!["answer"]);
+        assert!(manager.load_errors().is_empty());
+        assert_eq!(entry_count(&cache_dir), 0);
+        drop(manager);
+
+        // Precompiled components are ignored unless enabled
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        assert_eq!(entry_count(&cache_dir), 1);
+        assert!(manager.precompile_component("answer").await.is_err());
+        drop(manager);
+
+        // A precompiled component is never loaded without its `.wasm` file
+        tokio::fs::remove_file(tempdir.path().join("answer.wasm")).await?;
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_precompiled_components(true)
+            .build()
+            .await?;
+        assert!(manager.list_components().await.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_outdated_precompiled_component_is_ignored() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let cache_dir = tempdir.path().join(CACHE_DIR);
+        let engine = Engine::default();
+        let hash = crate::reconcile::content_hash(ANSWER_WAT.as_bytes());
+        let component = Component::new(&engine, ANSWER_WAT)?;
+        let path = precompiled_path(tempdir.path(), "answer");
+        write_entry(&path, &component, &cache_key(&cache_dir)?, &hash)?;
+
+        let load = |id, hash| load_precompiled(&engine, tempdir.path(), &cache_dir, id, hash);
+        assert!(load("answer", &hash).is_some());
+        let other_hash = crate::reconcile::content_hash(b"a newer component");
+        assert!(load("answer", &other_hash).is_none());
+        assert!(load("missing", &hash).is_none());
+
+        // A component serialized without the key isn't loaded
+        write_atomically(&path, &component.serialize()?)?;
+        assert!(load("answer", &hash).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Unloading components once the calls running them have finished
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use tokio::sync::Notify;
+use tracing::{info, instrument, warn};
+
+use crate::{LifecycleManager, WassetteError};
+
+/// Counts the calls of a component, so that it can be unloaded once they have finished
+#[derive(Debug, Default)]
+pub(crate) struct CallTracker {
+    /// The number of calls that are running or waiting for a permit
+    in_flight: AtomicUsize,
+    /// Set while the component is being unloaded, new calls are refused
+    draining: AtomicBool,
+    /// Notified whenever a call finishes
+    finished: Notify,
+}
+
+/// Counts a call as in flight until dropped
+pub(crate) struct InFlightCall(Arc<CallTracker>);
+
+impl Drop for InFlightCall {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.0.finished.notify_waiters();
+    }
+}
+
+impl CallTracker {
+    /// Counts a new call of the component, or returns `None` if the component is being unloaded
+    pub(crate) fn start_call(self: &Arc<Self>) -> Option<InFlightCall> {
+        // Counted before checking the flag, so that draining never misses a call that got through
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let call = InFlightCall(self.clone());
+        (!self.draining.load(Ordering::SeqCst)).then_some(call)
+    }
+
+    /// Whether the component is being unloaded and refuses new calls
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// The number of calls that are running or waiting for a permit
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits for every call to finish
+    async fn drained(&self) {
+        loop {
+            let finished = self.finished.notified();
+            if self.in_flight() == 0 {
+                return;
+            }
+            finished.await;
+        }
+    }
+}
+
+impl LifecycleManager {
+    /// Unloads a component like [`LifecycleManager::unload_component`], but only once the calls
+    /// running it have finished. New calls are refused with [`WassetteError::ComponentDraining`]
+    /// while they do.
+    ///
+    /// If the calls are still running after `drain_timeout`, the component is left loaded and
+    /// accepts calls again, and an error is returned. So does a component that fails to unload.
+    #[instrument(skip(self))]
+    pub async fn unload_component_graceful(
+        &self,
+        id: &str,
+        drain_timeout: Duration,
+    ) -> Result<(), WassetteError> {
+        let Some(slot) = self.components.read().await.get(id).cloned() else {
+            return self.unload_component(id).await;
+        };
+        slot.tracker.draining.store(true, Ordering::SeqCst);
+        info!(
+            component_id = %id,
+            in_flight = slot.tracker.in_flight(),
+            "Draining component before unloading it"
+        );
+
+        if tokio::time::timeout(drain_timeout, slot.tracker.drained())
+            .await
+            .is_err()
+        {
+            slot.tracker.draining.store(false, Ordering::SeqCst);
+            let in_flight = slot.tracker.in_flight();
+            warn!(component_id = %id, in_flight, "Component calls didn't finish, not unloading it");
+            return Err(anyhow!(
+                "Component {id} still has {in_flight} calls running after {}s, it was not unloaded",
+                drain_timeout.as_secs_f64()
+            )
+            .into());
+        }
+        let result = self.unload_component(id).await;
+        if result.is_err() {
+            // The component is still loaded, so it has to accept calls again
+            slot.tracker.draining.store(false, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+
+    fn spawn_spin(
+        manager: &LifecycleManager,
+    ) -> tokio::task::JoinHandle<Result<String, WassetteError>> {
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            manager
+                .execute_component_call("spin", "spin", r#"{"n": 200000000}"#)
+                .await
+        })
+    }
+
+    /// Waits until `count` calls of the spin component are in flight
+    async fn wait_for_calls(manager: &LifecycleManager, count: usize) {
+        let slot = manager.components.read().await["spin"].clone();
+        while slot.tracker.in_flight() < count {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 8))]
+    async fn test_unload_waits_for_in_flight_calls() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        load_wat_component(&manager, "spin", SPIN_WAT).await?;
+
+        let calls: Vec<_> = (0..3).map(|_| spawn_spin(&manager)).collect();
+        wait_for_calls(&manager, 3).await;
+
+        let unload = tokio::spawn({
+            let manager = manager.clone();
+            async move {
+                manager
+                    .unload_component_graceful("spin", Duration::from_secs(60))
+                    .await
+            }
+        });
+        let slot = manager.components.read().await["spin"].clone();
+        while !slot.tracker.draining.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+
+        // New calls are refused while draining
+        assert!(matches!(
+            manager
+                .execute_component_call("spin", "spin", r#"{"n": 1}"#)
+                .await,
+            Err(WassetteError::ComponentDraining(_))
+        ));
+
+        // The calls in flight finish before the component goes away
+        for call in calls {
+            let result = call.await??;
+            assert!(result.contains("200000000"), "{result}");
+        }
+        unload.await??;
+        assert!(manager.list_components().await.is_empty());
+        assert!(!tempdir.path().join("plugins/spin.wasm").exists());
+
+        Ok(())
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 8))]
+    async fn test_unload_drain_timeout_keeps_component() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        load_wat_component(&manager, "spin", SPIN_WAT).await?;
+
+        let call = spawn_spin(&manager);
+        wait_for_calls(&manager, 1).await;
+
+        assert!(manager
+            .unload_component_graceful("spin", Duration::from_millis(1))
+            .await
+            .is_err());
+        call.await??;
+
+        // The component is still loaded and accepts calls again
+        assert_eq!(manager.list_components().await, vec!["spin".to_string()]);
+        manager
+            .execute_component_call("spin", "spin", r#"{"n": 1}"#)
+            .await?;
+
+        // Without calls in flight, it is unloaded right away
+        manager
+            .unload_component_graceful("spin", Duration::from_millis(1))
+            .await?;
+        assert!(manager.list_components().await.is_empty());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_failed_unload_accepts_calls_again() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        load_wat_component(&manager, "spin", SPIN_WAT).await?;
+
+        // A directory in place of the component file can't be removed, so unloading fails
+        let component_path = tempdir.path().join("plugins/spin.wasm");
+        tokio::fs::remove_file(&component_path).await?;
+        tokio::fs::create_dir(&component_path).await?;
+        assert!(manager
+            .unload_component_graceful("spin", Duration::from_secs(1))
+            .await
+            .is_err());
+
+        assert_eq!(manager.list_components().await, vec!["spin".to_string()]);
+        manager
+            .execute_component_call("spin", "spin", r#"{"n": 1}"#)
+            .await?;
+
+        Ok(())
+    }
+}
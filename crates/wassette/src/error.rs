@@ -0,0 +1,84 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The errors returned by the public methods of [`crate::LifecycleManager`]
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::PolicyViolation;
+
+/// An error returned by [`crate::LifecycleManager`], which callers can match on instead of
+/// inspecting error messages. The variants wrapping an [`anyhow::Error`] display it unchanged, and
+/// `{:#}` shows its whole chain of causes.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum WassetteError {
+    /// No component with this ID is loaded
+    #[error("Component not found: {0}")]
+    ComponentNotFound(String),
+    /// The component is being unloaded with
+    /// [`crate::LifecycleManager::unload_component_graceful`] and doesn't accept new calls
+    #[error("Component {0} is being unloaded and doesn't accept new calls")]
+    ComponentDraining(String),
+    /// No loaded component exports a tool with this name
+    #[error("Tool not found: {0}")]
+    ToolNotFound(String),
+    /// Several loaded components export a tool with this name
+    #[error("Multiple components found for tool '{tool}': {}", .components.join(", "))]
+    ToolAmbiguous {
+        /// The name of the tool
+        tool: String,
+        /// The IDs of the components exporting the tool
+        components: Vec<String>,
+    },
+    /// A component ID can't be used as a file name in the plugin directory
+    #[error(
+        "Invalid component ID '{0}': only ASCII letters, digits, '-', '_' and '.' are allowed"
+    )]
+    InvalidComponentId(String),
+    /// The downloaded component doesn't match the digest pinned in its URI
+    #[error(
+        "Digest mismatch for component {uri}: expected sha256:{expected}, got sha256:{actual}"
+    )]
+    DigestMismatch {
+        /// The URI the component was loaded from
+        uri: String,
+        /// The hex-encoded digest pinned in the URI
+        expected: String,
+        /// The hex-encoded digest of the downloaded component
+        actual: String,
+    },
+    /// A component or policy couldn't be downloaded or read
+    #[error(transparent)]
+    DownloadFailed(anyhow::Error),
+    /// A component isn't a valid WebAssembly component or couldn't be compiled or linked
+    #[error(transparent)]
+    CompileFailed(anyhow::Error),
+    /// A component imports interfaces that the import filter doesn't allow
+    #[error(transparent)]
+    ImportDenied(anyhow::Error),
+    /// A policy couldn't be parsed, or can't be applied, e.g. because it expired
+    #[error(transparent)]
+    PolicyInvalid(anyhow::Error),
+    /// The arguments of a call don't match the parameters of the function
+    #[error(transparent)]
+    InvalidArguments(anyhow::Error),
+    /// A call went over a limit set by the policy of the component
+    #[error(transparent)]
+    PolicyViolation(PolicyViolation),
+    /// A call ran for longer than the timeout of its component's policy or of the manager
+    #[error("Component call timed out after {}s", .0.as_secs_f64())]
+    Timeout(Duration),
+    /// A call trapped or ran out of fuel. The [`wasmtime::Trap`] can be found in the chain of the
+    /// error, except for fuel exhaustion.
+    #[error(transparent)]
+    CallTrap(anyhow::Error),
+    /// A host function called by the component failed, as opposed to the component trapping
+    #[error(transparent)]
+    HostFailed(anyhow::Error),
+    /// Any other error, e.g. a failure to write to the plugin directory
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
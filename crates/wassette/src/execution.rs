@@ -0,0 +1,111 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Controls where component calls are executed.
+
+use std::future::Future;
+use std::num::NonZeroUsize;
+
+use anyhow::{Context, Result};
+
+/// Selects the runtime used to drive component calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionPool {
+    /// Run component calls on the caller's tokio runtime. This is the default.
+    #[default]
+    Shared,
+    /// Run component calls on a dedicated, bounded tokio runtime so CPU-heavy components cannot
+    /// starve the tasks (such as the MCP event loop) running on the caller's runtime.
+    Dedicated,
+}
+
+/// A dedicated multi-threaded runtime that component calls are spawned onto.
+///
+/// The runtime is shut down in the background on drop, because dropping a runtime from within
+/// an async context (which is where the last [`crate::LifecycleManager`] clone usually lives)
+/// would otherwise panic.
+pub(crate) struct DedicatedRuntime {
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl DedicatedRuntime {
+    pub(crate) fn new() -> Result<Self> {
+        let worker_threads = std::thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(worker_threads)
+            .max_blocking_threads(worker_threads)
+            .thread_name("wassette-exec")
+            .enable_all()
+            .build()
+            .context("Failed to create dedicated execution runtime")?;
+        Ok(Self {
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Runs the given future to completion on the dedicated runtime. Fails only if the task
+    /// panicked or was cancelled.
+    ///
+    /// The task is aborted when the returned future is dropped, e.g. because the call timed out,
+    /// so that it doesn't keep running on the runtime after nobody waits for it.
+    pub(crate) async fn run<F, T>(&self, fut: F) -> Result<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let runtime = self
+            .runtime
+            .as_ref()
+            .expect("Dedicated runtime is only taken on drop");
+        let mut task = AbortOnDrop(runtime.spawn(fut));
+        (&mut task.0)
+            .await
+            .context("Component execution task failed")
+    }
+}
+
+/// Aborts the task behind a [`tokio::task::JoinHandle`] when dropped, which only detaches it
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl Drop for DedicatedRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dropping_the_call_aborts_the_task() -> Result<()> {
+        let runtime = DedicatedRuntime::new()?;
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let call = runtime.run(async move {
+            // Only dropped, never sent on, so the receiver sees the task going away
+            let _tx = tx;
+            std::future::pending::<()>().await;
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(50), call)
+            .await
+            .is_err());
+
+        tokio::time::timeout(Duration::from_secs(5), rx)
+            .await
+            .context("the task kept running after the call was dropped")?
+            .expect_err("the task never sends");
+        Ok(())
+    }
+}
@@ -1,45 +1,117 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use ipnet::IpNet;
+use policy::PortRange;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
 use tracing::{debug, warn};
 use url::Url;
 use wasmtime::component::Resource;
 use wasmtime_wasi::p2::{IoView, WasiView};
 use wasmtime_wasi_http::bindings::http::types;
-use wasmtime_wasi_http::types::{HostFutureIncomingResponse, OutgoingRequestConfig};
-use wasmtime_wasi_http::{HttpResult, WasiHttpView};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::io::TokioIo;
+use wasmtime_wasi_http::types::{
+    HostFutureIncomingResponse, IncomingResponse, OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::{hyper_request_error, HttpResult, WasiHttpView};
 
+/// A host from the network section of a policy, optionally restricted to a scheme and to a set of
+/// ports. The host can be a wildcard like `*.example.com`, which matches any subdomain of
+/// `example.com`, or `*`, which matches every host. A port suffix like `api.example.com:8443`
+/// restricts the host to that port.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct AllowedHost {
+struct HostPattern {
     scheme: Option<String>,
     host: String,
+    ports: Option<Vec<PortRange>>,
 }
 
-impl AllowedHost {
+/// Returns the port written in `host_str`, which was parsed as `url`. [`Url`] drops ports that are
+/// the default of the scheme, so those are looked for in the authority of `host_str`.
+fn explicit_port(host_str: &str, url: &Url) -> Option<u16> {
+    let authority = host_str
+        .split_once("://")
+        .map_or(host_str, |(_, rest)| rest);
+    let authority = authority.split('/').next().unwrap_or(authority);
+    url.port().or_else(|| {
+        url.port_or_known_default()
+            .filter(|port| authority.ends_with(&format!(":{port}")))
+    })
+}
+
+impl HostPattern {
     fn from_str(host_str: &str) -> Result<Self> {
-        if let Ok(url) = Url::parse(host_str) {
-            Ok(AllowedHost {
+        // `api.example.com:8443` parses as a URL with the scheme `api.example.com` and no host
+        if let Some(url) = Url::parse(host_str).ok().filter(Url::has_host) {
+            Ok(HostPattern {
                 scheme: Some(url.scheme().to_string()),
                 host: url.host_str().unwrap_or("").to_string(),
+                ports: explicit_port(host_str, &url).map(|port| vec![PortRange::single(port)]),
             })
         } else if let Ok(url) = Url::parse(&format!("http://{host_str}")) {
-            Ok(AllowedHost {
+            Ok(HostPattern {
                 scheme: None,
                 host: url.host_str().unwrap_or("").to_string(),
+                ports: explicit_port(host_str, &url).map(|port| vec![PortRange::single(port)]),
             })
         } else {
             Err(anyhow::anyhow!("Invalid host format: {}", host_str))
         }
     }
 
-    fn matches(&self, request_host: &str, request_scheme: Option<&str>) -> bool {
-        if self.host != request_host {
+    fn parse_all(hosts: HashSet<String>) -> Result<HashSet<Self>> {
+        let mut parsed_hosts = HashSet::new();
+        for host_str in hosts {
+            match HostPattern::from_str(&host_str) {
+                Ok(parsed_host) => {
+                    parsed_hosts.insert(parsed_host);
+                }
+                Err(e) => {
+                    warn!("Failed to parse host '{}': {}", host_str, e);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(parsed_hosts)
+    }
+
+    fn matches(
+        &self,
+        request_host: &str,
+        request_scheme: Option<&str>,
+        request_port: Option<u16>,
+    ) -> bool {
+        let host_matches = if self.host == "*" {
+            true
+        } else if let Some(domain) = self.host.strip_prefix("*.") {
+            request_host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.'))
+        } else {
+            self.host == request_host
+        };
+        if !host_matches {
             return false;
         }
 
+        if let Some(ports) = &self.ports {
+            let port_allowed =
+                request_port.is_some_and(|port| ports.iter().any(|range| range.contains(port)));
+            if !port_allowed {
+                return false;
+            }
+        }
+
         match (&self.scheme, request_scheme) {
             (Some(allowed_scheme), Some(req_scheme)) => allowed_scheme == req_scheme,
             _ => true,
@@ -47,6 +119,210 @@ impl AllowedHost {
     }
 }
 
+/// Parses address ranges from the network section of a policy, e.g. `10.0.0.0/8` or `fd00::/8`
+fn parse_cidrs(cidrs: HashSet<String>) -> Result<Vec<IpNet>> {
+    cidrs
+        .iter()
+        .map(|cidr| {
+            cidr.parse::<IpNet>()
+                .with_context(|| format!("Invalid CIDR '{cidr}'"))
+        })
+        .collect()
+}
+
+/// Returns the IP address of a host that is an IP literal. IPv6 hosts of URIs are in brackets.
+fn ip_literal(host: &str) -> Option<IpAddr> {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse()
+        .ok()
+}
+
+/// Checks the addresses a hostname resolved to against the address ranges of a policy. Addresses
+/// in a denied range are refused, and if `require_in_range` is set, so are addresses outside of
+/// every allowed range.
+fn check_resolved_addresses(
+    addresses: &[IpAddr],
+    allowed_cidrs: &[IpNet],
+    denied_cidrs: &[IpNet],
+    require_in_range: bool,
+) -> bool {
+    !addresses.is_empty()
+        && addresses.iter().all(|address| {
+            !denied_cidrs.iter().any(|net| net.contains(address))
+                && (!require_in_range || allowed_cidrs.iter().any(|net| net.contains(address)))
+        })
+}
+
+/// Sends a request over a connection to one of `addresses`, which the hostname of the request was
+/// resolved to and checked against the address ranges of a policy. Unlike
+/// [`wasmtime_wasi_http::types::default_send_request_handler`], the hostname isn't resolved again, so a host whose DNS
+/// answer changes in between can't get the request sent to an address that wasn't checked. The
+/// hostname is still used to verify the certificate of TLS connections.
+async fn send_to_addresses(
+    mut request: hyper::Request<HyperOutgoingBody>,
+    OutgoingRequestConfig {
+        use_tls,
+        connect_timeout,
+        first_byte_timeout,
+        between_bytes_timeout,
+    }: OutgoingRequestConfig,
+    addresses: &[SocketAddr],
+) -> Result<IncomingResponse, types::ErrorCode> {
+    let tcp_stream = timeout(connect_timeout, TcpStream::connect(addresses))
+        .await
+        .map_err(|_| types::ErrorCode::ConnectionTimeout)?
+        .map_err(|e| {
+            warn!(?addresses, error = %e, "Failed to connect to the resolved addresses");
+            types::ErrorCode::ConnectionRefused
+        })?;
+
+    let (mut sender, worker) = if use_tls {
+        let host = request.uri().host().unwrap_or_default();
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let server_name = rustls::pki_types::ServerName::try_from(host.to_string())
+            .map_err(|_| types::ErrorCode::HttpRequestUriInvalid)?;
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.into(),
+            })
+            .with_no_client_auth();
+        let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(server_name, tcp_stream)
+            .await
+            .map_err(|e| {
+                warn!(error = %e, "TLS handshake of HTTP request failed");
+                types::ErrorCode::TlsProtocolError
+            })?;
+        handshake(TokioIo::new(stream), connect_timeout).await?
+    } else {
+        handshake(TokioIo::new(tcp_stream), connect_timeout).await?
+    };
+
+    // Only requests to proxies carry the scheme and authority
+    *request.uri_mut() = hyper::Uri::builder()
+        .path_and_query(
+            request
+                .uri()
+                .path_and_query()
+                .map(|path_and_query| path_and_query.as_str())
+                .unwrap_or("/"),
+        )
+        .build()
+        .expect("the path comes from a valid URI");
+
+    let resp = timeout(first_byte_timeout, sender.send_request(request))
+        .await
+        .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+        .map_err(hyper_request_error)?
+        .map(|body| body.map_err(hyper_request_error).boxed());
+
+    Ok(IncomingResponse {
+        resp,
+        worker: Some(worker),
+        between_bytes_timeout,
+    })
+}
+
+/// Performs the HTTP/1 handshake on `stream`, spawning the task driving the connection
+async fn handshake<S>(
+    stream: TokioIo<S>,
+    connect_timeout: Duration,
+) -> Result<
+    (
+        hyper::client::conn::http1::SendRequest<HyperOutgoingBody>,
+        wasmtime_wasi::runtime::AbortOnDropJoinHandle<()>,
+    ),
+    types::ErrorCode,
+>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) = timeout(
+        connect_timeout,
+        hyper::client::conn::http1::handshake(stream),
+    )
+    .await
+    .map_err(|_| types::ErrorCode::ConnectionTimeout)?
+    .map_err(hyper_request_error)?;
+    let worker = wasmtime_wasi::runtime::spawn(async move {
+        if let Err(e) = connection.await {
+            warn!(error = %e, "HTTP connection failed");
+        }
+    });
+    Ok((sender, worker))
+}
+
+/// A canned response returned by an [`HttpInterceptor`] in place of a real network response
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    /// The HTTP status code
+    pub status: u16,
+    /// The response headers
+    pub headers: Vec<(String, String)>,
+    /// The response body
+    pub body: Bytes,
+}
+
+impl MockResponse {
+    /// Creates a response with the given status code and body and no headers
+    pub fn new(status: u16, body: impl Into<Bytes>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Adds a header to the response
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    fn into_incoming_response(
+        self,
+        between_bytes_timeout: Duration,
+    ) -> Result<IncomingResponse, types::ErrorCode> {
+        let mut builder = hyper::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let body = Full::new(self.body).map_err(|never| match never {}).boxed();
+        let resp = builder
+            .body(body)
+            .map_err(|e| types::ErrorCode::InternalError(Some(e.to_string())))?;
+
+        Ok(IncomingResponse {
+            resp,
+            worker: None,
+            between_bytes_timeout,
+        })
+    }
+}
+
+/// Intercepts outgoing HTTP requests made by components, so that component behavior can be tested
+/// deterministically without network access.
+///
+/// Requests are only handed to the interceptor after they have passed the network policy. When an
+/// interceptor is set, requests it doesn't answer are refused rather than sent to the network.
+pub trait HttpInterceptor: Send + Sync {
+    /// Returns the canned response for the given request, or `None` if the request should be
+    /// refused
+    fn intercept(&self, request: &hyper::Request<HyperOutgoingBody>) -> Option<MockResponse>;
+}
+
+/// Answers requests whose full URI (e.g. `https://example.com/`) is a key in the map
+impl HttpInterceptor for HashMap<String, MockResponse> {
+    fn intercept(&self, request: &hyper::Request<HyperOutgoingBody>) -> Option<MockResponse> {
+        self.get(&request.uri().to_string()).cloned()
+    }
+}
+
 /// WassetteWasiState is a wrapper around a WASI state that enforces network policies by filtering
 /// outgoing HTTP requests based on a list of allowed hosts from the component's policy document.
 pub struct WassetteWasiState<T> {
@@ -54,32 +330,93 @@ pub struct WassetteWasiState<T> {
     pub inner: T,
 
     /// Set of allowed hosts for network requests (extracted from policy document)
-    allowed_hosts: HashSet<AllowedHost>,
+    allowed_hosts: HashSet<HostPattern>,
+
+    /// Set of denied hosts for network requests, which take precedence over the allowed hosts
+    denied_hosts: HashSet<HostPattern>,
+
+    /// Address ranges that requests can be sent to
+    allowed_cidrs: Vec<IpNet>,
+
+    /// Address ranges that requests can't be sent to, which take precedence over everything that
+    /// is allowed
+    denied_cidrs: Vec<IpNet>,
+
+    /// Whether hostnames allowed by a host rule must also resolve to an allowed address range
+    resolved_address_checks: bool,
+
+    /// Optional interceptor that answers outgoing requests instead of the network
+    http_mock: Option<Arc<dyn HttpInterceptor>>,
 }
 
 impl<T> WassetteWasiState<T> {
     /// Create a new WassetteWasiState with the given allowed hosts
     pub fn new(inner: T, allowed_hosts: HashSet<String>) -> Result<Self> {
-        let mut parsed_hosts = HashSet::new();
-
-        for host_str in allowed_hosts {
-            match AllowedHost::from_str(&host_str) {
-                Ok(parsed_host) => {
-                    parsed_hosts.insert(parsed_host);
-                }
-                Err(e) => {
-                    warn!("Failed to parse allowed host '{}': {}", host_str, e);
-                    return Err(e);
-                }
-            }
-        }
-
         Ok(Self {
             inner,
-            allowed_hosts: parsed_hosts,
+            allowed_hosts: HostPattern::parse_all(allowed_hosts)?,
+            denied_hosts: HashSet::new(),
+            allowed_cidrs: Vec::new(),
+            denied_cidrs: Vec::new(),
+            resolved_address_checks: false,
+            http_mock: None,
         })
     }
 
+    /// Blocks requests to the given hosts, even if they are also allowed
+    pub fn with_denied_hosts(mut self, denied_hosts: HashSet<String>) -> Result<Self> {
+        self.denied_hosts = HostPattern::parse_all(denied_hosts)?;
+        Ok(self)
+    }
+
+    /// Restricts the given allowed hosts to the given ports. The keys must be hosts that were passed
+    /// to [`WassetteWasiState::new`]
+    pub fn with_allowed_ports(
+        mut self,
+        allowed_ports: HashMap<String, Vec<PortRange>>,
+    ) -> Result<Self> {
+        let mut restricted = HashMap::new();
+        for (host_str, ports) in allowed_ports {
+            restricted.insert(HostPattern::from_str(&host_str)?, ports);
+        }
+        self.allowed_hosts = self
+            .allowed_hosts
+            .into_iter()
+            .map(|mut pattern| {
+                if let Some(ports) = restricted.get(&pattern) {
+                    pattern.ports = Some(ports.clone());
+                }
+                pattern
+            })
+            .collect();
+        Ok(self)
+    }
+
+    /// Allows requests to the addresses in the `allowed` ranges and blocks requests to the
+    /// addresses in the `denied` ranges, given in CIDR notation. Hosts that are IP literals are
+    /// checked directly, and hostnames that aren't allowed by a host rule are resolved and allowed
+    /// if all of their addresses are in an allowed range. The addresses of every hostname are
+    /// checked against the denied ranges.
+    pub fn with_cidrs(mut self, allowed: HashSet<String>, denied: HashSet<String>) -> Result<Self> {
+        self.allowed_cidrs = parse_cidrs(allowed)?;
+        self.denied_cidrs = parse_cidrs(denied)?;
+        Ok(self)
+    }
+
+    /// Sets whether hostnames allowed by a host rule must also resolve to addresses in an allowed
+    /// range, when there are any. Off by default.
+    pub fn with_resolved_address_checks(mut self, enabled: bool) -> Self {
+        self.resolved_address_checks = enabled;
+        self
+    }
+
+    /// Routes outgoing HTTP requests that are allowed by the network policy through the given
+    /// [`HttpInterceptor`] instead of the network
+    pub fn with_http_mock(mut self, interceptor: Arc<dyn HttpInterceptor>) -> Self {
+        self.http_mock = Some(interceptor);
+        self
+    }
+
     /// Check if a host is allowed by the policy
     fn is_host_allowed(&self, uri: &hyper::Uri) -> bool {
         let request_host = if let Some(host) = uri.host() {
@@ -89,15 +426,105 @@ impl<T> WassetteWasiState<T> {
         };
 
         let request_scheme = uri.scheme().map(|s| s.as_str());
+        let request_port = uri.port_u16().or(match request_scheme {
+            Some("https") => Some(443),
+            Some("http") => Some(80),
+            _ => None,
+        });
+
+        let request_ip = ip_literal(&request_host);
+        if request_ip.is_some_and(|ip| self.denied_cidrs.iter().any(|net| net.contains(&ip))) {
+            return false;
+        }
 
         let req = request_host.to_ascii_lowercase();
+        if self
+            .denied_hosts
+            .iter()
+            .any(|denied_host| denied_host.matches(&req, request_scheme, request_port))
+        {
+            return false;
+        }
         for allowed_host in &self.allowed_hosts {
-            if allowed_host.matches(&req, request_scheme) {
+            if allowed_host.matches(&req, request_scheme, request_port) {
                 return true;
             }
         }
 
-        false
+        request_ip.is_some_and(|ip| self.allowed_cidrs.iter().any(|net| net.contains(&ip)))
+    }
+
+    /// Returns whether the addresses of the hostname of a request have to be checked against the
+    /// address ranges before it is sent, and if so, whether they must be in an allowed range
+    fn resolution_required(&self, uri: &hyper::Uri, host_allowed: bool) -> Option<bool> {
+        if uri.host().and_then(ip_literal).is_some() {
+            return None;
+        }
+        let require_in_range =
+            !self.allowed_cidrs.is_empty() && (!host_allowed || self.resolved_address_checks);
+        let check_denied = host_allowed && !self.denied_cidrs.is_empty();
+        (require_in_range || check_denied).then_some(require_in_range)
+    }
+
+    /// Sends a request once the addresses its hostname resolves to have been checked against the
+    /// address ranges. The request is sent to the checked addresses, or answered by the
+    /// [`HttpInterceptor`], in a task since the lookup can't block the caller.
+    fn send_after_resolving(
+        &self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+        require_in_range: bool,
+    ) -> HostFutureIncomingResponse {
+        let allowed_cidrs = self.allowed_cidrs.clone();
+        let denied_cidrs = self.denied_cidrs.clone();
+        let http_mock = self.http_mock.clone();
+        HostFutureIncomingResponse::pending(wasmtime_wasi::runtime::spawn(async move {
+            let uri = request.uri().clone();
+            let host = uri.host().unwrap_or_default();
+            let port = uri
+                .port_u16()
+                .unwrap_or(if config.use_tls { 443 } else { 80 });
+            let socket_addresses: Vec<SocketAddr> =
+                match tokio::net::lookup_host((host, port)).await {
+                    Ok(addresses) => addresses.collect(),
+                    Err(e) => {
+                        warn!(uri = %uri, error = %e, "Failed to resolve host of HTTP request");
+                        return Ok(Err(types::ErrorCode::DnsError(types::DnsErrorPayload {
+                            rcode: Some(e.to_string()),
+                            info_code: None,
+                        })));
+                    }
+                };
+            let addresses: Vec<IpAddr> = socket_addresses
+                .iter()
+                .map(|address| address.ip())
+                .collect();
+            if !check_resolved_addresses(
+                &addresses,
+                &allowed_cidrs,
+                &denied_cidrs,
+                require_in_range,
+            ) {
+                warn!(
+                    uri = %uri,
+                    ?addresses,
+                    ?allowed_cidrs,
+                    ?denied_cidrs,
+                    "HTTP request blocked by network policy"
+                );
+                return Ok(Err(types::ErrorCode::HttpRequestDenied));
+            }
+            debug!(uri = %uri, ?addresses, "HTTP request allowed by network policy");
+
+            if let Some(mock) = http_mock {
+                let Some(response) = mock.intercept(&request) else {
+                    warn!(uri = %uri, "HTTP request not answered by mock, refusing request");
+                    return Ok(Err(types::ErrorCode::ConnectionRefused));
+                };
+                return Ok(response.into_incoming_response(config.between_bytes_timeout));
+            }
+            Ok(send_to_addresses(request, config, &socket_addresses).await)
+        }))
     }
 }
 
@@ -139,10 +566,18 @@ impl<T: WasiHttpView> WasiHttpView for WassetteWasiState<T> {
             return Err(types::ErrorCode::HttpRequestUriInvalid.into());
         }
 
-        if !self.is_host_allowed(uri) {
+        let host_allowed = self.is_host_allowed(uri);
+        if let Some(require_in_range) = self.resolution_required(uri, host_allowed) {
+            return Ok(self.send_after_resolving(request, config, require_in_range));
+        }
+
+        if !host_allowed {
             warn!(
                 uri = %uri,
                 allowed_hosts = ?self.allowed_hosts,
+                denied_hosts = ?self.denied_hosts,
+                allowed_cidrs = ?self.allowed_cidrs,
+                denied_cidrs = ?self.denied_cidrs,
                 "HTTP request blocked by network policy"
             );
             return Err(types::ErrorCode::HttpRequestDenied.into());
@@ -150,6 +585,17 @@ impl<T: WasiHttpView> WasiHttpView for WassetteWasiState<T> {
 
         debug!(uri = %uri, "HTTP request allowed by network policy");
 
+        if let Some(mock) = &self.http_mock {
+            let Some(response) = mock.intercept(&request) else {
+                warn!(uri = %uri, "HTTP request not answered by mock, refusing request");
+                return Err(types::ErrorCode::ConnectionRefused.into());
+            };
+            debug!(uri = %uri, status = response.status, "HTTP request answered by mock");
+            return Ok(HostFutureIncomingResponse::ready(Ok(
+                response.into_incoming_response(config.between_bytes_timeout)
+            )));
+        }
+
         self.inner.send_request(request, config)
     }
 
@@ -304,4 +750,373 @@ mod tests {
         assert!(state.is_host_allowed(&uri1));
         assert!(state.is_host_allowed(&uri2));
     }
+
+    #[test]
+    fn test_wildcard_host_allowed() {
+        let allowed_hosts = HashSet::from(["*.example.com".to_string()]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts).unwrap();
+
+        let subdomain: hyper::Uri = "https://api.example.com".parse().unwrap();
+        let nested: hyper::Uri = "https://a.b.example.com".parse().unwrap();
+        let apex: hyper::Uri = "https://example.com".parse().unwrap();
+        let lookalike: hyper::Uri = "https://notexample.com".parse().unwrap();
+
+        assert!(state.is_host_allowed(&subdomain));
+        assert!(state.is_host_allowed(&nested));
+        assert!(!state.is_host_allowed(&apex));
+        assert!(!state.is_host_allowed(&lookalike));
+    }
+
+    #[test]
+    fn test_wildcard_only_matches_subdomains() {
+        let allowed_hosts = HashSet::from(["*.example.com".to_string()]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts).unwrap();
+
+        let subdomain: hyper::Uri = "https://a.example.com".parse().unwrap();
+        let apex: hyper::Uri = "https://example.com".parse().unwrap();
+        let suffix_attack: hyper::Uri = "https://evil.com.attacker.net".parse().unwrap();
+        let prefix_attack: hyper::Uri = "https://a.example.com.attacker.net".parse().unwrap();
+
+        assert!(state.is_host_allowed(&subdomain));
+        assert!(!state.is_host_allowed(&apex));
+        assert!(!state.is_host_allowed(&suffix_attack));
+        assert!(!state.is_host_allowed(&prefix_attack));
+    }
+
+    #[test]
+    fn test_host_with_port_suffix() {
+        let allowed_hosts = HashSet::from([
+            "api.example.com:8443".to_string(),
+            "*.internal.example.com:80".to_string(),
+            "https://secure.example.com:443".to_string(),
+        ]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts).unwrap();
+
+        let on_port: hyper::Uri = "https://api.example.com:8443".parse().unwrap();
+        let default_port: hyper::Uri = "https://api.example.com".parse().unwrap();
+        assert!(state.is_host_allowed(&on_port));
+        assert!(!state.is_host_allowed(&default_port));
+
+        // Ports that are the default of a scheme still restrict the host
+        let http: hyper::Uri = "http://db.internal.example.com".parse().unwrap();
+        let other_port: hyper::Uri = "http://db.internal.example.com:8080".parse().unwrap();
+        assert!(state.is_host_allowed(&http));
+        assert!(!state.is_host_allowed(&other_port));
+
+        let https: hyper::Uri = "https://secure.example.com".parse().unwrap();
+        let https_other_port: hyper::Uri = "https://secure.example.com:8443".parse().unwrap();
+        assert!(state.is_host_allowed(&https));
+        assert!(!state.is_host_allowed(&https_other_port));
+
+        // A denied port only blocks that port
+        let state = WassetteWasiState::new(
+            create_mock_wasi_state(),
+            HashSet::from(["admin.example.com".to_string()]),
+        )
+        .unwrap()
+        .with_denied_hosts(HashSet::from(["admin.example.com:8443".to_string()]))
+        .unwrap();
+        let denied: hyper::Uri = "https://admin.example.com:8443".parse().unwrap();
+        let allowed: hyper::Uri = "https://admin.example.com".parse().unwrap();
+        assert!(!state.is_host_allowed(&denied));
+        assert!(state.is_host_allowed(&allowed));
+    }
+
+    #[test]
+    fn test_deny_wins_over_overlapping_allow() {
+        let allowed_hosts = HashSet::from(["*.internal.myorg.com".to_string()]);
+        let denied_hosts = HashSet::from(["secrets.internal.myorg.com".to_string()]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts)
+            .unwrap()
+            .with_denied_hosts(denied_hosts)
+            .unwrap();
+
+        let allowed: hyper::Uri = "https://api.internal.myorg.com".parse().unwrap();
+        let denied: hyper::Uri = "https://secrets.internal.myorg.com".parse().unwrap();
+        let denied_upper: hyper::Uri = "http://SECRETS.internal.myorg.com".parse().unwrap();
+
+        assert!(state.is_host_allowed(&allowed));
+        assert!(!state.is_host_allowed(&denied));
+        assert!(!state.is_host_allowed(&denied_upper));
+    }
+
+    #[test]
+    fn test_v1_1_deny_rules_exclude_denied_hosts() {
+        let policy = policy::PolicyParser::parse_str(
+            r#"
+version: "1.1"
+permissions:
+  network:
+    allow:
+    - host: "*.example.com"
+    deny:
+    - host: "secret.example.com"
+"#,
+        )
+        .unwrap();
+        policy.validate().unwrap();
+        let tempdir = tempfile::tempdir().unwrap();
+        let template = crate::wasistate::create_wasi_state_template_from_policy(
+            &policy,
+            tempdir.path(),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            template.allowed_hosts,
+            HashSet::from(["*.example.com".to_string()])
+        );
+        assert_eq!(
+            template.denied_hosts,
+            HashSet::from(["secret.example.com".to_string()])
+        );
+
+        let state = WassetteWasiState::new(create_mock_wasi_state(), template.allowed_hosts)
+            .unwrap()
+            .with_denied_hosts(template.denied_hosts)
+            .unwrap();
+        let allowed: hyper::Uri = "https://api.example.com".parse().unwrap();
+        let denied: hyper::Uri = "https://secret.example.com".parse().unwrap();
+        assert!(state.is_host_allowed(&allowed));
+        assert!(!state.is_host_allowed(&denied));
+    }
+
+    #[test]
+    fn test_host_allowed_on_restricted_ports() {
+        let allowed_hosts =
+            HashSet::from(["api.example.com".to_string(), "cdn.example.com".to_string()]);
+        let allowed_ports = HashMap::from([(
+            "api.example.com".to_string(),
+            vec![PortRange::single(443), "8000-8080".parse().unwrap()],
+        )]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts)
+            .unwrap()
+            .with_allowed_ports(allowed_ports)
+            .unwrap();
+
+        let https: hyper::Uri = "https://api.example.com".parse().unwrap();
+        let explicit_443: hyper::Uri = "http://api.example.com:443".parse().unwrap();
+        let in_range: hyper::Uri = "http://api.example.com:8042".parse().unwrap();
+        let http: hyper::Uri = "http://api.example.com".parse().unwrap();
+        let out_of_range: hyper::Uri = "http://api.example.com:8081".parse().unwrap();
+
+        assert!(state.is_host_allowed(&https));
+        assert!(state.is_host_allowed(&explicit_443));
+        assert!(state.is_host_allowed(&in_range));
+        assert!(!state.is_host_allowed(&http));
+        assert!(!state.is_host_allowed(&out_of_range));
+
+        // Hosts without a port restriction can be reached on any port
+        let unrestricted: hyper::Uri = "http://cdn.example.com:9999".parse().unwrap();
+        assert!(state.is_host_allowed(&unrestricted));
+    }
+
+    #[test]
+    fn test_ip_literals_checked_against_cidrs() {
+        let state = WassetteWasiState::new(create_mock_wasi_state(), HashSet::new())
+            .unwrap()
+            .with_cidrs(
+                HashSet::from(["10.0.0.0/8".to_string(), "fd00::/8".to_string()]),
+                HashSet::from(["10.1.0.0/16".to_string()]),
+            )
+            .unwrap();
+
+        let in_range: hyper::Uri = "http://10.2.3.4/".parse().unwrap();
+        let out_of_range: hyper::Uri = "http://192.168.1.1/".parse().unwrap();
+        let denied: hyper::Uri = "http://10.1.2.3:8080/".parse().unwrap();
+        let ipv6_in_range: hyper::Uri = "http://[fd12::1]:8080/".parse().unwrap();
+        let ipv6_out_of_range: hyper::Uri = "http://[2001:db8::1]/".parse().unwrap();
+
+        assert!(state.is_host_allowed(&in_range));
+        assert!(!state.is_host_allowed(&out_of_range));
+        assert!(!state.is_host_allowed(&denied));
+        assert!(state.is_host_allowed(&ipv6_in_range));
+        assert!(!state.is_host_allowed(&ipv6_out_of_range));
+
+        // IP literals are never resolved
+        assert_eq!(state.resolution_required(&in_range, true), None);
+        assert_eq!(state.resolution_required(&out_of_range, false), None);
+    }
+
+    #[test]
+    fn test_denied_cidr_wins_over_allowed_host() {
+        let allowed_hosts = HashSet::from(["10.0.0.1".to_string()]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts)
+            .unwrap()
+            .with_cidrs(HashSet::new(), HashSet::from(["10.0.0.0/24".to_string()]))
+            .unwrap();
+
+        let uri: hyper::Uri = "http://10.0.0.1/".parse().unwrap();
+        assert!(!state.is_host_allowed(&uri));
+    }
+
+    #[test]
+    fn test_invalid_cidr() {
+        let result = WassetteWasiState::new(create_mock_wasi_state(), HashSet::new())
+            .unwrap()
+            .with_cidrs(HashSet::from(["10.0.0.0/33".to_string()]), HashSet::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_hostnames_resolved_when_needed() {
+        let allowed_hosts = HashSet::from(["api.example.com".to_string()]);
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts.clone())
+            .unwrap()
+            .with_cidrs(HashSet::from(["10.0.0.0/8".to_string()]), HashSet::new())
+            .unwrap();
+        let allowed: hyper::Uri = "https://api.example.com/".parse().unwrap();
+        let other: hyper::Uri = "https://other.example.com/".parse().unwrap();
+
+        // Hostnames without a host rule must resolve to an allowed range
+        assert_eq!(state.resolution_required(&allowed, true), None);
+        assert_eq!(state.resolution_required(&other, false), Some(true));
+
+        let state = state.with_resolved_address_checks(true);
+        assert_eq!(state.resolution_required(&allowed, true), Some(true));
+
+        // Hostnames allowed by a host rule are only checked against the denied ranges
+        let state = WassetteWasiState::new(create_mock_wasi_state(), allowed_hosts)
+            .unwrap()
+            .with_cidrs(HashSet::new(), HashSet::from(["10.0.0.0/8".to_string()]))
+            .unwrap();
+        assert_eq!(state.resolution_required(&allowed, true), Some(false));
+        assert_eq!(state.resolution_required(&other, false), None);
+    }
+
+    #[test]
+    fn test_check_resolved_addresses() {
+        let allowed: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()];
+        let denied: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert!(check_resolved_addresses(
+            &[ip("10.2.0.1"), ip("fd00::1")],
+            &allowed,
+            &denied,
+            true
+        ));
+        // Every address must be in range, since any of them may be connected to
+        assert!(!check_resolved_addresses(
+            &[ip("10.2.0.1"), ip("2001:db8::1")],
+            &allowed,
+            &denied,
+            true
+        ));
+        assert!(check_resolved_addresses(
+            &[ip("2001:db8::1")],
+            &allowed,
+            &denied,
+            false
+        ));
+        assert!(!check_resolved_addresses(
+            &[ip("10.1.0.1")],
+            &allowed,
+            &denied,
+            false
+        ));
+        assert!(!check_resolved_addresses(&[], &allowed, &denied, false));
+    }
+
+    #[tokio::test]
+    async fn test_resolved_hostname_checked_against_cidrs() {
+        let responses = HashMap::from([(
+            "http://localhost/".to_string(),
+            MockResponse::new(200, "local"),
+        )]);
+        let send = |allowed_cidrs: &[&str]| {
+            let state = WassetteWasiState::new(create_mock_wasi_state(), HashSet::new())
+                .unwrap()
+                .with_cidrs(
+                    allowed_cidrs.iter().map(|cidr| cidr.to_string()).collect(),
+                    HashSet::new(),
+                )
+                .unwrap()
+                .with_http_mock(Arc::new(responses.clone()));
+            let request = hyper::Request::builder()
+                .uri("http://localhost/")
+                .body(
+                    http_body_util::Empty::new()
+                        .map_err(|never| match never {})
+                        .boxed(),
+                )
+                .unwrap();
+            let config = OutgoingRequestConfig {
+                use_tls: false,
+                connect_timeout: Duration::from_secs(1),
+                first_byte_timeout: Duration::from_secs(1),
+                between_bytes_timeout: Duration::from_secs(1),
+            };
+            let uri = request.uri().clone();
+            let host_allowed = state.is_host_allowed(&uri);
+            let require_in_range = state.resolution_required(&uri, host_allowed).unwrap();
+            state.send_after_resolving(request, config, require_in_range)
+        };
+
+        let status = |response: HostFutureIncomingResponse| async move {
+            let result = match response {
+                HostFutureIncomingResponse::Pending(handle) => handle.await,
+                HostFutureIncomingResponse::Ready(result) => result,
+                HostFutureIncomingResponse::Consumed => unreachable!(),
+            };
+            result
+                .unwrap()
+                .map(|response| response.resp.status().as_u16())
+        };
+
+        assert!(matches!(
+            status(send(&["127.0.0.0/8", "::1/128"])).await,
+            Ok(200)
+        ));
+        assert!(matches!(
+            status(send(&["10.0.0.0/8", "fd00::/8"])).await,
+            Err(types::ErrorCode::HttpRequestDenied)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_sent_to_the_checked_addresses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+        });
+
+        // The host can't be resolved, so the request only reaches the server if it's sent to the
+        // given address without resolving the host again
+        let request = hyper::Request::builder()
+            .uri(format!("http://rebinding.invalid:{}/", address.port()))
+            .body(
+                http_body_util::Empty::new()
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap();
+        let config = OutgoingRequestConfig {
+            use_tls: false,
+            connect_timeout: Duration::from_secs(5),
+            first_byte_timeout: Duration::from_secs(5),
+            between_bytes_timeout: Duration::from_secs(5),
+        };
+        let response = send_to_addresses(request, config, &[address])
+            .await
+            .unwrap();
+        assert_eq!(response.resp.status(), 200);
+        let body = response
+            .resp
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(&body[..], b"ok");
+    }
 }
@@ -0,0 +1,194 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Load-time filtering of the interfaces a component is allowed to import.
+
+use anyhow::{bail, Result};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+/// A coarse guardrail that rejects components importing forbidden interfaces, regardless of the
+/// permissions granted by their policy.
+///
+/// Patterns name either a whole package (`wasi:sockets`) or a single interface
+/// (`wasi:sockets/tcp`), optionally with a version (`wasi:sockets/tcp@0.2.0`). A pattern without
+/// a version matches every version.
+#[derive(Debug, Clone, Default)]
+pub struct ImportFilter {
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+}
+
+impl ImportFilter {
+    /// Returns a filter that allows every import
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects components importing an interface matching `pattern`. Deny patterns take
+    /// precedence over allow patterns.
+    pub fn deny(mut self, pattern: impl Into<String>) -> Self {
+        self.deny.push(pattern.into());
+        self
+    }
+
+    /// Only allows imports matching one of the allowed patterns. Once any allow pattern is set,
+    /// components importing anything else are rejected.
+    pub fn allow(mut self, pattern: impl Into<String>) -> Self {
+        self.allow.get_or_insert_with(Vec::new).push(pattern.into());
+        self
+    }
+
+    /// Checks a single import name against the filter
+    pub fn check_import(&self, import: &str) -> Result<()> {
+        if let Some(pattern) = self.deny.iter().find(|p| import_matches(p, import)) {
+            bail!("Component imports forbidden interface '{import}' (denied by '{pattern}')");
+        }
+        if let Some(allow) = &self.allow {
+            if !allow.iter().any(|p| import_matches(p, import)) {
+                bail!("Component imports interface '{import}' which is not in the allow list");
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks all imports of the given component against the filter
+    pub(crate) fn check_component(&self, component: &Component, engine: &Engine) -> Result<()> {
+        if self.allow.is_none() && self.deny.is_empty() {
+            return Ok(());
+        }
+        for (import, _) in component.component_type().imports(engine) {
+            self.check_import(import)?;
+        }
+        Ok(())
+    }
+}
+
+fn import_matches(pattern: &str, import: &str) -> bool {
+    let (import_name, import_version) = match import.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (import, None),
+    };
+    let (pattern_name, pattern_version) = match pattern.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (pattern, None),
+    };
+
+    if pattern_version.is_some() && pattern_version != import_version {
+        return false;
+    }
+
+    import_name == pattern_name
+        || import_name
+            .strip_prefix(pattern_name)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::LifecycleManager;
+
+    const SOCKETS_COMPONENT_WAT: &str = r#"
+(component
+  (import "wasi:sockets/tcp@0.2.0" (instance))
+)
+"#;
+
+    #[test(tokio::test)]
+    async fn test_load_rejects_denied_import() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path().join("plugins"))
+            .with_import_filter(ImportFilter::new().deny("wasi:sockets"))
+            .build()
+            .await?;
+
+        let component_path = tempdir.path().join("uses_sockets.wasm");
+        tokio::fs::write(&component_path, SOCKETS_COMPONENT_WAT).await?;
+        let err = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await
+            .expect_err("Component importing wasi:sockets should be rejected");
+        assert!(
+            format!("{err:#}").contains("wasi:sockets/tcp@0.2.0"),
+            "unexpected error: {err:#}"
+        );
+        assert!(manager.list_components().await.is_empty());
+
+        // Components that don't import denied interfaces still load
+        load_wat_component(&manager, "config_reader", CONFIG_READER_COMPONENT_WAT).await?;
+        assert_eq!(manager.list_components().await, vec!["config_reader"]);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_startup_rejects_denied_import() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(
+            tempdir.path().join("uses_sockets.wasm"),
+            SOCKETS_COMPONENT_WAT,
+        )
+        .await?;
+
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_import_filter(ImportFilter::new().deny("wasi:sockets"))
+            .build()
+            .await?;
+        assert!(manager.list_components().await.is_empty());
+
+        let summary = manager.reconcile().await?;
+        assert_eq!(summary.failed.len(), 1);
+        assert!(summary.failed[0].1.contains("forbidden interface"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_matches() {
+        assert!(import_matches("wasi:sockets", "wasi:sockets/tcp@0.2.0"));
+        assert!(import_matches("wasi:sockets/tcp", "wasi:sockets/tcp@0.2.0"));
+        assert!(import_matches(
+            "wasi:sockets/tcp@0.2.0",
+            "wasi:sockets/tcp@0.2.0"
+        ));
+        assert!(!import_matches(
+            "wasi:sockets/tcp@0.2.1",
+            "wasi:sockets/tcp@0.2.0"
+        ));
+        assert!(!import_matches(
+            "wasi:sockets/tcp",
+            "wasi:sockets/tcp-create-socket@0.2.0"
+        ));
+        assert!(!import_matches("wasi:sock", "wasi:sockets/tcp@0.2.0"));
+        assert!(!import_matches("wasi:http", "wasi:sockets/tcp@0.2.0"));
+    }
+
+    #[test]
+    fn test_deny_takes_precedence() {
+        let filter = ImportFilter::new()
+            .allow("wasi:sockets")
+            .deny("wasi:sockets/udp");
+
+        assert!(filter.check_import("wasi:sockets/tcp@0.2.0").is_ok());
+        let err = filter.check_import("wasi:sockets/udp@0.2.0").unwrap_err();
+        assert!(err.to_string().contains("wasi:sockets/udp@0.2.0"));
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let filter = ImportFilter::new().allow("wasi:config");
+
+        assert!(filter.check_import("wasi:config/store@0.2.0-draft").is_ok());
+        assert!(filter.check_import("wasi:http/types@0.2.0").is_err());
+    }
+
+    #[test]
+    fn test_default_allows_everything() {
+        let filter = ImportFilter::new();
+        assert!(filter.check_import("wasi:sockets/tcp@0.2.0").is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Components that are registered by URI and only compiled when they are first used
+
+use anyhow::anyhow;
+use tracing::{info, instrument};
+
+use crate::{loader, LifecycleManager, WassetteError};
+
+impl LifecycleManager {
+    /// Registers the component at `uri` under the ID [`LifecycleManager::load_component`] would
+    /// give it, without downloading or compiling it. The component is loaded from `uri` by the
+    /// first [`LifecycleManager::execute_component_call`] or [`LifecycleManager::get_component`]
+    /// for its ID, so that rarely used components don't take up memory until they are needed.
+    ///
+    /// Until then, the component isn't listed by [`LifecycleManager::list_components`] and its
+    /// tools aren't known. A component already loaded under the ID is left as is. Lazy
+    /// registration isn't available with [`crate::LifecycleManagerBuilder::with_package_ids`],
+    /// since the package of a component is only known once it is downloaded.
+    #[instrument(skip(self))]
+    pub async fn register_component_lazy(&self, uri: &str) -> Result<String, WassetteError> {
+        if self.package_ids {
+            return Err(anyhow!(
+                "Components can't be registered lazily when they are identified by their package"
+            )
+            .into());
+        }
+        let (uri_without_digest, _) = loader::split_digest(uri)?;
+        let id = loader::component_id_from_uri(uri_without_digest)
+            .map_err(WassetteError::DownloadFailed)?;
+        crate::validate_component_id(&id)?;
+        if self.components.read().await.contains_key(&id) {
+            info!(component_id = %id, "Component is already loaded, not registering it lazily");
+            return Ok(id);
+        }
+        self.lazy_components
+            .lock()
+            .unwrap()
+            .insert(id.clone(), uri.to_string());
+        info!(component_id = %id, "Registered component, it is compiled on first use");
+        Ok(id)
+    }
+
+    /// Returns whether the component with the given ID was registered with
+    /// [`LifecycleManager::register_component_lazy`] and hasn't been compiled yet
+    pub fn is_lazy(&self, id: &str) -> bool {
+        self.lazy_components.lock().unwrap().contains_key(id)
+    }
+
+    /// Loads the component with the given ID if it was registered lazily and hasn't been loaded
+    /// yet. The registration is kept if loading fails, so that the next use tries again.
+    pub(crate) async fn load_if_lazy(&self, id: &str) -> Result<(), WassetteError> {
+        let Some(uri) = self.lazy_components.lock().unwrap().get(id).cloned() else {
+            return Ok(());
+        };
+        info!(component_id = %id, uri, "Loading lazily registered component");
+        self.load_component(&uri).await?;
+        self.lazy_components.lock().unwrap().remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::tests::*;
+
+    fn cached_components(manager: &LifecycleManager) -> usize {
+        crate::cache::entry_count(&manager.cache_dir)
+    }
+
+    #[test(tokio::test)]
+    async fn test_lazy_component_compiled_on_first_call() -> anyhow::Result<()> {
+        let manager = create_test_manager().await?;
+        let tempdir = tempfile::tempdir()?;
+        let component_path = tempdir.path().join("answer.wasm");
+        tokio::fs::write(&component_path, ANSWER_WAT).await?;
+
+        let id = manager
+            .register_component_lazy(&format!("file://{}", component_path.display()))
+            .await?;
+        assert_eq!(id, "answer");
+        assert!(manager.is_lazy(&id));
+        assert_eq!(cached_components(&manager), 0);
+        assert!(manager.list_components().await.is_empty());
+
+        let result = manager.execute_component_call(&id, "answer", "{}").await?;
+        assert!(result.contains("42"), "unexpected result: {result}");
+        assert!(!manager.is_lazy(&id));
+        assert_eq!(cached_components(&manager), 1);
+        assert_eq!(manager.list_components().await, vec![id.clone()]);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_lazy_component_loaded_by_get_component() -> anyhow::Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+
+        let id = manager
+            .register_component_lazy(&format!("file://{}", component_path.display()))
+            .await?;
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert!(manager.get_component_id_for_tool("fetch").await.is_err());
+
+        assert!(manager.get_component(&id).await.is_some());
+        assert!(!manager.is_lazy(&id));
+        assert!(manager.get_component_id_for_tool("fetch").await.is_ok());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_lazy_component_that_fails_to_load_stays_registered() -> anyhow::Result<()> {
+        let manager = create_test_manager().await?;
+        let tempdir = tempfile::tempdir()?;
+        let component_path = tempdir.path().join("broken.wasm");
+        tokio::fs::write(&component_path, "not a component").await?;
+
+        let id = manager
+            .register_component_lazy(&format!("file://{}", component_path.display()))
+            .await?;
+        assert!(matches!(
+            manager.execute_component_call(&id, "run", "{}").await,
+            Err(WassetteError::CompileFailed(_))
+        ));
+        assert!(manager.is_lazy(&id));
+
+        // Unloading drops the registration
+        manager.unload_component(&id).await?;
+        assert!(!manager.is_lazy(&id));
+
+        Ok(())
+    }
+}
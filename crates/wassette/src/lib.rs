@@ -8,39 +8,301 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, SystemTime};
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, Context, Result};
 use component2json::{
-    component_exports_to_json_schema, component_exports_to_tools, create_placeholder_results,
-    json_to_vals, vals_to_json, FunctionIdentifier, ToolMetadata,
+    component_exports_to_json_schema_with_options, component_exports_to_tools_with_options,
+    create_placeholder_results, json_to_vals, vals_to_json, ComponentDocs, FunctionIdentifier,
+    SchemaCache, SchemaOptions, ToolMetadata,
 };
-use policy::PolicyParser;
+use policy::{PolicyError, PolicyParser};
 use serde_json::Value;
-use tokio::fs::DirEntry;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
 use tracing::{debug, info, instrument, warn};
-use wasmtime::component::{Component, InstancePre, Linker};
+use wasmtime::component::types::{self, ComponentItem};
+use wasmtime::component::{Component, InstancePre, Linker, LinkerInstance, ResourceType};
 use wasmtime::{Engine, Store};
 use wasmtime_wasi_config::WasiConfig;
 
+mod cache;
+mod drain;
+mod error;
+mod execution;
 mod http;
+mod imports;
+mod lazy;
 mod loader;
+mod metadata;
+mod metrics;
 mod policy_internal;
+mod reconcile;
+mod registry_auth;
+mod result_cache;
+mod retry;
+mod subscribers;
+#[cfg(feature = "opentelemetry")]
+mod telemetry;
+#[cfg(any(test, feature = "test-fixtures"))]
+#[doc(hidden)]
+pub mod test_fixtures;
+mod transform;
 mod wasistate;
-
-pub use http::WassetteWasiState;
+#[cfg(feature = "notify")]
+mod watch;
+
+use drain::CallTracker;
+pub use error::WassetteError;
+use execution::DedicatedRuntime;
+pub use execution::ExecutionPool;
+pub use http::{HttpInterceptor, MockResponse, WassetteWasiState};
+pub use imports::ImportFilter;
 use loader::{ComponentResource, PolicyResource};
-use policy_internal::PolicyRegistry;
-pub use policy_internal::{PermissionGrantRequest, PermissionRule, PolicyInfo};
+pub use metadata::ComponentMetadata;
+pub use metrics::ComponentMetrics;
+use metrics::PrometheusMetrics;
+pub use policy_internal::{PermissionGrantRequest, PermissionRule, PolicyInfo, RevokeResult};
+use policy_internal::{PolicyRegistry, PreparedPolicy};
+pub use reconcile::{ProgressCallback, ReconcileProgress, ReconcileStatus, ReconcileSummary};
+pub use registry_auth::RegistryCredentials;
+use result_cache::ResultCache;
+pub use retry::RetryPolicy;
+use subscribers::ToolsChangedCallback;
+pub use transform::{ResultFormat, ResultTransformer};
 use wasistate::WasiState;
 pub use wasistate::{
-    create_wasi_state_template_from_policy, CustomResourceLimiter, WasiStateTemplate,
+    create_wasi_state_template_from_policy, CustomResourceLimiter, PolicyViolation,
+    WasiStateTemplate,
 };
+#[cfg(feature = "notify")]
+pub use watch::PluginDirWatcher;
 
 const DOWNLOADS_DIR: &str = "downloads";
 
+/// Separates the component ID from the tool name in a qualified tool name like
+/// `weather::get_forecast`, which names the tool of that component even when other components
+/// export a tool with the same name
+pub const TOOL_NAME_SEPARATOR: &str = "::";
+
+/// Splits a tool name into the component ID it is qualified with, if any, and the tool name
+fn split_qualified_tool_name(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(TOOL_NAME_SEPARATOR) {
+        Some((component_id, tool_name)) => (Some(component_id), tool_name),
+        None => (None, name),
+    }
+}
+
+/// The default limit on how much linear memory a single component call may allocate, in bytes
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+/// The default limit on how long a single component call may run before it is aborted
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the engine's epoch is incremented. This is the granularity at which timeouts are detected.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Returns whether a component ID can be used as a file name inside the plugin directory, i.e. it
+/// is only made of ASCII alphanumerics, `-`, `_` and `.`, and isn't `.` or `..`
+pub(crate) fn is_valid_component_id(component_id: &str) -> bool {
+    !component_id.is_empty()
+        && component_id != "."
+        && component_id != ".."
+        && component_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Fails with [`WassetteError::InvalidComponentId`] unless [`is_valid_component_id`]
+pub(crate) fn validate_component_id(component_id: &str) -> Result<(), WassetteError> {
+    if !is_valid_component_id(component_id) {
+        return Err(WassetteError::InvalidComponentId(component_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns the name of a file of the component with the given ID in the plugin directory.
+///
+/// Components are only loaded under valid IDs, so other IDs never have files. Their names are
+/// hex encoded behind a `%`, which valid IDs never contain, so that looking them up stays inside
+/// the plugin directory and never finds the files of another component.
+pub(crate) fn component_file_name(component_id: &str, extension: &str) -> String {
+    if is_valid_component_id(component_id) {
+        return format!("{component_id}.{extension}");
+    }
+    let hex: String = component_id.bytes().map(|b| format!("{b:02x}")).collect();
+    format!("%{hex}.{extension}")
+}
+
+/// Makes a name, like the WIT package of a component, safe to use as a component ID by replacing
+/// every character that isn't allowed in IDs with `_`
+pub(crate) fn sanitize_component_id(component_id: &str) -> String {
+    component_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Starts a thread that increments the engine's epoch every [`EPOCH_TICK`], which is what lets
+/// store epoch deadlines trip. The thread stops once the engine is dropped.
+///
+/// This is a plain thread rather than a tokio task so that the ticks keep coming even when a
+/// component call blocks the thread of a current-thread runtime.
+fn spawn_epoch_ticker(engine: Weak<Engine>) -> Result<()> {
+    std::thread::Builder::new()
+        .name("wassette-epoch-ticker".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            match engine.upgrade() {
+                Some(engine) => engine.increment_epoch(),
+                None => break,
+            }
+        })
+        .context("Failed to start the epoch ticker thread")?;
+    Ok(())
+}
+
+/// Builds the linker providing the WASI, HTTP and config interfaces to components. It is the same
+/// for every component, since everything a policy grants lives in the store, so it is built once
+/// per manager and every component is pre-instantiated with it when loaded. Calls then only create
+/// a store and instantiate the [`InstancePre`].
+fn build_linker(engine: &Engine) -> Result<Linker<WassetteWasiState<WasiState>>> {
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+
+    // Use the standard HTTP linker - filtering happens at WasiHttpView level
+    wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
+
+    wasmtime_wasi_config::add_to_linker(&mut linker, |h: &mut WassetteWasiState<WasiState>| {
+        WasiConfig::from(&h.inner.wasi_config_vars)
+    })?;
+    Ok(linker)
+}
+
+/// Builds the linker for components whose policy only grants environment access: the WASI
+/// interfaces except sockets, and the config interface, without HTTP. The network imports of a
+/// component are defined to trap when it is pre-instantiated, see
+/// [`define_network_imports_as_traps`], so that it still instantiates but can't reach the network
+/// at all.
+fn build_environment_only_linker(engine: &Engine) -> Result<Linker<WassetteWasiState<WasiState>>> {
+    use wasmtime_wasi::p2::bindings::{cli, clocks, filesystem, random, LinkOptions};
+    use wasmtime_wasi::p2::{IoImpl, WasiImpl};
+
+    fn host<T: wasmtime_wasi::p2::WasiView>(state: &mut T) -> WasiImpl<&mut T> {
+        WasiImpl(IoImpl(state))
+    }
+    let host = host::<WassetteWasiState<WasiState>>;
+
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi_io::add_to_linker_async(&mut linker)?;
+    clocks::wall_clock::add_to_linker_get_host(&mut linker, host)?;
+    clocks::monotonic_clock::add_to_linker_get_host(&mut linker, host)?;
+    filesystem::types::add_to_linker_get_host(&mut linker, host)?;
+    filesystem::preopens::add_to_linker_get_host(&mut linker, host)?;
+    random::random::add_to_linker_get_host(&mut linker, host)?;
+    random::insecure::add_to_linker_get_host(&mut linker, host)?;
+    random::insecure_seed::add_to_linker_get_host(&mut linker, host)?;
+    cli::exit::add_to_linker_get_host(&mut linker, &(&LinkOptions::default()).into(), host)?;
+    cli::environment::add_to_linker_get_host(&mut linker, host)?;
+    cli::stdin::add_to_linker_get_host(&mut linker, host)?;
+    cli::stdout::add_to_linker_get_host(&mut linker, host)?;
+    cli::stderr::add_to_linker_get_host(&mut linker, host)?;
+    cli::terminal_input::add_to_linker_get_host(&mut linker, host)?;
+    cli::terminal_output::add_to_linker_get_host(&mut linker, host)?;
+    cli::terminal_stdin::add_to_linker_get_host(&mut linker, host)?;
+    cli::terminal_stdout::add_to_linker_get_host(&mut linker, host)?;
+    cli::terminal_stderr::add_to_linker_get_host(&mut linker, host)?;
+
+    wasmtime_wasi_config::add_to_linker(&mut linker, |h: &mut WassetteWasiState<WasiState>| {
+        WasiConfig::from(&h.inner.wasi_config_vars)
+    })?;
+    Ok(linker)
+}
+
+/// Defines the `wasi:sockets` and `wasi:http` imports of `component` as functions that trap, so
+/// components importing them still instantiate with the [`build_environment_only_linker`] linker.
+///
+/// Resources that alias a resource seen in an earlier import are left to the type checker, the
+/// same as it does for `(eq ...)` bounded imports.
+fn define_network_imports_as_traps(
+    linker: &mut Linker<WassetteWasiState<WasiState>>,
+    engine: &Engine,
+    component: &Component,
+) -> Result<()> {
+    fn stub_instance(
+        linker: &mut LinkerInstance<'_, WassetteWasiState<WasiState>>,
+        engine: &Engine,
+        interface: &str,
+        instance: &types::ComponentInstance,
+        seen: &mut Vec<ResourceType>,
+    ) -> Result<()> {
+        for (name, item) in instance.exports(engine) {
+            match item {
+                ComponentItem::ComponentFunc(_) => {
+                    let qualified_name = format!("{interface}#{name}");
+                    linker.func_new(name, move |_, _, _| {
+                        anyhow::bail!("`{qualified_name}` is not available to components that only have environment access")
+                    })?;
+                }
+                ComponentItem::Resource(ty) if !seen.contains(&ty) => {
+                    seen.push(ty);
+                    linker.resource(name, ResourceType::host::<()>(), |_, _| Ok(()))?;
+                }
+                ComponentItem::ComponentInstance(nested) => {
+                    stub_instance(
+                        &mut linker.instance(name)?,
+                        engine,
+                        interface,
+                        &nested,
+                        seen,
+                    )?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn record_resources(
+        engine: &Engine,
+        instance: &types::ComponentInstance,
+        seen: &mut Vec<ResourceType>,
+    ) {
+        for (_, item) in instance.exports(engine) {
+            match item {
+                ComponentItem::Resource(ty) if !seen.contains(&ty) => seen.push(ty),
+                ComponentItem::ComponentInstance(nested) => record_resources(engine, &nested, seen),
+                _ => {}
+            }
+        }
+    }
+
+    let mut seen = Vec::new();
+    for (name, item) in component.component_type().imports(engine) {
+        let ComponentItem::ComponentInstance(instance) = item else {
+            continue;
+        };
+        if name.starts_with("wasi:sockets/") || name.starts_with("wasi:http/") {
+            stub_instance(
+                &mut linker.instance(name)?,
+                engine,
+                name,
+                &instance,
+                &mut seen,
+            )?;
+        } else {
+            record_resources(engine, &instance, &mut seen);
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 struct ToolInfo {
     component_id: String,
@@ -54,15 +316,50 @@ struct ComponentRegistry {
     component_map: HashMap<String, Vec<String>>,
 }
 
+/// A tool exported by a loaded component, as returned by [`LifecycleManager::list_tools_detailed`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolDescriptor {
+    /// The ID of the component exporting the tool
+    pub component_id: String,
+    /// The name the tool is called by
+    pub normalized_name: String,
+    /// The JSON schema of the tool
+    pub schema: Value,
+    /// Whether other loaded components export a tool with the same name, in which case calling it
+    /// by name fails
+    pub ambiguous: bool,
+}
+
+/// Details about a loaded component, as returned by [`LifecycleManager::component_info`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentInfo {
+    /// The ID of the component
+    pub id: String,
+    /// The size of the component binary
+    pub wasm_size_bytes: u64,
+    /// The SHA-256 hash of the component binary
+    pub sha256: [u8; 32],
+    /// When the component was loaded
+    pub loaded_at: SystemTime,
+    /// The number of tools the component exports
+    pub tool_count: usize,
+}
+
 /// The returned status when loading a component
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LoadResult {
     /// Indicates that the component was loaded but replaced a currently loaded component
     Replaced,
     /// Indicates that the component did not exist and is now loaded
     New,
+    /// Indicates that an identical component was already loaded, so nothing changed
+    Unchanged,
 }
 
+/// The outcome of loading one component with [`LifecycleManager::load_components`]: the URI it
+/// was loaded from, and its ID and [`LoadResult`] or the error
+pub type BatchLoadResult = (String, Result<(String, LoadResult), WassetteError>);
+
 impl ComponentRegistry {
     fn new() -> Self {
         Self::default()
@@ -90,11 +387,12 @@ impl ComponentRegistry {
         Ok(())
     }
 
-    fn get_function_identifier(&self, tool_name: &str) -> Option<&FunctionIdentifier> {
+    /// Returns the tool with the given name exported by the given component
+    fn get_component_tool(&self, component_id: &str, tool_name: &str) -> Option<&ToolInfo> {
         self.tool_map
-            .get(tool_name)
-            .and_then(|tool_infos| tool_infos.first())
-            .map(|tool_info| &tool_info.identifier)
+            .get(tool_name)?
+            .iter()
+            .find(|tool_info| tool_info.component_id == component_id)
     }
 
     fn unregister_component(&mut self, component_id: &str) {
@@ -110,14 +408,25 @@ impl ComponentRegistry {
         }
     }
 
+    fn component_tool_count(&self, component_id: &str) -> usize {
+        self.component_map.get(component_id).map_or(0, Vec::len)
+    }
+
     fn get_tool_info(&self, tool_name: &str) -> Option<&Vec<ToolInfo>> {
         self.tool_map.get(tool_name)
     }
 
-    fn list_tools(&self) -> Vec<Value> {
+    fn list_tools_detailed(&self) -> Vec<ToolDescriptor> {
         self.tool_map
-            .values()
-            .flat_map(|tools| tools.iter().map(|t| t.schema.clone()))
+            .iter()
+            .flat_map(|(name, tools)| {
+                tools.iter().map(|t| ToolDescriptor {
+                    component_id: t.component_id.clone(),
+                    normalized_name: name.clone(),
+                    schema: t.schema.clone(),
+                    ambiguous: tools.len() > 1,
+                })
+            })
             .collect()
     }
 }
@@ -127,21 +436,343 @@ impl ComponentRegistry {
 pub struct LifecycleManager {
     engine: Arc<Engine>,
     linker: Arc<Linker<WassetteWasiState<WasiState>>>,
-    components: Arc<RwLock<HashMap<String, ComponentInstance>>>,
+    environment_only_linker: Arc<Linker<WassetteWasiState<WasiState>>>,
+    components: Arc<RwLock<HashMap<String, ComponentSlot>>>,
     registry: Arc<RwLock<ComponentRegistry>>,
     policy_registry: Arc<RwLock<PolicyRegistry>>,
     oci_client: Arc<oci_wasm::WasmClient>,
+    registry_credentials: Arc<RegistryCredentials>,
     http_client: reqwest::Client,
+    retry_policy: RetryPolicy,
     plugin_dir: PathBuf,
+    cache_dir: PathBuf,
+    precompiled_components: bool,
     environment_vars: HashMap<String, String>,
+    execution_runtime: Option<Arc<DedicatedRuntime>>,
+    result_transformer: Arc<RwLock<Option<Arc<dyn ResultTransformer>>>>,
+    import_filter: Arc<ImportFilter>,
+    http_mock: Option<Arc<dyn HttpInterceptor>>,
+    resolved_address_checks: bool,
+    result_format: ResultFormat,
+    component_docs: bool,
+    package_ids: bool,
+    call_timeout: Option<Duration>,
+    compile_concurrency: usize,
+    max_concurrent_calls: Option<usize>,
+    metrics: Arc<RwLock<HashMap<String, ComponentMetrics>>>,
+    prometheus: PrometheusMetrics,
+    lazy_components: Arc<Mutex<HashMap<String, String>>>,
+    component_metadata: Arc<RwLock<HashMap<String, ComponentMetadata>>>,
+    result_cache: Arc<Mutex<ResultCache>>,
+    tools_changed_subscribers: Arc<Mutex<Vec<ToolsChangedCallback>>>,
+    base_policy: Option<Arc<WasiStateTemplate>>,
+    load_errors: Arc<Vec<(String, anyhow::Error)>>,
+    schema_cache: Arc<Mutex<SchemaCache>>,
+    /// The number of times a policy file was written
+    #[cfg(test)]
+    policy_writes: Arc<std::sync::atomic::AtomicUsize>,
+    #[cfg(feature = "opentelemetry")]
+    tracer: Option<Arc<opentelemetry::global::BoxedTracer>>,
 }
 
+/// A component linked with the interfaces provided to components, ready to be instantiated
+type ComponentPre = InstancePre<WassetteWasiState<WasiState>>;
+
 /// A representation of a loaded component instance. It contains both the base component info and a
 /// pre-instantiated component ready for execution
 #[derive(Clone)]
 pub struct ComponentInstance {
     component: Arc<Component>,
     instance_pre: Arc<InstancePre<WassetteWasiState<WasiState>>>,
+    /// Pre-instantiated without HTTP and sockets on the first call under a policy that only
+    /// grants environment access, or why that failed, see
+    /// [`LifecycleManager::environment_only_pre`]
+    environment_only_pre: Arc<OnceLock<Result<ComponentPre, String>>>,
+    content_hash: Arc<str>,
+    docs: Option<Arc<ComponentDocs>>,
+    wasm_size: u64,
+    loaded_at: SystemTime,
+}
+
+/// Holds the loaded component of an ID. Calls hold a read lock on the slot for as long as they
+/// run, so that reloading a component under the same ID waits for the calls of the old component
+/// to finish before swapping it, and new calls wait for the swap.
+#[derive(Clone)]
+struct ComponentSlot {
+    instance: Arc<RwLock<Arc<ComponentInstance>>>,
+    /// Bounds the number of calls of the component running at the same time, if limited
+    calls: Option<Arc<Semaphore>>,
+    /// Counts the calls of the component, see [`LifecycleManager::unload_component_graceful`]
+    tracker: Arc<CallTracker>,
+}
+
+impl ComponentSlot {
+    fn new(instance: ComponentInstance, max_concurrent_calls: Option<usize>) -> Self {
+        Self {
+            instance: Arc::new(RwLock::new(Arc::new(instance))),
+            calls: max_concurrent_calls.map(|max| Arc::new(Semaphore::new(max))),
+            tracker: Arc::default(),
+        }
+    }
+
+    /// Returns the component currently loaded in the slot
+    async fn instance(&self) -> Arc<ComponentInstance> {
+        self.instance.read().await.clone()
+    }
+
+    /// Makes `instance` the component loaded in the slot once the calls running the current one
+    /// have finished, returning the one it replaces
+    async fn replace(&self, instance: ComponentInstance) -> Arc<ComponentInstance> {
+        std::mem::replace(&mut *self.instance.write().await, Arc::new(instance))
+    }
+}
+
+/// A builder for a [`LifecycleManager`], for when the defaults used by
+/// [`LifecycleManager::new`] need to be changed
+pub struct LifecycleManagerBuilder {
+    plugin_dir: PathBuf,
+    cache_dir: Option<PathBuf>,
+    precompiled_components: bool,
+    environment_vars: HashMap<String, String>,
+    oci_client: Option<oci_client::Client>,
+    registry_credentials: Option<RegistryCredentials>,
+    http_client: Option<reqwest::Client>,
+    retry_policy: RetryPolicy,
+    execution_pool: ExecutionPool,
+    startup_progress: Option<ProgressCallback>,
+    import_filter: ImportFilter,
+    http_mock: Option<Arc<dyn HttpInterceptor>>,
+    resolved_address_checks: bool,
+    result_format: ResultFormat,
+    component_docs: bool,
+    package_ids: bool,
+    call_timeout: Option<Duration>,
+    compile_concurrency: usize,
+    max_concurrent_calls: Option<usize>,
+    #[cfg(feature = "opentelemetry")]
+    tracer: Option<Arc<opentelemetry::global::BoxedTracer>>,
+}
+
+impl LifecycleManagerBuilder {
+    fn new(plugin_dir: impl AsRef<Path>) -> Self {
+        Self {
+            plugin_dir: plugin_dir.as_ref().to_path_buf(),
+            cache_dir: None,
+            precompiled_components: false,
+            environment_vars: HashMap::new(),
+            oci_client: None,
+            registry_credentials: None,
+            http_client: None,
+            retry_policy: RetryPolicy::default(),
+            execution_pool: ExecutionPool::default(),
+            startup_progress: None,
+            import_filter: ImportFilter::default(),
+            http_mock: None,
+            resolved_address_checks: false,
+            result_format: ResultFormat::default(),
+            component_docs: false,
+            package_ids: false,
+            call_timeout: Some(DEFAULT_CALL_TIMEOUT),
+            compile_concurrency: reconcile::default_compile_concurrency(),
+            max_concurrent_calls: None,
+            #[cfg(feature = "opentelemetry")]
+            tracer: None,
+        }
+    }
+
+    /// Sets the directory compiled components are cached in. Defaults to the `.cache` directory
+    /// of the plugin directory. The directory is created only accessible to the current user.
+    pub fn with_cache_dir(mut self, cache_dir: impl AsRef<Path>) -> Self {
+        self.cache_dir = Some(cache_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets whether components precompiled with [`LifecycleManager::precompile_component`] are
+    /// loaded instead of compiling their `.wasm` file. Off by default.
+    pub fn with_precompiled_components(mut self, enabled: bool) -> Self {
+        self.precompiled_components = enabled;
+        self
+    }
+
+    /// Sets the environment variables that policies can expose to components
+    pub fn with_environment_vars(mut self, environment_vars: HashMap<String, String>) -> Self {
+        self.environment_vars = environment_vars;
+        self
+    }
+
+    /// Sets the client used to pull components from OCI registries
+    pub fn with_oci_client(mut self, oci_client: oci_client::Client) -> Self {
+        self.oci_client = Some(oci_client);
+        self
+    }
+
+    /// Sets the credentials used to pull components from private OCI registries. Defaults to the
+    /// credentials in the Docker config, see [`RegistryCredentials::from_docker_config`].
+    pub fn with_registry_credentials(mut self, credentials: RegistryCredentials) -> Self {
+        self.registry_credentials = Some(credentials);
+        self
+    }
+
+    /// Sets the client used to download components and policies over HTTP
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Sets how downloads of components and policies are retried after transient network or
+    /// server failures. Defaults to 3 attempts, see [`RetryPolicy`].
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the [`ExecutionPool`] used to run component calls.
+    ///
+    /// With [`ExecutionPool::Dedicated`], calls are spawned onto a separate runtime sized to the
+    /// available parallelism, so that CPU-heavy components don't block the caller's runtime.
+    pub fn with_execution_pool(mut self, execution_pool: ExecutionPool) -> Self {
+        self.execution_pool = execution_pool;
+        self
+    }
+
+    /// Sets a callback that is invoked for every component processed while loading the plugin
+    /// directory on startup
+    pub fn with_startup_progress(mut self, callback: ProgressCallback) -> Self {
+        self.startup_progress = Some(callback);
+        self
+    }
+
+    /// Sets the [`ImportFilter`] that components are checked against when they are loaded.
+    /// Components importing forbidden interfaces are refused, whatever their policy grants.
+    pub fn with_import_filter(mut self, import_filter: ImportFilter) -> Self {
+        self.import_filter = import_filter;
+        self
+    }
+
+    /// Sets an [`HttpInterceptor`] that answers the outgoing HTTP requests of all components
+    /// instead of the network. Requests are still checked against each component's network
+    /// policy first. Meant for testing components without network access.
+    pub fn with_http_mock(mut self, interceptor: Arc<dyn HttpInterceptor>) -> Self {
+        self.http_mock = Some(interceptor);
+        self
+    }
+
+    /// Sets whether hostnames allowed by a host rule of a policy must also resolve to an address
+    /// in one of the policy's allowed CIDR ranges, for policies that allow any. Off by default.
+    pub fn with_resolved_address_checks(mut self, enabled: bool) -> Self {
+        self.resolved_address_checks = enabled;
+        self
+    }
+
+    /// Sets the [`ResultFormat`] used to render the results of component calls
+    pub fn with_result_format(mut self, result_format: ResultFormat) -> Self {
+        self.result_format = result_format;
+        self
+    }
+
+    /// Sets whether the WIT doc comments embedded in components are used as tool and field
+    /// descriptions. Decoding the WIT adds to the load time of every component, so this is off by
+    /// default.
+    pub fn with_component_docs(mut self, enabled: bool) -> Self {
+        self.component_docs = enabled;
+        self
+    }
+
+    /// Sets whether loaded components are identified by their WIT package
+    /// (`namespace:name@version`, made safe for file names) rather than by their file name, so
+    /// that the same component downloaded under two names is only loaded once. Components without
+    /// a package name, which most toolchains produce, still use their file name. Off by default.
+    pub fn with_package_ids(mut self, enabled: bool) -> Self {
+        self.package_ids = enabled;
+        self
+    }
+
+    /// Sets how long a single component call may run before it is aborted with an error. Defaults
+    /// to [`DEFAULT_CALL_TIMEOUT`]; `None` lets calls run for as long as they like, unless the
+    /// policy of their component sets a timeout.
+    pub fn with_call_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    /// Sets how many components are compiled at the same time when loading the plugin directory.
+    /// Defaults to the number of available CPUs; `1` compiles them one after the other.
+    pub fn with_compile_concurrency(mut self, concurrency: usize) -> Self {
+        self.compile_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets how many calls of the same component may run at the same time. Calls over the limit
+    /// wait for a running call to finish instead of failing. Every call still gets its own
+    /// instance of the component. Unlimited by default.
+    pub fn with_max_concurrent_calls(mut self, max_calls: Option<usize>) -> Self {
+        self.max_concurrent_calls = max_calls.map(|max| max.max(1));
+        self
+    }
+
+    /// Creates the lifecycle manager and loads all components found in the plugin directory
+    #[instrument(skip_all, fields(plugin_dir = %self.plugin_dir.display()))]
+    pub async fn build(self) -> Result<LifecycleManager> {
+        if !self.plugin_dir.exists() {
+            fs::create_dir_all(&self.plugin_dir)?;
+        }
+
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        config.async_support(true);
+        config.consume_fuel(true);
+        // Epoch interruption is always on, since policies can set a timeout even when the manager
+        // has none, and only a deadline can stop wasm that never yields
+        config.epoch_interruption(true);
+        let engine = Arc::new(wasmtime::Engine::new(&config)?);
+        spawn_epoch_ticker(Arc::downgrade(&engine))?;
+
+        let mut manager = LifecycleManager::new_with_policy(
+            engine,
+            &self.plugin_dir,
+            self.environment_vars,
+            self.oci_client.unwrap_or_default(),
+            self.http_client.unwrap_or_default(),
+        )
+        .await?;
+        manager.execution_runtime = match self.execution_pool {
+            ExecutionPool::Shared => None,
+            ExecutionPool::Dedicated => Some(Arc::new(DedicatedRuntime::new()?)),
+        };
+        manager.registry_credentials = Arc::new(
+            self.registry_credentials
+                .unwrap_or_else(RegistryCredentials::from_docker_config),
+        );
+        if let Some(cache_dir) = self.cache_dir {
+            manager.cache_dir = cache_dir;
+        }
+        manager.precompiled_components = self.precompiled_components;
+        manager.retry_policy = self.retry_policy;
+        manager.import_filter = Arc::new(self.import_filter);
+        manager.http_mock = self.http_mock;
+        manager.resolved_address_checks = self.resolved_address_checks;
+        manager.result_format = self.result_format;
+        manager.component_docs = self.component_docs;
+        manager.package_ids = self.package_ids;
+        manager.call_timeout = self.call_timeout;
+        manager.compile_concurrency = self.compile_concurrency;
+        manager.max_concurrent_calls = self.max_concurrent_calls;
+        #[cfg(feature = "opentelemetry")]
+        {
+            manager.tracer = self.tracer;
+        }
+
+        // Components that fail to load are logged and skipped, so that the server still comes up
+        // with the others
+        let (_, load_errors) = match &self.startup_progress {
+            Some(callback) => manager.reconcile_collecting_errors(|p| callback(p)).await?,
+            None => manager.reconcile_collecting_errors(|_| {}).await?,
+        };
+        manager.load_errors = Arc::new(load_errors);
+
+        info!("LifecycleManager initialized successfully");
+        Ok(manager)
+    }
 }
 
 impl LifecycleManager {
@@ -181,29 +812,22 @@ impl LifecycleManager {
         oci_client: oci_client::Client,
         http_client: reqwest::Client,
     ) -> Result<Self> {
-        let components_dir = plugin_dir.as_ref();
-
-        if !components_dir.exists() {
-            fs::create_dir_all(components_dir)?;
-        }
-
-        let mut config = wasmtime::Config::new();
-        config.wasm_component_model(true);
-        config.async_support(true);
-        let engine = Arc::new(wasmtime::Engine::new(&config)?);
+        Self::builder(plugin_dir)
+            .with_environment_vars(environment_vars)
+            .with_oci_client(oci_client)
+            .with_http_client(http_client)
+            .build()
+            .await
+    }
 
-        // Create the lifecycle manager
-        Self::new_with_policy(
-            engine,
-            components_dir,
-            environment_vars,
-            oci_client,
-            http_client,
-        )
-        .await
+    /// Returns a [`LifecycleManagerBuilder`] for configuring a lifecycle manager before any
+    /// components are loaded from the given plugin directory
+    pub fn builder(plugin_dir: impl AsRef<Path>) -> LifecycleManagerBuilder {
+        LifecycleManagerBuilder::new(plugin_dir)
     }
 
-    /// Creates a lifecycle manager with custom clients and WASI state template
+    /// Creates a lifecycle manager with custom clients and WASI state template. No components are
+    /// loaded yet, that is done by a subsequent [`LifecycleManager::reconcile`]
     #[instrument(skip_all)]
     async fn new_with_policy(
         engine: Arc<Engine>,
@@ -214,66 +838,8 @@ impl LifecycleManager {
     ) -> Result<Self> {
         info!("Creating new LifecycleManager");
 
-        let mut registry = ComponentRegistry::new();
-        let mut components = HashMap::new();
-        let mut policy_registry = PolicyRegistry::default();
-
-        let mut linker = Linker::new(engine.as_ref());
-        wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
-
-        // Use the standard HTTP linker - filtering happens at WasiHttpView level
-        wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
-
-        wasmtime_wasi_config::add_to_linker(
-            &mut linker,
-            |h: &mut WassetteWasiState<WasiState>| WasiConfig::from(&h.inner.wasi_config_vars),
-        )?;
-
-        let linker = Arc::new(linker);
-
-        let loaded_components =
-            load_components_parallel(plugin_dir.as_ref(), &engine, &linker).await?;
-
-        for (component_instance, name) in loaded_components.into_iter() {
-            let tool_metadata =
-                component_exports_to_tools(&component_instance.component, &engine, true);
-            registry
-                .register_tools(&name, tool_metadata)
-                .context("unable to insert component into registry")?;
-            components.insert(name.clone(), component_instance);
-
-            // Check for co-located policy file and restore policy association
-            let policy_path = plugin_dir.as_ref().join(format!("{name}.policy.yaml"));
-            if policy_path.exists() {
-                match tokio::fs::read_to_string(&policy_path).await {
-                    Ok(policy_content) => match PolicyParser::parse_str(&policy_content) {
-                        Ok(policy) => {
-                            match wasistate::create_wasi_state_template_from_policy(
-                                &policy,
-                                plugin_dir.as_ref(),
-                                &environment_vars,
-                            ) {
-                                Ok(wasi_template) => {
-                                    policy_registry
-                                        .component_policies
-                                        .insert(name.clone(), Arc::new(wasi_template));
-                                    info!(component_id = %name, "Restored policy association from co-located file");
-                                }
-                                Err(e) => {
-                                    warn!(component_id = %name, error = %e, "Failed to create WASI template from policy");
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!(component_id = %name, error = %e, "Failed to parse co-located policy file");
-                        }
-                    },
-                    Err(e) => {
-                        warn!(component_id = %name, error = %e, "Failed to read co-located policy file");
-                    }
-                }
-            }
-        }
+        let linker = Arc::new(build_linker(&engine)?);
+        let environment_only_linker = Arc::new(build_environment_only_linker(&engine)?);
 
         // Make sure the plugin dir exists and also create a subdirectory for temporary staging of downloaded files
         tokio::fs::create_dir_all(&plugin_dir)
@@ -282,75 +848,311 @@ impl LifecycleManager {
         tokio::fs::create_dir_all(plugin_dir.as_ref().join(DOWNLOADS_DIR))
             .await
             .context("Failed to create downloads directory")?;
+        let base_policy =
+            policy_internal::load_base_policy(plugin_dir.as_ref(), &environment_vars).await?;
 
-        info!("LifecycleManager initialized successfully");
         Ok(Self {
             engine,
             linker,
-            components: Arc::new(RwLock::new(components)),
-            registry: Arc::new(RwLock::new(registry)),
-            policy_registry: Arc::new(RwLock::new(policy_registry)),
+            environment_only_linker,
+            components: Arc::new(RwLock::new(HashMap::new())),
+            registry: Arc::new(RwLock::new(ComponentRegistry::new())),
+            policy_registry: Arc::new(RwLock::new(PolicyRegistry::default())),
             oci_client: Arc::new(oci_wasm::WasmClient::new(oci_client)),
+            registry_credentials: Arc::default(),
             http_client,
+            retry_policy: RetryPolicy::default(),
             plugin_dir: plugin_dir.as_ref().to_path_buf(),
+            cache_dir: plugin_dir.as_ref().join(cache::CACHE_DIR),
+            precompiled_components: false,
             environment_vars,
+            execution_runtime: None,
+            result_transformer: Arc::new(RwLock::new(None)),
+            import_filter: Arc::new(ImportFilter::default()),
+            http_mock: None,
+            resolved_address_checks: false,
+            result_format: ResultFormat::default(),
+            component_docs: false,
+            package_ids: false,
+            call_timeout: None,
+            compile_concurrency: reconcile::default_compile_concurrency(),
+            max_concurrent_calls: None,
+            metrics: Arc::default(),
+            prometheus: PrometheusMetrics::new(),
+            lazy_components: Arc::default(),
+            component_metadata: Arc::default(),
+            result_cache: Arc::default(),
+            tools_changed_subscribers: Arc::default(),
+            base_policy,
+            load_errors: Arc::default(),
+            schema_cache: Arc::default(),
+            #[cfg(test)]
+            policy_writes: Arc::default(),
+            #[cfg(feature = "opentelemetry")]
+            tracer: None,
         })
     }
 
     /// Loads a new component from the given URI. This URI can be a file path, an OCI reference, or a URL.
     ///
     /// If a component with the given id already exists, it will be updated with the new component.
-    /// Returns the new ID and whether or not this component was replaced.
+    /// If the already loaded component is byte-for-byte identical, it is left untouched and
+    /// [`LoadResult::Unchanged`] is returned. Returns the new ID and whether or not this component
+    /// was replaced.
+    ///
+    /// The URI can pin the SHA-256 digest of the component with a `#sha256=<hex>` fragment, e.g.
+    /// `oci://ghcr.io/org/tool:1.0#sha256=...`. The downloaded bytes are then checked against it
+    /// before the component is compiled, and loading fails if they don't match.
     #[instrument(skip(self))]
-    pub async fn load_component(&self, uri: &str) -> Result<(String, LoadResult)> {
-        debug!(uri, "Loading component");
+    pub async fn load_component(&self, uri: &str) -> Result<(String, LoadResult), WassetteError> {
+        self.load_component_from_uri(uri, None).await
+    }
 
-        let downloaded_resource =
-            loader::load_resource::<ComponentResource>(uri, &self.oci_client, &self.http_client)
-                .await?;
+    /// Loads a component like [`LifecycleManager::load_component`] and attaches a policy to it
+    /// like [`LifecycleManager::attach_policy`], without a window in which the component can be
+    /// called without the policy.
+    ///
+    /// The policy is downloaded and validated before the component is compiled. If it is invalid,
+    /// the component isn't loaded. The policy is applied before the component is made available
+    /// for calls.
+    #[instrument(skip(self))]
+    pub async fn load_component_with_policy(
+        &self,
+        uri: &str,
+        policy_uri: &str,
+    ) -> Result<(String, LoadResult), WassetteError> {
+        self.load_component_from_uri(uri, Some(policy_uri)).await
+    }
+
+    async fn load_component_from_uri(
+        &self,
+        uri: &str,
+        policy_uri: Option<&str>,
+    ) -> Result<(String, LoadResult), WassetteError> {
+        debug!(uri, "Loading component");
+        let (uri, expected_digest) = loader::split_digest(uri)?;
+
+        let downloaded_resource = loader::load_resource::<ComponentResource>(
+            uri,
+            &self.oci_client,
+            &self.registry_credentials,
+            &self.http_client,
+            &self.retry_policy,
+        )
+        .await
+        .map_err(WassetteError::DownloadFailed)?;
 
         let wasm_bytes = tokio::fs::read(downloaded_resource.as_ref())
             .await
-            .context("Failed to read component file")?;
+            .context("Failed to read component file")
+            .map_err(WassetteError::DownloadFailed)?;
+        let package_id = self
+            .package_ids
+            .then(|| loader::DownloadedResource::package_id(&wasm_bytes))
+            .flatten();
+        let id = match &package_id {
+            Some(id) => id.clone(),
+            None => downloaded_resource.id()?,
+        };
+        let content_hash = reconcile::content_hash(&wasm_bytes);
+        if let Some(expected_digest) = expected_digest {
+            if expected_digest != content_hash {
+                return Err(WassetteError::DigestMismatch {
+                    uri: uri.to_string(),
+                    expected: expected_digest,
+                    actual: content_hash,
+                });
+            }
+        }
 
-        let component = Component::new(&self.engine, wasm_bytes).map_err(|e| anyhow::anyhow!("Failed to compile component from path: {}. Error: {}. Please ensure the file is a valid WebAssembly component.", downloaded_resource.as_ref().display(), e))?;
-        // Pre-instantiate the component
-        let instance_pre = self.linker.instantiate_pre(&component)?;
-        let id = downloaded_resource.id()?;
-        let tool_metadata = component_exports_to_tools(&component, &self.engine, true);
+        let policy = match policy_uri {
+            Some(policy_uri) => Some(self.prepare_policy(policy_uri).await?),
+            None => None,
+        };
 
-        {
-            let mut registry_write = self.registry.write().await;
-            registry_write.unregister_component(&id);
-            registry_write.register_tools(&id, tool_metadata)?;
+        // Components identified by their package are stored under that ID, so that loading them
+        // again under another file name replaces the same file
+        let source = format!("path: {}", downloaded_resource.as_ref().display());
+        let persist = async {
+            match package_id {
+                Some(_) => {
+                    downloaded_resource
+                        .copy_as(&self.plugin_dir, format!("{id}.wasm"))
+                        .await
+                }
+                None => downloaded_resource.copy_to(&self.plugin_dir).await,
+            }
+        };
+        let res = self
+            .compile_and_install(&id, &wasm_bytes, content_hash, &source, persist, policy)
+            .await?;
+        Ok((id, res))
+    }
+
+    /// Loads a component from bytes that are already in memory, e.g. bundled in the embedding
+    /// binary, under the given ID. The bytes are written to `{id}.wasm` in the plugin directory,
+    /// so the component is loaded again on the next start.
+    ///
+    /// The ID must only contain ASCII letters, digits, `-`, `_` and `.`, so that it can be used as
+    /// a file name. Like [`LifecycleManager::load_component`], a component already loaded under
+    /// that ID is replaced, unless it is byte-for-byte identical.
+    #[instrument(skip(self, bytes))]
+    pub async fn load_component_bytes(
+        &self,
+        id: &str,
+        bytes: &[u8],
+    ) -> Result<LoadResult, WassetteError> {
+        validate_component_id(id)?;
+        let content_hash = reconcile::content_hash(bytes);
+        let path = self.plugin_dir.join(format!("{id}.wasm"));
+        let persist = async { Ok(tokio::fs::write(&path, bytes).await?) };
+        self.compile_and_install(id, bytes, content_hash, "in-memory bytes", persist, None)
+            .await
+    }
+
+    /// Compiles a component, stores it in the plugin directory with `persist`, installs it under
+    /// `id` after attaching `policy` to it if given, and registers its tools. `source` describes
+    /// where the bytes came from, for errors.
+    async fn compile_and_install(
+        &self,
+        id: &str,
+        wasm_bytes: &[u8],
+        content_hash: String,
+        source: &str,
+        persist: impl std::future::Future<Output = Result<()>>,
+        policy: Option<PreparedPolicy>,
+    ) -> Result<LoadResult, WassetteError> {
+        validate_component_id(id)?;
+        if self.is_loaded_component_identical(id, &content_hash).await {
+            info!(component_id = %id, "Component is already loaded and unchanged");
+            if let Some(policy) = policy {
+                self.install_policy(id, policy).await?;
+            }
+            return Ok(LoadResult::Unchanged);
         }
 
-        if let Err(e) = downloaded_resource.copy_to(&self.plugin_dir).await {
-            let mut registry_write = self.registry.write().await;
-            registry_write.unregister_component(&id);
-            bail!(
+        let docs = self.decode_component_docs(id, wasm_bytes);
+        let component = cache::load_or_compile(&self.engine, &self.cache_dir, wasm_bytes).map_err(|e| WassetteError::CompileFailed(anyhow::anyhow!("Failed to compile component from {}. Error: {}. Please ensure the file is a valid WebAssembly component.", source, e)))?;
+        self.import_filter
+            .check_component(&component, &self.engine)
+            .with_context(|| format!("Refusing to load component {id}"))
+            .map_err(WassetteError::ImportDenied)?;
+        // Pre-instantiate the component
+        let instance_pre = self
+            .linker
+            .instantiate_pre(&component)
+            .map_err(WassetteError::CompileFailed)?;
+        let tool_metadata = self.component_tools(&component, docs.as_deref());
+
+        if let Err(e) = persist.await {
+            return Err(anyhow!(
                 "Failed to copy component to destination: {}. Error: {}",
                 self.plugin_dir.display(),
                 e
-            );
+            )
+            .into());
+        }
+
+        // Applied before the component can be called, so that no call runs without it
+        if let Some(policy) = policy {
+            self.install_policy(id, policy).await?;
         }
 
         let res = self
-            .components
-            .write()
-            .await
-            .insert(
-                id.clone(),
+            .install_component(
+                id,
                 ComponentInstance {
                     component: Arc::new(component),
                     instance_pre: Arc::new(instance_pre),
+                    environment_only_pre: Arc::default(),
+                    content_hash: content_hash.into(),
+                    docs,
+                    wasm_size: wasm_bytes.len() as u64,
+                    loaded_at: SystemTime::now(),
                 },
             )
-            .map(|_| LoadResult::Replaced)
-            .unwrap_or(LoadResult::New);
+            .await;
+        // Registered once the component is in place, so that its tools never name functions of
+        // the component it replaces
+        {
+            let mut registry_write = self.registry.write().await;
+            registry_write.unregister_component(id);
+            registry_write.register_tools(id, tool_metadata)?;
+        }
 
-        info!("Successfully loaded component");
-        Ok((id, res))
+        info!(component_id = %id, "Successfully loaded component");
+        self.notify_tools_changed();
+        Ok(res)
+    }
+
+    /// Makes `instance` the loaded component of `id`. A component already loaded under that ID is
+    /// only replaced once the calls running it have finished.
+    async fn install_component(&self, id: &str, instance: ComponentInstance) -> LoadResult {
+        self.result_cache.lock().unwrap().remove_component(id);
+        let slot = {
+            let mut components = self.components.write().await;
+            match components.get(id) {
+                Some(slot) => slot.clone(),
+                None => {
+                    components.insert(
+                        id.to_string(),
+                        ComponentSlot::new(instance, self.max_concurrent_calls),
+                    );
+                    self.prometheus.set_components_loaded(components.len());
+                    return LoadResult::New;
+                }
+            }
+        };
+        let new_hash = instance.content_hash.clone();
+        // The map isn't locked while waiting, so calls of other components go on
+        let old = slot.replace(instance).await;
+        if old.content_hash != new_hash {
+            self.release_compiled(&old.content_hash).await;
+        }
+        LoadResult::Replaced
+    }
+
+    /// Decodes the WIT docs of a component, if enabled with
+    /// [`LifecycleManagerBuilder::with_component_docs`]. Components whose WIT can't be decoded are
+    /// still loaded, with the generated descriptions.
+    fn decode_component_docs(
+        &self,
+        component_id: &str,
+        wasm_bytes: &[u8],
+    ) -> Option<Arc<ComponentDocs>> {
+        if !self.component_docs {
+            return None;
+        }
+        match ComponentDocs::decode(wasm_bytes) {
+            Ok(docs) => Some(Arc::new(docs)),
+            Err(e) => {
+                warn!(component_id, error = %e, "Failed to decode component docs");
+                None
+            }
+        }
+    }
+
+    /// Returns the tools exported by a component, described with `docs` if available
+    fn component_tools(
+        &self,
+        component: &Component,
+        docs: Option<&ComponentDocs>,
+    ) -> Vec<ToolMetadata> {
+        component_exports_to_tools_with_options(
+            component,
+            &self.engine,
+            true,
+            docs,
+            SchemaOptions::default(),
+        )
+    }
+
+    /// Returns true if a component with the given id is loaded and was built from content with the
+    /// given hash
+    async fn is_loaded_component_identical(&self, component_id: &str, content_hash: &str) -> bool {
+        self.loaded_component(component_id)
+            .await
+            .is_some_and(|instance| &*instance.content_hash == content_hash)
     }
 
     /// Helper function to remove a file with consistent logging and error handling
@@ -391,8 +1193,9 @@ impl LifecycleManager {
     /// and removes all associated files from disk, making it the reverse operation of load_component.
     /// This function fails if any files cannot be removed (except when they don't exist).
     #[instrument(skip(self))]
-    pub async fn unload_component(&self, id: &str) -> Result<()> {
+    pub async fn unload_component(&self, id: &str) -> Result<(), WassetteError> {
         debug!("Unloading component and removing files from disk");
+        validate_component_id(id)?;
 
         // Remove files first, then clean up memory on success
         let component_file = self.component_path(id);
@@ -407,49 +1210,208 @@ impl LifecycleManager {
         self.remove_file_if_exists(&metadata_path, "policy metadata file", id)
             .await?;
 
+        let meta_path = self.get_component_meta_path(id);
+        self.remove_file_if_exists(&meta_path, "component metadata file", id)
+            .await?;
+
+        let precompiled_path = cache::precompiled_path(&self.plugin_dir, id);
+        self.remove_file_if_exists(&precompiled_path, "precompiled component", id)
+            .await?;
+
         // Only cleanup memory after all files are successfully removed
-        self.components.write().await.remove(id);
-        self.registry.write().await.unregister_component(id);
-        self.cleanup_policy_registry(id).await;
+        if self.forget_component(id).await {
+            self.notify_tools_changed();
+        }
 
         info!(component_id = %id, "Component unloaded successfully");
         Ok(())
     }
 
-    /// Returns the component ID for a given tool name.
-    /// If there are multiple components with the same tool name, returns an error.
-    #[instrument(skip(self))]
-    pub async fn get_component_id_for_tool(&self, tool_name: &str) -> Result<String> {
-        let registry = self.registry.read().await;
-        let tool_infos = registry
-            .get_tool_info(tool_name)
-            .context("Tool not found")?;
-
-        if tool_infos.len() > 1 {
-            bail!(
-                "Multiple components found for tool '{}': {}",
-                tool_name,
-                tool_infos
-                    .iter()
-                    .map(|info| info.component_id.as_str())
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            );
+    /// Drops the compile cache entry and schema compiled from the contents with the given hash,
+    /// unless another loaded component was loaded from the same contents
+    async fn release_compiled(&self, content_hash: &str) {
+        let slots: Vec<ComponentSlot> = self.components.read().await.values().cloned().collect();
+        for slot in slots {
+            if &*slot.instance().await.content_hash == content_hash {
+                return;
+            }
         }
+        cache::remove(&self.cache_dir, content_hash);
+        if let Some(digest) = reconcile::content_digest(content_hash) {
+            self.schema_cache.lock().unwrap().remove(&digest);
+        }
+    }
 
-        Ok(tool_infos[0].component_id.clone())
+    /// Removes a component and its tools from memory, leaving the files in the plugin directory
+    /// alone. Returns whether the component was loaded.
+    async fn forget_component(&self, id: &str) -> bool {
+        let slot = {
+            let mut components = self.components.write().await;
+            let slot = components.remove(id);
+            self.prometheus.set_components_loaded(components.len());
+            slot
+        };
+        if let Some(slot) = &slot {
+            let content_hash = slot.instance().await.content_hash.clone();
+            self.release_compiled(&content_hash).await;
+        }
+        self.registry.write().await.unregister_component(id);
+        self.metrics.write().await.remove(id);
+        self.prometheus.remove_component(id);
+        self.lazy_components.lock().unwrap().remove(id);
+        self.component_metadata.write().await.remove(id);
+        self.result_cache.lock().unwrap().remove_component(id);
+        self.cleanup_policy_registry(id).await;
+        {
+            let mut policy_registry = self.policy_registry.write().await;
+            policy_registry.fuel_limits.remove(id);
+            policy_registry.memory_limits.remove(id);
+        }
+        slot.is_some()
     }
 
-    /// Lists all available tools across all components
+    /// Loads several components concurrently. A component that fails to load doesn't prevent the
+    /// others from loading: the outcome for every URI is reported individually, in the order the
+    /// URIs were given, along with the component ID and [`LoadResult`] on success.
+    #[instrument(skip(self))]
+    pub async fn load_components(&self, uris: &[String]) -> Vec<BatchLoadResult> {
+        futures::future::join_all(
+            uris.iter()
+                .map(|uri| async move { (uri.clone(), self.load_component(uri).await) }),
+        )
+        .await
+    }
+
+    /// Unloads several components. A component that fails to unload doesn't prevent the others
+    /// from being unloaded: the outcome for every ID is reported individually, in the order the
+    /// IDs were given.
+    #[instrument(skip(self))]
+    pub async fn unload_components(
+        &self,
+        ids: &[String],
+    ) -> Vec<(String, Result<(), WassetteError>)> {
+        futures::future::join_all(
+            ids.iter()
+                .map(|id| async move { (id.clone(), self.unload_component(id).await) }),
+        )
+        .await
+    }
+
+    /// Returns the component ID for a given tool name.
+    /// If there are multiple components with the same tool name, returns an error.
+    ///
+    /// The tool name can be qualified with the ID of a component, as in `component_id::tool_name`
+    /// (see [`TOOL_NAME_SEPARATOR`]), to pick the tool of that component when several export it.
+    /// A name no component exports resolves to the component that provides it in its metadata,
+    /// see [`ComponentMetadata::provides`].
+    #[instrument(skip(self))]
+    pub async fn get_component_id_for_tool(
+        &self,
+        tool_name: &str,
+    ) -> Result<String, WassetteError> {
+        let registry = self.registry.read().await;
+        if let (Some(component_id), unqualified) = split_qualified_tool_name(tool_name) {
+            return registry
+                .get_component_tool(component_id, unqualified)
+                .map(|tool_info| tool_info.component_id.clone())
+                .ok_or_else(|| WassetteError::ToolNotFound(tool_name.to_string()));
+        }
+
+        let Some(tool_infos) = registry.get_tool_info(tool_name) else {
+            // Fall back to the names components declare in their metadata
+            let mut components = self.components_providing(tool_name).await;
+            return match components.len() {
+                0 => Err(WassetteError::ToolNotFound(tool_name.to_string())),
+                1 => Ok(components.remove(0)),
+                _ => Err(WassetteError::ToolAmbiguous {
+                    tool: tool_name.to_string(),
+                    components,
+                }),
+            };
+        };
+
+        if tool_infos.len() > 1 {
+            return Err(WassetteError::ToolAmbiguous {
+                tool: tool_name.to_string(),
+                components: tool_infos
+                    .iter()
+                    .map(|info| info.component_id.clone())
+                    .collect(),
+            });
+        }
+
+        Ok(tool_infos[0].component_id.clone())
+    }
+
+    /// Returns the names of the tools exported by the given component, if it is loaded
+    #[instrument(skip(self))]
+    pub async fn get_component_tool_names(&self, component_id: &str) -> Option<Vec<String>> {
+        self.registry
+            .read()
+            .await
+            .component_map
+            .get(component_id)
+            .cloned()
+    }
+
+    /// Lists all available tools across all components
     #[instrument(skip(self))]
     pub async fn list_tools(&self) -> Vec<Value> {
-        self.registry.read().await.list_tools()
+        self.list_tools_detailed()
+            .await
+            .into_iter()
+            .map(|tool| tool.schema)
+            .collect()
+    }
+
+    /// Lists all available tools across all components, along with the component exporting each
+    /// of them
+    #[instrument(skip(self))]
+    pub async fn list_tools_detailed(&self) -> Vec<ToolDescriptor> {
+        self.registry.read().await.list_tools_detailed()
     }
 
     /// Returns the requested component. Returns `None` if the component is not found.
+    ///
+    /// A component registered with [`LifecycleManager::register_component_lazy`] is loaded first,
+    /// and `None` is returned if that fails.
     #[instrument(skip(self))]
     pub async fn get_component(&self, component_id: &str) -> Option<ComponentInstance> {
-        self.components.read().await.get(component_id).cloned()
+        if let Err(e) = self.load_if_lazy(component_id).await {
+            warn!(component_id, error = %e, "Failed to load lazily registered component");
+            return None;
+        }
+        self.loaded_component(component_id).await
+    }
+
+    /// Returns the component loaded under the given ID, without loading lazily registered ones
+    async fn loaded_component(&self, component_id: &str) -> Option<ComponentInstance> {
+        let slot = self.components.read().await.get(component_id).cloned()?;
+        let instance = slot.instance().await;
+        Some(ComponentInstance::clone(&instance))
+    }
+
+    /// Returns the size, hash, load time and number of tools of a loaded component
+    pub async fn component_info(&self, component_id: &str) -> Option<ComponentInfo> {
+        let instance = self.loaded_component(component_id).await?;
+        let tool_count = self
+            .registry
+            .read()
+            .await
+            .component_tool_count(component_id);
+        Some(ComponentInfo {
+            id: component_id.to_string(),
+            wasm_size_bytes: instance.wasm_size,
+            sha256: reconcile::content_digest(&instance.content_hash).unwrap_or_default(),
+            loaded_at: instance.loaded_at,
+            tool_count,
+        })
+    }
+
+    /// Returns the ID and error of every component in the plugin directory that failed to load
+    /// when the manager was created
+    pub fn load_errors(&self) -> &[(String, anyhow::Error)] {
+        &self.load_errors
     }
 
     /// Lists all loaded components by their IDs
@@ -461,16 +1423,64 @@ impl LifecycleManager {
     /// Gets the schema for a specific component
     #[instrument(skip(self))]
     pub async fn get_component_schema(&self, component_id: &str) -> Option<Value> {
-        let component_instance = self.get_component(component_id).await?;
-        Some(component_exports_to_json_schema(
-            &component_instance.component,
-            self.engine.as_ref(),
-            true,
-        ))
+        let component_instance = self.loaded_component(component_id).await?;
+        let component = &component_instance.component;
+        let compute = || {
+            component_exports_to_json_schema_with_options(
+                component,
+                &self.engine,
+                true,
+                component_instance.docs.as_deref(),
+                SchemaOptions::default(),
+            )
+        };
+        // The docs are decoded from the component binary, so the hash covers them as well
+        Some(
+            match reconcile::content_digest(&component_instance.content_hash) {
+                Some(digest) => self
+                    .schema_cache
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(digest, true, compute),
+                None => compute(),
+            },
+        )
+    }
+
+    /// The number of component schemas generated so far, the others having been served from the
+    /// schema cache
+    #[cfg(test)]
+    fn schemas_computed(&self) -> usize {
+        self.schema_cache.lock().unwrap().computed()
+    }
+
+    /// Returns the component pre-instantiated with the linker of policies that only grant
+    /// environment access, see [`build_environment_only_linker`]. It is built on the first such
+    /// call, so that other components neither pay for it nor fail to load when it can't be built,
+    /// in which case only the calls under such a policy fail.
+    fn environment_only_pre<'a>(
+        &self,
+        component: &'a ComponentInstance,
+    ) -> Result<&'a ComponentPre, WassetteError> {
+        component
+            .environment_only_pre
+            .get_or_init(|| {
+                let mut linker = Linker::clone(&self.environment_only_linker);
+                define_network_imports_as_traps(&mut linker, &self.engine, &component.component)
+                    .and_then(|()| linker.instantiate_pre(&component.component))
+                    .map_err(|e| format!("{e:#}"))
+            })
+            .as_ref()
+            .map_err(|e| {
+                WassetteError::PolicyInvalid(anyhow!(
+                    "The component isn't available to policies that only grant environment access: {e}"
+                ))
+            })
     }
 
     fn component_path(&self, component_id: &str) -> PathBuf {
-        self.plugin_dir.join(format!("{component_id}.wasm"))
+        self.plugin_dir
+            .join(component_file_name(component_id, "wasm"))
     }
 
     async fn get_wasi_state_for_component(
@@ -483,56 +1493,357 @@ impl LifecycleManager {
             .component_policies
             .get(component_id)
             .cloned()
-            .unwrap_or_else(Self::create_default_policy_template);
+            .unwrap_or_else(|| self.create_default_policy_template());
+
+        // Checked on every use, so that time-limited policies stop granting access once expired
+        if let Some(policy) = &policy_template.policy {
+            policy.validate().map_err(|e| match e {
+                PolicyError::Expired { .. } => anyhow!(
+                    "Refusing to run component '{component_id}', its policy is no longer valid: {e}"
+                ),
+                e => anyhow!(
+                    "Refusing to run component '{component_id}', its policy is invalid: {e}"
+                ),
+            })?;
+        }
 
         let wasi_state = policy_template.build()?;
         let allowed_hosts = policy_template.allowed_hosts.clone();
+        let denied_hosts = policy_template.denied_hosts.clone();
+        let allowed_ports = policy_template.allowed_ports.clone();
+        let allowed_cidrs = policy_template.allowed_cidrs.clone();
+        let denied_cidrs = policy_template.denied_cidrs.clone();
         let resource_limiter = wasi_state.resource_limiter.clone();
 
-        let wassette_wasi_state = WassetteWasiState::new(wasi_state, allowed_hosts)?;
+        let mut wassette_wasi_state = WassetteWasiState::new(wasi_state, allowed_hosts)?
+            .with_denied_hosts(denied_hosts)?
+            .with_allowed_ports(allowed_ports)?
+            .with_cidrs(allowed_cidrs, denied_cidrs)?
+            .with_resolved_address_checks(self.resolved_address_checks);
+        if let Some(mock) = &self.http_mock {
+            wassette_wasi_state = wassette_wasi_state.with_http_mock(mock.clone());
+        }
         Ok((wassette_wasi_state, resource_limiter))
     }
 
-    /// Executes a function call on a WebAssembly component
+    /// Returns the [`ResultFormat`] used to render the results of component calls
+    pub fn result_format(&self) -> ResultFormat {
+        self.result_format
+    }
+
+    /// Returns the [`ExecutionPool`] currently used to run component calls
+    pub fn execution_pool(&self) -> ExecutionPool {
+        if self.execution_runtime.is_some() {
+            ExecutionPool::Dedicated
+        } else {
+            ExecutionPool::Shared
+        }
+    }
+
+    /// Executes a function call on a WebAssembly component. `function_name` may be qualified with
+    /// the component ID, see [`LifecycleManager::get_component_id_for_tool`].
     #[instrument(skip(self))]
     pub async fn execute_component_call(
         &self,
         component_id: &str,
         function_name: &str,
         parameters: &str,
-    ) -> Result<String> {
-        let component = self
-            .get_component(component_id)
+    ) -> Result<String, WassetteError> {
+        let result = self
+            .execute_call(component_id, function_name, parameters, None)
+            .await?;
+        self.render_call_result(component_id, function_name, &result)
             .await
-            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+    }
 
-        let (state, resource_limiter) = self.get_wasi_state_for_component(component_id).await?;
+    /// Executes a function call like [`LifecycleManager::execute_component_call`], returning the
+    /// JSON result of the component instead of rendering it with the [`ResultFormat`] of the
+    /// manager. [`LifecycleManager::render_call_result`] renders it like
+    /// [`LifecycleManager::execute_component_call`] would have.
+    #[instrument(skip(self))]
+    pub async fn execute_component_call_json(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+    ) -> Result<Value, WassetteError> {
+        self.execute_call(component_id, function_name, parameters, None)
+            .await
+    }
 
-        let mut store = Store::new(self.engine.as_ref(), state);
+    /// Renders the JSON `result` of a call of `function_name` with the [`ResultFormat`] of the
+    /// manager
+    pub async fn render_call_result(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        result: &Value,
+    ) -> Result<String, WassetteError> {
+        let (_, tool_name) = split_qualified_tool_name(function_name);
+        Ok(self.render_result(component_id, tool_name, result).await?)
+    }
 
-        // Apply memory limits if configured in the policy by setting up a limiter closure
-        // that extracts the resource limiter from the WasiState
-        if resource_limiter.is_some() {
-            store.limiter(|state: &mut WassetteWasiState<WasiState>| {
-                // Extract the resource limiter from the inner state
-                state
-                    .inner
-                    .resource_limiter
-                    .as_mut()
-                    .expect("Resource limiter should be present - checked above")
-            });
+    /// Executes a function call like [`LifecycleManager::execute_component_call`], aborting it
+    /// with [`WassetteError::Timeout`] after `timeout`. If the policy of the component or the
+    /// manager sets a shorter timeout, that one applies instead.
+    ///
+    #[instrument(skip(self))]
+    pub async fn execute_component_call_with_timeout(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        timeout: Duration,
+    ) -> Result<String, WassetteError> {
+        let result = self
+            .execute_call(component_id, function_name, parameters, Some(timeout))
+            .await?;
+        self.render_call_result(component_id, function_name, &result)
+            .await
+    }
+
+    async fn execute_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Value, WassetteError> {
+        #[cfg(feature = "opentelemetry")]
+        let span = self.start_call_span(component_id, function_name);
+        let result = self
+            .execute_call_untraced(component_id, function_name, parameters, timeout)
+            .await;
+        #[cfg(feature = "opentelemetry")]
+        if let Some(span) = span {
+            span.end(&result);
         }
+        result
+    }
 
-        let instance = component.instance_pre.instantiate_async(&mut store).await?;
+    async fn execute_call_untraced(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Value, WassetteError> {
+        self.load_if_lazy(component_id).await?;
+        let start = std::time::Instant::now();
+        let cache_policy = self.result_cache_policy(component_id).await;
+        if let Some(cache_policy) = &cache_policy {
+            // Cached results are only returned to calls that would be allowed to run
+            self.check_callable(component_id).await?;
+            let cached = self.result_cache.lock().unwrap().get(
+                component_id,
+                function_name,
+                parameters,
+                cache_policy,
+            );
+            if let Some(result) = cached {
+                debug!(component_id, function_name, "Returning cached result");
+                self.record_call(component_id, function_name, start.elapsed(), None)
+                    .await;
+                return Ok(result);
+            }
+        }
+        let timeout = self.effective_call_timeout(component_id, timeout).await;
+        let call = async {
+            match &self.execution_runtime {
+                Some(runtime) => {
+                    let manager = self.clone();
+                    let component_id = component_id.to_string();
+                    let function_name = function_name.to_string();
+                    let parameters = parameters.to_string();
+                    runtime
+                        .run(async move {
+                            manager
+                                .run_component_call(
+                                    &component_id,
+                                    &function_name,
+                                    &parameters,
+                                    timeout,
+                                )
+                                .await
+                        })
+                        .await?
+                }
+                None => {
+                    self.run_component_call(component_id, function_name, parameters, timeout)
+                        .await
+                }
+            }
+        };
+        // The epoch deadline only interrupts running wasm, this also ends calls stuck in the host
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call)
+                .await
+                .unwrap_or(Err(WassetteError::Timeout(timeout))),
+            None => call.await,
+        };
+        if !matches!(
+            result,
+            Err(WassetteError::ComponentNotFound(_) | WassetteError::ComponentDraining(_))
+        ) {
+            self.record_call(
+                component_id,
+                function_name,
+                start.elapsed(),
+                result.as_ref().err(),
+            )
+            .await;
+        }
+        if let (Some(cache_policy), Ok(result)) = (&cache_policy, &result) {
+            self.result_cache.lock().unwrap().insert(
+                component_id,
+                function_name,
+                parameters,
+                result.clone(),
+                cache_policy,
+            );
+        }
+        result
+    }
 
-        // Use the new function identifier lookup instead of dot-splitting
-        let function_id = self
-            .registry
+    /// Returns how long a call of a component may run: the shortest of the execution timeout of
+    /// its policy, the manager's call timeout and the timeout of the call itself
+    async fn effective_call_timeout(
+        &self,
+        component_id: &str,
+        timeout_override: Option<Duration>,
+    ) -> Option<Duration> {
+        let policy_timeout = self
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .get(component_id)
+            .and_then(|template| template.execution_timeout);
+        [policy_timeout, self.call_timeout, timeout_override]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Fails like a call would if the component can't be called right now, because it isn't
+    /// loaded, is being unloaded or its policy can't be applied, e.g. because it expired
+    async fn check_callable(&self, component_id: &str) -> Result<(), WassetteError> {
+        let slot = self
+            .components
+            .read()
+            .await
+            .get(component_id)
+            .cloned()
+            .ok_or_else(|| WassetteError::ComponentNotFound(component_id.to_string()))?;
+        if slot.tracker.is_draining() {
+            return Err(WassetteError::ComponentDraining(component_id.to_string()));
+        }
+        self.get_wasi_state_for_component(component_id)
+            .await
+            .map_err(WassetteError::PolicyInvalid)?;
+        Ok(())
+    }
+
+    async fn run_component_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Value, WassetteError> {
+        let slot = self
+            .components
             .read()
             .await
-            .get_function_identifier(function_name)
-            .ok_or_else(|| anyhow!("Unknown tool name: {}", function_name))?
+            .get(component_id)
+            .cloned()
+            .ok_or_else(|| WassetteError::ComponentNotFound(component_id.to_string()))?;
+        let _in_flight = slot
+            .tracker
+            .start_call()
+            .ok_or_else(|| WassetteError::ComponentDraining(component_id.to_string()))?;
+        // Calls over the limit wait for a running call to finish
+        let _permit = match &slot.calls {
+            Some(calls) => Some(
+                calls
+                    .acquire()
+                    .await
+                    .expect("call semaphores are never closed"),
+            ),
+            None => None,
+        };
+        // Held until the call returns, so that a reload of the component waits for it
+        let component = slot.instance.read().await;
+
+        let (mut state, resource_limiter) = self
+            .get_wasi_state_for_component(component_id)
+            .await
+            .map_err(WassetteError::PolicyInvalid)?;
+        let (fuel_limit, memory_limit) = {
+            let policy_registry = self.policy_registry.read().await;
+            (
+                policy_registry.fuel_limits.get(component_id).copied(),
+                policy_registry
+                    .memory_limits
+                    .get(component_id)
+                    .copied()
+                    .unwrap_or(DEFAULT_MEMORY_LIMIT),
+            )
+        };
+
+        // The memory cap applies on top of any memory limit from the policy
+        state.inner.resource_limiter = Some(
+            resource_limiter
+                .unwrap_or_else(|| CustomResourceLimiter::new(wasmtime::StoreLimits::default()))
+                .with_memory_cap(memory_limit),
+        );
+
+        let mut store = Store::new(self.engine.as_ref(), state);
+        store.set_fuel(fuel_limit.unwrap_or(u64::MAX))?;
+        store.limiter(|state: &mut WassetteWasiState<WasiState>| {
+            state
+                .inner
+                .resource_limiter
+                .as_mut()
+                .expect("Resource limiter should be present - set above")
+        });
+
+        // Calls without a timeout get a deadline that never trips
+        let ticks = timeout.map_or(u64::MAX, |timeout| {
+            let ticks = timeout.as_millis().div_ceil(EPOCH_TICK.as_millis()).max(1);
+            u64::try_from(ticks).unwrap_or(u64::MAX)
+        });
+        store.set_epoch_deadline(ticks);
+
+        let instance_pre = if store.data().inner.environment_only {
+            self.environment_only_pre(&component)?
+        } else {
+            &component.instance_pre
+        };
+        let instance = instance_pre
+            .instantiate_async(&mut store)
+            .await
+            .map_err(|e| self.map_call_error(e, fuel_limit, timeout))?;
+
+        let tool_name = match split_qualified_tool_name(function_name) {
+            (Some(qualifier), _) if qualifier != component_id => {
+                return Err(WassetteError::ToolNotFound(function_name.to_string()))
+            }
+            (_, tool_name) => tool_name,
+        };
+        let registry = self.registry.read().await;
+        let mut tool = registry.get_component_tool(component_id, tool_name);
+        if tool.is_none() && self.provides_tool_name(component_id, tool_name).await {
+            // A name the component provides is an alias of its only tool
+            if let Some([only_tool]) = registry.component_map.get(component_id).map(Vec::as_slice) {
+                tool = registry.get_component_tool(component_id, only_tool);
+            }
+        }
+        let function_id = tool
+            .ok_or_else(|| WassetteError::ToolNotFound(function_name.to_string()))?
+            .identifier
             .clone();
+        drop(registry);
 
         let (interface_name, func_name) = (
             function_id.interface_name.as_deref().unwrap_or(""),
@@ -572,156 +1883,128 @@ impl LifecycleManager {
                 .ok_or_else(|| anyhow!("Function not found: {}", func_name))?
         };
 
-        let params: serde_json::Value = serde_json::from_str(parameters)?;
-        let argument_vals = json_to_vals(&params, &func.params(&store))?;
+        let params: serde_json::Value = serde_json::from_str(parameters)
+            .map_err(|e| WassetteError::InvalidArguments(e.into()))?;
+        let argument_vals = json_to_vals(&params, &func.params(&store))
+            .map_err(|e| WassetteError::InvalidArguments(e.into()))?;
 
         let mut results = create_placeholder_results(&func.results(&store));
 
         func.call_async(&mut store, &argument_vals, &mut results)
-            .await?;
+            .await
+            .map_err(|e| self.map_call_error(e, fuel_limit, timeout))?;
 
         let result_json = vals_to_json(&results);
-
-        if let Some(result_str) = result_json.as_str() {
-            Ok(result_str.to_string())
-        } else {
-            Ok(serde_json::to_string(&result_json)?)
-        }
+        Ok(self.transform_result(tool_name, result_json).await?)
     }
 
-    // Granular permission system methods
-}
-// Load components in parallel for improved startup performance
-async fn load_components_parallel(
-    plugin_dir: &Path,
-    engine: &Arc<Engine>,
-    linker: &Arc<Linker<WassetteWasiState<WasiState>>>,
-) -> Result<Vec<(ComponentInstance, String)>> {
-    let mut entries = tokio::fs::read_dir(plugin_dir).await?;
-    let mut load_futures = Vec::new();
-
-    while let Some(entry) = entries.next_entry().await? {
-        let engine = engine.clone();
-        let linker = linker.clone();
-        let future = async move {
-            match load_component_from_entry(engine, &linker, entry).await {
-                Ok(Some(result)) => Some(Ok(result)),
-                Ok(None) => None,
-                Err(e) => Some(Err(e)),
+    /// Replaces the traps raised when a call runs past its epoch deadline or exhausts its fuel with
+    /// a [`WassetteError::Timeout`] and an error saying so, to tell them apart from traps raised by
+    /// the component itself. Errors caused by a [`PolicyViolation`] are replaced by the violation
+    /// itself.
+    ///
+    /// Other traps are returned as a [`WassetteError::CallTrap`] whose message has the trap code
+    /// and the wasm backtrace, and whose chain still holds the [`wasmtime::Trap`]. Errors that
+    /// aren't traps come from host functions and are returned as a [`WassetteError::HostFailed`].
+    fn map_call_error(
+        &self,
+        error: anyhow::Error,
+        fuel_limit: Option<u64>,
+        timeout: Option<Duration>,
+    ) -> WassetteError {
+        // Limiters report policy violations as errors, which end up wrapped in the trap
+        if let Some(violation) = error
+            .chain()
+            .find_map(|e| e.downcast_ref::<PolicyViolation>())
+        {
+            return WassetteError::PolicyViolation(violation.clone());
+        }
+        let Some(trap) = error.downcast_ref::<wasmtime::Trap>().copied() else {
+            return WassetteError::HostFailed(error);
+        };
+        let error = match (trap, timeout) {
+            (wasmtime::Trap::Interrupt, Some(timeout)) => return WassetteError::Timeout(timeout),
+            (wasmtime::Trap::OutOfFuel, _) => match fuel_limit {
+                Some(fuel) => {
+                    anyhow!("Component call ran out of fuel after consuming {fuel} units")
+                }
+                None => anyhow!("Component call ran out of fuel"),
+            },
+            _ => {
+                let message = match error.downcast_ref::<wasmtime::WasmBacktrace>() {
+                    Some(backtrace) => format!("Component trapped ({trap:?}): {trap}\n{backtrace}"),
+                    None => format!("Component trapped ({trap:?}): {trap}"),
+                };
+                error.context(message)
             }
         };
-        load_futures.push(future);
-    }
-
-    let results = futures::future::join_all(load_futures).await;
-    let mut components = Vec::new();
-
-    for result in results.into_iter().flatten() {
-        match result {
-            Ok(component) => components.push(component),
-            Err(e) => warn!("Failed to load component: {}", e),
-        }
+        WassetteError::CallTrap(error)
     }
 
-    Ok(components)
+    // Granular permission system methods
 }
-
 impl LifecycleManager {
-    /// Revoke storage permission from a component by URI (removes all access types for that URI)
+    /// Revoke storage permission from a component by URI (removes all access types for that URI),
+    /// like [`LifecycleManager::revoke_permission`] with a `storage` permission
     #[instrument(skip(self))]
     pub async fn revoke_storage_permission_by_uri(
         &self,
         component_id: &str,
         uri: &str,
-    ) -> Result<()> {
-        info!(
-            component_id,
-            uri, "Revoking storage permission by URI from component"
-        );
-        if !self.components.read().await.contains_key(component_id) {
-            return Err(anyhow!("Component not found: {}", component_id));
-        }
-
-        if uri.is_empty() {
-            return Err(anyhow!("Storage URI cannot be empty"));
-        }
-
-        let mut policy = self.load_or_create_component_policy(component_id).await?;
-        self.remove_storage_permission_by_uri_from_policy(&mut policy, uri)?;
-        self.save_component_policy(component_id, &policy).await?;
-        self.update_policy_registry(component_id, &policy).await?;
-
-        info!(component_id, uri, "Storage permission revoked successfully");
-        Ok(())
+    ) -> Result<RevokeResult> {
+        self.revoke_permission(component_id, "storage", &serde_json::json!({ "uri": uri }))
+            .await
     }
+}
 
-    /// Remove all storage permissions for a specific URI from policy
-    fn remove_storage_permission_by_uri_from_policy(
-        &self,
-        policy: &mut policy::PolicyDocument,
-        uri: &str,
-    ) -> Result<()> {
-        if let Some(storage_perms) = &mut policy.permissions.storage {
-            if let Some(allow_set) = &mut storage_perms.allow {
-                allow_set.retain(|perm| perm.uri != uri);
-                // Clean up empty structures
-                if allow_set.is_empty() {
-                    storage_perms.allow = None;
-                }
-            }
+/// Reads the policy co-located with a component in the plugin directory, if there is one, and
+/// turns it into a WASI state template. Problems with the policy file are logged and skipped so
+/// that they don't prevent the component itself from loading.
+async fn load_colocated_policy(
+    manager: &LifecycleManager,
+    component_id: &str,
+) -> Option<WasiStateTemplate> {
+    let policy_path = manager.get_component_policy_path(component_id);
+    if !policy_path.exists() {
+        return None;
+    }
+    let policy_content = match tokio::fs::read_to_string(&policy_path).await {
+        Ok(policy_content) => policy_content,
+        Err(e) => {
+            warn!(component_id = %component_id, error = %e, "Failed to read co-located policy file");
+            return None;
+        }
+    };
+    let policy = match PolicyParser::parse_str(&policy_content) {
+        Ok(policy) => policy,
+        Err(e) => {
+            warn!(component_id = %component_id, error = %e, "Failed to parse co-located policy file");
+            return None;
+        }
+    };
+    match manager.create_policy_template(&policy) {
+        Ok(wasi_template) => {
+            info!(component_id = %component_id, "Restored policy association from co-located file");
+            Some(wasi_template)
+        }
+        Err(e) => {
+            warn!(component_id = %component_id, error = %e, "Failed to create WASI template from policy");
+            None
         }
-        Ok(())
     }
 }
 
-async fn load_component_from_entry(
-    engine: Arc<Engine>,
-    linker: &Linker<WassetteWasiState<WasiState>>,
-    entry: DirEntry,
-) -> Result<Option<(ComponentInstance, String)>> {
-    let start_time = Instant::now();
-    let is_file = entry
-        .metadata()
-        .await
-        .map(|m| m.is_file())
-        .context("unable to read file metadata")?;
-    let is_wasm = entry
-        .path()
-        .extension()
-        .map(|ext| ext == "wasm")
-        .unwrap_or(false);
-    if !(is_file && is_wasm) {
-        return Ok(None);
-    }
-    let entry_path = entry.path();
-    let component =
-        tokio::task::spawn_blocking(move || Component::from_file(&engine, entry_path)).await??;
-    let name = entry
-        .path()
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .map(String::from)
-        .context("wasm file didn't have a valid file name")?;
-    info!(component_id = %name, elapsed = ?start_time.elapsed(), "component loaded");
-    let instance_pre = linker.instantiate_pre(&component)?;
-    Ok(Some((
-        ComponentInstance {
-            component: Arc::new(component),
-            instance_pre: Arc::new(instance_pre),
-        },
-        name,
-    )))
-}
-
 #[cfg(test)]
 mod tests {
     use std::ops::Deref;
     use std::path::PathBuf;
     use std::process::Command;
+    use std::time::Instant;
 
     use test_log::test;
 
     use super::*;
+    use crate::test_fixtures::*;
 
     pub(crate) const TEST_COMPONENT_ID: &str = "fetch_rs";
 
@@ -803,7 +2086,10 @@ mod tests {
         assert!(load_result.is_err()); // Expected since we're using invalid WASM
 
         let lookup_result = manager.get_component_id_for_tool("non-existent").await;
-        assert!(lookup_result.is_err());
+        assert!(matches!(
+            lookup_result,
+            Err(WassetteError::ToolNotFound(tool)) if tool == "non-existent"
+        ));
 
         Ok(())
     }
@@ -814,6 +2100,29 @@ mod tests {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_startup_skips_undecodable_component() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let component_bytes = std::fs::read(build_example_component().await?)?;
+        std::fs::write(tempdir.path().join("fetch_rs.wasm"), component_bytes)?;
+        let random_bytes: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        std::fs::write(tempdir.path().join("garbage.wasm"), random_bytes)?;
+
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+
+        assert_eq!(
+            manager.list_components().await,
+            vec!["fetch_rs".to_string()]
+        );
+        assert!(manager.get_component("fetch_rs").await.is_some());
+        assert!(manager.get_component_id_for_tool("fetch").await.is_ok());
+
+        let load_errors = manager.load_errors();
+        assert_eq!(load_errors.len(), 1);
+        assert_eq!(load_errors[0].0, "garbage");
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_load_and_unload_component() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -848,6 +2157,32 @@ mod tests {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_component_info() -> Result<()> {
+        let manager = create_test_manager().await?;
+        assert!(manager.component_info(TEST_COMPONENT_ID).await.is_none());
+
+        let before_load = SystemTime::now();
+        manager.load_test_component().await?;
+        let bytes = tokio::fs::read(build_example_component().await?).await?;
+
+        let info = manager
+            .component_info(TEST_COMPONENT_ID)
+            .await
+            .expect("the component was just loaded");
+        assert_eq!(info.id, TEST_COMPONENT_ID);
+        assert_eq!(info.wasm_size_bytes, bytes.len() as u64);
+        assert_eq!(
+            info.sha256,
+            reconcile::content_digest(&reconcile::content_hash(&bytes)).unwrap()
+        );
+        assert!(info.loaded_at >= before_load);
+        assert_eq!(info.tool_count, manager.list_tools().await.len());
+        assert!(info.tool_count > 0);
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_duplicate_component_id() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -868,6 +2203,55 @@ mod tests {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_load_components_reports_partial_success() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+
+        let tempdir = tempfile::tempdir()?;
+        let broken_path = tempdir.path().join("broken.wasm");
+        tokio::fs::write(&broken_path, b"not a component").await?;
+        let uris = vec![
+            format!("file://{}", component_path.display()),
+            format!("file://{}", broken_path.display()),
+            "file:///does/not/exist.wasm".to_string(),
+        ];
+
+        let results = manager.load_components(&uris).await;
+        assert_eq!(results.len(), 3);
+        for ((uri, _), expected) in results.iter().zip(&uris) {
+            assert_eq!(uri, expected);
+        }
+        let (id, load_result) = results[0].1.as_ref().expect("valid component should load");
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert_eq!(*load_result, LoadResult::New);
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.is_err());
+        assert_eq!(manager.list_components().await, vec![TEST_COMPONENT_ID]);
+
+        // Unloading is idempotent, so only the invalid ID fails
+        let ids = vec![
+            TEST_COMPONENT_ID.to_string(),
+            "never-loaded".to_string(),
+            "../evil".to_string(),
+        ];
+        let results = manager.unload_components(&ids).await;
+        assert_eq!(results.len(), 3);
+        for ((id, _), expected) in results.iter().zip(&ids) {
+            assert_eq!(id, expected);
+        }
+        assert!(results[0].1.is_ok(), "{:?}", results[0].1);
+        assert!(results[1].1.is_ok(), "{:?}", results[1].1);
+        assert!(
+            matches!(results[2].1, Err(WassetteError::InvalidComponentId(_))),
+            "{:?}",
+            results[2].1
+        );
+        assert!(manager.list_components().await.is_empty());
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_component_reload() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -891,25 +2275,382 @@ mod tests {
     }
 
     #[test(tokio::test)]
-    async fn test_component_path_update() -> Result<()> {
+    async fn test_component_reload_unchanged() -> Result<()> {
         let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let uri = format!("file://{}", component_path.to_str().unwrap());
 
-        let component_id = "test-component";
-        let expected_path = manager.plugin_dir.join("test-component.wasm");
-        let actual_path = manager.component_path(component_id);
+        let (id, load_result) = manager.load_component(&uri).await?;
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert_eq!(load_result, LoadResult::New);
+
+        let (id, load_result) = manager.load_component(&uri).await?;
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert_eq!(load_result, LoadResult::Unchanged);
+
+        let tool_names = manager
+            .get_component_tool_names(TEST_COMPONENT_ID)
+            .await
+            .expect("Component should have registered tools");
+        assert_eq!(tool_names, vec!["fetch".to_string()]);
 
-        assert_eq!(actual_path, expected_path);
         Ok(())
     }
 
     #[test(tokio::test)]
-    async fn test_get_wasi_state_for_component_with_policy() -> Result<()> {
+    async fn test_load_component_with_digest() -> Result<()> {
         let manager = create_test_manager().await?;
-        manager.load_test_component().await?;
+        let component_path = build_example_component().await?;
+        let digest = reconcile::content_hash(&tokio::fs::read(&component_path).await?);
+        let uri = format!("file://{}", component_path.display());
 
-        // Create and attach a policy
-        let policy_content = r#"
-version: "1.0"
+        let (id, load_result) = manager
+            .load_component(&format!("{uri}#sha256={}", digest.to_uppercase()))
+            .await?;
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert_eq!(load_result, LoadResult::New);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_load_component_with_mismatching_digest() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let uri = format!("file://{}", component_path.display());
+
+        let wrong_digest = reconcile::content_hash(b"some other component");
+        let err = manager
+            .load_component(&format!("{uri}#sha256={wrong_digest}"))
+            .await
+            .unwrap_err();
+        match &err {
+            WassetteError::DigestMismatch { expected, .. } => assert_eq!(*expected, wrong_digest),
+            _ => panic!("unexpected error: {err}"),
+        }
+        assert!(err.to_string().contains(&wrong_digest));
+        assert!(manager.list_components().await.is_empty());
+
+        let err = manager
+            .load_component(&format!("{uri}#sha256=1234"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid sha256 digest"));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_load_component_bytes() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let bytes = tokio::fs::read(build_example_component().await?).await?;
+        let manager = LifecycleManager::new(&tempdir).await?;
+
+        for id in ["", "..", "../escape", "nested/component"] {
+            assert!(matches!(
+                manager.load_component_bytes(id, &bytes).await,
+                Err(WassetteError::InvalidComponentId(_))
+            ));
+        }
+        assert!(matches!(
+            manager.load_component_bytes("broken", b"not wasm").await,
+            Err(WassetteError::CompileFailed(_))
+        ));
+        assert!(manager.list_components().await.is_empty());
+
+        let result = manager.load_component_bytes("bundled", &bytes).await?;
+        assert_eq!(result, LoadResult::New);
+        assert_eq!(manager.get_component_id_for_tool("fetch").await?, "bundled");
+        let result = manager.load_component_bytes("bundled", &bytes).await?;
+        assert_eq!(result, LoadResult::Unchanged);
+
+        // The bytes are persisted, so the component is loaded again on the next start
+        assert!(tempdir.path().join("bundled.wasm").is_file());
+        drop(manager);
+        let manager = LifecycleManager::new(&tempdir).await?;
+        assert_eq!(manager.list_components().await, vec!["bundled".to_string()]);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_package_ids() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let plugin_dir = tempdir.path().join("plugins");
+        let manager = LifecycleManager::builder(&plugin_dir)
+            .with_package_ids(true)
+            .build()
+            .await?;
+
+        // A component carrying its WIT package name is identified by it, whatever its file name
+        let package = wat::parse_str(
+            r#"(component
+                (type (component
+                    (type (instance
+                        (type (func (param "x" u32) (result u32)))
+                        (export "echo" (func (type 0)))
+                    ))
+                    (export "test:pkg/iface@1.0.0" (instance (type 0)))
+                ))
+                (export "iface" (type 0))
+            )"#,
+        )?;
+        let first = tempdir.path().join("first-name.wasm");
+        let second = tempdir.path().join("second-name.wasm");
+        tokio::fs::write(&first, &package).await?;
+        tokio::fs::write(&second, &package).await?;
+
+        let (id, result) = manager
+            .load_component(&format!("file://{}", first.display()))
+            .await?;
+        assert_eq!(id, "test_pkg_1.0.0");
+        assert_eq!(result, LoadResult::New);
+        let (id, result) = manager
+            .load_component(&format!("file://{}", second.display()))
+            .await?;
+        assert_eq!(id, "test_pkg_1.0.0");
+        assert_eq!(result, LoadResult::Unchanged);
+        assert!(plugin_dir.join("test_pkg_1.0.0.wasm").exists());
+        assert!(!plugin_dir.join("first-name.wasm").exists());
+
+        // Components decoded as the generic `root:component` keep using their file name
+        let component = wat::parse_str(
+            r#"(component
+                (core module $m (func (export "f") (param i32) (result i32) local.get 0))
+                (core instance $i (instantiate $m))
+                (func (export "echo") (param "x" u32) (result u32) (canon lift (core func $i "f")))
+            )"#,
+        )?;
+        let path = tempdir.path().join("echo.wasm");
+        tokio::fs::write(&path, &component).await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", path.display()))
+            .await?;
+        assert_eq!(id, "echo");
+        assert!(plugin_dir.join("echo.wasm").exists());
+
+        let mut ids = manager.list_components().await;
+        ids.sort();
+        assert_eq!(ids, ["echo", "test_pkg_1.0.0"]);
+
+        // Reloading from the plugin directory finds the same IDs
+        let reloaded = LifecycleManager::builder(&plugin_dir)
+            .with_package_ids(true)
+            .build()
+            .await?;
+        let mut ids = reloaded.list_components().await;
+        ids.sort();
+        assert_eq!(ids, ["echo", "test_pkg_1.0.0"]);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_component_schema_is_cached() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_path = build_example_component().await?;
+        let uri = format!("file://{}", component_path.display());
+        manager.load_component(&uri).await?;
+
+        let first = manager
+            .get_component_schema(TEST_COMPONENT_ID)
+            .await
+            .unwrap();
+        let second = manager
+            .get_component_schema(TEST_COMPONENT_ID)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(manager.schemas_computed(), 1);
+
+        // Unloading drops the cached schema
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        manager.load_component(&uri).await?;
+        manager
+            .get_component_schema(TEST_COMPONENT_ID)
+            .await
+            .unwrap();
+        assert_eq!(manager.schemas_computed(), 2);
+
+        Ok(())
+    }
+
+    /// Loads the components `first` and `second`, which both export an `echo` tool, and
+    /// respectively a `first-only` and a `second-only` tool
+    async fn load_components_sharing_a_tool(manager: &LifecycleManager) -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        for (id, extra) in [("first", "first-only"), ("second", "second-only")] {
+            let path = tempdir.path().join(format!("{id}.wasm"));
+            let wat = format!(
+                r#"(component
+                    (core module $m (func (export "f") (param i32) (result i32) local.get 0))
+                    (core instance $i (instantiate $m))
+                    (func (export "echo") (param "x" u32) (result u32) (canon lift (core func $i "f")))
+                    (func (export "{extra}") (param "x" u32) (result u32) (canon lift (core func $i "f")))
+                )"#
+            );
+            tokio::fs::write(&path, wat).await?;
+            manager
+                .load_component(&format!("file://{}", path.display()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_list_tools_detailed_marks_ambiguous_tools() -> Result<()> {
+        let manager = create_test_manager().await?;
+        load_components_sharing_a_tool(&manager).await?;
+
+        let mut tools = manager.list_tools_detailed().await;
+        tools.sort_by(|a, b| {
+            (&a.normalized_name, &a.component_id).cmp(&(&b.normalized_name, &b.component_id))
+        });
+        let summary: Vec<_> = tools
+            .iter()
+            .map(|t| {
+                (
+                    t.normalized_name.as_str(),
+                    t.component_id.as_str(),
+                    t.ambiguous,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("echo", "first", true),
+                ("echo", "second", true),
+                ("first-only", "first", false),
+                ("second-only", "second", false),
+            ]
+        );
+        assert!(tools.iter().all(|t| t.schema["name"] == t.normalized_name));
+        assert_eq!(manager.list_tools().await.len(), 4);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_qualified_tool_names() -> Result<()> {
+        let manager = create_test_manager().await?;
+        load_components_sharing_a_tool(&manager).await?;
+
+        // Unqualified names still have to be unique
+        assert!(matches!(
+            manager.get_component_id_for_tool("echo").await,
+            Err(WassetteError::ToolAmbiguous { tool, .. }) if tool == "echo"
+        ));
+        assert_eq!(
+            manager.get_component_id_for_tool("first-only").await?,
+            "first"
+        );
+
+        let component_id = manager.get_component_id_for_tool("second::echo").await?;
+        assert_eq!(component_id, "second");
+        let result = manager
+            .execute_component_call(&component_id, "second::echo", r#"{"x": 7}"#)
+            .await?;
+        assert!(result.contains('7'), "{result}");
+        let result = manager
+            .execute_component_call("first", "echo", r#"{"x": 8}"#)
+            .await?;
+        assert!(result.contains('8'), "{result}");
+
+        // The qualifier has to name a component exporting the tool
+        assert!(matches!(
+            manager.get_component_id_for_tool("first::second-only").await,
+            Err(WassetteError::ToolNotFound(tool)) if tool == "first::second-only"
+        ));
+        assert!(matches!(
+            manager.get_component_id_for_tool("missing::echo").await,
+            Err(WassetteError::ToolNotFound(_))
+        ));
+        assert!(matches!(
+            manager
+                .execute_component_call("first", "second::echo", r#"{"x": 7}"#)
+                .await,
+            Err(WassetteError::ToolNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_component_path_update() -> Result<()> {
+        let manager = create_test_manager().await?;
+
+        let component_id = "test-component";
+        let expected_path = manager.plugin_dir.join("test-component.wasm");
+        let actual_path = manager.component_path(component_id);
+
+        assert_eq!(actual_path, expected_path);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_component_paths_stay_within_plugin_dir() -> Result<()> {
+        let manager = create_test_manager().await?;
+
+        let malicious_ids = [
+            "../evil",
+            "../../etc/passwd",
+            "nested/component",
+            "/etc/passwd",
+            "C:\\Windows\\System32\\evil",
+            "..",
+            "..\\..\\evil",
+        ];
+
+        for id in malicious_ids {
+            for path in [
+                manager.component_path(id),
+                manager.get_component_policy_path(id),
+                manager.get_component_metadata_path(id),
+            ] {
+                assert_eq!(
+                    path.parent(),
+                    Some(manager.plugin_dir.as_path()),
+                    "path for id {id:?} escaped the plugin dir: {}",
+                    path.display()
+                );
+                assert!(
+                    !path
+                        .components()
+                        .any(|c| matches!(c, std::path::Component::ParentDir)),
+                    "path for id {id:?} contains a parent dir component: {}",
+                    path.display()
+                );
+            }
+        }
+
+        // Invalid IDs never name the files of a component with a valid ID
+        assert_ne!(
+            manager.component_path("../evil"),
+            manager.component_path(".._evil")
+        );
+        assert_ne!(manager.component_path("a/b"), manager.component_path("a_b"));
+        assert!(matches!(
+            manager.unload_component("../evil").await,
+            Err(WassetteError::InvalidComponentId(_))
+        ));
+
+        assert_eq!(sanitize_component_id("../evil"), ".._evil");
+        assert_eq!(sanitize_component_id("/etc/passwd"), "_etc_passwd");
+        assert_eq!(sanitize_component_id("fetch_rs"), "fetch_rs");
+        assert_eq!(sanitize_component_id("my-component.v2"), "my-component.v2");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_wasi_state_for_component_with_policy() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        // Create and attach a policy
+        let policy_content = r#"
+version: "1.0"
 description: "Test policy"
 permissions:
   network:
@@ -932,6 +2673,55 @@ permissions:
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_load_component_with_policy() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let component_uri = format!("file://{}", build_example_component().await?.display());
+
+        // An invalid policy leaves nothing behind
+        let invalid_policy_path = manager.plugin_dir.join("invalid-policy.yaml");
+        tokio::fs::write(&invalid_policy_path, "version: \"2.0\"\npermissions: {}\n").await?;
+        let result = manager
+            .load_component_with_policy(
+                &component_uri,
+                &format!("file://{}", invalid_policy_path.display()),
+            )
+            .await;
+        assert!(
+            matches!(result, Err(WassetteError::PolicyInvalid(_))),
+            "unexpected result: {result:?}"
+        );
+        assert!(manager.list_components().await.is_empty());
+        assert!(manager.list_tools().await.is_empty());
+        assert!(!manager
+            .plugin_dir
+            .join(format!("{TEST_COMPONENT_ID}.wasm"))
+            .exists());
+
+        let policy_path = manager.plugin_dir.join("test-policy.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: \"example.com\"\n",
+        )
+        .await?;
+        let policy_uri = format!("file://{}", policy_path.display());
+        let (id, result) = manager
+            .load_component_with_policy(&component_uri, &policy_uri)
+            .await?;
+        assert_eq!(id, TEST_COMPONENT_ID);
+        assert_eq!(result, LoadResult::New);
+        let policy_info = manager.get_policy_info(&id).await.unwrap();
+        assert_eq!(policy_info.source_uri, policy_uri);
+        assert!(manager
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .contains_key(&id));
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_policy_restoration_on_startup() -> Result<()> {
         let tempdir = tempfile::tempdir()?;
@@ -995,11 +2785,12 @@ permissions:
 
         // Test attaching policy with invalid URI scheme
         let result = manager.attach_policy(TEST_COMPONENT_ID, invalid_uri).await;
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Unsupported policy scheme"));
+        match result {
+            Err(WassetteError::DownloadFailed(e)) => {
+                assert!(e.to_string().contains("Unsupported policy scheme"))
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
 
         Ok(())
     }
@@ -1026,9 +2817,359 @@ permissions:
             Ok(_) => {} // Success
             Err(e) => {
                 // Should not be a component lookup error
-                assert!(!e.to_string().contains("Component not found"));
+                assert!(!matches!(e, WassetteError::ComponentNotFound(_)));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_execute_component_call_on_dedicated_pool() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(&tempdir)
+            .with_execution_pool(ExecutionPool::Dedicated)
+            .build()
+            .await?;
+        assert_eq!(manager.execution_pool(), ExecutionPool::Dedicated);
+        load_wat_component(&manager, "spin", SPIN_WAT).await?;
+
+        // Errors from the dedicated runtime must be propagated back to the caller
+        let err = manager
+            .execute_component_call("does-not-exist", "spin", "{}")
+            .await
+            .expect_err("Calling an unknown component should fail");
+        assert!(matches!(err, WassetteError::ComponentNotFound(_)));
+
+        let result = manager
+            .execute_component_call("spin", "spin", r#"{"n": 1000}"#)
+            .await?;
+        assert!(result.contains("1000"), "{result}");
+
+        // Dropping the manager (and its runtime) from within an async context must not panic
+        drop(manager);
+
+        Ok(())
+    }
+
+    /// Compares how long a 1ms timer on the caller's runtime takes to fire while a CPU-heavy call
+    /// runs on each execution pool
+    #[test(tokio::test)]
+    async fn test_dedicated_pool_keeps_caller_runtime_responsive() -> Result<()> {
+        async fn timer_delay(pool: ExecutionPool) -> Result<Duration> {
+            let tempdir = tempfile::tempdir()?;
+            let manager = LifecycleManager::builder(tempdir.path().join("plugins"))
+                .with_execution_pool(pool)
+                .build()
+                .await?;
+            load_wat_component(&manager, "spin", SPIN_WAT).await?;
+
+            let call = tokio::spawn({
+                let manager = manager.clone();
+                async move {
+                    manager
+                        .execute_component_call("spin", "spin", r#"{"n": 300000000}"#)
+                        .await
+                }
+            });
+            let start = Instant::now();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            let delay = start.elapsed();
+            let result = call.await??;
+            assert!(result.contains("300000000"), "{result}");
+            Ok(delay)
+        }
+
+        // The test runtime has a single thread, so a call on the shared pool holds up every other
+        // task until it returns
+        let shared = timer_delay(ExecutionPool::Shared).await?;
+        let dedicated = timer_delay(ExecutionPool::Dedicated).await?;
+        tracing::info!(
+            ?shared,
+            ?dedicated,
+            "Delay of a 1ms timer during a CPU-heavy call"
+        );
+        assert!(
+            dedicated < shared,
+            "the timer took {dedicated:?} with a dedicated pool and {shared:?} with a shared one"
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_execute_component_call_with_http_mock() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let responses = HashMap::from([
+            (
+                "https://example.com/".to_string(),
+                MockResponse::new(200, "hello from the mock")
+                    .with_header("content-type", "text/plain"),
+            ),
+            (
+                "https://blocked.example.com/".to_string(),
+                MockResponse::new(200, "should never be returned"),
+            ),
+        ]);
+        let manager = LifecycleManager::builder(&tempdir)
+            .with_http_mock(Arc::new(responses))
+            .build()
+            .await?;
+
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.to_str().unwrap()))
+            .await?;
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "example.com"}),
+            )
+            .await?;
+
+        let result = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com/"}"#,
+            )
+            .await?;
+        assert!(
+            result.contains("hello from the mock"),
+            "unexpected result: {result}"
+        );
+
+        // Requests without a canned response are refused instead of going to the network
+        let result = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com/missing"}"#,
+            )
+            .await?;
+        assert!(result.contains("err"), "unexpected result: {result}");
+
+        // The network policy is still enforced before the mock is consulted
+        let result = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://blocked.example.com/"}"#,
+            )
+            .await?;
+        assert!(
+            !result.contains("should never be returned"),
+            "unexpected result: {result}"
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_shared_linker_keeps_wasi_state_per_component() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_with_env(
+            tempdir.path().join("plugins"),
+            HashMap::from([
+                ("GREETING".to_string(), "hello".to_string()),
+                ("SECRET".to_string(), "hunter2".to_string()),
+            ]),
+        )
+        .await?;
+
+        // Two copies of the component, whose policies grant one key each
+        let mut ids = Vec::new();
+        for (name, key) in [("greeting_reader", "GREETING"), ("secret_reader", "SECRET")] {
+            let component_path = tempdir.path().join(format!("{name}.wasm"));
+            tokio::fs::write(&component_path, CONFIG_READER_COMPONENT_WAT).await?;
+            let (id, _) = manager
+                .load_component(&format!("file://{}", component_path.display()))
+                .await?;
+            let policy_path = tempdir.path().join(format!("{name}.yaml"));
+            tokio::fs::write(
+                &policy_path,
+                format!("version: \"1.0\"\npermissions:\n  environment:\n    allow:\n      - key: \"{key}\"\n"),
+            )
+            .await?;
+            manager
+                .attach_policy(&id, &format!("file://{}", policy_path.display()))
+                .await?;
+            ids.push(id);
+        }
+
+        let get = |id: &str, key: &str| {
+            let manager = manager.clone();
+            let id = id.to_string();
+            let params = format!(r#"{{"key": "{key}"}}"#);
+            async move {
+                manager
+                    .execute_component_call(&id, "get-config", &params)
+                    .await
             }
+        };
+        assert!(get(&ids[0], "GREETING").await?.contains("hello"));
+        assert!(!get(&ids[0], "SECRET").await?.contains("hunter2"));
+        assert!(get(&ids[1], "SECRET").await?.contains("hunter2"));
+        assert!(!get(&ids[1], "GREETING").await?.contains("hello"));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_pre_instantiation_is_faster_than_linking_every_call() -> Result<()> {
+        const CALLS: u32 = 20;
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let component = manager.get_component(TEST_COMPONENT_ID).await.unwrap();
+
+        // What every call used to do: set up a linker and link the component
+        let start = Instant::now();
+        for _ in 0..CALLS {
+            let (state, _) = manager
+                .get_wasi_state_for_component(TEST_COMPONENT_ID)
+                .await?;
+            let mut store = Store::new(manager.engine.as_ref(), state);
+            store.set_fuel(u64::MAX)?;
+            store.set_epoch_deadline(u64::from(u32::MAX));
+            let linker = build_linker(&manager.engine)?;
+            linker
+                .instantiate_pre(&component.component)?
+                .instantiate_async(&mut store)
+                .await?;
         }
+        let linking = start.elapsed() / CALLS;
+
+        // What calls do now: instantiate the component pre-instantiated at load time
+        let start = Instant::now();
+        for _ in 0..CALLS {
+            let (state, _) = manager
+                .get_wasi_state_for_component(TEST_COMPONENT_ID)
+                .await?;
+            let mut store = Store::new(manager.engine.as_ref(), state);
+            store.set_fuel(u64::MAX)?;
+            store.set_epoch_deadline(u64::from(u32::MAX));
+            component.instance_pre.instantiate_async(&mut store).await?;
+        }
+        let pre_instantiated = start.elapsed() / CALLS;
+
+        tracing::info!(
+            ?linking,
+            ?pre_instantiated,
+            "Per-call instantiation latency"
+        );
+        assert!(
+            pre_instantiated < linking,
+            "pre-instantiated calls took {pre_instantiated:?}, linking took {linking:?}"
+        );
+
+        Ok(())
+    }
+
+    /// Environment-only policies must not link the sockets interfaces, even the ones wasmtime would
+    /// otherwise hand out without a network grant
+    #[test(tokio::test)]
+    async fn test_environment_only_policy_does_not_link_sockets() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        let component_path = tempdir.path().join("network_user.wasm");
+        tokio::fs::write(
+            &component_path,
+            r#"(component
+                (import "wasi:sockets/network@0.2.0" (instance $network
+                    (export "network" (type (sub resource)))))
+                (alias export $network "network" (type $network-type))
+                (import "wasi:sockets/instance-network@0.2.0" (instance $instance-network
+                    (alias outer 1 $network-type (type $n))
+                    (export "network" (type $network (eq $n)))
+                    (type $own (own $network))
+                    (export "instance-network" (func (result $own)))))
+                (core func $instance-network
+                    (canon lower (func $instance-network "instance-network")))
+                (core module $m
+                    (import "host" "instance-network" (func $instance-network (result i32)))
+                    (func (export "use-network") (result i32)
+                        (drop (call $instance-network))
+                        i32.const 1))
+                (core instance $host (export "instance-network" (func $instance-network)))
+                (core instance $i (instantiate $m (with "host" (instance $host))))
+                (func (export "use-network") (result u32)
+                    (canon lift (core func $i "use-network")))
+            )"#,
+        )
+        .await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        let result = manager
+            .execute_component_call(&id, "use-network", "{}")
+            .await?;
+        assert_eq!(result, "1");
+        // Only built for calls under an environment-only policy
+        let component = manager.get_component(&id).await.unwrap();
+        assert!(component.environment_only_pre.get().is_none());
+
+        let policy_path = tempdir.path().join("env-only.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  environment:\n    allow:\n      - key: \"GREETING\"\n",
+        )
+        .await?;
+        manager
+            .attach_policy(&id, &format!("file://{}", policy_path.display()))
+            .await?;
+
+        let err = manager
+            .execute_component_call(&id, "use-network", "{}")
+            .await
+            .expect_err("Sockets should not be linked for an environment-only policy");
+        assert!(
+            format!("{err:#}").contains("only have environment access"),
+            "{err:#}"
+        );
+        assert!(component.environment_only_pre.get().is_some());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_expired_policy_refuses_wasi_state() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let mut policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n")?;
+        let template = manager.create_policy_template(&policy)?;
+        manager
+            .policy_registry
+            .write()
+            .await
+            .component_policies
+            .insert("config_reader".to_string(), Arc::new(template));
+        manager
+            .get_wasi_state_for_component("config_reader")
+            .await?;
+
+        // A policy that expires while attached stops granting access
+        policy.expires_at = Some("2000-01-01T00:00:00Z".parse()?);
+        {
+            let mut policy_registry = manager.policy_registry.write().await;
+            let template = policy_registry
+                .component_policies
+                .get_mut("config_reader")
+                .unwrap();
+            Arc::make_mut(template).policy = Some(Arc::new(policy));
+        }
+        let err = manager
+            .get_wasi_state_for_component("config_reader")
+            .await
+            .err()
+            .expect("Component with an expired policy should not run");
+        let message = format!("{err:#}");
+        assert!(
+            message.contains("Refusing to run component 'config_reader'"),
+            "{message}"
+        );
+        assert!(message.contains("Policy expired at"), "{message}");
 
         Ok(())
     }
@@ -1144,6 +3285,87 @@ permissions:
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_revoke_permission_is_idempotent() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        // Without a policy, there is nothing to revoke and no policy file is written
+        let details = serde_json::json!({"host": "api.example.com"});
+        let result = manager
+            .revoke_permission(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        assert_eq!(
+            result,
+            RevokeResult {
+                removed: 0,
+                changed: false
+            }
+        );
+        let policy_path = manager.get_component_policy_path(TEST_COMPONENT_ID);
+        assert!(!policy_path.exists());
+
+        manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "cdn.example.com"}),
+            )
+            .await?;
+
+        let result = manager
+            .revoke_permission(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        assert_eq!(
+            result,
+            RevokeResult {
+                removed: 1,
+                changed: true
+            }
+        );
+        let policy_content = tokio::fs::read_to_string(&policy_path).await?;
+        assert!(!policy_content.contains("api.example.com"));
+        assert!(policy_content.contains("cdn.example.com"));
+
+        // Revoking it again changes nothing
+        let result = manager
+            .revoke_permission(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        assert_eq!(
+            result,
+            RevokeResult {
+                removed: 0,
+                changed: false
+            }
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(&policy_path).await?,
+            policy_content
+        );
+
+        // Storage permissions are revoked by URI, whatever their access
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "storage",
+                &serde_json::json!({"uri": "fs:///tmp/test", "access": ["read"]}),
+            )
+            .await?;
+        let result = manager
+            .revoke_storage_permission_by_uri(TEST_COMPONENT_ID, "fs:///tmp/test")
+            .await?;
+        assert_eq!(result.removed, 1);
+        let result = manager
+            .revoke_storage_permission_by_uri(TEST_COMPONENT_ID, "fs:///tmp/test")
+            .await?;
+        assert!(!result.changed);
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_reset_permission() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1251,4 +3473,466 @@ permissions:
 
         Ok(())
     }
+
+    #[test(tokio::test)]
+    async fn test_call_timeout() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path().join("plugins"))
+            .with_call_timeout(Some(Duration::from_millis(200)))
+            .build()
+            .await?;
+
+        let (id, _) = load_wat_component(&manager, "spin", SPIN_FOREVER_WAT).await?;
+
+        let error = manager
+            .execute_component_call(&id, "spin", "{}")
+            .await
+            .expect_err("a component that loops forever should time out");
+        assert!(
+            matches!(error, WassetteError::Timeout(timeout) if timeout == Duration::from_millis(200))
+        );
+        assert_eq!(error.to_string(), "Component call timed out after 0.2s");
+
+        Ok(())
+    }
+
+    #[test(tokio::test(flavor = "multi_thread"))]
+    async fn test_reload_waits_for_in_flight_calls() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        // Spins for `n` iterations and returns the version of the component
+        let spin = |version: u32| {
+            format!(
+                r#"(component
+                    (core module $m
+                        (func (export "spin") (param i32) (result i32) (local i32)
+                            (block (loop
+                                (br_if 1 (i32.ge_u (local.get 1) (local.get 0)))
+                                (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+                                (br 0)))
+                            i32.const {version}))
+                    (core instance $i (instantiate $m))
+                    (func (export "spin") (param "n" u32) (result u32)
+                        (canon lift (core func $i "spin")))
+                )"#
+            )
+        };
+        let old_dir = tempdir.path().join("old");
+        let new_dir = tempdir.path().join("new");
+        for (dir, version) in [(&old_dir, 11), (&new_dir, 22)] {
+            tokio::fs::create_dir_all(dir).await?;
+            tokio::fs::write(dir.join("spin.wasm"), spin(version)).await?;
+        }
+        manager
+            .load_component(&format!("file://{}", old_dir.join("spin.wasm").display()))
+            .await?;
+
+        let call = tokio::spawn({
+            let manager = manager.clone();
+            async move {
+                manager
+                    .execute_component_call("spin", "spin", r#"{"n": 300000000}"#)
+                    .await
+            }
+        });
+        // Wait for the call to hold the component
+        let slot = manager.components.read().await["spin"].clone();
+        while slot.instance.try_write().is_ok() && !call.is_finished() {
+            tokio::task::yield_now().await;
+        }
+
+        let (_, result) = manager
+            .load_component(&format!("file://{}", new_dir.join("spin.wasm").display()))
+            .await?;
+        assert_eq!(result, LoadResult::Replaced);
+
+        // The call in flight finished on the component it started with
+        let old_result = call.await??;
+        assert!(old_result.contains("11"), "{old_result}");
+        let new_result = manager
+            .execute_component_call("spin", "spin", r#"{"n": 1}"#)
+            .await?;
+        assert!(new_result.contains("22"), "{new_result}");
+
+        Ok(())
+    }
+
+    /// Answers every request after a short delay, recording how many requests were answered at
+    /// the same time
+    #[derive(Default)]
+    struct SlowMock {
+        running: std::sync::atomic::AtomicUsize,
+        max_running: std::sync::atomic::AtomicUsize,
+        answered: std::sync::atomic::AtomicUsize,
+    }
+
+    impl HttpInterceptor for SlowMock {
+        fn intercept(
+            &self,
+            _request: &hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
+        ) -> Option<MockResponse> {
+            use std::sync::atomic::Ordering;
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(50));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.answered.fetch_add(1, Ordering::SeqCst);
+            Some(MockResponse::new(200, "slow"))
+        }
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 8))]
+    async fn test_max_concurrent_calls() -> Result<()> {
+        use std::sync::atomic::Ordering;
+
+        let tempdir = tempfile::tempdir()?;
+        let mock = Arc::new(SlowMock::default());
+        let manager = LifecycleManager::builder(&tempdir)
+            .with_http_mock(mock.clone())
+            .with_max_concurrent_calls(Some(2))
+            .build()
+            .await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "example.com"}),
+            )
+            .await?;
+
+        let calls = (0..6).map(|_| {
+            let manager = manager.clone();
+            tokio::spawn(async move {
+                manager
+                    .execute_component_call(
+                        TEST_COMPONENT_ID,
+                        "fetch",
+                        r#"{"url": "https://example.com/"}"#,
+                    )
+                    .await
+            })
+        });
+        for result in futures::future::join_all(calls).await {
+            let result = result??;
+            assert!(result.contains("slow"), "unexpected result: {result}");
+        }
+
+        // All calls completed, but never more than two at a time
+        assert_eq!(mock.answered.load(Ordering::SeqCst), 6);
+        assert!(mock.max_running.load(Ordering::SeqCst) <= 2);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_policy_execution_timeout() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        let (id, _) = load_wat_component(&manager, "spin", SPIN_FOREVER_WAT).await?;
+
+        let policy_path = tempdir.path().join("timeout.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  runtime:\n    execution_timeout_ms: 100\n",
+        )
+        .await?;
+        manager
+            .attach_policy(&id, &format!("file://{}", policy_path.display()))
+            .await?;
+
+        // The policy's timeout applies instead of the much longer default
+        let error = manager
+            .execute_component_call(&id, "spin", "{}")
+            .await
+            .expect_err("a component that loops forever should time out");
+        assert!(
+            matches!(error, WassetteError::Timeout(timeout) if timeout == Duration::from_millis(100)),
+            "unexpected error: {error:?}"
+        );
+
+        // A policy can't extend the manager's timeout
+        let policy_path = tempdir.path().join("long-timeout.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  runtime:\n    execution_timeout_ms: 60000\n",
+        )
+        .await?;
+        let manager = LifecycleManager::builder(tempdir.path().join("short"))
+            .with_call_timeout(Some(Duration::from_millis(100)))
+            .build()
+            .await?;
+        let (id, _) = load_wat_component(&manager, "spin", SPIN_FOREVER_WAT).await?;
+        manager
+            .attach_policy(&id, &format!("file://{}", policy_path.display()))
+            .await?;
+        assert_eq!(
+            manager.effective_call_timeout(&id, None).await,
+            Some(Duration::from_millis(100))
+        );
+
+        // Without a manager timeout, the policy's timeout still interrupts the loop
+        let manager = LifecycleManager::builder(tempdir.path().join("unlimited"))
+            .with_call_timeout(None)
+            .build()
+            .await?;
+        let (id, _) = load_wat_component(&manager, "spin", SPIN_FOREVER_WAT).await?;
+        manager
+            .attach_policy(
+                &id,
+                &format!("file://{}", tempdir.path().join("timeout.yaml").display()),
+            )
+            .await?;
+        let error = manager
+            .execute_component_call(&id, "spin", "{}")
+            .await
+            .expect_err("the policy's timeout should stop the loop");
+        assert!(
+            matches!(error, WassetteError::Timeout(timeout) if timeout == Duration::from_millis(100)),
+            "unexpected error: {error:?}"
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_call_site_timeout() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        let (id, _) = load_wat_component(&manager, "spin", SPIN_FOREVER_WAT).await?;
+
+        // The call-site timeout applies instead of the much longer default
+        let error = manager
+            .execute_component_call_with_timeout(&id, "spin", "{}", Duration::from_millis(100))
+            .await
+            .expect_err("a component that loops forever should time out");
+        assert!(
+            matches!(error, WassetteError::Timeout(timeout) if timeout == Duration::from_millis(100)),
+            "unexpected error: {error:?}"
+        );
+
+        // A shorter timeout in the policy wins over the call-site timeout
+        let policy_path = tempdir.path().join("timeout.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  runtime:\n    execution_timeout_ms: 50\n",
+        )
+        .await?;
+        manager
+            .attach_policy(&id, &format!("file://{}", policy_path.display()))
+            .await?;
+        let error = manager
+            .execute_component_call_with_timeout(&id, "spin", "{}", Duration::from_secs(10))
+            .await
+            .expect_err("a component that loops forever should time out");
+        assert!(
+            matches!(error, WassetteError::Timeout(timeout) if timeout == Duration::from_millis(50)),
+            "unexpected error: {error:?}"
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_trap_reports_code_and_backtrace() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let tempdir = tempfile::tempdir()?;
+        let component_path = tempdir.path().join("crash.wasm");
+        tokio::fs::write(
+            &component_path,
+            r#"(component
+                (core module $m
+                    (func $fail unreachable)
+                    (func (export "crash") call $fail))
+                (core instance $i (instantiate $m))
+                (func (export "crash") (canon lift (core func $i "crash")))
+            )"#,
+        )
+        .await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        let error = manager
+            .execute_component_call(&id, "crash", "{}")
+            .await
+            .expect_err("a component executing unreachable should trap");
+        let WassetteError::CallTrap(trap) = &error else {
+            panic!("expected a trap, got {error:?}");
+        };
+        assert_eq!(
+            trap.downcast_ref::<wasmtime::Trap>(),
+            Some(&wasmtime::Trap::UnreachableCodeReached)
+        );
+        let message = error.to_string();
+        assert!(message.contains("UnreachableCodeReached"), "{message}");
+        assert!(message.contains("unreachable"), "{message}");
+        assert!(message.contains("wasm backtrace"), "{message}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_fuel_limit() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        // Counts to a million, which takes a few million instructions
+        let component_path = tempdir.path().join("count.wasm");
+        tokio::fs::write(
+            &component_path,
+            r#"(component
+                (core module $m
+                    (func (export "count") (result i32)
+                        (local $i i32)
+                        (loop $l
+                            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                            (br_if $l (i32.lt_u (local.get $i) (i32.const 1000000))))
+                        (local.get $i)))
+                (core instance $i (instantiate $m))
+                (func (export "count") (result u32) (canon lift (core func $i "count")))
+            )"#,
+        )
+        .await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        // Without a limit the call runs to completion
+        let result = manager.execute_component_call(&id, "count", "{}").await?;
+        assert!(result.contains("1000000"), "unexpected result: {result}");
+
+        manager.set_fuel_limit(&id, 10_000).await?;
+        let error = manager
+            .execute_component_call(&id, "count", "{}")
+            .await
+            .expect_err("the call should exhaust its fuel");
+        assert!(matches!(error, WassetteError::CallTrap(_)));
+        assert_eq!(
+            error.to_string(),
+            "Component call ran out of fuel after consuming 10000 units"
+        );
+
+        assert!(manager
+            .set_fuel_limit("does-not-exist", 10_000)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_limit() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        // Grows its memory by the given number of 64 KiB pages
+        let component_path = tempdir.path().join("grow.wasm");
+        tokio::fs::write(
+            &component_path,
+            r#"(component
+                (core module $m
+                    (memory 1)
+                    (func (export "grow") (param i32) (result i32)
+                        (memory.grow (local.get 0))))
+                (core instance $i (instantiate $m))
+                (func (export "grow") (param "pages" u32) (result s32)
+                    (canon lift (core func $i "grow")))
+            )"#,
+        )
+        .await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        // Within the default limit
+        manager
+            .execute_component_call(&id, "grow", r#"{"pages": 16}"#)
+            .await?;
+
+        manager.set_memory_limit(&id, 1024 * 1024).await?;
+        let error = manager
+            .execute_component_call(&id, "grow", r#"{"pages": 100}"#)
+            .await
+            .expect_err("growing past the limit should fail");
+        let error = format!("{error:#}");
+        assert!(
+            error.contains("exceeded its memory limit"),
+            "unexpected error: {error}"
+        );
+
+        // The manager keeps working after a call hit the limit
+        manager
+            .execute_component_call(&id, "grow", r#"{"pages": 1}"#)
+            .await?;
+
+        assert!(manager
+            .set_memory_limit("does-not-exist", 1024 * 1024)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_policy_memory_limit() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+
+        let component_path = tempdir.path().join("grow.wasm");
+        tokio::fs::write(
+            &component_path,
+            r#"(component
+                (core module $m
+                    (memory 1)
+                    (func (export "grow") (param i32) (result i32)
+                        (memory.grow (local.get 0))))
+                (core instance $i (instantiate $m))
+                (func (export "grow") (param "pages" u32) (result s32)
+                    (canon lift (core func $i "grow")))
+            )"#,
+        )
+        .await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        let policy_path = tempdir.path().join("memory.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  runtime:\n    memory_limit_mb: 1\n",
+        )
+        .await?;
+        manager
+            .attach_policy(&id, &format!("file://{}", policy_path.display()))
+            .await?;
+
+        // 8 pages of 64 KiB stay within 1 MiB
+        manager
+            .execute_component_call(&id, "grow", r#"{"pages": 8}"#)
+            .await?;
+
+        let error = manager
+            .execute_component_call(&id, "grow", r#"{"pages": 32}"#)
+            .await
+            .expect_err("growing past the policy limit should fail");
+        match error {
+            WassetteError::PolicyViolation(violation) => assert_eq!(
+                violation,
+                PolicyViolation::MemoryLimit {
+                    limit_bytes: 1024 * 1024,
+                    requested_bytes: 33 * 64 * 1024,
+                }
+            ),
+            other => panic!("unexpected error: {other:#}"),
+        }
+
+        Ok(())
+    }
 }
@@ -10,6 +10,9 @@ use tokio::fs::metadata;
 use tokio::io::AsyncWriteExt;
 use tracing::{debug, warn};
 
+use crate::retry::{get_with_retry, oci_with_retry};
+use crate::{RegistryCredentials, RetryPolicy};
+
 /// Represents a downloaded resource, either from a local file or a temporary one.
 pub enum DownloadedResource {
     Local(PathBuf),
@@ -46,17 +49,9 @@ impl DownloadedResource {
     pub fn id(&self) -> Result<String> {
         // NOTE(thomastaylor312): Unfortunately the rust tooling (and I think some of the others),
         // doesn't preserve the package ID from the wit world defined for the component. It just
-        // ends up as "root-component". So for now we rely on the file name to give us a unique ID
-        // for the component.
-        // let decoded = wit_parser::decoding::decode(&wasm_bytes)
-        //     .map_err(|e| anyhow::anyhow!("Failed to decode component from path: {}. Error: {}. Please ensure the file is a valid WebAssembly component.", file.as_ref().display(), e))?;
-
-        // let pkg_id = decoded.package();
-        // // SAFETY: The package ID is guaranteed to be valid because we just decoded it
-        // let pkg = decoded.resolve().packages.get(pkg_id).unwrap();
-        // // Format the package name without the colon so it is valid on all systems. We are using the
-        // // package name as a unique key on the filesystem as well
-        // let id = format!("{}-{}", pkg.name.namespace, pkg.name.name);
+        // ends up as "root-component". So by default we rely on the file name to give us a unique
+        // ID for the component. Components that do carry their package name can opt into
+        // `package_id` instead.
 
         // Load the component to see if it is valid
         let maybe_id = match self {
@@ -69,7 +64,37 @@ impl DownloadedResource {
             .ok_or_else(|| anyhow::anyhow!("Failed to extract resource ID from path"))
     }
 
+    /// Returns the ID of a component given by its WIT package, i.e. `namespace:name@version` made
+    /// safe to use as a file name, e.g. `wasi_http_0.2.0`.
+    ///
+    /// Components built by most toolchains don't carry their package name and decode as
+    /// `root:component`, in which case `None` is returned and the file name should be used instead.
+    pub fn package_id(wasm_bytes: &[u8]) -> Option<String> {
+        let decoded = match wit_parser::decoding::decode(wasm_bytes) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                debug!(error = %e, "Failed to decode component WIT, falling back to file name");
+                return None;
+            }
+        };
+        let name = &decoded.resolve().packages[decoded.package()].name;
+        if name.namespace == "root" && name.name == "component" {
+            return None;
+        }
+        Some(crate::sanitize_component_id(&name.to_string()))
+    }
+
     pub async fn copy_to(self, dest: impl AsRef<Path>) -> Result<()> {
+        let file_name = self
+            .as_ref()
+            .file_name()
+            .context("Path to copy is missing filename")?
+            .to_owned();
+        self.copy_as(dest, file_name).await
+    }
+
+    /// Copies the resource into the `dest` directory under the given file name
+    pub async fn copy_as(self, dest: impl AsRef<Path>, file_name: impl AsRef<Path>) -> Result<()> {
         let meta = tokio::fs::metadata(&dest).await?;
         if !meta.is_dir() {
             bail!(
@@ -77,19 +102,12 @@ impl DownloadedResource {
                 dest.as_ref().display()
             );
         }
+        let dest = dest.as_ref().join(file_name);
         match self {
             DownloadedResource::Local(path) => {
-                let dest = dest.as_ref().join(
-                    path.file_name()
-                        .context("Path to copy is missing filename")?,
-                );
                 tokio::fs::copy(path, dest).await?;
             }
             DownloadedResource::Temp((tempdir, file)) => {
-                let dest = dest.as_ref().join(
-                    file.file_name()
-                        .context("Path to copy is missing filename")?,
-                );
                 match tokio::fs::rename(&file, &dest).await {
                     Ok(()) => {}
                     Err(e) if e.raw_os_error() == Some(18) => {
@@ -136,8 +154,14 @@ pub trait Loadable: Sized {
     async fn from_oci_reference(
         reference: &str,
         oci_client: &oci_client::Client,
+        credentials: &RegistryCredentials,
+        retry: &RetryPolicy,
+    ) -> Result<DownloadedResource>;
+    async fn from_url(
+        url: &str,
+        http_client: &reqwest::Client,
+        retry: &RetryPolicy,
     ) -> Result<DownloadedResource>;
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource>;
 }
 
 /// Loadable implementation for WebAssembly components
@@ -170,12 +194,14 @@ impl Loadable for ComponentResource {
     async fn from_oci_reference(
         reference: &str,
         oci_client: &oci_client::Client,
+        credentials: &RegistryCredentials,
+        retry: &RetryPolicy,
     ) -> Result<DownloadedResource> {
         let reference: oci_client::Reference =
             reference.parse().context("Failed to parse OCI reference")?;
-        let data = oci_wasm::WasmClient::from(oci_client.clone())
-            .pull(&reference, &oci_client::secrets::RegistryAuth::Anonymous)
-            .await?;
+        let auth = credentials.auth_for(&reference);
+        let client = oci_wasm::WasmClient::from(oci_client.clone());
+        let data = oci_with_retry(retry, || client.pull(&reference, &auth)).await?;
         let (downloaded_resource, mut file) = DownloadedResource::new_temp_file(
             reference.repository().replace('/', "_"),
             Self::FILE_EXTENSION,
@@ -189,8 +215,12 @@ impl Loadable for ComponentResource {
         Ok(downloaded_resource)
     }
 
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource> {
-        let resp = http_client.get(url).send().await?;
+    async fn from_url(
+        url: &str,
+        http_client: &reqwest::Client,
+        retry: &RetryPolicy,
+    ) -> Result<DownloadedResource> {
+        let resp = get_with_retry(http_client, url, retry).await?;
         let status = resp.status();
         if !status.is_success() {
             let body = resp.text().await.unwrap_or_default();
@@ -244,11 +274,17 @@ impl Loadable for PolicyResource {
     async fn from_oci_reference(
         _reference: &str,
         _oci_client: &oci_client::Client,
+        _credentials: &RegistryCredentials,
+        _retry: &RetryPolicy,
     ) -> Result<DownloadedResource> {
         bail!("OCI references are not supported for policy resources. Use 'file://' or 'https://' schemes instead.")
     }
 
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource> {
+    async fn from_url(
+        url: &str,
+        http_client: &reqwest::Client,
+        retry: &RetryPolicy,
+    ) -> Result<DownloadedResource> {
         let url_obj = reqwest::Url::parse(url)?;
         let filename = url_obj
             .path_segments()
@@ -261,7 +297,7 @@ impl Loadable for PolicyResource {
         let (downloaded_resource, mut temp_file) =
             DownloadedResource::new_temp_file(&temp_file_name, Self::FILE_EXTENSION).await?;
 
-        let response = http_client.get(url).send().await?;
+        let response = get_with_retry(http_client, url, retry).await?;
         if !response.status().is_success() {
             bail!(
                 "Failed to download policy from {}: {}",
@@ -281,11 +317,60 @@ impl Loadable for PolicyResource {
     }
 }
 
+/// The URI fragment that pins the SHA-256 digest of a component, e.g.
+/// `oci://ghcr.io/org/tool:1.0#sha256=<hex>`
+const DIGEST_FRAGMENT: &str = "#sha256=";
+
+/// Splits the expected digest off a component URI. Returns the URI without the fragment and the
+/// lowercase hex encoded digest, if the URI has one.
+pub(crate) fn split_digest(uri: &str) -> Result<(&str, Option<String>)> {
+    let uri = uri.trim();
+    let Some((uri, digest)) = uri.rsplit_once(DIGEST_FRAGMENT) else {
+        return Ok((uri, None));
+    };
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Invalid sha256 digest '{digest}': expected 64 hexadecimal characters");
+    }
+    Ok((uri, Some(digest.to_ascii_lowercase())))
+}
+
+/// Returns the ID a component loaded from `uri` gets, without downloading it. This is the name of
+/// the file it would be downloaded to, see [`DownloadedResource::id`].
+pub(crate) fn component_id_from_uri(uri: &str) -> Result<String> {
+    let uri = uri.trim();
+    let (scheme, reference) = uri
+        .split_once("://")
+        .context("Invalid component reference. Should be of the form scheme://reference")?;
+    let id = match scheme {
+        "file" => Path::new(reference)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(str::to_string),
+        "oci" => {
+            let reference: oci_client::Reference =
+                reference.parse().context("Failed to parse OCI reference")?;
+            Some(reference.repository().replace('/', "_"))
+        }
+        "https" => reqwest::Url::parse(uri)?
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .map(|name| {
+                name.trim_end_matches(&format!(".{}", ComponentResource::FILE_EXTENSION))
+                    .to_string()
+            }),
+        _ => bail!("Unsupported component scheme: {}", scheme),
+    };
+    id.filter(|id| !id.is_empty())
+        .context("Failed to extract resource ID from URI")
+}
+
 /// Generic resource loading function
 pub(crate) async fn load_resource<T: Loadable>(
     uri: &str,
     oci_client: &oci_wasm::WasmClient,
+    credentials: &RegistryCredentials,
     http_client: &reqwest::Client,
+    retry: &RetryPolicy,
 ) -> Result<DownloadedResource> {
     let uri = uri.trim();
     let error_message = format!(
@@ -296,8 +381,8 @@ pub(crate) async fn load_resource<T: Loadable>(
 
     match scheme {
         "file" => T::from_local_file(Path::new(reference)).await,
-        "oci" => T::from_oci_reference(reference, oci_client).await,
-        "https" => T::from_url(uri, http_client).await,
+        "oci" => T::from_oci_reference(reference, oci_client, credentials, retry).await,
+        "https" => T::from_url(uri, http_client, retry).await,
         _ => bail!("Unsupported {} scheme: {}", T::RESOURCE_TYPE, scheme),
     }
 }
@@ -0,0 +1,270 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Metadata declared next to a component in the plugin directory, like the other components it
+//! depends on
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::LifecycleManager;
+
+/// The contents of the optional `<id>.meta.yaml` file next to a component, e.g.
+///
+/// ```yaml
+/// requires: ["weather"]
+/// strict: true
+/// provides: ["get-forecast"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComponentMetadata {
+    /// IDs of the components this component calls, which need to be loaded alongside it
+    pub requires: Vec<String>,
+    /// Whether a missing required component fails [`LifecycleManager::reconcile`] instead of
+    /// only being logged
+    pub strict: bool,
+    /// Tool names that resolve to this component in addition to the names of its own tools
+    pub provides: Vec<String>,
+}
+
+impl LifecycleManager {
+    pub(crate) fn get_component_meta_path(&self, component_id: &str) -> PathBuf {
+        self.plugin_dir
+            .join(crate::component_file_name(component_id, "meta.yaml"))
+    }
+
+    /// Returns the metadata declared for a loaded component, if it has any
+    pub async fn get_component_metadata(&self, component_id: &str) -> Option<ComponentMetadata> {
+        self.component_metadata
+            .read()
+            .await
+            .get(component_id)
+            .cloned()
+    }
+
+    /// Returns the components whose required components aren't loaded, along with the IDs of the
+    /// missing components, sorted by component ID. Lazily registered components count as loaded.
+    pub async fn missing_dependencies(&self) -> Vec<(String, Vec<String>)> {
+        let components = self.components.read().await;
+        let mut missing: Vec<_> = self
+            .component_metadata
+            .read()
+            .await
+            .iter()
+            .filter_map(|(id, metadata)| {
+                let missing: Vec<String> = metadata
+                    .requires
+                    .iter()
+                    .filter(|required| {
+                        !components.contains_key(required.as_str()) && !self.is_lazy(required)
+                    })
+                    .cloned()
+                    .collect();
+                (!missing.is_empty()).then(|| (id.clone(), missing))
+            })
+            .collect();
+        missing.sort_by(|(a, _), (b, _)| a.cmp(b));
+        missing
+    }
+
+    /// Reads the metadata file of a component, replacing the metadata known for it. A file that
+    /// can't be read is logged and ignored, so that it doesn't prevent the component from loading.
+    pub(crate) async fn load_component_metadata(&self, component_id: &str) {
+        let path = self.get_component_meta_path(component_id);
+        let metadata = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => match serde_yaml::from_str::<ComponentMetadata>(&content)
+                .with_context(|| format!("Invalid metadata file {}", path.display()))
+            {
+                Ok(metadata) => Some(metadata),
+                Err(e) => {
+                    warn!(component_id, error = %format!("{e:#}"), "Ignoring component metadata");
+                    None
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!(component_id, error = %e, "Failed to read component metadata file");
+                None
+            }
+        };
+        let mut component_metadata = self.component_metadata.write().await;
+        match metadata {
+            Some(metadata) => component_metadata.insert(component_id.to_string(), metadata),
+            None => component_metadata.remove(component_id),
+        };
+    }
+
+    /// Logs the components whose required components aren't loaded. Fails if any of them is
+    /// strict about it.
+    pub(crate) async fn check_dependencies(&self) -> Result<()> {
+        let mut strict = Vec::new();
+        for (component_id, missing) in self.missing_dependencies().await {
+            let is_strict = self
+                .get_component_metadata(&component_id)
+                .await
+                .is_some_and(|metadata| metadata.strict);
+            if is_strict {
+                error!(component_id, ?missing, "Required components are not loaded");
+                strict.push(format!("{component_id} requires {}", missing.join(", ")));
+            } else {
+                warn!(component_id, ?missing, "Required components are not loaded");
+            }
+        }
+        if !strict.is_empty() {
+            bail!("Required components are not loaded: {}", strict.join("; "));
+        }
+        Ok(())
+    }
+
+    /// Returns the IDs of the components that declare `tool_name` in their `provides` list
+    pub(crate) async fn components_providing(&self, tool_name: &str) -> Vec<String> {
+        let mut components: Vec<String> = self
+            .component_metadata
+            .read()
+            .await
+            .iter()
+            .filter(|(_, metadata)| metadata.provides.iter().any(|alias| alias == tool_name))
+            .map(|(id, _)| id.clone())
+            .collect();
+        components.sort();
+        components
+    }
+
+    /// Returns whether the component declares `tool_name` in its `provides` list
+    pub(crate) async fn provides_tool_name(&self, component_id: &str, tool_name: &str) -> bool {
+        self.component_metadata
+            .read()
+            .await
+            .get(component_id)
+            .is_some_and(|metadata| metadata.provides.iter().any(|alias| alias == tool_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::WassetteError;
+
+    #[test(tokio::test)]
+    async fn test_missing_dependencies() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(tempdir.path().join("answer.wasm"), ANSWER_WAT).await?;
+        tokio::fs::write(
+            tempdir.path().join("answer.meta.yaml"),
+            "requires: [\"weather\", \"clock\"]\n",
+        )
+        .await?;
+
+        // Missing dependencies of components that aren't strict are only reported
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        assert_eq!(
+            manager.missing_dependencies().await,
+            vec![(
+                "answer".to_string(),
+                vec!["weather".to_string(), "clock".to_string()]
+            )]
+        );
+
+        tokio::fs::write(tempdir.path().join("weather.wasm"), ANSWER_WAT).await?;
+        manager.reconcile().await?;
+        assert_eq!(
+            manager.missing_dependencies().await,
+            vec![("answer".to_string(), vec!["clock".to_string()])]
+        );
+
+        tokio::fs::write(tempdir.path().join("clock.wasm"), ANSWER_WAT).await?;
+        manager.reconcile().await?;
+        assert!(manager.missing_dependencies().await.is_empty());
+
+        // Unloading a dependency makes it missing again
+        manager.unload_component("clock").await?;
+        assert_eq!(
+            manager.missing_dependencies().await,
+            vec![("answer".to_string(), vec!["clock".to_string()])]
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_strict_dependencies_fail_startup() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(tempdir.path().join("answer.wasm"), ANSWER_WAT).await?;
+        tokio::fs::write(
+            tempdir.path().join("answer.meta.yaml"),
+            "requires: [\"weather\"]\nstrict: true\n",
+        )
+        .await?;
+
+        let err = LifecycleManager::new(tempdir.path())
+            .await
+            .err()
+            .expect("missing strict dependency should fail startup");
+        assert!(err.to_string().contains("answer requires weather"), "{err}");
+
+        tokio::fs::write(tempdir.path().join("weather.wasm"), ANSWER_WAT).await?;
+        LifecycleManager::new(tempdir.path()).await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_provided_tool_names_resolve_to_component() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(tempdir.path().join("answer.wasm"), ANSWER_WAT).await?;
+        tokio::fs::write(
+            tempdir.path().join("answer.meta.yaml"),
+            "provides: [\"ultimate-question\"]\n",
+        )
+        .await?;
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+
+        assert_eq!(
+            manager
+                .get_component_id_for_tool("ultimate-question")
+                .await?,
+            "answer"
+        );
+        let result = manager
+            .execute_component_call("answer", "ultimate-question", "{}")
+            .await?;
+        assert!(result.contains("42"), "unexpected result: {result}");
+
+        // Two components providing the same name make it ambiguous
+        tokio::fs::write(tempdir.path().join("other.wasm"), ANSWER_WAT).await?;
+        tokio::fs::write(
+            tempdir.path().join("other.meta.yaml"),
+            "provides: [\"ultimate-question\"]\n",
+        )
+        .await?;
+        manager.reconcile().await?;
+        assert!(matches!(
+            manager.get_component_id_for_tool("ultimate-question").await,
+            Err(WassetteError::ToolAmbiguous { components, .. }) if components == ["answer", "other"]
+        ));
+
+        // Metadata files with unknown fields are ignored
+        tokio::fs::write(
+            tempdir.path().join("other.meta.yaml"),
+            "provide: [\"ultimate-question\"]\n",
+        )
+        .await?;
+        manager.reconcile().await?;
+        assert_eq!(manager.get_component_metadata("other").await, None);
+        assert_eq!(
+            manager
+                .get_component_id_for_tool("ultimate-question")
+                .await?,
+            "answer"
+        );
+
+        Ok(())
+    }
+}
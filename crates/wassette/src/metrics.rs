@@ -0,0 +1,314 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Counters of the calls made to each component, also exported as Prometheus metrics
+
+use std::time::{Duration, SystemTime};
+
+use std::collections::HashMap;
+
+use prometheus::core::{Collector, MetricVec, MetricVecBuilder};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry};
+
+use crate::{split_qualified_tool_name, LifecycleManager, PolicyViolation, WassetteError};
+
+/// The calls made to a component since it was loaded, see [`LifecycleManager::get_metrics`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComponentMetrics {
+    /// The number of calls, successful or not
+    pub call_count: u64,
+    /// The number of calls that failed
+    pub error_count: u64,
+    /// The time spent in calls, in milliseconds
+    pub total_duration_ms: u64,
+    /// When the component was last called
+    pub last_called_at: Option<SystemTime>,
+}
+
+impl ComponentMetrics {
+    /// The share of calls that failed, between 0 and 1. `0` if the component was never called
+    pub fn error_rate(&self) -> f64 {
+        if self.call_count == 0 {
+            0.0
+        } else {
+            self.error_count as f64 / self.call_count as f64
+        }
+    }
+
+    fn record(&mut self, duration: Duration, succeeded: bool) {
+        self.call_count += 1;
+        if !succeeded {
+            self.error_count += 1;
+        }
+        let duration_ms = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        self.total_duration_ms = self.total_duration_ms.saturating_add(duration_ms);
+        self.last_called_at = Some(SystemTime::now());
+    }
+}
+
+/// The `function` label of calls of tools that don't exist, so that callers can't add a series
+/// for every name they make up
+const UNKNOWN_FUNCTION: &str = "unknown";
+
+/// The Prometheus metrics of a manager, see [`LifecycleManager::prometheus_registry`]
+#[derive(Clone)]
+pub(crate) struct PrometheusMetrics {
+    registry: Registry,
+    tool_calls: IntCounterVec,
+    tool_call_duration: HistogramVec,
+    components_loaded: IntGauge,
+    policy_violations: IntCounterVec,
+}
+
+impl PrometheusMetrics {
+    pub(crate) fn new() -> Self {
+        let tool_calls = IntCounterVec::new(
+            Opts::new(
+                "wassette_tool_calls_total",
+                "Calls of the tools of components",
+            ),
+            &["component", "function", "status"],
+        )
+        .expect("the metric is valid");
+        let tool_call_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "wassette_tool_call_duration_seconds",
+                "How long calls of the tools of components took",
+            ),
+            &["component", "function"],
+        )
+        .expect("the metric is valid");
+        let components_loaded =
+            IntGauge::new("wassette_components_loaded", "Components currently loaded")
+                .expect("the metric is valid");
+        let policy_violations = IntCounterVec::new(
+            Opts::new(
+                "wassette_policy_violations_total",
+                "Calls aborted because the component went over the memory limit of its policy",
+            ),
+            &["component", "type"],
+        )
+        .expect("the metric is valid");
+
+        // Every manager has a registry of its own, so the names are never registered twice
+        let registry = Registry::new();
+        registry
+            .register(Box::new(tool_calls.clone()))
+            .and_then(|()| registry.register(Box::new(tool_call_duration.clone())))
+            .and_then(|()| registry.register(Box::new(components_loaded.clone())))
+            .and_then(|()| registry.register(Box::new(policy_violations.clone())))
+            .expect("the metrics are registered once");
+        Self {
+            registry,
+            tool_calls,
+            tool_call_duration,
+            components_loaded,
+            policy_violations,
+        }
+    }
+
+    pub(crate) fn set_components_loaded(&self, count: usize) {
+        self.components_loaded
+            .set(i64::try_from(count).unwrap_or(i64::MAX));
+    }
+
+    /// Removes every series of a component, so that unloaded components don't accumulate
+    pub(crate) fn remove_component(&self, component_id: &str) {
+        remove_component_series(&self.tool_calls, component_id);
+        remove_component_series(&self.tool_call_duration, component_id);
+        remove_component_series(&self.policy_violations, component_id);
+    }
+
+    fn record_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        duration: Duration,
+        error: Option<&WassetteError>,
+    ) {
+        let function_name = match error {
+            Some(WassetteError::ToolNotFound(_)) => UNKNOWN_FUNCTION,
+            _ => function_name,
+        };
+        let status = if error.is_some() { "error" } else { "ok" };
+        self.tool_calls
+            .with_label_values(&[component_id, function_name, status])
+            .inc();
+        self.tool_call_duration
+            .with_label_values(&[component_id, function_name])
+            .observe(duration.as_secs_f64());
+        if let Some(WassetteError::PolicyViolation(violation)) = error {
+            let violation_type = match violation {
+                PolicyViolation::MemoryLimit { .. } => "memory_limit",
+            };
+            self.policy_violations
+                .with_label_values(&[component_id, violation_type])
+                .inc();
+        }
+    }
+}
+
+/// Removes the series of `metrics` whose `component` label is `component_id`
+fn remove_component_series<T: MetricVecBuilder>(metrics: &MetricVec<T>, component_id: &str) {
+    for family in metrics.collect() {
+        for metric in family.get_metric() {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            if labels.get("component") == Some(&component_id) {
+                // The series was just collected, so it can only be missing if it was removed
+                // concurrently
+                let _ = metrics.remove(&labels);
+            }
+        }
+    }
+}
+
+impl LifecycleManager {
+    /// Returns the registry of the Prometheus metrics of the manager: the
+    /// `wassette_tool_calls_total` counter and `wassette_tool_call_duration_seconds` histogram of
+    /// the calls of every function of every component, the `wassette_components_loaded` gauge, and
+    /// the `wassette_policy_violations_total` counter of calls aborted for going over the memory
+    /// limit of the policy of the component. Denied network requests and storage accesses are
+    /// reported to the component rather than aborting the call, so they aren't counted.
+    ///
+    /// Calls of tools that don't exist are labeled with the `unknown` function, and the series of
+    /// a component are removed when it is unloaded.
+    pub fn prometheus_registry(&self) -> &Registry {
+        &self.prometheus.registry
+    }
+
+    /// Returns the metrics of the calls made to a component, or `None` if it hasn't been called
+    /// since it was loaded
+    pub async fn get_metrics(&self, component_id: &str) -> Option<ComponentMetrics> {
+        self.metrics.read().await.get(component_id).cloned()
+    }
+
+    /// Returns the metrics of every component that has been called, sorted by component ID
+    pub async fn list_metrics(&self) -> Vec<(String, ComponentMetrics)> {
+        let mut metrics: Vec<_> = self
+            .metrics
+            .read()
+            .await
+            .iter()
+            .map(|(id, metrics)| (id.clone(), metrics.clone()))
+            .collect();
+        metrics.sort_by(|(a, _), (b, _)| a.cmp(b));
+        metrics
+    }
+
+    /// Records a call of `function_name`, which failed with `error` if there is one
+    pub(crate) async fn record_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        duration: Duration,
+        error: Option<&WassetteError>,
+    ) {
+        let (_, function_name) = split_qualified_tool_name(function_name);
+        self.prometheus
+            .record_call(component_id, function_name, duration, error);
+        self.metrics
+            .write()
+            .await
+            .entry(component_id.to_string())
+            .or_default()
+            .record(duration, error.is_none());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::tests::*;
+
+    /// Returns the metrics of `manager` in the Prometheus text format
+    fn scrape(manager: &LifecycleManager) -> String {
+        prometheus::TextEncoder::new()
+            .encode_to_string(&manager.prometheus_registry().gather())
+            .unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn test_calls_are_counted() -> anyhow::Result<()> {
+        let manager = create_test_manager().await?;
+        let tempdir = tempfile::tempdir()?;
+        let component_path = tempdir.path().join("crash.wasm");
+        tokio::fs::write(
+            &component_path,
+            r#"(component
+                (core module $m
+                    (func (export "ok") (result i32) i32.const 1)
+                    (func (export "crash") unreachable))
+                (core instance $i (instantiate $m))
+                (func (export "ok") (result u32) (canon lift (core func $i "ok")))
+                (func (export "crash") (canon lift (core func $i "crash")))
+            )"#,
+        )
+        .await?;
+        let (id, _) = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        assert_eq!(manager.get_metrics(&id).await, None);
+
+        manager.execute_component_call(&id, "ok", "{}").await?;
+        manager.execute_component_call(&id, "ok", "{}").await?;
+        assert!(manager
+            .execute_component_call(&id, "crash", "{}")
+            .await
+            .is_err());
+
+        let metrics = manager.get_metrics(&id).await.unwrap();
+        assert_eq!(metrics.call_count, 3);
+        assert_eq!(metrics.error_count, 1);
+        assert!((metrics.error_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+        assert!(metrics.last_called_at.is_some());
+        assert_eq!(manager.list_metrics().await, vec![(id.clone(), metrics)]);
+
+        let scraped = scrape(&manager);
+        for sample in [
+            r#"wassette_tool_calls_total{component="crash",function="ok",status="ok"} 2"#,
+            r#"wassette_tool_calls_total{component="crash",function="crash",status="error"} 1"#,
+            r#"wassette_tool_call_duration_seconds_count{component="crash",function="ok"} 2"#,
+            "wassette_components_loaded 1",
+        ] {
+            assert!(scraped.contains(sample), "{sample} missing from {scraped}");
+        }
+
+        // Calls to components that aren't loaded aren't counted
+        assert!(matches!(
+            manager.execute_component_call("missing", "ok", "{}").await,
+            Err(WassetteError::ComponentNotFound(_))
+        ));
+        assert_eq!(manager.list_metrics().await.len(), 1);
+
+        // Calls of made up tools share a single series
+        for tool in ["made-up", "also-made-up"] {
+            assert!(matches!(
+                manager.execute_component_call(&id, tool, "{}").await,
+                Err(WassetteError::ToolNotFound(_))
+            ));
+        }
+        let scraped = scrape(&manager);
+        assert!(
+            scraped.contains(
+                r#"wassette_tool_calls_total{component="crash",function="unknown",status="error"} 2"#
+            ),
+            "{scraped}"
+        );
+        assert!(!scraped.contains("made-up"));
+
+        // Unloading a component drops its metrics and its Prometheus series
+        manager.unload_component(&id).await?;
+        assert_eq!(manager.get_metrics(&id).await, None);
+        let scraped = scrape(&manager);
+        assert!(scraped.contains("wassette_components_loaded 0"));
+        assert!(!scraped.contains(r#"component="crash""#), "{scraped}");
+
+        Ok(())
+    }
+}
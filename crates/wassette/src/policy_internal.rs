@@ -4,18 +4,87 @@
 //! Policy management structures and types
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use policy::{
-    AccessType, EnvironmentPermission, NetworkHostPermission, NetworkPermission, PolicyDocument,
-    PolicyParser, StoragePermission,
+    AccessType, EnvironmentPermission, NetworkHostPermission, NetworkPermission, Permissions,
+    PolicyDocument, PolicyParser, SerializerOptions, StoragePermission,
 };
 use serde::{Deserialize, Serialize};
 use tracing::{info, instrument};
 
-use crate::WasiStateTemplate;
+use crate::loader::DownloadedResource;
+use crate::{WasiStateTemplate, WassetteError};
+
+/// Name of the policy in the plugin directory that every component policy is merged over
+pub(crate) const BASE_POLICY_FILE: &str = "base.policy.yaml";
+
+/// Reads the base policy of the plugin directory, if there is one, and turns it into the
+/// template used for components without a policy
+pub(crate) async fn load_base_policy(
+    plugin_dir: &Path,
+    environment_vars: &HashMap<String, String>,
+) -> Result<Option<Arc<WasiStateTemplate>>> {
+    let policy_path = plugin_dir.join(BASE_POLICY_FILE);
+    if !tokio::fs::try_exists(&policy_path).await? {
+        return Ok(None);
+    }
+    let policy = PolicyParser::parse_file(&policy_path)
+        .map_err(|e| anyhow!("Invalid base policy {}: {e}", policy_path.display()))?;
+    let template =
+        crate::create_wasi_state_template_from_policy(&policy, plugin_dir, environment_vars)
+            .with_context(|| format!("Invalid base policy {}", policy_path.display()))?;
+    info!(path = %policy_path.display(), "Loaded base policy");
+    Ok(Some(Arc::new(template)))
+}
+
+/// Serializes a policy the way it is stored next to its component
+fn policy_yaml(policy: &PolicyDocument) -> Result<String> {
+    // Keep the comments of policies that were edited by hand
+    Ok(PolicyParser::to_yaml_with_options(
+        policy,
+        &SerializerOptions {
+            preserve_comments: true,
+        },
+    )?)
+}
+
+/// Returns a line diff of two texts, see [`crate::LifecycleManager::grant_permission_dry_run`]
+fn diff_lines(old: &str, new: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    if old == new {
+        return String::new();
+    }
+    // The length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push_str(&format!("  {}\n", old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] > common[i + 1][j]) {
+            diff.push_str(&format!("+ {}\n", new[j]));
+            j += 1;
+        } else {
+            diff.push_str(&format!("- {}\n", old[i]));
+            i += 1;
+        }
+    }
+    diff
+}
 
 /// Granular permission rule types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,11 +114,47 @@ pub struct PermissionGrantRequest {
     pub details: serde_json::Value,
 }
 
+/// The outcome of [`crate::LifecycleManager::revoke_permission`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevokeResult {
+    /// The number of rules removed from the policy
+    pub removed: usize,
+    /// Whether the policy changed, which is false if the permission wasn't granted
+    pub changed: bool,
+}
+
+/// A change of a component's policy that hasn't been saved yet
+struct PolicyChange {
+    before: PolicyDocument,
+    after: PolicyDocument,
+    /// The number of rules removed by the change
+    removed: usize,
+}
+
+/// Removes the items matching `predicate`, returning how many were removed
+fn remove_matching<T>(items: &mut Vec<T>, predicate: impl Fn(&T) -> bool) -> usize {
+    let len = items.len();
+    items.retain(|item| !predicate(item));
+    len - items.len()
+}
+
 /// Registry for storing policy templates associated with components
 #[derive(Default)]
 pub(crate) struct PolicyRegistry {
     /// Maps component IDs to their associated policy templates
     pub(crate) component_policies: HashMap<String, Arc<WasiStateTemplate>>,
+    /// Maps component IDs to the fuel a single call of the component may consume
+    pub(crate) fuel_limits: HashMap<String, u64>,
+    /// Maps component IDs to the linear memory a single call of the component may allocate, in
+    /// bytes. Components without an entry are limited to [`crate::DEFAULT_MEMORY_LIMIT`]
+    pub(crate) memory_limits: HashMap<String, usize>,
+}
+
+/// A policy that was downloaded and validated, but isn't attached to a component yet
+pub(crate) struct PreparedPolicy {
+    uri: String,
+    downloaded: DownloadedResource,
+    template: WasiStateTemplate,
 }
 
 /// Information about a policy attached to a component
@@ -73,45 +178,131 @@ impl crate::LifecycleManager {
     /// in the plugin directory specified by the `plugin_dir`, co-located with
     /// the component. The component_id must be the ID of a component that is
     /// already loaded.
-    pub async fn attach_policy(&self, component_id: &str, policy_uri: &str) -> Result<()> {
+    pub async fn attach_policy(
+        &self,
+        component_id: &str,
+        policy_uri: &str,
+    ) -> Result<(), WassetteError> {
         info!(component_id, policy_uri, "Attaching policy to component");
 
         if !self.components.read().await.contains_key(component_id) {
-            return Err(anyhow!("Component not found: {}", component_id));
+            return Err(WassetteError::ComponentNotFound(component_id.to_string()));
         }
 
-        let downloaded_policy = crate::loader::load_resource::<crate::PolicyResource>(
+        let policy = self.prepare_policy(policy_uri).await?;
+        self.install_policy(component_id, policy).await?;
+
+        info!(component_id, policy_uri, "Policy attached successfully");
+        Ok(())
+    }
+
+    /// Downloads and validates a policy, without attaching it to a component yet
+    pub(crate) async fn prepare_policy(
+        &self,
+        policy_uri: &str,
+    ) -> Result<PreparedPolicy, WassetteError> {
+        let downloaded = crate::loader::load_resource::<crate::PolicyResource>(
             policy_uri,
             &self.oci_client,
+            &self.registry_credentials,
             &self.http_client,
+            &self.retry_policy,
         )
-        .await?;
-
-        let policy = PolicyParser::parse_file(downloaded_policy.as_ref())?;
+        .await
+        .map_err(WassetteError::DownloadFailed)?;
+
+        let policy = PolicyParser::parse_file(downloaded.as_ref())
+            .map_err(|e| WassetteError::PolicyInvalid(e.into()))?;
+        let template = self
+            .create_policy_template(&policy)
+            .map_err(WassetteError::PolicyInvalid)?;
+
+        Ok(PreparedPolicy {
+            uri: policy_uri.to_string(),
+            downloaded,
+            template,
+        })
+    }
 
+    /// Stores a policy returned by [`Self::prepare_policy`] next to the component and applies it
+    /// to the component's next calls
+    pub(crate) async fn install_policy(
+        &self,
+        component_id: &str,
+        policy: PreparedPolicy,
+    ) -> Result<(), WassetteError> {
         let policy_path = self.get_component_policy_path(component_id);
-        tokio::fs::copy(downloaded_policy.as_ref(), &policy_path).await?;
+        tokio::fs::copy(policy.downloaded.as_ref(), &policy_path)
+            .await
+            .context("Failed to store the policy in the plugin directory")?;
 
         // Store metadata about the policy source
         let metadata = serde_json::json!({
-            "source_uri": policy_uri,
+            "source_uri": policy.uri,
             "attached_at": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
         });
         let metadata_path = self.get_component_metadata_path(component_id);
-        tokio::fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?).await?;
+        let metadata =
+            serde_json::to_string_pretty(&metadata).context("Failed to serialize the metadata")?;
+        tokio::fs::write(&metadata_path, metadata)
+            .await
+            .context("Failed to store the metadata of the policy")?;
 
-        let wasi_template = crate::create_wasi_state_template_from_policy(
-            &policy,
-            &self.plugin_dir,
-            &self.environment_vars,
-        )?;
         self.policy_registry
             .write()
             .await
             .component_policies
-            .insert(component_id.to_string(), Arc::new(wasi_template));
+            .insert(component_id.to_string(), Arc::new(policy.template));
+        self.result_cache
+            .lock()
+            .unwrap()
+            .remove_component(component_id);
+        Ok(())
+    }
 
-        info!(component_id, policy_uri, "Policy attached successfully");
+    /// Limits how much fuel a single call of a component may consume. Every executed WebAssembly
+    /// instruction costs roughly one unit of fuel, so this bounds the CPU time of a call
+    /// independently of how fast the host is. Calls of components without a limit can consume an
+    /// unlimited amount of fuel. The component_id must be the ID of a component that is already
+    /// loaded.
+    pub async fn set_fuel_limit(&self, component_id: &str, fuel: u64) -> Result<()> {
+        info!(component_id, fuel, "Setting fuel limit for component");
+
+        if !self.components.read().await.contains_key(component_id) {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+
+        self.policy_registry
+            .write()
+            .await
+            .fuel_limits
+            .insert(component_id.to_string(), fuel);
+        Ok(())
+    }
+
+    /// Limits how much linear memory a single call of a component may allocate, in bytes. Calls
+    /// that try to grow their memory past the limit fail with an error. Components without a limit
+    /// are limited to [`crate::DEFAULT_MEMORY_LIMIT`]. The component_id must be the ID of a
+    /// component that is already loaded.
+    pub async fn set_memory_limit(
+        &self,
+        component_id: &str,
+        max_memory_bytes: usize,
+    ) -> Result<()> {
+        info!(
+            component_id,
+            max_memory_bytes, "Setting memory limit for component"
+        );
+
+        if !self.components.read().await.contains_key(component_id) {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+
+        self.policy_registry
+            .write()
+            .await
+            .memory_limits
+            .insert(component_id.to_string(), max_memory_bytes);
         Ok(())
     }
 
@@ -119,6 +310,7 @@ impl crate::LifecycleManager {
     /// component and remove the policy file from the plugin directory.
     pub async fn detach_policy(&self, component_id: &str) -> Result<()> {
         info!(component_id, "Detaching policy from component");
+        crate::validate_component_id(component_id)?;
 
         // Remove files first, then clean up memory on success
         let policy_path = self.get_component_policy_path(component_id);
@@ -177,17 +369,72 @@ impl crate::LifecycleManager {
         })
     }
 
+    /// Returns the permissions in force for a component: those of its policy merged over the base
+    /// policy, the base policy's alone if it has no policy, or none at all
+    #[instrument(skip(self))]
+    pub async fn get_effective_permissions(&self, component_id: &str) -> Result<Permissions> {
+        if !self.components.read().await.contains_key(component_id) {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+        let template = self.policy_template_for(component_id).await;
+        Ok(template
+            .policy
+            .as_ref()
+            .map(|policy| policy.permissions.clone())
+            .unwrap_or_default())
+    }
+
+    /// Returns the policy template a component runs with
+    pub(crate) async fn policy_template_for(&self, component_id: &str) -> Arc<WasiStateTemplate> {
+        self.policy_registry
+            .read()
+            .await
+            .component_policies
+            .get(component_id)
+            .cloned()
+            .unwrap_or_else(|| self.create_default_policy_template())
+    }
+
     pub(crate) fn get_component_policy_path(&self, component_id: &str) -> PathBuf {
-        self.plugin_dir.join(format!("{component_id}.policy.yaml"))
+        self.plugin_dir
+            .join(crate::component_file_name(component_id, "policy.yaml"))
     }
 
     pub(crate) fn get_component_metadata_path(&self, component_id: &str) -> PathBuf {
         self.plugin_dir
-            .join(format!("{component_id}.policy.meta.json"))
+            .join(crate::component_file_name(component_id, "policy.meta.json"))
     }
 
-    pub(crate) fn create_default_policy_template() -> Arc<WasiStateTemplate> {
-        Arc::new(WasiStateTemplate::default())
+    /// Returns the template of components without a policy, which is the base policy if there
+    /// is one
+    pub(crate) fn create_default_policy_template(&self) -> Arc<WasiStateTemplate> {
+        self.base_policy.clone().unwrap_or_default()
+    }
+
+    /// Creates the template of a component policy, merged over the base policy if there is one
+    pub(crate) fn create_policy_template(
+        &self,
+        policy: &PolicyDocument,
+    ) -> Result<WasiStateTemplate> {
+        let merged;
+        let policy = match self
+            .base_policy
+            .as_ref()
+            .and_then(|base| base.policy.as_ref())
+        {
+            Some(base) => {
+                merged = base
+                    .merge_with(policy)
+                    .context("Failed to merge the policy with the base policy")?;
+                &merged
+            }
+            None => policy,
+        };
+        crate::create_wasi_state_template_from_policy(
+            policy,
+            &self.plugin_dir,
+            &self.environment_vars,
+        )
     }
 
     /// Helper function to clean up policy registry for a component
@@ -197,6 +444,10 @@ impl crate::LifecycleManager {
             .await
             .component_policies
             .remove(component_id);
+        self.result_cache
+            .lock()
+            .unwrap()
+            .remove_component(component_id);
     }
 
     /// Grant a specific permission rule to a component
@@ -211,26 +462,127 @@ impl crate::LifecycleManager {
             component_id,
             permission_type, "Granting permission to component"
         );
+        let change = self
+            .change_permission(component_id, permission_type, details, true)
+            .await?;
+        self.save_component_policy(component_id, &change.after)
+            .await?;
+        self.update_policy_registry(component_id, &change.after)
+            .await?;
+
+        info!(
+            component_id,
+            permission_type, "Permission granted successfully"
+        );
+        Ok(())
+    }
+
+    /// Grants several permission rules to a component at once. Every rule is validated before
+    /// any is applied, and the policy file is written and the component's policy rebuilt once.
+    /// If any rule is invalid, the policy is left unchanged.
+    #[instrument(skip(self))]
+    pub async fn grant_permissions(
+        &self,
+        component_id: &str,
+        rules: Vec<PermissionRule>,
+    ) -> Result<()> {
+        info!(
+            component_id,
+            count = rules.len(),
+            "Granting permissions to component"
+        );
         if !self.components.read().await.contains_key(component_id) {
             return Err(anyhow!("Component not found: {}", component_id));
         }
+        for rule in &rules {
+            self.validate_permission_rule(rule)?;
+        }
 
-        let permission_rule = self.parse_permission_rule(permission_type, details)?;
-        self.validate_permission_rule(&permission_rule)?;
         let mut policy = self.load_or_create_component_policy(component_id).await?;
-        self.add_permission_rule_to_policy(&mut policy, permission_rule)?;
+        for rule in rules {
+            self.add_permission_rule_to_policy(&mut policy, rule)?;
+        }
         self.save_component_policy(component_id, &policy).await?;
         self.update_policy_registry(component_id, &policy).await?;
 
-        info!(
-            component_id,
-            permission_type, "Permission granted successfully"
-        );
+        info!(component_id, "Permissions granted successfully");
         Ok(())
     }
 
-    /// Parse a permission rule from the request details
-    fn parse_permission_rule(
+    /// Returns how [`Self::grant_permission`] would change the policy of the component, as a
+    /// line diff of the policy file, without changing anything. Removed lines start with `-`,
+    /// added lines with `+` and unchanged lines with a space. The diff is empty if the permission
+    /// is already granted.
+    #[instrument(skip(self))]
+    pub async fn grant_permission_dry_run(
+        &self,
+        component_id: &str,
+        permission_type: &str,
+        details: &serde_json::Value,
+    ) -> Result<String> {
+        let change = self
+            .change_permission(component_id, permission_type, details, true)
+            .await?;
+        self.policy_diff(component_id, &change)
+    }
+
+    /// Returns how [`Self::revoke_permission`] would change the policy of the component, like
+    /// [`Self::grant_permission_dry_run`]
+    #[instrument(skip(self))]
+    pub async fn revoke_permission_dry_run(
+        &self,
+        component_id: &str,
+        permission_type: &str,
+        details: &serde_json::Value,
+    ) -> Result<String> {
+        let change = self
+            .change_permission(component_id, permission_type, details, false)
+            .await?;
+        self.policy_diff(component_id, &change)
+    }
+
+    /// Grants or revokes a permission in the policy of a component without saving it
+    async fn change_permission(
+        &self,
+        component_id: &str,
+        permission_type: &str,
+        details: &serde_json::Value,
+        grant: bool,
+    ) -> Result<PolicyChange> {
+        if !self.components.read().await.contains_key(component_id) {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+
+        let permission_rule = self.parse_permission_rule(permission_type, details)?;
+        self.validate_permission_rule(&permission_rule)?;
+        let before = self.load_or_create_component_policy(component_id).await?;
+        let mut after = before.clone();
+        let removed = if grant {
+            self.add_permission_rule_to_policy(&mut after, permission_rule)?;
+            0
+        } else {
+            self.remove_permission_rule_from_policy(&mut after, permission_rule)?
+        };
+        Ok(PolicyChange {
+            before,
+            after,
+            removed,
+        })
+    }
+
+    /// Diffs the policy files before and after a change of a component's policy. A component
+    /// without a policy file starts from an empty one
+    fn policy_diff(&self, component_id: &str, change: &PolicyChange) -> Result<String> {
+        let before = if self.get_component_policy_path(component_id).exists() {
+            policy_yaml(&change.before)?
+        } else {
+            String::new()
+        };
+        Ok(diff_lines(&before, &policy_yaml(&change.after)?))
+    }
+
+    /// Parse a permission rule from the request details, as given to [`Self::grant_permission`]
+    pub fn parse_permission_rule(
         &self,
         permission_type: &str,
         details: &serde_json::Value,
@@ -243,6 +595,7 @@ impl crate::LifecycleManager {
                     .ok_or_else(|| anyhow!("Missing 'host' field for network permission"))?;
                 PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
                     host: host.to_string(),
+                    ports: None,
                 }))
             }
             "storage" => {
@@ -266,11 +619,7 @@ impl crate::LifecycleManager {
                     let access_types: Result<Vec<AccessType>> = access_array
                         .iter()
                         .map(|v| v.as_str().ok_or_else(|| anyhow!("Invalid access type")))
-                        .map(|s| match s? {
-                            "read" => Ok(AccessType::Read),
-                            "write" => Ok(AccessType::Write),
-                            other => Err(anyhow!("Invalid access type: {}", other)),
-                        })
+                        .map(|s| Ok(s?.parse::<AccessType>()?))
                         .collect();
 
                     PermissionRule::Storage(StoragePermission {
@@ -353,7 +702,7 @@ impl crate::LifecycleManager {
                 description: Some(format!(
                     "Auto-generated policy for component: {component_id}"
                 )),
-                permissions: Default::default(),
+                ..Default::default()
             })
         }
     }
@@ -512,8 +861,10 @@ impl crate::LifecycleManager {
         policy: &PolicyDocument,
     ) -> Result<()> {
         let policy_path = self.get_component_policy_path(component_id);
-        let policy_yaml = serde_yaml::to_string(policy)?;
-        tokio::fs::write(&policy_path, policy_yaml).await?;
+        tokio::fs::write(&policy_path, policy_yaml(policy)?).await?;
+        #[cfg(test)]
+        self.policy_writes
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         Ok(())
     }
 
@@ -523,73 +874,82 @@ impl crate::LifecycleManager {
         component_id: &str,
         policy: &PolicyDocument,
     ) -> Result<()> {
-        let wasi_template = crate::create_wasi_state_template_from_policy(
-            policy,
-            &self.plugin_dir,
-            &self.environment_vars,
-        )?;
+        let wasi_template = self.create_policy_template(policy)?;
         self.policy_registry
             .write()
             .await
             .component_policies
             .insert(component_id.to_string(), Arc::new(wasi_template));
+        // Results cached under the old policy may not be allowed under the new one
+        self.result_cache
+            .lock()
+            .unwrap()
+            .remove_component(component_id);
         Ok(())
     }
 
     /// Validate permission rule
     fn validate_permission_rule(&self, rule: &PermissionRule) -> Result<()> {
         match rule {
-            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission { host })) => {
-                if host.is_empty() {
-                    return Err(anyhow!("Network host cannot be empty"));
-                }
+            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
+                host,
+                ..
+            })) if host.is_empty() => Err(anyhow!("Network host cannot be empty")),
+            // TODO: the validation should verify if the uri is actually valid or not
+            // Note: access can be empty for revocation operations, but not for grant operations
+            // The validation for non-empty access is now done during parsing
+            PermissionRule::Storage(storage) if storage.uri.is_empty() => {
+                Err(anyhow!("Storage URI cannot be empty"))
             }
-            PermissionRule::Storage(storage) => {
-                // TODO: the validation should verify if the uri is actually valid or not
-                if storage.uri.is_empty() {
-                    return Err(anyhow!("Storage URI cannot be empty"));
-                }
-                // Note: access can be empty for revocation operations, but not for grant operations
-                // The validation for non-empty access is now done during parsing
+            PermissionRule::Environment(env) if env.key.is_empty() => {
+                Err(anyhow!("Environment variable key cannot be empty"))
             }
-            PermissionRule::Environment(env) => {
-                if env.key.is_empty() {
-                    return Err(anyhow!("Environment variable key cannot be empty"));
-                }
-            }
-            _ => {}
+            _ => Ok(()),
         }
-        Ok(())
     }
 
-    /// Revoke a specific permission rule from a component
+    /// Revoke a specific permission rule from a component. Network hosts and environment keys
+    /// are removed by exact rule, storage permissions by URI regardless of their access types.
+    ///
+    /// Revoking is idempotent: revoking a permission that isn't granted succeeds without changing
+    /// the policy, and returns [`RevokeResult::changed`] as false.
     #[instrument(skip(self))]
     pub async fn revoke_permission(
         &self,
         component_id: &str,
         permission_type: &str,
         details: &serde_json::Value,
-    ) -> Result<()> {
+    ) -> Result<RevokeResult> {
         info!(
             component_id,
             permission_type, "Revoking permission from component"
         );
-        if !self.components.read().await.contains_key(component_id) {
-            return Err(anyhow!("Component not found: {}", component_id));
+        let change = self
+            .change_permission(component_id, permission_type, details, false)
+            .await?;
+        let result = RevokeResult {
+            removed: change.removed,
+            changed: change.removed > 0,
+        };
+        if !result.changed {
+            info!(
+                component_id,
+                permission_type, "Permission was not granted, nothing to revoke"
+            );
+            return Ok(result);
         }
-
-        let permission_rule = self.parse_permission_rule(permission_type, details)?;
-        self.validate_permission_rule(&permission_rule)?;
-        let mut policy = self.load_or_create_component_policy(component_id).await?;
-        self.remove_permission_rule_from_policy(&mut policy, permission_rule)?;
-        self.save_component_policy(component_id, &policy).await?;
-        self.update_policy_registry(component_id, &policy).await?;
+        self.save_component_policy(component_id, &change.after)
+            .await?;
+        self.update_policy_registry(component_id, &change.after)
+            .await?;
 
         info!(
             component_id,
-            permission_type, "Permission revoked successfully"
+            permission_type,
+            removed = result.removed,
+            "Permission revoked successfully"
         );
-        Ok(())
+        Ok(result)
     }
 
     /// Reset all permissions for a component
@@ -617,11 +977,12 @@ impl crate::LifecycleManager {
     }
 
     /// Remove permission rule from policy
+    /// Removes a permission rule from a policy, returning how many rules were removed
     fn remove_permission_rule_from_policy(
         &self,
         policy: &mut PolicyDocument,
         rule: PermissionRule,
-    ) -> Result<()> {
+    ) -> Result<usize> {
         match rule {
             PermissionRule::Network(network) => {
                 self.remove_network_permission_from_policy(policy, network)
@@ -650,17 +1011,18 @@ impl crate::LifecycleManager {
         &self,
         policy: &mut PolicyDocument,
         network: NetworkPermission,
-    ) -> Result<()> {
+    ) -> Result<usize> {
+        let mut removed = 0;
         if let Some(network_perms) = &mut policy.permissions.network {
             if let Some(allow_set) = &mut network_perms.allow {
-                allow_set.retain(|perm| perm != &network);
+                removed = remove_matching(allow_set, |perm| perm == &network);
                 // Clean up empty structures
                 if allow_set.is_empty() {
                     network_perms.allow = None;
                 }
             }
         }
-        Ok(())
+        Ok(removed)
     }
 
     /// Remove storage permission from policy
@@ -668,18 +1030,19 @@ impl crate::LifecycleManager {
         &self,
         policy: &mut PolicyDocument,
         storage: StoragePermission,
-    ) -> Result<()> {
+    ) -> Result<usize> {
+        let mut removed = 0;
         if let Some(storage_perms) = &mut policy.permissions.storage {
             if let Some(allow_set) = &mut storage_perms.allow {
                 // Remove all permissions for the given URI, regardless of access type
-                allow_set.retain(|perm| perm.uri != storage.uri);
+                removed = remove_matching(allow_set, |perm| perm.uri == storage.uri);
                 // Clean up empty structures
                 if allow_set.is_empty() {
                     storage_perms.allow = None;
                 }
             }
         }
-        Ok(())
+        Ok(removed)
     }
 
     /// Remove environment permission from policy
@@ -687,17 +1050,18 @@ impl crate::LifecycleManager {
         &self,
         policy: &mut PolicyDocument,
         env: EnvironmentPermission,
-    ) -> Result<()> {
+    ) -> Result<usize> {
+        let mut removed = 0;
         if let Some(env_perms) = &mut policy.permissions.environment {
             if let Some(allow_set) = &mut env_perms.allow {
-                allow_set.retain(|perm| perm != &env);
+                removed = remove_matching(allow_set, |perm| perm == &env);
                 // Clean up empty structures
                 if allow_set.is_empty() {
                     env_perms.allow = None;
                 }
             }
         }
-        Ok(())
+        Ok(removed)
     }
 
     /// Remove resource permission from policy
@@ -705,12 +1069,13 @@ impl crate::LifecycleManager {
         &self,
         policy: &mut PolicyDocument,
         _details: serde_json::Value,
-    ) -> Result<()> {
+    ) -> Result<usize> {
+        let mut removed = 0;
         if let Some(resources) = &mut policy.permissions.resources {
             if let Some(limits) = &mut resources.limits {
                 // For now, we remove the memory limit entirely when revoking resource permission
                 // TODO: In the future, we might want to be more granular and only remove specific limits
-                limits.memory = None;
+                removed = usize::from(limits.memory.take().is_some());
 
                 // Clean up empty structures
                 if limits.cpu.is_none() && limits.memory.is_none() {
@@ -720,7 +1085,7 @@ impl crate::LifecycleManager {
             // If resources has no limits and no requests, we could remove the entire resources section
             // but for now we keep it for forward compatibility
         }
-        Ok(())
+        Ok(removed)
     }
 }
 
@@ -728,6 +1093,7 @@ impl crate::LifecycleManager {
 mod tests {
     use super::*;
     use crate::tests::*;
+    use crate::LifecycleManager;
 
     #[tokio::test]
     async fn test_policy_attachment_and_detachment() -> Result<()> {
@@ -780,6 +1146,27 @@ permissions:
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_prepare_policy_rejects_expired_policy() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let policy_path = manager.plugin_dir.join("expired.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\nexpires_at: \"2000-01-01T00:00:00Z\"\npermissions: {}\n",
+        )
+        .await?;
+
+        let err = manager
+            .prepare_policy(&format!("file://{}", policy_path.display()))
+            .await
+            .err()
+            .expect("Expired policy should not be attached");
+        assert!(matches!(err, WassetteError::PolicyInvalid(_)), "{err:#}");
+        assert!(format!("{err:#}").contains("Policy expired at"), "{err:#}");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_policy_attachment_component_not_found() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -796,11 +1183,104 @@ permissions: {}
 
         // Test attaching policy to non-existent component
         let result = manager.attach_policy("non-existent", &policy_uri).await;
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Component not found"));
+        assert!(matches!(
+            result,
+            Err(WassetteError::ComponentNotFound(id)) if id == "non-existent"
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_base_policy_merged_under_component_policies() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(
+            tempdir.path().join(BASE_POLICY_FILE),
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "base.example.com"
+"#,
+        )
+        .await?;
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        let hosts = |template: &WasiStateTemplate| {
+            let mut hosts: Vec<_> = template.allowed_hosts.iter().cloned().collect();
+            hosts.sort();
+            hosts
+        };
+
+        // Components without a policy get the base policy
+        assert_eq!(
+            hosts(&manager.create_default_policy_template()),
+            vec!["base.example.com"]
+        );
+
+        let policy_path = tempdir.path().join("test-policy.yaml");
+        tokio::fs::write(
+            &policy_path,
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "example.com"
+      - host: "base.example.com"
+"#,
+        )
+        .await?;
+        manager
+            .attach_policy(
+                TEST_COMPONENT_ID,
+                &format!("file://{}", policy_path.display()),
+            )
+            .await?;
+        let template =
+            manager.policy_registry.read().await.component_policies[TEST_COMPONENT_ID].clone();
+        assert_eq!(hosts(&template), vec!["base.example.com", "example.com"]);
+
+        // Only the component's own policy is stored next to it
+        let stored =
+            tokio::fs::read_to_string(manager.get_component_policy_path(TEST_COMPONENT_ID)).await?;
+        assert_eq!(stored, tokio::fs::read_to_string(&policy_path).await?);
+
+        // Granted permissions are merged over the base policy as well
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "api.example.com"}),
+            )
+            .await?;
+        let template =
+            manager.policy_registry.read().await.component_policies[TEST_COMPONENT_ID].clone();
+        assert_eq!(
+            hosts(&template),
+            vec!["api.example.com", "base.example.com", "example.com"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_base_policy() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(
+            tempdir.path().join(BASE_POLICY_FILE),
+            "version: \"2.0\"\npermissions: {}\n",
+        )
+        .await?;
+        let err = LifecycleManager::new(tempdir.path())
+            .await
+            .err()
+            .expect("invalid base policy should be rejected");
+        assert!(format!("{err:#}").contains("base policy"), "{err:#}");
 
         Ok(())
     }
@@ -1102,6 +1582,71 @@ permissions: {}
         Ok(())
     }
 
+    #[test]
+    fn test_policy_yaml_keeps_comments() -> Result<()> {
+        let mut policy = PolicyParser::parse_str(
+            "# Reviewed by the security team
+version: \"1.0\"\npermissions:\n  environment:\n    allow:\n    # Needed for logging\n    - key: \"LOG_LEVEL\"\n",
+        )?;
+        policy
+            .permissions
+            .environment
+            .as_mut()
+            .and_then(|environment| environment.allow.as_mut())
+            .unwrap()
+            .push(EnvironmentPermission {
+                key: "HOME".to_string(),
+            });
+
+        let policy_content = policy_yaml(&policy)?;
+        assert!(policy_content.starts_with("# Reviewed by the security team\n"));
+        assert!(policy_content.contains("    # Needed for logging\n    - key: LOG_LEVEL\n"));
+        assert!(policy_content.contains("HOME"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grant_environment_pattern_round_trip() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let rule =
+            manager.parse_permission_rule("environment", &serde_json::json!({"key": "API_*"}))?;
+        let mut policy = manager
+            .load_or_create_component_policy("config_reader")
+            .await?;
+        manager.add_permission_rule_to_policy(&mut policy, rule)?;
+
+        // The pattern is stored as is, and matches the variables after the policy is read back
+        let policy = PolicyParser::parse_str(&policy_yaml(&policy)?)?;
+        let keys: Vec<_> = policy
+            .permissions
+            .environment
+            .iter()
+            .flat_map(|environment| environment.allow.iter().flatten())
+            .map(|rule| rule.key.as_str())
+            .collect();
+        assert_eq!(keys, ["API_*"]);
+
+        let environment_vars = HashMap::from(
+            [
+                ("API_KEY", "api-key-value"),
+                ("API_SECRET", "api-secret-value"),
+                ("OTHER", "other-value"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        let template = crate::create_wasi_state_template_from_policy(
+            &policy,
+            Path::new("/tmp"),
+            &environment_vars,
+        )?;
+        let mut granted: Vec<_> = template.config_vars.keys().map(String::as_str).collect();
+        granted.sort();
+        assert_eq!(granted, ["API_KEY", "API_SECRET"]);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_grant_permission_updates_policy_registry() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1122,6 +1667,186 @@ permissions: {}
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_grant_permissions_batch() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let policy_path = manager.get_component_policy_path(TEST_COMPONENT_ID);
+
+        let rules = |host: &str| -> Result<Vec<PermissionRule>> {
+            Ok(vec![
+                manager.parse_permission_rule(
+                    "storage",
+                    &serde_json::json!({"uri": "fs:///tmp/batch", "access": ["read"]}),
+                )?,
+                manager.parse_permission_rule("network", &serde_json::json!({"host": host}))?,
+                manager
+                    .parse_permission_rule("environment", &serde_json::json!({"key": "HOME"}))?,
+            ])
+        };
+
+        // One invalid rule fails the whole batch
+        let err = manager
+            .grant_permissions(TEST_COMPONENT_ID, rules("")?)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("host cannot be empty"), "{err}");
+        assert!(!policy_path.exists());
+        assert!(!manager
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .contains_key(TEST_COMPONENT_ID));
+
+        let writes = manager
+            .policy_writes
+            .load(std::sync::atomic::Ordering::Relaxed);
+        manager
+            .grant_permissions(TEST_COMPONENT_ID, rules("api.example.com")?)
+            .await?;
+        assert_eq!(
+            manager
+                .policy_writes
+                .load(std::sync::atomic::Ordering::Relaxed),
+            writes + 1,
+            "the policy is written once for the whole batch"
+        );
+        let policy = PolicyParser::parse_file(&policy_path)?;
+        let permissions = policy.permissions;
+        assert_eq!(permissions.storage.unwrap().allow.unwrap().len(), 1);
+        assert_eq!(permissions.network.unwrap().allow.unwrap().len(), 1);
+        assert_eq!(
+            permissions.environment.unwrap().allow.unwrap()[0].key,
+            "HOME"
+        );
+        assert!(manager
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .contains_key(TEST_COMPONENT_ID));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_effective_permissions() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        assert_eq!(
+            manager.get_effective_permissions(TEST_COMPONENT_ID).await?,
+            Permissions::default()
+        );
+        assert!(manager.get_effective_permissions("missing").await.is_err());
+
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "api.example.com"}),
+            )
+            .await?;
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "environment",
+                &serde_json::json!({"key": "API_KEY"}),
+            )
+            .await?;
+
+        let permissions = manager.get_effective_permissions(TEST_COMPONENT_ID).await?;
+        assert_eq!(
+            permissions.network.unwrap().allow.unwrap(),
+            [NetworkPermission::Host(NetworkHostPermission {
+                host: "api.example.com".to_string(),
+                ports: None,
+            })]
+        );
+        assert_eq!(
+            permissions.environment.unwrap().allow.unwrap(),
+            [EnvironmentPermission {
+                key: "API_KEY".to_string(),
+            }]
+        );
+        assert!(permissions.storage.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grant_permission_dry_run() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let policy_path = manager.get_component_policy_path(TEST_COMPONENT_ID);
+
+        // Without a policy file, the whole new policy is added
+        let details = serde_json::json!({"host": "api.example.com"});
+        let diff = manager
+            .grant_permission_dry_run(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        assert!(diff
+            .lines()
+            .any(|line| line.starts_with('+') && line.contains("host: api.example.com")));
+        assert!(diff.lines().all(|line| line.starts_with('+')), "{diff}");
+        assert!(!policy_path.exists());
+        assert!(!manager
+            .policy_registry
+            .read()
+            .await
+            .component_policies
+            .contains_key(TEST_COMPONENT_ID));
+
+        // With one, only the changed lines are marked
+        manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        let policy_content = tokio::fs::read_to_string(&policy_path).await?;
+        let other = serde_json::json!({"host": "cdn.example.com"});
+        let diff = manager
+            .grant_permission_dry_run(TEST_COMPONENT_ID, "network", &other)
+            .await?;
+        let changed: Vec<_> = diff.lines().filter(|line| !line.starts_with(' ')).collect();
+        assert_eq!(changed.len(), 1, "{diff}");
+        assert!(changed[0].starts_with('+') && changed[0].contains("host: cdn.example.com"));
+        assert_eq!(
+            tokio::fs::read_to_string(&policy_path).await?,
+            policy_content
+        );
+
+        // Granting a permission that is already granted changes nothing
+        assert_eq!(
+            manager
+                .grant_permission_dry_run(TEST_COMPONENT_ID, "network", &details)
+                .await?,
+            ""
+        );
+
+        let diff = manager
+            .revoke_permission_dry_run(TEST_COMPONENT_ID, "network", &details)
+            .await?;
+        assert!(diff
+            .lines()
+            .any(|line| line.starts_with('-') && line.contains("host: api.example.com")));
+        assert!(!diff.lines().any(|line| line.starts_with('+')), "{diff}");
+        assert_eq!(
+            tokio::fs::read_to_string(&policy_path).await?,
+            policy_content
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nb\nc\n"), "");
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nx\nc\nd\n"),
+            "  a\n- b\n+ x\n  c\n+ d\n"
+        );
+        assert_eq!(diff_lines("", "a\n"), "+ a\n");
+    }
+
     #[tokio::test]
     async fn test_grant_permission_to_existing_policy() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1166,6 +1891,7 @@ permissions:
         let network_rule =
             PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
                 host: "example.com".to_string(),
+                ports: None,
             }));
         let serialized = serde_json::to_string(&network_rule)?;
         assert!(serialized.contains("example.com"));
@@ -1188,6 +1914,7 @@ permissions:
         let network_perm =
             PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
                 host: "example.com".to_string(),
+                ports: None,
             }));
         let storage_perm = PermissionRule::Storage(StoragePermission {
             uri: "fs:///tmp".to_string(),
@@ -1214,9 +1941,13 @@ permissions:
         // Test pattern matching works correctly
         let rule = PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
             host: "test.com".to_string(),
+            ports: None,
         }));
         match rule {
-            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission { host })) => {
+            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
+                host,
+                ..
+            })) => {
                 assert_eq!(host, "test.com");
             }
             _ => panic!("Expected network permission"),
@@ -1237,6 +1968,7 @@ permissions:
             version: "1.0".to_string(),
             description: Some("Test policy".to_string()),
             permissions: policy::Permissions::default(),
+            ..Default::default()
         };
 
         // Test adding resource permission
@@ -1293,6 +2025,7 @@ permissions:
             version: "1.0".to_string(),
             description: Some("Test policy with memory limits".to_string()),
             permissions: policy::Permissions::default(),
+            ..Default::default()
         };
 
         // First add memory resource permission
@@ -0,0 +1,772 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Reconciling the loaded components with the contents of the plugin directory.
+//!
+//! A reconcile pass records every component it loads together with a hash of its contents. Running
+//! it again (for example after a pass that partially failed) only compiles the components that
+//! aren't loaded yet or whose contents changed, so recovery is incremental instead of starting
+//! from scratch.
+//!
+//! The outcome of every component is also written to a marker file in the cache directory as soon
+//! as it is known, so that a pass in a new process resumes as well: components that loaded before
+//! are deserialized from the compile cache, components whose contents failed to compile before are
+//! skipped, and the cache entries of components whose files were deleted in the meantime are
+//! removed.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use component2json::{ContentHash, ToolMetadata};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{ComponentInstance, LifecycleManager};
+
+/// The file in the cache directory recording the outcome of the last reconcile pass
+const MARKER_FILE: &str = "reconcile.json";
+
+/// What happened to a single component during a reconcile pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconcileStatus {
+    /// The component was compiled and registered
+    Loaded,
+    /// The component was already loaded and its contents haven't changed, so it was skipped
+    Unchanged,
+    /// The component could not be loaded. Contains the error message
+    Failed(String),
+}
+
+/// A progress update emitted once for every component processed during a reconcile pass
+#[derive(Debug, Clone)]
+pub struct ReconcileProgress {
+    /// The ID of the component that was just processed
+    pub component_id: String,
+    /// What happened to the component
+    pub status: ReconcileStatus,
+    /// The number of components processed so far, including this one
+    pub completed: usize,
+    /// The total number of components found in the plugin directory
+    pub total: usize,
+}
+
+/// A callback that receives [`ReconcileProgress`] updates
+pub type ProgressCallback = Arc<dyn Fn(&ReconcileProgress) + Send + Sync>;
+
+/// The result of a reconcile pass
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileSummary {
+    /// IDs of the components that were (re)loaded
+    pub loaded: Vec<String>,
+    /// IDs of the components that were already loaded and unchanged
+    pub unchanged: Vec<String>,
+    /// IDs of the components that failed to load, along with the error message
+    pub failed: Vec<(String, String)>,
+    /// IDs of the components that were loaded but whose files are no longer in the plugin
+    /// directory, so they were unloaded
+    pub unloaded: Vec<String>,
+}
+
+/// The outcome of the last reconcile pass for every component file, persisted in the cache
+/// directory so that a pass in a new process can resume from it
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ReconcileMarker {
+    /// The engine the components were compiled with, see [`crate::cache::engine_fingerprint`].
+    /// Compile errors of another engine aren't reused.
+    engine: String,
+    components: BTreeMap<String, MarkerEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MarkerEntry {
+    /// The content hash of the component file
+    hash: String,
+    /// Why the contents failed to compile, if they did
+    compile_error: Option<String>,
+}
+
+impl ReconcileMarker {
+    /// Reads the marker in `cache_dir`. A missing or unreadable marker is empty, and so are the
+    /// compile errors of a marker written for another engine.
+    async fn read(cache_dir: &Path, engine: String) -> Self {
+        let path = cache_dir.join(MARKER_FILE);
+        let mut marker = match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice::<Self>(&contents).unwrap_or_else(|e| {
+                warn!(path = %path.display(), error = %e, "Ignoring unreadable reconcile marker");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        if marker.engine != engine {
+            marker
+                .components
+                .values_mut()
+                .for_each(|entry| entry.compile_error = None);
+            marker.engine = engine;
+        }
+        marker
+    }
+
+    /// Writes the marker to `cache_dir`. Failures are only logged, since the marker merely saves
+    /// work on the next pass.
+    fn write(&self, cache_dir: &Path) {
+        let path = cache_dir.join(MARKER_FILE);
+        let written = serde_json::to_vec(self)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| crate::cache::write_atomically(&path, &contents));
+        if let Err(e) = written {
+            warn!(path = %path.display(), error = %e, "Failed to write reconcile marker");
+        }
+    }
+
+    /// Returns why the component failed to compile, if it did with the same contents
+    fn compile_error(&self, component_id: &str, content_hash: &str) -> Option<&str> {
+        self.components
+            .get(component_id)
+            .filter(|entry| entry.hash == content_hash)
+            .and_then(|entry| entry.compile_error.as_deref())
+    }
+
+    fn record(&mut self, component_id: &str, content_hash: &str, compile_error: Option<String>) {
+        self.components.insert(
+            component_id.to_string(),
+            MarkerEntry {
+                hash: content_hash.to_string(),
+                compile_error,
+            },
+        );
+    }
+}
+
+/// Returns the hex encoded SHA-256 hash of the given component bytes
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Decodes a hash returned by [`content_hash`] back into its bytes
+pub(crate) fn content_digest(content_hash: &str) -> Option<ContentHash> {
+    let mut digest = ContentHash::default();
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(content_hash.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    (content_hash.len() == 64).then_some(digest)
+}
+
+impl LifecycleManager {
+    /// Loads every component in the plugin directory that isn't loaded yet or whose contents
+    /// changed since it was loaded. Components that fail to load are reported in the summary and
+    /// don't prevent the others from loading, so calling this again resumes where a previous pass
+    /// left off.
+    pub async fn reconcile(&self) -> Result<ReconcileSummary> {
+        self.reconcile_with_progress(|_| {}).await
+    }
+
+    /// Same as [`LifecycleManager::reconcile`], but calls `on_progress` after each component has
+    /// been processed
+    #[instrument(skip_all, fields(plugin_dir = %self.plugin_dir.display()))]
+    pub async fn reconcile_with_progress(
+        &self,
+        on_progress: impl Fn(&ReconcileProgress),
+    ) -> Result<ReconcileSummary> {
+        let (summary, _) = self.reconcile_collecting_errors(on_progress).await?;
+        Ok(summary)
+    }
+
+    /// Same as [`LifecycleManager::reconcile_with_progress`], but also returns the errors of the
+    /// components that failed to load
+    pub(crate) async fn reconcile_collecting_errors(
+        &self,
+        on_progress: impl Fn(&ReconcileProgress),
+    ) -> Result<(ReconcileSummary, Vec<(String, anyhow::Error)>)> {
+        let candidates = list_component_files(&self.plugin_dir).await?;
+        let total = candidates.len();
+        let marker = Mutex::new(
+            ReconcileMarker::read(
+                &self.cache_dir,
+                crate::cache::engine_fingerprint(&self.engine),
+            )
+            .await,
+        );
+        let marker = &marker;
+
+        // Components are compiled concurrently, but `buffered` yields them in the order of
+        // `candidates`, so they are registered in the same order regardless of which compilation
+        // finishes first
+        let mut prepared = futures::stream::iter(candidates)
+            .map(|(component_id, path)| async move {
+                let prepared = self
+                    .prepare_component(&component_id, &path, Some(marker))
+                    .await;
+                (component_id, prepared)
+            })
+            .buffered(self.compile_concurrency);
+
+        let mut summary = ReconcileSummary::default();
+        let mut errors = Vec::new();
+        let mut completed = 0;
+        while let Some((component_id, prepared)) = prepared.next().await {
+            let result = match prepared {
+                Ok(Some(prepared)) => self
+                    .register_prepared(&component_id, prepared)
+                    .await
+                    .map(|()| true),
+                Ok(None) => Ok(false),
+                Err(e) => Err(e),
+            };
+            let status = match result {
+                Ok(true) => ReconcileStatus::Loaded,
+                Ok(false) => ReconcileStatus::Unchanged,
+                Err(e) => {
+                    warn!(component_id = %component_id, error = %e, "Failed to load component");
+                    let status = ReconcileStatus::Failed(e.to_string());
+                    errors.push((component_id.clone(), e));
+                    status
+                }
+            };
+            if !matches!(status, ReconcileStatus::Failed(_)) {
+                self.load_component_metadata(&component_id).await;
+                if let Some(instance) = self.loaded_component(&component_id).await {
+                    marker
+                        .lock()
+                        .unwrap()
+                        .record(&component_id, &instance.content_hash, None);
+                }
+            }
+            // Written after every component, so that a pass interrupted by the process exiting
+            // still resumes from here
+            marker.lock().unwrap().write(&self.cache_dir);
+            completed += 1;
+            on_progress(&ReconcileProgress {
+                component_id: component_id.clone(),
+                status: status.clone(),
+                completed,
+                total,
+            });
+            match status {
+                ReconcileStatus::Loaded => summary.loaded.push(component_id),
+                ReconcileStatus::Unchanged => summary.unchanged.push(component_id),
+                ReconcileStatus::Failed(e) => summary.failed.push((component_id, e)),
+            }
+        }
+        summary.unloaded = self.remove_deleted_components(marker).await;
+        info!(
+            loaded = summary.loaded.len(),
+            unchanged = summary.unchanged.len(),
+            failed = summary.failed.len(),
+            unloaded = summary.unloaded.len(),
+            "Reconciled plugin directory"
+        );
+        self.check_dependencies().await?;
+        Ok((summary, errors))
+    }
+
+    /// Unloads the components whose files are no longer in the plugin directory, and forgets the
+    /// marker entries and compile cache entries of the files deleted since the last pass, even if
+    /// that happened while no process was running. Returns the IDs of the unloaded components.
+    async fn remove_deleted_components(&self, marker: &Mutex<ReconcileMarker>) -> Vec<String> {
+        let mut unloaded = Vec::new();
+        let loaded: Vec<String> = self.components.read().await.keys().cloned().collect();
+        for component_id in loaded {
+            // Checked now rather than against the listing of the pass, so that a component loaded
+            // in the meantime isn't mistaken for a deleted one
+            if !self.component_path(&component_id).exists()
+                && self.forget_component(&component_id).await
+            {
+                info!(component_id, "Component file removed, unloaded component");
+                unloaded.push(component_id);
+            }
+        }
+
+        let stale: Vec<(String, String)> = marker
+            .lock()
+            .unwrap()
+            .components
+            .iter()
+            .filter(|(component_id, _)| !self.component_path(component_id).exists())
+            .map(|(component_id, entry)| (component_id.clone(), entry.hash.clone()))
+            .collect();
+        if !stale.is_empty() {
+            for (_, content_hash) in &stale {
+                self.release_compiled(content_hash).await;
+            }
+            let mut marker = marker.lock().unwrap();
+            for (component_id, _) in &stale {
+                marker.components.remove(component_id);
+            }
+            marker.write(&self.cache_dir);
+        }
+        unloaded.sort();
+        unloaded
+    }
+
+    /// Reads and compiles a single component from the plugin directory, without registering it.
+    /// Returns `None` if an identical component is already loaded. A precompiled component
+    /// (see [`LifecycleManager::precompile_component`]) is used instead of compiling if enabled and
+    /// it matches.
+    ///
+    /// With the `marker` of a reconcile pass, it fails right away if the contents failed to
+    /// compile before, and records it in the marker if they fail to compile now.
+    pub(crate) async fn prepare_component(
+        &self,
+        component_id: &str,
+        path: &Path,
+        marker: Option<&Mutex<ReconcileMarker>>,
+    ) -> Result<Option<PreparedComponent>> {
+        let start_time = Instant::now();
+        // The error is shown on its own at startup, so it names the file and what would be valid
+        crate::validate_component_id(component_id).with_context(|| {
+            format!(
+                "Can't load {}: the file name before `.wasm` is the component ID, which may only \
+                 contain ASCII letters, digits, '-', '_' and '.'",
+                path.display()
+            )
+        })?;
+        let wasm_bytes = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read component file {}", path.display()))?;
+        let content_hash = content_hash(&wasm_bytes);
+
+        if self
+            .is_loaded_component_identical(component_id, &content_hash)
+            .await
+        {
+            debug!(component_id, "Component unchanged, skipping");
+            return Ok(None);
+        }
+        if let Some(marker) = marker {
+            let mut marker = marker.lock().unwrap();
+            if let Some(error) = marker.compile_error(component_id, &content_hash) {
+                anyhow::bail!("Component failed to compile when it was last loaded: {error}");
+            }
+            // Recorded again once the outcome is known
+            marker.components.remove(component_id);
+        }
+
+        let wasm_size = wasm_bytes.len() as u64;
+        let docs = self.decode_component_docs(component_id, &wasm_bytes);
+        let engine = self.engine.clone();
+        let plugin_dir = self.plugin_dir.clone();
+        let cache_dir = self.cache_dir.clone();
+        let precompiled_components = self.precompiled_components;
+        let id = component_id.to_string();
+        let hash = content_hash.clone();
+        let component = tokio::task::spawn_blocking(move || {
+            let precompiled = precompiled_components
+                .then(|| {
+                    crate::cache::load_precompiled(&engine, &plugin_dir, &cache_dir, &id, &hash)
+                })
+                .flatten();
+            match precompiled {
+                Some(component) => Ok(component),
+                None => crate::cache::load_or_compile(&engine, &cache_dir, &wasm_bytes),
+            }
+        })
+        .await?;
+        let component = match component {
+            Ok(component) => component,
+            Err(e) => {
+                if let Some(marker) = marker {
+                    marker
+                        .lock()
+                        .unwrap()
+                        .record(component_id, &content_hash, Some(e.to_string()));
+                }
+                return Err(e);
+            }
+        };
+        self.import_filter
+            .check_component(&component, &self.engine)?;
+        let instance_pre = self.linker.instantiate_pre(&component)?;
+        let tool_metadata = self.component_tools(&component, docs.as_deref());
+
+        Ok(Some(PreparedComponent {
+            instance: ComponentInstance {
+                component: Arc::new(component),
+                instance_pre: Arc::new(instance_pre),
+                environment_only_pre: Arc::default(),
+                content_hash: content_hash.into(),
+                docs,
+                wasm_size,
+                loaded_at: SystemTime::now(),
+            },
+            tool_metadata,
+            start_time,
+        }))
+    }
+
+    /// Restores the co-located policy of a component returned by
+    /// [`LifecycleManager::prepare_component`], then installs the component and registers its
+    /// tools
+    pub(crate) async fn register_prepared(
+        &self,
+        component_id: &str,
+        prepared: PreparedComponent,
+    ) -> Result<()> {
+        // Applied before the component can be called, so that no call runs without it. Without a
+        // usable co-located policy, e.g. because it was deleted, the component has none, rather
+        // than the one of the component it replaces.
+        let policy = crate::load_colocated_policy(self, component_id).await;
+        {
+            let mut policy_registry = self.policy_registry.write().await;
+            match policy {
+                Some(wasi_template) => {
+                    policy_registry
+                        .component_policies
+                        .insert(component_id.to_string(), Arc::new(wasi_template));
+                }
+                None => {
+                    policy_registry.component_policies.remove(component_id);
+                }
+            }
+        }
+
+        self.install_component(component_id, prepared.instance)
+            .await;
+        {
+            let mut registry = self.registry.write().await;
+            registry.unregister_component(component_id);
+            registry
+                .register_tools(component_id, prepared.tool_metadata)
+                .context("unable to insert component into registry")?;
+        }
+
+        info!(component_id, elapsed = ?prepared.start_time.elapsed(), "component loaded");
+        Ok(())
+    }
+}
+
+/// A compiled component that is ready to be registered
+pub(crate) struct PreparedComponent {
+    instance: ComponentInstance,
+    tool_metadata: Vec<ToolMetadata>,
+    start_time: Instant,
+}
+
+/// The default number of components compiled at the same time, one per available CPU
+pub(crate) fn default_compile_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// Returns the ID and path of every component file in the plugin directory, sorted by ID
+async fn list_component_files(plugin_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut entries = tokio::fs::read_dir(plugin_dir).await?;
+    let mut components = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_file = entry
+            .metadata()
+            .await
+            .map(|m| m.is_file())
+            .context("unable to read file metadata")?;
+        let is_wasm = path.extension().map(|ext| ext == "wasm").unwrap_or(false);
+        if !(is_file && is_wasm) {
+            continue;
+        }
+        let Some(component_id) = path.file_stem().and_then(|s| s.to_str()) else {
+            warn!(path = %path.display(), "wasm file didn't have a valid file name");
+            continue;
+        };
+        components.push((component_id.to_string(), path.clone()));
+    }
+
+    components.sort();
+    Ok(components)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::tests::*;
+
+    #[test(tokio::test)]
+    async fn test_startup_uses_compile_cache() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let component_bytes = tokio::fs::read(build_example_component().await?).await?;
+        tokio::fs::write(tempdir.path().join("fetch.wasm"), &component_bytes).await?;
+
+        let start = Instant::now();
+        let first = LifecycleManager::new(tempdir.path()).await?;
+        let uncached = start.elapsed();
+        let first_schema = first.get_component_schema("fetch").await;
+        drop(first);
+
+        let cache_dir = tempdir.path().join(crate::cache::CACHE_DIR);
+        assert_eq!(crate::cache::entry_count(&cache_dir), 1);
+
+        let start = Instant::now();
+        let second = LifecycleManager::new(tempdir.path()).await?;
+        let cached = start.elapsed();
+        assert!(
+            cached < uncached,
+            "loading from the cache took {cached:?}, compiling took {uncached:?}"
+        );
+        assert_eq!(second.get_component_schema("fetch").await, first_schema);
+        assert_eq!(second.list_tools().await.len(), 1);
+
+        // Unloading the component removes its cache entry
+        second.unload_component("fetch").await?;
+        assert_eq!(crate::cache::entry_count(&cache_dir), 0);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_reconcile_resumes_after_partial_startup() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let component_bytes = tokio::fs::read(build_example_component().await?).await?;
+
+        // Simulate a startup where one component is valid and another one is broken
+        tokio::fs::write(tempdir.path().join("good.wasm"), &component_bytes).await?;
+        tokio::fs::write(tempdir.path().join("broken.wasm"), b"not a component").await?;
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_startup_progress(Arc::new(move |progress: &ReconcileProgress| {
+                recorded.lock().unwrap().push(progress.clone());
+            }))
+            .build()
+            .await?;
+
+        assert_eq!(manager.list_components().await, vec!["good".to_string()]);
+        {
+            let updates = updates.lock().unwrap();
+            assert_eq!(updates.len(), 2);
+            assert!(updates.iter().all(|p| p.total == 2));
+            let mut completed: Vec<_> = updates.iter().map(|p| p.completed).collect();
+            completed.sort();
+            assert_eq!(completed, vec![1, 2]);
+            let broken = updates.iter().find(|p| p.component_id == "broken").unwrap();
+            assert!(matches!(broken.status, ReconcileStatus::Failed(_)));
+        }
+
+        // Fix the broken component and resume: only the fixed component is compiled again
+        tokio::fs::write(tempdir.path().join("broken.wasm"), &component_bytes).await?;
+        let summary = manager.reconcile().await?;
+        assert_eq!(summary.loaded, vec!["broken".to_string()]);
+        assert_eq!(summary.unchanged, vec!["good".to_string()]);
+        assert!(summary.failed.is_empty());
+
+        let mut components = manager.list_components().await;
+        components.sort();
+        assert_eq!(components, vec!["broken".to_string(), "good".to_string()]);
+
+        // Nothing changed, so nothing is reloaded
+        let summary = manager.reconcile().await?;
+        assert!(summary.loaded.is_empty());
+        assert_eq!(summary.unchanged.len(), 2);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_reconcile_resumes_across_restarts() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let cache_dir = tempdir.path().join(crate::cache::CACHE_DIR);
+        tokio::fs::write(tempdir.path().join("good.wasm"), ANSWER_WAT).await?;
+        tokio::fs::write(tempdir.path().join("other.wasm"), OTHER_WAT).await?;
+        tokio::fs::write(tempdir.path().join("broken.wasm"), b"\0asm corrupt").await?;
+
+        let first = LifecycleManager::new(tempdir.path()).await?;
+        assert_eq!(first.list_components().await.len(), 2);
+        assert_eq!(crate::cache::entry_count(&cache_dir), 2);
+        drop(first);
+
+        // A component deleted while no process is running has its cache entry removed, and the
+        // component that failed to compile isn't compiled again
+        tokio::fs::remove_file(tempdir.path().join("other.wasm")).await?;
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+        let second = LifecycleManager::builder(tempdir.path())
+            .with_startup_progress(Arc::new(move |progress: &ReconcileProgress| {
+                recorded.lock().unwrap().push(progress.clone());
+            }))
+            .build()
+            .await?;
+        assert_eq!(second.list_components().await, vec!["good".to_string()]);
+        assert_eq!(crate::cache::entry_count(&cache_dir), 1);
+        {
+            let updates = updates.lock().unwrap();
+            let broken = updates.iter().find(|p| p.component_id == "broken").unwrap();
+            assert!(
+                matches!(&broken.status, ReconcileStatus::Failed(e) if e.contains("when it was last loaded")),
+                "{:?}",
+                broken.status
+            );
+        }
+
+        // Components whose files are deleted while loaded are unloaded by the next pass
+        tokio::fs::remove_file(tempdir.path().join("good.wasm")).await?;
+        let summary = second.reconcile().await?;
+        assert_eq!(summary.unloaded, vec!["good".to_string()]);
+        assert!(second.list_components().await.is_empty());
+        assert_eq!(crate::cache::entry_count(&cache_dir), 0);
+
+        // A fixed component is compiled again
+        tokio::fs::write(tempdir.path().join("broken.wasm"), ANSWER_WAT).await?;
+        let summary = second.reconcile().await?;
+        assert_eq!(summary.loaded, vec!["broken".to_string()]);
+        assert!(summary.failed.is_empty());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_parallel_startup_skips_corrupt_component() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let ids = ["alpha", "bravo", "delta", "echo", "foxtrot"];
+        for id in ids {
+            tokio::fs::write(tempdir.path().join(format!("{id}.wasm")), ANSWER_WAT).await?;
+        }
+        tokio::fs::write(tempdir.path().join("charlie.wasm"), b"\0asm corrupt").await?;
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_startup_progress(Arc::new(move |progress: &ReconcileProgress| {
+                recorded.lock().unwrap().push(progress.component_id.clone());
+            }))
+            .build()
+            .await?;
+
+        let mut components = manager.list_components().await;
+        components.sort();
+        assert_eq!(components, ids);
+
+        // Progress is reported and components are registered in the order of their IDs, no
+        // matter which compilation finished first
+        assert_eq!(
+            *updates.lock().unwrap(),
+            ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"]
+        );
+        let registry = manager.registry.read().await;
+        let registered: Vec<_> = registry.tool_map["answer"]
+            .iter()
+            .map(|tool| tool.component_id.as_str())
+            .collect();
+        assert_eq!(registered, ids);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_reload_without_colocated_policy_drops_old_policy() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        tokio::fs::write(tempdir.path().join("answer.wasm"), ANSWER_WAT).await?;
+        tokio::fs::write(
+            tempdir.path().join("answer.policy.yaml"),
+            "version: \"1.0\"\npermissions:\n  environment:\n    allow:\n      - key: \"GREETING\"\n",
+        )
+        .await?;
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        let has_policy = || async {
+            manager
+                .policy_registry
+                .read()
+                .await
+                .component_policies
+                .contains_key("answer")
+        };
+        assert!(has_policy().await);
+
+        // The policy file is deleted and the component changes, so it is reloaded without one
+        tokio::fs::remove_file(tempdir.path().join("answer.policy.yaml")).await?;
+        tokio::fs::write(tempdir.path().join("answer.wasm"), OTHER_WAT).await?;
+        let summary = manager.reconcile().await?;
+        assert_eq!(summary.loaded, vec!["answer".to_string()]);
+        assert!(!has_policy().await);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_reconcile_names_files_with_invalid_ids() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path()).await?;
+        tokio::fs::write(tempdir.path().join("my component.wasm"), b"").await?;
+
+        let summary = manager.reconcile().await?;
+        let [(component_id, error)] = &summary.failed[..] else {
+            panic!("unexpected failures: {:?}", summary.failed);
+        };
+        assert_eq!(component_id, "my component");
+        assert!(error.contains("my component.wasm"), "{error}");
+        assert!(error.contains("ASCII letters, digits"), "{error}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test(flavor = "multi_thread"))]
+    async fn test_parallel_startup_is_faster_than_serial() -> Result<()> {
+        let component_bytes = tokio::fs::read(build_example_component().await?).await?;
+        let ids = ["one", "two", "three", "four", "five"];
+
+        // Every copy gets a different custom section, so that none of them is served from the
+        // compile cache of another
+        let plugin_dir = || -> Result<tempfile::TempDir> {
+            let tempdir = tempfile::tempdir()?;
+            for id in ids {
+                let mut bytes = component_bytes.clone();
+                let section = [&[id.len() as u8], id.as_bytes()].concat();
+                bytes.extend([0, section.len() as u8]);
+                bytes.extend(section);
+                std::fs::write(tempdir.path().join(format!("{id}.wasm")), bytes)?;
+            }
+            Ok(tempdir)
+        };
+
+        let serial_dir = plugin_dir()?;
+        let start = Instant::now();
+        let serial = LifecycleManager::builder(serial_dir.path())
+            .with_compile_concurrency(1)
+            .build()
+            .await?;
+        let serial_time = start.elapsed();
+
+        let parallel_dir = plugin_dir()?;
+        let start = Instant::now();
+        let parallel = LifecycleManager::builder(parallel_dir.path())
+            .with_compile_concurrency(ids.len())
+            .build()
+            .await?;
+        let parallel_time = start.elapsed();
+
+        tracing::info!(?serial_time, ?parallel_time, "Startup with 5 components");
+        assert_eq!(serial.list_components().await.len(), ids.len());
+        assert_eq!(parallel.list_components().await.len(), ids.len());
+        if default_compile_concurrency() > 1 {
+            assert!(
+                parallel_time < serial_time,
+                "parallel startup took {parallel_time:?}, serial startup took {serial_time:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(b"component"), content_hash(b"component"));
+        assert_ne!(content_hash(b"component"), content_hash(b"other component"));
+        assert_eq!(content_hash(b"").len(), 64);
+        assert_eq!(
+            content_digest(&content_hash(b"component")),
+            Some(component2json::SchemaCache::hash(b"component"))
+        );
+        assert_eq!(content_digest("not a hash"), None);
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Credentials for pulling components from private OCI registries
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use oci_client::secrets::RegistryAuth;
+use oci_client::Reference;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+/// Credentials for OCI registries, keyed by registry host.
+///
+/// Components are pulled anonymously from registries without credentials.
+#[derive(Clone, Default)]
+pub struct RegistryCredentials {
+    auths: HashMap<String, RegistryAuth>,
+}
+
+impl fmt::Debug for RegistryCredentials {
+    // Only the registries are shown, never the secrets
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryCredentials")
+            .field("registries", &self.auths.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl RegistryCredentials {
+    /// Returns empty credentials, with which every registry is accessed anonymously
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the credentials for a registry host, e.g. `ghcr.io` or `localhost:5000`
+    pub fn insert(&mut self, registry: &str, auth: RegistryAuth) {
+        self.auths.insert(normalize_registry(registry), auth);
+    }
+
+    /// Loads the credentials from the Docker config at `$DOCKER_CONFIG/config.json`, or
+    /// `~/.docker/config.json` if `DOCKER_CONFIG` isn't set. A missing config results in no
+    /// credentials, a config that can't be read is logged and ignored.
+    pub fn from_docker_config() -> Self {
+        let Some(path) = docker_config_path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            debug!(path = %path.display(), "No Docker config found, using anonymous registry access");
+            return Self::default();
+        }
+        match Self::from_docker_config_file(&path) {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Ignoring unreadable Docker config");
+                Self::default()
+            }
+        }
+    }
+
+    /// Loads the credentials from the Docker config file at `path`
+    pub fn from_docker_config_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read Docker config {}", path.display()))?;
+        Self::from_docker_config_json(&contents)
+    }
+
+    /// Parses the contents of a Docker `config.json`. Entries with a `registrytoken` use bearer
+    /// authentication, entries with an `auth` or a `username` and `password` use basic
+    /// authentication. Credential helpers (`credsStore` and `credHelpers`) aren't supported.
+    pub fn from_docker_config_json(json: &str) -> Result<Self> {
+        let config: DockerConfig = serde_json::from_str(json).context("Invalid Docker config")?;
+        let mut credentials = Self::default();
+        for (registry, entry) in config.auths {
+            match entry.into_auth() {
+                Ok(Some(auth)) => credentials.insert(&registry, auth),
+                Ok(None) => debug!(registry, "Docker config entry has no usable credentials"),
+                Err(e) => warn!(registry, error = %e, "Ignoring invalid Docker config entry"),
+            }
+        }
+        Ok(credentials)
+    }
+
+    /// Returns the credentials for the registry of `reference`, or anonymous access if there
+    /// are none
+    pub fn auth_for(&self, reference: &Reference) -> RegistryAuth {
+        self.auths
+            .get(&normalize_registry(reference.registry()))
+            .cloned()
+            .unwrap_or(RegistryAuth::Anonymous)
+    }
+}
+
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, DockerAuthEntry>,
+}
+
+#[derive(Deserialize)]
+struct DockerAuthEntry {
+    #[serde(default)]
+    auth: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    registrytoken: Option<String>,
+}
+
+impl DockerAuthEntry {
+    fn into_auth(self) -> Result<Option<RegistryAuth>> {
+        if let Some(token) = self.registrytoken.filter(|token| !token.is_empty()) {
+            return Ok(Some(RegistryAuth::Bearer(token)));
+        }
+        if let Some(auth) = self.auth.filter(|auth| !auth.is_empty()) {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(auth.trim())
+                .context("`auth` is not valid base64")?;
+            let decoded = String::from_utf8(decoded).context("`auth` is not valid UTF-8")?;
+            let (username, password) = decoded
+                .split_once(':')
+                .context("`auth` is not of the form username:password")?;
+            return Ok(Some(RegistryAuth::Basic(
+                username.to_string(),
+                password.to_string(),
+            )));
+        }
+        match (self.username, self.password) {
+            (Some(username), Some(password)) => Ok(Some(RegistryAuth::Basic(username, password))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Reduces a registry as written in a Docker config (`https://index.docker.io/v1/`) or in a
+/// reference (`docker.io`) to its host, so that both can be compared
+fn normalize_registry(registry: &str) -> String {
+    let host = registry
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = host.split('/').next().unwrap_or(host).to_ascii_lowercase();
+    match host.as_str() {
+        "docker.io" | "registry-1.docker.io" => "index.docker.io".to_string(),
+        _ => host,
+    }
+}
+
+fn docker_config_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("DOCKER_CONFIG") {
+        return Some(PathBuf::from(dir).join("config.json"));
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".docker").join("config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCKER_CONFIG: &str = r#"{
+        "auths": {
+            "ghcr.io": { "auth": "YWxpY2U6czNjcmV0" },
+            "https://index.docker.io/v1/": { "username": "bob", "password": "hunter2" },
+            "registry.example.com:5000": { "registrytoken": "tok3n" },
+            "broken.example.com": { "auth": "not base64!" },
+            "helper.example.com": {}
+        },
+        "credsStore": "desktop"
+    }"#;
+
+    fn auth_for(credentials: &RegistryCredentials, reference: &str) -> RegistryAuth {
+        credentials.auth_for(&reference.parse().unwrap())
+    }
+
+    #[test]
+    fn test_auth_selected_by_registry_host() {
+        let credentials = RegistryCredentials::from_docker_config_json(DOCKER_CONFIG).unwrap();
+
+        assert_eq!(
+            auth_for(&credentials, "ghcr.io/microsoft/fetch-rs:latest"),
+            RegistryAuth::Basic("alice".to_string(), "s3cret".to_string())
+        );
+        let docker_hub = RegistryAuth::Basic("bob".to_string(), "hunter2".to_string());
+        assert_eq!(
+            auth_for(&credentials, "docker.io/library/tool:1"),
+            docker_hub
+        );
+        assert_eq!(auth_for(&credentials, "library/tool:1"), docker_hub);
+        assert_eq!(
+            auth_for(&credentials, "registry.example.com:5000/team/tool:1"),
+            RegistryAuth::Bearer("tok3n".to_string())
+        );
+
+        // Registries without (usable) credentials fall back to anonymous access
+        for reference in [
+            "quay.io/team/tool:1",
+            "registry.example.com/team/tool:1",
+            "broken.example.com/tool:1",
+            "helper.example.com/tool:1",
+        ] {
+            assert_eq!(
+                auth_for(&credentials, reference),
+                RegistryAuth::Anonymous,
+                "{reference}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_docker_config_env() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("config.json"), DOCKER_CONFIG).unwrap();
+
+        temp_env::with_var("DOCKER_CONFIG", Some(dir.path()), || {
+            let credentials = RegistryCredentials::from_docker_config();
+            assert_eq!(
+                auth_for(&credentials, "ghcr.io/microsoft/fetch-rs:latest"),
+                RegistryAuth::Basic("alice".to_string(), "s3cret".to_string())
+            );
+            // Secrets don't end up in logs
+            assert!(!format!("{credentials:?}").contains("s3cret"));
+        });
+
+        let empty = tempfile::tempdir().unwrap();
+        temp_env::with_var("DOCKER_CONFIG", Some(empty.path()), || {
+            let credentials = RegistryCredentials::from_docker_config();
+            assert_eq!(
+                auth_for(&credentials, "ghcr.io/microsoft/fetch-rs:latest"),
+                RegistryAuth::Anonymous
+            );
+        });
+    }
+
+    #[test]
+    fn test_insert_normalizes_registry() {
+        let mut credentials = RegistryCredentials::new();
+        credentials.insert(
+            "https://GHCR.io/",
+            RegistryAuth::Bearer("token".to_string()),
+        );
+        assert_eq!(
+            auth_for(&credentials, "ghcr.io/org/tool:1"),
+            RegistryAuth::Bearer("token".to_string())
+        );
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Caching the results of calls, for components whose policy enables it with `runtime.cache`
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use policy::CachePolicy;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::LifecycleManager;
+
+/// The results of successful calls, by component
+#[derive(Default)]
+pub(crate) struct ResultCache {
+    components: HashMap<String, ComponentResults>,
+}
+
+#[derive(Default)]
+struct ComponentResults {
+    entries: HashMap<[u8; 32], CachedResult>,
+    /// Incremented on every use, to find the least recently used entry
+    uses: u64,
+}
+
+struct CachedResult {
+    result: Value,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+/// Identifies a call of a component by its tool and arguments. The tool name is used without the
+/// component qualifier and the arguments in canonical JSON, so that calls differing only in how
+/// they are written share a result.
+fn call_key(function_name: &str, parameters: &str) -> [u8; 32] {
+    let (_, tool_name) = crate::split_qualified_tool_name(function_name);
+    let parameters = match serde_json::from_str::<Value>(parameters) {
+        Ok(value) => canonical_json(&value),
+        // Such calls fail before running, and are never cached
+        Err(_) => parameters.to_string(),
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(tool_name.as_bytes());
+    hasher.update([0]);
+    hasher.update(parameters.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Writes a JSON value without whitespace and with the keys of objects sorted
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        value => value.to_string(),
+    }
+}
+
+impl ResultCache {
+    /// Returns the result of an earlier call with the same tool and arguments, if it isn't older
+    /// than the TTL of `policy`
+    pub(crate) fn get(
+        &mut self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        policy: &CachePolicy,
+    ) -> Option<Value> {
+        let results = self.components.get_mut(component_id)?;
+        let key = call_key(function_name, parameters);
+        let entry = results.entries.get_mut(&key)?;
+        if entry.stored_at.elapsed() >= Duration::from_millis(policy.ttl_ms) {
+            results.entries.remove(&key);
+            return None;
+        }
+        results.uses += 1;
+        entry.last_used = results.uses;
+        Some(entry.result.clone())
+    }
+
+    /// Stores the result of a successful call, dropping expired results and then the least
+    /// recently used ones to stay within the `max_entries` of `policy`
+    pub(crate) fn insert(
+        &mut self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        result: Value,
+        policy: &CachePolicy,
+    ) {
+        let results = self.components.entry(component_id.to_string()).or_default();
+        let ttl = Duration::from_millis(policy.ttl_ms);
+        results
+            .entries
+            .retain(|_, entry| entry.stored_at.elapsed() < ttl);
+        let key = call_key(function_name, parameters);
+        while results.entries.len() >= policy.max_entries && !results.entries.contains_key(&key) {
+            let Some(oldest) = results
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            results.entries.remove(&oldest);
+        }
+        results.uses += 1;
+        results.entries.insert(
+            key,
+            CachedResult {
+                result,
+                stored_at: Instant::now(),
+                last_used: results.uses,
+            },
+        );
+    }
+
+    /// Drops the results of a component, e.g. because it was replaced
+    pub(crate) fn remove_component(&mut self, component_id: &str) {
+        self.components.remove(component_id);
+    }
+}
+
+impl LifecycleManager {
+    /// Returns the cache settings of a component, if its policy enables caching
+    pub(crate) async fn result_cache_policy(&self, component_id: &str) -> Option<CachePolicy> {
+        let template = self.policy_template_for(component_id).await;
+        let runtime = template.policy.as_ref()?.permissions.runtime.as_ref()?;
+        runtime.cache.clone().filter(|cache| cache.enabled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use serde_json::json;
+    use test_log::test;
+
+    use super::*;
+    use crate::tests::*;
+    use crate::{HttpInterceptor, MockResponse};
+
+    fn cache_policy(ttl_ms: u64, max_entries: usize) -> CachePolicy {
+        CachePolicy {
+            enabled: true,
+            ttl_ms,
+            max_entries,
+        }
+    }
+
+    #[test]
+    fn test_least_recently_used_result_is_dropped() {
+        let policy = cache_policy(60_000, 2);
+        let mut cache = ResultCache::default();
+        cache.insert("c", "f", "1", json!("one"), &policy);
+        cache.insert("c", "f", "2", json!("two"), &policy);
+        assert_eq!(cache.get("c", "f", "1", &policy), Some(json!("one")));
+
+        cache.insert("c", "f", "3", json!("three"), &policy);
+        assert_eq!(cache.get("c", "f", "2", &policy), None);
+        assert_eq!(cache.get("c", "f", "1", &policy), Some(json!("one")));
+        assert_eq!(cache.get("c", "f", "3", &policy), Some(json!("three")));
+
+        // Results are kept per component and tool
+        assert_eq!(cache.get("c", "c::f", "1", &policy), Some(json!("one")));
+        assert_eq!(cache.get("other", "f", "1", &policy), None);
+        assert_eq!(cache.get("c", "g", "1", &policy), None);
+        cache.remove_component("c");
+        assert_eq!(cache.get("c", "f", "1", &policy), None);
+    }
+
+    #[test]
+    fn test_arguments_are_compared_as_json() {
+        let policy = cache_policy(60_000, 8);
+        let mut cache = ResultCache::default();
+        cache.insert(
+            "c",
+            "f",
+            r#"{"a": 1, "b": [true, {"y": 2, "x": 1}]}"#,
+            json!("one"),
+            &policy,
+        );
+        assert_eq!(
+            cache.get("c", "f", r#"{"b":[true,{"x":1,"y":2}],"a":1}"#, &policy),
+            Some(json!("one"))
+        );
+        assert_eq!(
+            cache.get(
+                "c",
+                "f",
+                r#"{"a": 2, "b": [true, {"x": 1, "y": 2}]}"#,
+                &policy
+            ),
+            None
+        );
+    }
+
+    /// Answers every request, counting them
+    #[derive(Default)]
+    struct CountingMock {
+        answered: AtomicUsize,
+    }
+
+    impl HttpInterceptor for CountingMock {
+        fn intercept(
+            &self,
+            _request: &hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
+        ) -> Option<MockResponse> {
+            let answered = self.answered.fetch_add(1, Ordering::SeqCst) + 1;
+            Some(MockResponse::new(200, format!("response {answered}")))
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_cached_results_expire() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let mock = Arc::new(CountingMock::default());
+        let manager = LifecycleManager::builder(tempdir.path().join("plugins"))
+            .with_http_mock(mock.clone())
+            .build()
+            .await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        let policy_path = tempdir.path().join("policy.yaml");
+        tokio::fs::write(
+            &policy_path,
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "example.com"
+  runtime:
+    cache:
+      ttl_ms: 500
+"#,
+        )
+        .await?;
+        manager
+            .attach_policy(
+                TEST_COMPONENT_ID,
+                &format!("file://{}", policy_path.display()),
+            )
+            .await?;
+
+        let fetch = |url: &str| {
+            let manager = manager.clone();
+            let params = format!(r#"{{"url": "{url}"}}"#);
+            async move {
+                manager
+                    .execute_component_call(TEST_COMPONENT_ID, "fetch", &params)
+                    .await
+            }
+        };
+        let first = fetch("https://example.com/").await?;
+        assert!(first.contains("response 1"), "unexpected result: {first}");
+        assert_eq!(fetch("https://example.com/").await?, first);
+        assert_eq!(mock.answered.load(Ordering::SeqCst), 1);
+
+        // Other arguments are a different call
+        let other = fetch("https://example.com/other").await?;
+        assert!(other.contains("response 2"), "unexpected result: {other}");
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let expired = fetch("https://example.com/").await?;
+        assert!(
+            expired.contains("response 3"),
+            "unexpected result: {expired}"
+        );
+        assert_eq!(mock.answered.load(Ordering::SeqCst), 3);
+
+        // Cache hits count as calls
+        assert_eq!(
+            manager
+                .get_metrics(TEST_COMPONENT_ID)
+                .await
+                .unwrap()
+                .call_count,
+            4
+        );
+
+        // Changing the policy drops the cached results
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &json!({"host": "example.org"}),
+            )
+            .await?;
+        let regranted = fetch("https://example.com/").await?;
+        assert!(
+            regranted.contains("response 4"),
+            "unexpected result: {regranted}"
+        );
+        manager
+            .revoke_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &json!({"host": "example.com"}),
+            )
+            .await?;
+        let revoked = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com/"}"#,
+            )
+            .await;
+        assert!(revoked.map_or(true, |result| result != regranted));
+        assert_eq!(mock.answered.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Retrying downloads that fail because of transient network or server problems
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use oci_client::errors::OciDistributionError;
+use reqwest::StatusCode;
+use tracing::warn;
+
+/// How downloads of components and policies are retried after transient failures: connection
+/// errors, timeouts and `5xx` or `429 Too Many Requests` responses. Other client errors are never
+/// retried.
+///
+/// The delay doubles after every attempt, starting at `base_delay` and capped at `max_delay`. A
+/// `Retry-After` header with a number of seconds takes precedence over the computed delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first one. `1` disables retries
+    pub max_attempts: u32,
+    /// The delay before the first retry
+    pub base_delay: Duration,
+    /// The longest delay between two attempts
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The delay after the given failed attempt, starting at 1
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Sends a GET request to `url`, retrying according to `policy`. The response of the last attempt
+/// is returned whatever its status, so callers report errors as before.
+pub(crate) async fn get_with_retry(
+    http_client: &reqwest::Client,
+    url: &str,
+    policy: &RetryPolicy,
+) -> Result<reqwest::Response> {
+    let mut attempt = 1;
+    loop {
+        let delay = match http_client.get(url).send().await {
+            Ok(response)
+                if is_transient_status(response.status()) && attempt < policy.max_attempts =>
+            {
+                let delay = retry_after(&response)
+                    .map(|delay| delay.min(policy.max_delay))
+                    .unwrap_or_else(|| policy.delay(attempt));
+                warn!(url, status = %response.status(), attempt, ?delay, "Download failed, retrying");
+                delay
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if is_transient_request_error(&e) && attempt < policy.max_attempts => {
+                let delay = policy.delay(attempt);
+                warn!(url, error = %e, attempt, ?delay, "Download failed, retrying");
+                delay
+            }
+            Err(e) => return Err(e.into()),
+        };
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Runs an OCI registry `operation`, retrying transient failures according to `policy`
+pub(crate) async fn oci_with_retry<T, F, Fut>(policy: &RetryPolicy, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if is_transient_oci_error(&e) && attempt < policy.max_attempts => {
+                let delay = policy.delay(attempt);
+                warn!(error = %e, attempt, ?delay, "Pulling from registry failed, retrying");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_transient_request_error(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.status().is_some_and(is_transient_status)
+}
+
+fn is_transient_oci_error(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<OciDistributionError>() {
+        Some(OciDistributionError::RequestError(e)) => is_transient_request_error(e),
+        Some(OciDistributionError::ServerError { code, .. }) => StatusCode::from_u16(*code)
+            .map(is_transient_status)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Returns the delay of a `Retry-After` header given in seconds. HTTP dates aren't supported and
+/// fall back to the computed delay.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?;
+    let seconds = value.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use test_log::test;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Starts an HTTP server that answers the first `failures` requests with `failure_status` and
+    /// then with `200 OK`. Returns its URL and the number of requests received so far.
+    async fn flaky_server(
+        failures: usize,
+        failure_status: &'static str,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/component.wasm", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                    format!("HTTP/1.1 {failure_status}\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                        .to_string()
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_retries_until_success() {
+        let (url, requests) = flaky_server(2, "503 Service Unavailable").await;
+        let response = get_with_retry(&reqwest::Client::new(), &url, &fast_policy())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test(tokio::test)]
+    async fn test_gives_up_after_max_attempts() {
+        let (url, requests) = flaky_server(5, "429 Too Many Requests").await;
+        let response = get_with_retry(&reqwest::Client::new(), &url, &fast_policy())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test(tokio::test)]
+    async fn test_client_errors_are_not_retried() {
+        let (url, requests) = flaky_server(1, "404 Not Found").await;
+        let response = get_with_retry(&reqwest::Client::new(), &url, &fast_policy())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let (url, requests) = flaky_server(1, "503 Service Unavailable").await;
+        let response = get_with_retry(&reqwest::Client::new(), &url, &RetryPolicy::none())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_oci_retries_only_transient_errors() {
+        let attempts = AtomicUsize::new(0);
+        let result = oci_with_retry(&fast_policy(), || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(OciDistributionError::ServerError {
+                    code: 502,
+                    url: "https://registry.example.com".to_string(),
+                    message: "bad gateway".to_string(),
+                }
+                .into())
+            } else {
+                Ok(42)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicUsize::new(0);
+        let result: Result<()> = oci_with_retry(&fast_policy(), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(OciDistributionError::UnauthorizedError {
+                url: "https://registry.example.com".to_string(),
+            }
+            .into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_exponential_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(4), Duration::from_secs(5));
+        assert_eq!(policy.delay(40), Duration::from_secs(5));
+    }
+}
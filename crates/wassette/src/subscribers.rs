@@ -0,0 +1,81 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Telling subscribers, like the clients of an MCP server, when the loaded tools change
+
+use std::sync::Arc;
+
+use crate::LifecycleManager;
+
+/// Called after a component was loaded, replaced or unloaded
+pub(crate) type ToolsChangedCallback = Arc<dyn Fn() + Send + Sync>;
+
+impl LifecycleManager {
+    /// Calls `on_change` whenever [`LifecycleManager::load_component`],
+    /// [`LifecycleManager::load_component_with_policy`],
+    /// [`LifecycleManager::load_component_bytes`] or [`LifecycleManager::unload_component`]
+    /// change the loaded components, e.g. to send a `notifications/tools/list_changed` to the
+    /// connected clients. Loading a component that is already loaded and unchanged doesn't call
+    /// it.
+    ///
+    /// The callback runs on the task that changed the components, so it should hand off any
+    /// slow work, like sending notifications, to another task. Changes made by
+    /// [`LifecycleManager::reconcile`] or the plugin directory watcher aren't reported here, the
+    /// watcher has its own callback.
+    pub fn subscribe_tools_changed(&self, on_change: impl Fn() + Send + Sync + 'static) {
+        self.tools_changed_subscribers
+            .lock()
+            .unwrap()
+            .push(Arc::new(on_change));
+    }
+
+    /// Calls every subscriber of [`LifecycleManager::subscribe_tools_changed`]
+    pub(crate) fn notify_tools_changed(&self) {
+        // Cloned, so that a subscriber can subscribe more callbacks without deadlocking
+        let subscribers = self.tools_changed_subscribers.lock().unwrap().clone();
+        for on_change in subscribers {
+            on_change();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::LoadResult;
+
+    #[test(tokio::test)]
+    async fn test_subscribers_notified_of_loads_and_unloads() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        let notified = Arc::new(AtomicUsize::new(0));
+        manager.subscribe_tools_changed({
+            let notified = notified.clone();
+            move || {
+                notified.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        load_wat_component(&manager, "answer", ANSWER_WAT).await?;
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+
+        // Nothing changes when the same component is loaded again
+        let (_, result) = load_wat_component(&manager, "answer", ANSWER_WAT).await?;
+        assert_eq!(result, LoadResult::Unchanged);
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+
+        manager.unload_component("answer").await?;
+        assert_eq!(notified.load(Ordering::SeqCst), 2);
+
+        // Unloading a component that isn't loaded doesn't change the tools either
+        manager.unload_component("answer").await?;
+        assert_eq!(notified.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! OpenTelemetry spans for component calls
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use opentelemetry::global::{BoxedSpan, BoxedTracer, ObjectSafeTracerProvider};
+use opentelemetry::trace::{Span, Status, Tracer};
+use opentelemetry::{Context, InstrumentationScope, KeyValue};
+
+use crate::{LifecycleManager, LifecycleManagerBuilder, WassetteError};
+
+/// The name of the span of every component call
+const CALL_SPAN_NAME: &str = "component.call";
+
+impl LifecycleManagerBuilder {
+    /// Records every component call as a `component.call` span with a tracer of `provider`. The
+    /// span is a child of the OpenTelemetry context current when the call is made, so calls made
+    /// within a context extracted from the headers of an HTTP request join the trace of the
+    /// caller. Without a tracer provider, calls aren't traced.
+    pub fn with_tracer_provider(mut self, provider: &impl ObjectSafeTracerProvider) -> Self {
+        let scope = InstrumentationScope::builder("wassette")
+            .with_version(env!("CARGO_PKG_VERSION"))
+            .build();
+        self.tracer = Some(Arc::new(BoxedTracer::new(provider.boxed_tracer(scope))));
+        self
+    }
+}
+
+/// The span of a running component call
+pub(crate) struct CallSpan {
+    span: BoxedSpan,
+    start: Instant,
+}
+
+impl CallSpan {
+    /// Ends the span with the duration and outcome of the call
+    pub(crate) fn end<T>(mut self, result: &Result<T, WassetteError>) {
+        let duration_ms = i64::try_from(self.start.elapsed().as_millis()).unwrap_or(i64::MAX);
+        self.span
+            .set_attribute(KeyValue::new("component.duration_ms", duration_ms));
+        self.span
+            .set_attribute(KeyValue::new("component.error", result.is_err()));
+        if let Err(e) = result {
+            self.span.set_status(Status::error(e.to_string()));
+        }
+        self.span.end();
+    }
+}
+
+impl LifecycleManager {
+    /// Starts the span of a call, if calls are traced
+    pub(crate) fn start_call_span(
+        &self,
+        component_id: &str,
+        function_name: &str,
+    ) -> Option<CallSpan> {
+        let tracer = self.tracer.as_ref()?;
+        let mut span = tracer.start_with_context(CALL_SPAN_NAME, &Context::current());
+        span.set_attribute(KeyValue::new("component.id", component_id.to_string()));
+        span.set_attribute(KeyValue::new(
+            "component.function",
+            function_name.to_string(),
+        ));
+        Some(CallSpan {
+            span,
+            start: Instant::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::context::FutureExt as _;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt as _, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::Value;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+
+    fn attribute(span: &opentelemetry_sdk::trace::SpanData, key: &str) -> Option<Value> {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == key)
+            .map(|attribute| attribute.value.clone())
+    }
+
+    #[test(tokio::test)]
+    async fn test_calls_are_traced() -> anyhow::Result<()> {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path().join("plugins"))
+            .with_tracer_provider(&provider)
+            .build()
+            .await?;
+        load_wat_component(&manager, "answer", ANSWER_WAT).await?;
+
+        // The call is made within the context of a remote caller, as if it came over HTTP
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736")?;
+        let parent_span_id = SpanId::from_hex("00f067aa0ba902b7")?;
+        let parent = Context::new().with_remote_span_context(SpanContext::new(
+            trace_id,
+            parent_span_id,
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+        manager
+            .execute_component_call("answer", "answer", "{}")
+            .with_context(parent)
+            .await?;
+        assert!(manager
+            .execute_component_call("answer", "missing", "{}")
+            .await
+            .is_err());
+
+        let spans = exporter.get_finished_spans()?;
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|span| span.name == CALL_SPAN_NAME));
+
+        let succeeded = &spans[0];
+        assert_eq!(succeeded.span_context.trace_id(), trace_id);
+        assert_eq!(succeeded.parent_span_id, parent_span_id);
+        assert_eq!(
+            attribute(succeeded, "component.id"),
+            Some(Value::from("answer"))
+        );
+        assert_eq!(
+            attribute(succeeded, "component.function"),
+            Some(Value::from("answer"))
+        );
+        assert_eq!(
+            attribute(succeeded, "component.error"),
+            Some(Value::from(false))
+        );
+        assert!(matches!(
+            attribute(succeeded, "component.duration_ms"),
+            Some(Value::I64(duration)) if duration >= 0
+        ));
+
+        let failed = &spans[1];
+        assert_ne!(failed.span_context.trace_id(), trace_id);
+        assert_eq!(
+            attribute(failed, "component.function"),
+            Some(Value::from("missing"))
+        );
+        assert_eq!(
+            attribute(failed, "component.error"),
+            Some(Value::from(true))
+        );
+        assert!(matches!(failed.status, Status::Error { .. }));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Components and helpers shared by the tests of this crate and of the crates built on it, which
+//! enable the `test-fixtures` feature to use them
+
+use crate::{LifecycleManager, LoadResult};
+
+/// A component exporting `answer: func() -> u32`, which returns 42
+pub const ANSWER_WAT: &str = r#"(component
+    (core module $m (func (export "f") (result i32) i32.const 42))
+    (core instance $i (instantiate $m))
+    (func (export "answer") (result u32) (canon lift (core func $i "f")))
+)"#;
+
+/// A component exporting `other: func() -> u32`, which returns 7, for tests that need a second
+/// component that isn't the same as [`ANSWER_WAT`]
+pub const OTHER_WAT: &str = r#"(component
+    (core module $m (func (export "other") (result i32) (i32.const 7)))
+    (core instance $i (instantiate $m))
+    (func (export "other") (result u32) (canon lift (core func $i "other")))
+)"#;
+
+/// A component exporting `spin: func()`, which loops forever
+pub const SPIN_FOREVER_WAT: &str = r#"(component
+    (core module $m (func (export "spin") (loop $l (br $l))))
+    (core instance $i (instantiate $m))
+    (func (export "spin") (canon lift (core func $i "spin")))
+)"#;
+
+/// A component exporting `spin: func(n: u32) -> u32`, which counts to `n`, keeping a CPU busy,
+/// and returns it
+pub const SPIN_WAT: &str = r#"(component
+    (core module $m
+        (func (export "spin") (param i32) (result i32) (local i32)
+            (block (loop
+                (br_if 1 (i32.ge_u (local.get 1) (local.get 0)))
+                (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+                (br 0)))
+            local.get 1))
+    (core instance $i (instantiate $m))
+    (func (export "spin") (param "n" u32) (result u32)
+        (canon lift (core func $i "spin")))
+)"#;
+
+/// A minimal component that only imports `wasi:config/store` and exports a single
+/// `get-config: func(key: string) -> option<string>` function
+pub const CONFIG_READER_COMPONENT_WAT: &str = r#"
+(component
+  (import "wasi:config/store@0.2.0-draft" (instance $store
+    (type $error-ty (variant (case "upstream" string) (case "io" string)))
+    (export "error" (type $error (eq $error-ty)))
+    (type $get-ty (func (param "key" string) (result (result (option string) (error $error)))))
+    (export "get" (func (type $get-ty)))
+  ))
+
+  (core module $libc
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 1024))
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+      (local $ret i32)
+      (local.set $ret
+        (i32.and
+          (i32.add (global.get $heap) (i32.sub (local.get 2) (i32.const 1)))
+          (i32.xor (i32.sub (local.get 2) (i32.const 1)) (i32.const -1))))
+      (global.set $heap (i32.add (local.get $ret) (local.get 3)))
+      (local.get $ret))
+  )
+  (core instance $libc (instantiate $libc))
+
+  (core func $get (canon lower (func $store "get")
+    (memory $libc "memory") (realloc (func $libc "realloc"))))
+
+  (core module $main
+    (import "libc" "memory" (memory 1))
+    (import "store" "get" (func $get (param i32 i32 i32)))
+    (func (export "get-config") (param i32 i32) (result i32)
+      (call $get (local.get 0) (local.get 1) (i32.const 16))
+      (if (i32.ne (i32.load8_u (i32.const 16)) (i32.const 0)) (then unreachable))
+      (i32.const 20))
+  )
+  (core instance $main (instantiate $main
+    (with "libc" (instance $libc))
+    (with "store" (instance (export "get" (func $get))))
+  ))
+
+  (func (export "get-config") (param "key" string) (result (option string))
+    (canon lift (core func $main "get-config")
+      (memory $libc "memory") (realloc (func $libc "realloc"))))
+)
+"#;
+
+/// Writes `wat` to `<name>.wasm` in a temporary directory and loads it into `manager` from its
+/// `file://` URI, like a component a client asked to load
+pub async fn load_wat_component(
+    manager: &LifecycleManager,
+    name: &str,
+    wat: &str,
+) -> anyhow::Result<(String, LoadResult)> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(format!("{name}.wasm"));
+    tokio::fs::write(&path, wat).await?;
+    Ok(manager
+        .load_component(&format!("file://{}", path.display()))
+        .await?)
+}
@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Hooks for post-processing component results before they are returned to the caller.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// Controls how the JSON result of a component call is rendered into the string returned by
+/// [`LifecycleManager::execute_component_call`](crate::LifecycleManager::execute_component_call)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResultFormat {
+    /// String results are returned as plain text, everything else is serialized as JSON. A string
+    /// that happens to contain JSON is indistinguishable from a structured result. This is the
+    /// default, for compatibility with existing clients.
+    #[default]
+    Text,
+    /// Every result is serialized as JSON, so string results are returned quoted
+    Json,
+    /// Results are returned as plain text only if the tool's output schema declares a plain
+    /// `string`, and serialized as JSON otherwise. The format of a tool's result therefore
+    /// depends only on its signature, never on the value it returned.
+    Schema,
+}
+
+impl ResultFormat {
+    /// Renders `result` according to this format. `output_schema` is the output schema of the
+    /// tool that produced the result, if it has one.
+    pub fn render(self, result: &Value, output_schema: Option<&Value>) -> Result<String> {
+        let as_text = match self {
+            ResultFormat::Text => true,
+            ResultFormat::Json => false,
+            ResultFormat::Schema => output_schema
+                .and_then(|schema| schema.get("type"))
+                .is_some_and(|ty| ty == "string"),
+        };
+        match result.as_str() {
+            Some(text) if as_text => Ok(text.to_string()),
+            _ => Ok(serde_json::to_string(result)?),
+        }
+    }
+}
+
+/// Post-processes the result of every component call.
+///
+/// This is an extension point for cross-cutting concerns such as capping output size, redacting
+/// sensitive data or reformatting results, without having to modify each component.
+///
+/// The transformer runs after the component's return values have been converted to JSON and
+/// before they are serialized into the string returned by
+/// [`LifecycleManager::execute_component_call`](crate::LifecycleManager::execute_component_call).
+/// Any validation against a tool's output schema (such as the one MCP clients perform on
+/// structured content) therefore sees the *transformed* value, so transformers that change the
+/// shape of a result should keep it compatible with the tool's advertised output schema.
+pub trait ResultTransformer: Send + Sync {
+    /// Transforms the `result` of a call to `tool_name`. Returning an error rejects the result and
+    /// fails the call with that error.
+    fn transform(&self, tool_name: &str, result: Value) -> Result<Value>;
+}
+
+impl crate::LifecycleManager {
+    /// Sets the [`ResultTransformer`] applied to the results of all component calls, replacing
+    /// any previously set transformer
+    pub async fn set_result_transformer(&self, transformer: Arc<dyn ResultTransformer>) {
+        *self.result_transformer.write().await = Some(transformer);
+    }
+
+    /// Removes the [`ResultTransformer`], if any, so results are returned unchanged
+    pub async fn clear_result_transformer(&self) {
+        *self.result_transformer.write().await = None;
+    }
+
+    pub(crate) async fn transform_result(&self, tool_name: &str, result: Value) -> Result<Value> {
+        match self.result_transformer.read().await.as_ref() {
+            Some(transformer) => transformer.transform(tool_name, result),
+            None => Ok(result),
+        }
+    }
+
+    pub(crate) async fn render_result(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        result: &Value,
+    ) -> Result<String> {
+        let output_schema = match self.result_format {
+            ResultFormat::Schema => self
+                .registry
+                .read()
+                .await
+                .get_component_tool(component_id, tool_name)
+                .and_then(|tool| tool.schema.get("outputSchema").cloned()),
+            _ => None,
+        };
+        self.result_format.render(result, output_schema.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+    use component2json::{FunctionIdentifier, ToolMetadata};
+    use serde_json::json;
+    use test_log::test;
+
+    use super::*;
+    use crate::tests::*;
+
+    /// Truncates string results to at most `max_len` characters
+    struct Truncate {
+        max_len: usize,
+    }
+
+    impl ResultTransformer for Truncate {
+        fn transform(&self, _tool_name: &str, result: Value) -> Result<Value> {
+            Ok(match result {
+                Value::String(s) => Value::String(s.chars().take(self.max_len).collect()),
+                other => other,
+            })
+        }
+    }
+
+    struct RejectTool(&'static str);
+
+    impl ResultTransformer for RejectTool {
+        fn transform(&self, tool_name: &str, result: Value) -> Result<Value> {
+            if tool_name == self.0 {
+                bail!("Result of {tool_name} was rejected");
+            }
+            Ok(result)
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_result_transformer_truncates() -> Result<()> {
+        let manager = create_test_manager().await?;
+
+        manager
+            .set_result_transformer(Arc::new(Truncate { max_len: 5 }))
+            .await;
+        let result = manager
+            .transform_result("get-config", json!("hello world"))
+            .await?;
+        assert_eq!(result, json!("hello"));
+
+        manager.clear_result_transformer().await;
+        let result = manager
+            .transform_result("get-config", json!("hello world"))
+            .await?;
+        assert_eq!(result, json!("hello world"));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_result_transformer_rejects() -> Result<()> {
+        let manager = create_test_manager().await?;
+
+        manager
+            .set_result_transformer(Arc::new(RejectTool("get-config")))
+            .await;
+        let err = manager
+            .transform_result("get-config", json!("hello"))
+            .await
+            .expect_err("The transformer should reject the result");
+        assert!(err.to_string().contains("rejected"));
+        assert_eq!(
+            manager.transform_result("other", json!("hello")).await?,
+            json!("hello")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_text() {
+        let format = ResultFormat::Text;
+        assert_eq!(format.render(&json!("hello"), None).unwrap(), "hello");
+        assert_eq!(
+            format.render(&json!(r#"{"a":1}"#), None).unwrap(),
+            r#"{"a":1}"#
+        );
+        assert_eq!(format.render(&json!({"a": 1}), None).unwrap(), r#"{"a":1}"#);
+        assert_eq!(format.render(&Value::Null, None).unwrap(), "null");
+    }
+
+    #[test]
+    fn test_render_json() {
+        let format = ResultFormat::Json;
+        let schema = json!({"type": "string"});
+        assert_eq!(
+            format.render(&json!("hello"), Some(&schema)).unwrap(),
+            r#""hello""#
+        );
+        assert_eq!(
+            format.render(&json!(r#"{"a":1}"#), None).unwrap(),
+            r#""{\"a\":1}""#
+        );
+        assert_eq!(format.render(&json!({"a": 1}), None).unwrap(), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_render_schema() {
+        let format = ResultFormat::Schema;
+        let string_schema = json!({"type": "string"});
+        let option_schema = json!({"anyOf": [{"type": "null"}, {"type": "string"}]});
+        assert_eq!(
+            format
+                .render(&json!(r#"{"a":1}"#), Some(&string_schema))
+                .unwrap(),
+            r#"{"a":1}"#
+        );
+        assert_eq!(
+            format
+                .render(&json!("hello"), Some(&option_schema))
+                .unwrap(),
+            r#""hello""#
+        );
+        assert_eq!(format.render(&json!("hello"), None).unwrap(), r#""hello""#);
+        // A transformer may have changed the shape of a result, the value always wins
+        assert_eq!(
+            format
+                .render(&json!({"a": 1}), Some(&string_schema))
+                .unwrap(),
+            r#"{"a":1}"#
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_render_result_uses_registered_output_schema() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.registry.write().await.register_tools(
+            "config_reader",
+            vec![ToolMetadata {
+                identifier: FunctionIdentifier {
+                    package_name: None,
+                    interface_name: None,
+                    function_name: "get-config".to_string(),
+                },
+                normalized_name: "get-config".to_string(),
+                schema: json!({
+                    "outputSchema": {"anyOf": [{"type": "null"}, {"type": "string"}]}
+                }),
+            }],
+        )?;
+        let result = json!(r#"{"a":1}"#);
+
+        let render = |format: ResultFormat, tool_name: &'static str| {
+            let mut manager = crate::LifecycleManager::clone(&manager);
+            manager.result_format = format;
+            let result = result.clone();
+            async move {
+                manager
+                    .render_result("config_reader", tool_name, &result)
+                    .await
+            }
+        };
+        assert_eq!(
+            render(ResultFormat::Text, "get-config").await?,
+            r#"{"a":1}"#
+        );
+        // `get-config` returns an `option<string>`, which the schema doesn't declare as plain text
+        for format in [ResultFormat::Json, ResultFormat::Schema] {
+            assert_eq!(render(format, "get-config").await?, r#""{\"a\":1}""#);
+        }
+        // Without a schema, the value is serialized as JSON
+        assert_eq!(
+            render(ResultFormat::Schema, "unknown").await?,
+            r#""{\"a\":1}""#
+        );
+
+        Ok(())
+    }
+}
@@ -3,22 +3,80 @@
 
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-
-use policy::{AccessType, PolicyDocument};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use policy::{
+    AccessType, NetworkPermission, PolicyDocument, PortRange, StoragePermission,
+    STORAGE_URI_SCHEMES,
+};
 use wasmtime_wasi::p2::WasiCtxBuilder;
 use wasmtime_wasi_config::WasiConfigVariables;
 use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
 
+/// A limit set by the policy of a component that a call of the component went over. Component
+/// calls fail with this error as a [`crate::WassetteError::PolicyViolation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The component tried to grow its memory past `runtime.memory_limit_mb`
+    MemoryLimit {
+        /// The limit from the policy, in bytes
+        limit_bytes: usize,
+        /// The memory size the component asked for, in bytes
+        requested_bytes: usize,
+    },
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::MemoryLimit {
+                limit_bytes,
+                requested_bytes,
+            } => write!(
+                f,
+                "Component exceeded the memory limit of its policy: it tried to use {requested_bytes} bytes, but the policy allows {limit_bytes} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
 /// Custom resource limiter that stores the limits
 #[derive(Clone)]
 pub struct CustomResourceLimiter {
     limits: wasmtime::StoreLimits,
+    memory_cap: Option<usize>,
+    policy_memory_limit: Option<usize>,
+    /// The total size of the linear memories of the store, in bytes
+    memory_used: usize,
 }
 
 impl CustomResourceLimiter {
     /// Create a new CustomResourceLimiter with the given limits
     pub fn new(limits: wasmtime::StoreLimits) -> Self {
-        Self { limits }
+        Self {
+            limits,
+            memory_cap: None,
+            policy_memory_limit: None,
+            memory_used: 0,
+        }
+    }
+
+    /// Limits the total size of all linear memories to `max_bytes` as required by a policy.
+    /// Growing a memory past the limit fails the call with a [`PolicyViolation::MemoryLimit`].
+    pub fn with_policy_memory_limit(mut self, max_bytes: usize) -> Self {
+        self.policy_memory_limit = Some(max_bytes);
+        self
+    }
+
+    /// Caps the total size of all linear memories at `max_bytes`. Unlike the store limits, which
+    /// make `memory.grow` fail, growing a memory past the cap traps with an error explaining why.
+    pub fn with_memory_cap(mut self, max_bytes: usize) -> Self {
+        self.memory_cap = Some(max_bytes);
+        self
     }
 }
 
@@ -29,7 +87,28 @@ impl wasmtime::ResourceLimiter for CustomResourceLimiter {
         desired: usize,
         _maximum: Option<usize>,
     ) -> anyhow::Result<bool> {
-        self.limits.memory_growing(current, desired, _maximum)
+        // Components can have several memories, the limits apply to all of them together
+        let total = self
+            .memory_used
+            .saturating_sub(current)
+            .saturating_add(desired);
+        if let Some(limit) = self.policy_memory_limit.filter(|limit| total > *limit) {
+            return Err(PolicyViolation::MemoryLimit {
+                limit_bytes: limit,
+                requested_bytes: total,
+            }
+            .into());
+        }
+        if let Some(cap) = self.memory_cap.filter(|cap| total > *cap) {
+            anyhow::bail!(
+                "Component exceeded its memory limit: growing memory from {current} to {desired} bytes brings the total to {total} bytes, over the limit of {cap} bytes"
+            );
+        }
+        let allowed = self.limits.memory_growing(current, desired, _maximum)?;
+        if allowed {
+            self.memory_used = total;
+        }
+        Ok(allowed)
     }
 
     fn table_growing(
@@ -48,6 +127,9 @@ pub struct WasiState {
     pub http: wasmtime_wasi_http::WasiHttpCtx,
     pub wasi_config_vars: WasiConfigVariables,
     pub resource_limiter: Option<CustomResourceLimiter>,
+    /// Whether the policy only grants environment access, so that the component is instantiated
+    /// without HTTP and sockets, see [`WasiStateTemplate::grants_only_environment`]
+    pub environment_only: bool,
 }
 
 impl wasmtime_wasi::p2::IoView for WasiState {
@@ -85,7 +167,10 @@ impl WasiStateTemplate {
         // Note(mossaka): removed ctx_builder.inherit_network() to implement deny-by-default network policy
         // For HTTP requests to work, we need to allow TCP and DNS lookups when there are network permissions
         // But HTTP-level filtering happens in WassetteWasiState::send_request
-        if self.network_perms.allow_tcp || !self.allowed_hosts.is_empty() {
+        if self.network_perms.allow_tcp
+            || !self.allowed_hosts.is_empty()
+            || !self.allowed_cidrs.is_empty()
+        {
             ctx_builder.allow_tcp(true);
             ctx_builder.allow_ip_name_lookup(true);
         } else {
@@ -107,12 +192,35 @@ impl WasiStateTemplate {
             table: wasmtime_wasi::ResourceTable::default(),
             http: WasiHttpCtx::new(),
             wasi_config_vars: WasiConfigVariables::from_iter(self.config_vars.clone()),
-            resource_limiter: self
-                .store_limits
-                .as_ref()
-                .map(|limits| CustomResourceLimiter::new(limits.clone())),
+            resource_limiter: self.resource_limiter(),
+            environment_only: self.grants_only_environment(),
         })
     }
+
+    /// Whether the template comes from a policy that grants no network or storage access, only
+    /// environment variables. Components without a policy get the full set of interfaces, which
+    /// refuse everything the default policy doesn't grant.
+    pub fn grants_only_environment(&self) -> bool {
+        self.policy.is_some()
+            && self.preopened_dirs.is_empty()
+            && self.allowed_hosts.is_empty()
+            && self.allowed_cidrs.is_empty()
+            && !self.network_perms.allow_tcp
+            && !self.network_perms.allow_udp
+            && !self.network_perms.allow_ip_name_lookup
+    }
+
+    fn resource_limiter(&self) -> Option<CustomResourceLimiter> {
+        let limiter = self.store_limits.clone().map(CustomResourceLimiter::new);
+        match self.runtime_memory_limit {
+            Some(limit) => Some(
+                limiter
+                    .unwrap_or_else(|| CustomResourceLimiter::new(wasmtime::StoreLimits::default()))
+                    .with_policy_memory_limit(limit),
+            ),
+            None => limiter,
+        }
+    }
 }
 
 /// A struct that presents the arguments passed to `wasmtime_wasi::WasiCtxBuilder::preopened_dir`
@@ -150,10 +258,29 @@ pub struct WasiStateTemplate {
     pub preopened_dirs: Vec<PreopenedDir>,
     /// Allowed network hosts for HTTP requests
     pub allowed_hosts: HashSet<String>,
+    /// Denied network hosts for HTTP requests, which take precedence over the allowed hosts
+    pub denied_hosts: HashSet<String>,
+    /// Ports the allowed hosts are restricted to. Allowed hosts without an entry can be reached
+    /// on any port
+    pub allowed_ports: HashMap<String, Vec<PortRange>>,
+    /// Allowed address ranges for HTTP requests, in CIDR notation
+    pub allowed_cidrs: HashSet<String>,
+    /// Denied address ranges for HTTP requests, in CIDR notation, which take precedence over
+    /// everything that is allowed
+    pub denied_cidrs: HashSet<String>,
     /// Memory limit in bytes for the component
     pub memory_limit: Option<u64>,
     /// Store limits for wasmtime (built from memory_limit)
     pub store_limits: Option<wasmtime::StoreLimits>,
+    /// Memory limit in bytes from `runtime.memory_limit_mb`. Going over it fails the call with a
+    /// [`PolicyViolation`]
+    pub runtime_memory_limit: Option<usize>,
+    /// How long a single call may run, from `runtime.execution_timeout_ms`. Overrides the call
+    /// timeout of the [`crate::LifecycleManager`]
+    pub execution_timeout: Option<Duration>,
+    /// The policy the template was created from. It is validated again every time the template
+    /// is used, so that a policy stops granting access once it has expired.
+    pub policy: Option<Arc<PolicyDocument>>,
 }
 
 impl Default for WasiStateTemplate {
@@ -166,8 +293,15 @@ impl Default for WasiStateTemplate {
             config_vars: HashMap::new(),
             preopened_dirs: Vec::new(),
             allowed_hosts: HashSet::new(),
+            denied_hosts: HashSet::new(),
+            allowed_ports: HashMap::new(),
+            allowed_cidrs: HashSet::new(),
+            denied_cidrs: HashSet::new(),
             memory_limit: None,
             store_limits: None,
+            runtime_memory_limit: None,
+            execution_timeout: None,
+            policy: None,
         }
     }
 }
@@ -182,6 +316,10 @@ pub fn create_wasi_state_template_from_policy(
     let network_perms = extract_network_perms(policy);
     let preopened_dirs = extract_storage_permissions(policy, plugin_dir)?;
     let allowed_hosts = extract_allowed_hosts(policy);
+    let denied_hosts = extract_denied_hosts(policy);
+    let allowed_ports = extract_allowed_ports(policy);
+    let allowed_cidrs = extract_allowed_cidrs(policy);
+    let denied_cidrs = extract_denied_cidrs(policy);
     let memory_limit = extract_memory_limit(policy)?;
     let store_limits = memory_limit
         .map(|limit| -> anyhow::Result<wasmtime::StoreLimits> {
@@ -193,14 +331,28 @@ pub fn create_wasi_state_template_from_policy(
                 .build())
         })
         .transpose()?;
+    let runtime_memory_limit = extract_runtime_memory_limit(policy)?;
+    let execution_timeout = policy
+        .permissions
+        .runtime
+        .as_ref()
+        .and_then(|runtime| runtime.execution_timeout_ms)
+        .map(Duration::from_millis);
 
     Ok(WasiStateTemplate {
         network_perms,
         config_vars: env_vars,
         preopened_dirs,
         allowed_hosts,
+        denied_hosts,
+        allowed_ports,
+        allowed_cidrs,
+        denied_cidrs,
         memory_limit,
         store_limits,
+        runtime_memory_limit,
+        execution_timeout,
+        policy: Some(Arc::new(policy.clone())),
         ..Default::default()
     })
 }
@@ -211,11 +363,22 @@ pub(crate) fn extract_env_vars(
 ) -> anyhow::Result<HashMap<String, String>> {
     let mut env_vars = HashMap::new();
     if let Some(env_perms) = &policy.permissions.environment {
-        if let Some(env_allow_vec) = &env_perms.allow {
-            for env_allow in env_allow_vec {
-                if let Some(value) = environment_vars.get(&env_allow.key) {
-                    env_vars.insert(env_allow.key.clone(), value.clone());
+        let allowed = env_perms.allow.as_deref().unwrap_or_default();
+        let denied = env_perms.deny.as_deref().unwrap_or_default();
+        for (key, value) in environment_vars {
+            // Keys can be patterns like `AWS_*`, which expose every matching variable. A variable
+            // allowed by its exact name is only hidden by a deny rule with its exact name
+            let allowed_exactly = allowed.iter().any(|allow| allow.key == *key);
+            let is_allowed = allowed_exactly || allowed.iter().any(|allow| allow.matches(key));
+            let is_denied = denied.iter().any(|deny| {
+                if allowed_exactly {
+                    deny.key == *key
+                } else {
+                    deny.matches(key)
                 }
+            });
+            if is_allowed && !is_denied {
+                env_vars.insert(key.clone(), value.clone());
             }
         }
     }
@@ -238,40 +401,134 @@ pub(crate) fn extract_network_perms(policy: &PolicyDocument) -> NetworkPermissio
 
 /// Extract allowed hosts from the policy document
 pub(crate) fn extract_allowed_hosts(policy: &PolicyDocument) -> HashSet<String> {
-    let mut allowed_hosts = HashSet::new();
+    policy
+        .permissions
+        .network
+        .as_ref()
+        .and_then(|network_perms| network_perms.allow.as_ref())
+        .map(|allow_list| extract_hosts(allow_list))
+        .unwrap_or_default()
+}
 
-    if let Some(network_perms) = &policy.permissions.network {
-        if let Some(allow_list) = &network_perms.allow {
-            for allow_entry in allow_list {
-                // The policy uses serde_json::Value, so we need to extract the host field
-                if let Ok(json_value) = serde_json::to_value(allow_entry) {
-                    if let Some(host) = json_value.get("host").and_then(|h| h.as_str()) {
-                        allowed_hosts.insert(host.to_string());
-                    }
-                }
+/// Extract denied hosts from the policy document
+pub(crate) fn extract_denied_hosts(policy: &PolicyDocument) -> HashSet<String> {
+    policy
+        .permissions
+        .network
+        .as_ref()
+        .and_then(|network_perms| network_perms.deny.as_ref())
+        .map(|deny_list| extract_hosts(deny_list))
+        .unwrap_or_default()
+}
+
+/// Extract allowed address ranges from the policy document
+pub(crate) fn extract_allowed_cidrs(policy: &PolicyDocument) -> HashSet<String> {
+    policy
+        .permissions
+        .network
+        .as_ref()
+        .and_then(|network_perms| network_perms.allow.as_ref())
+        .map(|allow_list| extract_cidrs(allow_list))
+        .unwrap_or_default()
+}
+
+/// Extract denied address ranges from the policy document
+pub(crate) fn extract_denied_cidrs(policy: &PolicyDocument) -> HashSet<String> {
+    policy
+        .permissions
+        .network
+        .as_ref()
+        .and_then(|network_perms| network_perms.deny.as_ref())
+        .map(|deny_list| extract_cidrs(deny_list))
+        .unwrap_or_default()
+}
+
+/// Extract the ports the allowed hosts are restricted to from the policy document. A host that is
+/// allowed several times is only restricted if every entry restricts it, to the union of the ports
+pub(crate) fn extract_allowed_ports(policy: &PolicyDocument) -> HashMap<String, Vec<PortRange>> {
+    let mut allowed_ports: HashMap<String, Vec<PortRange>> = HashMap::new();
+    let mut unrestricted = HashSet::new();
+    let allow_list = policy
+        .permissions
+        .network
+        .as_ref()
+        .and_then(|network_perms| network_perms.allow.as_deref())
+        .unwrap_or_default();
+    for entry in allow_list {
+        let NetworkPermission::Host(host) = entry else {
+            continue;
+        };
+        match &host.ports {
+            Some(ports) => allowed_ports
+                .entry(host.host.clone())
+                .or_default()
+                .extend(ports.iter().copied()),
+            None => {
+                unrestricted.insert(host.host.as_str());
             }
         }
     }
+    allowed_ports.retain(|host, _| !unrestricted.contains(host.as_str()));
+    allowed_ports
+}
 
-    allowed_hosts
+fn extract_hosts(entries: &[NetworkPermission]) -> HashSet<String> {
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            NetworkPermission::Host(host) => Some(host.host.clone()),
+            NetworkPermission::Cidr(_) => None,
+        })
+        .collect()
+}
+
+fn extract_cidrs(entries: &[NetworkPermission]) -> HashSet<String> {
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            NetworkPermission::Cidr(cidr) => Some(cidr.cidr.clone()),
+            NetworkPermission::Host(_) => None,
+        })
+        .collect()
 }
 
 pub(crate) fn extract_storage_permissions(
     policy: &PolicyDocument,
     plugin_dir: &Path,
 ) -> anyhow::Result<Vec<PreopenedDir>> {
-    let mut preopened_dirs = Vec::new();
+    let mut preopened_dirs: Vec<PreopenedDir> = Vec::new();
     if let Some(storage) = &policy.permissions.storage {
+        let deny = storage.deny.as_deref().unwrap_or_default();
         if let Some(allow) = &storage.allow {
             for storage_permission in allow {
-                if storage_permission.uri.starts_with("fs://") {
-                    let uri = storage_permission.uri.strip_prefix("fs://").unwrap();
-                    let path = Path::new(uri);
-                    let (file_perms, dir_perms) = calculate_permissions(&storage_permission.access);
-                    let guest_path = path.to_string_lossy().to_string();
-                    let host_path = plugin_dir.join(path);
+                let uri = STORAGE_URI_SCHEMES
+                    .iter()
+                    .find_map(|scheme| storage_permission.uri.strip_prefix(scheme));
+                if let Some(uri) = uri {
+                    let access = apply_storage_denies(storage_permission, deny)?;
+                    if access.is_empty() {
+                        continue;
+                    }
+                    let (guest_path, is_file) = storage_preopen_path(uri, plugin_dir)
+                        .with_context(|| {
+                            format!("Invalid storage URI '{}'", storage_permission.uri)
+                        })?;
+                    let (file_perms, mut dir_perms) = calculate_permissions(&access);
+                    // Only the file can be written, not the directory it is in
+                    if is_file {
+                        dir_perms = wasmtime_wasi::DirPerms::READ;
+                    }
+                    // Entries sharing a directory share its preopen, with the access of both
+                    if let Some(existing) = preopened_dirs
+                        .iter_mut()
+                        .find(|dir| dir.guest_path == guest_path)
+                    {
+                        existing.file_perms |= file_perms;
+                        existing.dir_perms |= dir_perms;
+                        continue;
+                    }
                     preopened_dirs.push(PreopenedDir {
-                        host_path,
+                        host_path: plugin_dir.join(&guest_path),
                         guest_path,
                         dir_perms,
                         file_perms,
@@ -283,6 +540,104 @@ pub(crate) fn extract_storage_permissions(
     Ok(preopened_dirs)
 }
 
+/// Returns the directory to preopen for the path of a storage URI, and whether the path is a
+/// single file. Directories can only be preopened as a whole, so a pattern like `work/agent/**`
+/// is preopened at the leading part without wildcards, `work/agent`, and the path of an existing
+/// file at its parent. Paths with `..` segments are rejected, since they could escape the
+/// directory they name.
+fn storage_preopen_path(path: &str, plugin_dir: &Path) -> anyhow::Result<(String, bool)> {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    if segments.contains(&"..") {
+        anyhow::bail!("'..' is not allowed in storage paths");
+    }
+    let literal: Vec<&str> = segments
+        .iter()
+        .copied()
+        .take_while(|segment| !segment.contains(['*', '?']))
+        .collect();
+    if literal.len() == segments.len() && plugin_dir.join(path).is_file() {
+        let parent = &literal[..literal.len() - 1];
+        return Ok((join_segments(parent), true));
+    }
+    Ok((join_segments(&literal), false))
+}
+
+/// Joins path segments split on `/`, where a leading empty segment stands for the root
+fn join_segments(segments: &[&str]) -> String {
+    match segments {
+        [] => ".".to_string(),
+        [""] => "/".to_string(),
+        segments => segments.join("/"),
+    }
+}
+
+/// Returns the access types of an allowed storage URI that remain after removing the access
+/// denied by every deny rule matching it. Fails if a deny rule only overlaps part of the allowed
+/// URI, because directories are preopened as a whole and such a rule can't be enforced.
+fn apply_storage_denies(
+    allowed: &StoragePermission,
+    deny: &[StoragePermission],
+) -> anyhow::Result<Vec<AccessType>> {
+    let allowed_prefix = literal_uri_prefix(&allowed.uri);
+    let mut access = allowed.access.clone();
+    for denied in deny {
+        if uri_pattern_matches(&denied.uri, &allowed.uri) {
+            access.retain(|a| !denied.access.contains(a));
+            continue;
+        }
+        let denied_prefix = literal_uri_prefix(&denied.uri);
+        if denied_prefix.starts_with(&allowed_prefix) || allowed_prefix.starts_with(&denied_prefix)
+        {
+            anyhow::bail!(
+                "Storage deny rule '{}' is nested inside allowed '{}' and can't be enforced",
+                denied.uri,
+                allowed.uri
+            );
+        }
+    }
+    Ok(access)
+}
+
+/// Returns the segments of a URI pattern before the first one containing a wildcard
+fn literal_uri_prefix(uri: &str) -> Vec<&str> {
+    uri.trim_end_matches('/')
+        .split('/')
+        .take_while(|segment| !segment.contains(['*', '?']))
+        .collect()
+}
+
+/// Matches a URI against a pattern where `*` matches a single path segment (or part of one) and
+/// `**` matches any number of segments
+fn uri_pattern_matches(pattern: &str, uri: &str) -> bool {
+    fn segments_match(pattern: &[&str], uri: &[&str]) -> bool {
+        match (pattern.split_first(), uri.split_first()) {
+            (None, None) => true,
+            (Some((&"**", rest)), _) => {
+                segments_match(rest, uri) || (!uri.is_empty() && segments_match(pattern, &uri[1..]))
+            }
+            (Some((p, pattern_rest)), Some((u, uri_rest))) => {
+                segment_matches(p, u) && segments_match(pattern_rest, uri_rest)
+            }
+            _ => false,
+        }
+    }
+
+    fn segment_matches(pattern: &str, segment: &str) -> bool {
+        match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                segment.len() >= prefix.len() + suffix.len()
+                    && segment.starts_with(prefix)
+                    && segment.ends_with(suffix)
+            }
+            None => pattern == segment,
+        }
+    }
+
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let uri: Vec<&str> = uri.trim_end_matches('/').split('/').collect();
+    segments_match(&pattern, &uri)
+}
+
 pub(crate) fn calculate_permissions(
     access_types: &[AccessType],
 ) -> (wasmtime_wasi::FilePerms, wasmtime_wasi::DirPerms) {
@@ -329,6 +684,27 @@ pub(crate) fn extract_memory_limit(policy: &PolicyDocument) -> anyhow::Result<Op
     Ok(None)
 }
 
+/// Extract the memory limit of `runtime.memory_limit_mb` in bytes from the policy document
+pub(crate) fn extract_runtime_memory_limit(
+    policy: &PolicyDocument,
+) -> anyhow::Result<Option<usize>> {
+    let Some(limit_mb) = policy
+        .permissions
+        .runtime
+        .as_ref()
+        .and_then(|runtime| runtime.memory_limit_mb)
+    else {
+        return Ok(None);
+    };
+    limit_mb
+        .checked_mul(1024 * 1024)
+        .and_then(|bytes| usize::try_from(bytes).ok())
+        .map(Some)
+        .ok_or_else(|| {
+            anyhow::anyhow!("Memory limit of {limit_mb} MiB too large for target architecture")
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use policy::{AccessType, PolicyParser};
@@ -366,8 +742,6 @@ permissions:
         access: ["write"]
       - uri: "fs://readwrite/path"
         access: ["read", "write"]
-      - uri: "http://not-fs"
-        access: ["read"]
 "#;
         PolicyParser::parse_str(yaml_content).unwrap()
     }
@@ -510,6 +884,71 @@ permissions:
         assert!(extracted_vars.is_empty());
     }
 
+    #[test]
+    fn test_extract_environment_variables_with_patterns() {
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy exposing a family of variables"
+permissions:
+  environment:
+    allow:
+      - key: "AWS_*"
+      - key: "HOME"
+    deny:
+      - key: "AWS_SECRET_*"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let env_vars = HashMap::from(
+            [
+                ("AWS_REGION", "eu-west-1"),
+                ("AWS_PROFILE", "dev"),
+                ("AWS_SECRET_ACCESS_KEY", "hunter2"),
+                ("HOME", "/home/user"),
+                ("MY_AWS_TOKEN", "token"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        let extracted_vars = extract_env_vars(&policy, &env_vars).unwrap();
+
+        let mut keys: Vec<_> = extracted_vars.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["AWS_PROFILE", "AWS_REGION", "HOME"]);
+    }
+
+    #[test]
+    fn test_extract_environment_variables_exact_keys_over_patterns() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  environment:
+    allow:
+      - key: "API_*"
+      - key: "INTERNAL_TOKEN"
+      - key: "INTERNAL_SECRET"
+    deny:
+      - key: "INTERNAL_*"
+      - key: "API_SECRET"
+      - key: "INTERNAL_SECRET"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let env_vars = HashMap::from(
+            [
+                ("API_KEY", "key"),
+                ("API_SECRET", "secret"),
+                ("INTERNAL_TOKEN", "token"),
+                ("INTERNAL_SECRET", "hunter2"),
+                ("INTERNAL_URL", "http://internal"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        let extracted_vars = extract_env_vars(&policy, &env_vars).unwrap();
+
+        // An exact allow wins over a deny pattern, but not over an exact deny
+        let mut keys: Vec<_> = extracted_vars.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["API_KEY", "INTERNAL_TOKEN"]);
+    }
+
     #[test]
     fn test_extract_network_permissions_with_allow() {
         let policy = create_test_policy();
@@ -547,6 +986,72 @@ permissions:
         assert!(!network_perms.allow_ip_name_lookup);
     }
 
+    #[test]
+    fn test_extract_allowed_ports() {
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy with port restrictions"
+permissions:
+  network:
+    allow:
+      - host: "api.example.com"
+        ports: [443]
+      - host: "api.example.com"
+        ports: ["8000-8080"]
+      - host: "cdn.example.com"
+      - host: "mixed.example.com"
+        ports: [443]
+      - host: "mixed.example.com"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let allowed_ports = extract_allowed_ports(&policy);
+
+        // Restrictions for the same host are merged, and an unrestricted entry lifts them
+        assert_eq!(
+            allowed_ports,
+            HashMap::from([(
+                "api.example.com".to_string(),
+                vec![PortRange::single(443), "8000-8080".parse().unwrap()]
+            )])
+        );
+        assert!(extract_allowed_ports(&create_test_policy()).is_empty());
+    }
+
+    #[test]
+    fn test_extract_cidrs() {
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy with address ranges"
+permissions:
+  network:
+    allow:
+      - host: "api.example.com"
+      - cidr: "10.0.0.0/8"
+      - cidr: "fd00::/8"
+    deny:
+      - cidr: "10.1.0.0/16"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let template =
+            create_wasi_state_template_from_policy(&policy, temp_dir.path(), &HashMap::new())
+                .unwrap();
+
+        assert_eq!(
+            template.allowed_hosts,
+            HashSet::from(["api.example.com".to_string()])
+        );
+        assert_eq!(
+            template.allowed_cidrs,
+            HashSet::from(["10.0.0.0/8".to_string(), "fd00::/8".to_string()])
+        );
+        assert_eq!(
+            template.denied_cidrs,
+            HashSet::from(["10.1.0.0/16".to_string()])
+        );
+        assert!(extract_allowed_cidrs(&create_test_policy()).is_empty());
+    }
+
     #[test]
     fn test_extract_storage_permissions() {
         let temp_dir = TempDir::new().unwrap();
@@ -584,21 +1089,124 @@ permissions:
     }
 
     #[test]
-    fn test_extract_storage_permissions_skips_non_fs_uri() {
+    fn test_extract_storage_permissions_schemes() {
         let temp_dir = TempDir::new().unwrap();
         let plugin_dir = temp_dir.path();
 
-        let policy = create_test_policy();
+        // Parsed without validation, which would reject the unknown scheme
+        let policy: PolicyDocument = serde_yaml::from_str(
+            r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://fs/path"
+        access: ["read"]
+      - uri: "file://file/path"
+        access: ["read"]
+      - uri: "http://not-fs"
+        access: ["read"]
+"#,
+        )
+        .unwrap();
         let preopened_dirs = extract_storage_permissions(&policy, plugin_dir).unwrap();
 
-        for dir in &preopened_dirs {
-            assert!(
-                dir.guest_path.starts_with("test/")
-                    || dir.guest_path.starts_with("write/")
-                    || dir.guest_path.starts_with("readwrite/")
-            );
+        let guest_paths: Vec<_> = preopened_dirs
+            .iter()
+            .map(|d| d.guest_path.as_str())
+            .collect();
+        assert_eq!(guest_paths, vec!["fs/path", "file/path"]);
+    }
+
+    #[test]
+    fn test_extract_storage_permissions_globs() {
+        let temp_dir = TempDir::new().unwrap();
+        let plugin_dir = temp_dir.path();
+        std::fs::create_dir_all(plugin_dir.join("settings")).unwrap();
+        std::fs::write(plugin_dir.join("settings/config.yaml"), "").unwrap();
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://work/agent/**"
+        access: ["read", "write"]
+      - uri: "fs://logs/*/today"
+        access: ["read"]
+      - uri: "fs://settings/config.yaml"
+        access: ["read"]
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let preopened_dirs = extract_storage_permissions(&policy, plugin_dir).unwrap();
+
+        let guest_paths: Vec<_> = preopened_dirs
+            .iter()
+            .map(|d| d.guest_path.as_str())
+            .collect();
+        assert_eq!(guest_paths, vec!["work/agent", "logs", "settings"]);
+        assert_eq!(preopened_dirs[0].host_path, plugin_dir.join("work/agent"));
+        assert!(preopened_dirs[0]
+            .dir_perms
+            .contains(wasmtime_wasi::DirPerms::MUTATE));
+
+        // Read-only entries can't be written, and neither can the directory of a single file
+        for read_only in &preopened_dirs[1..] {
+            assert_eq!(read_only.file_perms, wasmtime_wasi::FilePerms::READ);
+            assert_eq!(read_only.dir_perms, wasmtime_wasi::DirPerms::READ);
         }
-        assert_eq!(preopened_dirs.len(), 3);
+    }
+
+    #[test]
+    fn test_extract_storage_permissions_merges_shared_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let plugin_dir = temp_dir.path();
+        std::fs::create_dir_all(plugin_dir.join("work")).unwrap();
+        std::fs::write(plugin_dir.join("work/notes.txt"), "").unwrap();
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://work/notes.txt"
+        access: ["write"]
+      - uri: "fs://work/*"
+        access: ["read"]
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let preopened_dirs = extract_storage_permissions(&policy, plugin_dir).unwrap();
+
+        assert_eq!(preopened_dirs.len(), 1);
+        assert_eq!(preopened_dirs[0].guest_path, "work");
+        assert_eq!(
+            preopened_dirs[0].file_perms,
+            wasmtime_wasi::FilePerms::READ | wasmtime_wasi::FilePerms::WRITE
+        );
+        // Writing the file doesn't allow creating or removing files next to it
+        assert_eq!(preopened_dirs[0].dir_perms, wasmtime_wasi::DirPerms::READ);
+    }
+
+    #[test]
+    fn test_extract_storage_permissions_rejects_parent_segments() {
+        let temp_dir = TempDir::new().unwrap();
+        // Parsed without validation, so that only the preopen mapping checks the path
+        let policy: PolicyDocument = serde_yaml::from_str(
+            r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://work/../../etc"
+        access: ["read"]
+"#,
+        )
+        .unwrap();
+
+        let err = extract_storage_permissions(&policy, temp_dir.path())
+            .err()
+            .expect("the URI escapes the plugin directory");
+        assert!(err.to_string().contains("fs://work/../../etc"), "{err}");
     }
 
     #[test]
@@ -650,6 +1258,119 @@ permissions:
         );
     }
 
+    #[test]
+    fn test_deny_rules_win_over_overlapping_allows() {
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy with overlapping allow and deny rules"
+permissions:
+  network:
+    allow:
+      - host: "*.internal.myorg.com"
+    deny:
+      - host: "secrets.internal.myorg.com"
+  environment:
+    allow:
+      - key: "API_URL"
+      - key: "API_TOKEN"
+    deny:
+      - key: "API_TOKEN"
+  storage:
+    allow:
+      - uri: "fs://data/reports"
+        access: ["read", "write"]
+      - uri: "fs://data/keys"
+        access: ["read"]
+    deny:
+      - uri: "fs://data/*"
+        access: ["write"]
+      - uri: "fs://data/keys"
+        access: ["read"]
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        policy.validate().unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let env_vars = HashMap::from([
+            ("API_URL".to_string(), "https://api".to_string()),
+            ("API_TOKEN".to_string(), "secret".to_string()),
+        ]);
+        let template =
+            create_wasi_state_template_from_policy(&policy, temp_dir.path(), &env_vars).unwrap();
+
+        assert!(template.allowed_hosts.contains("*.internal.myorg.com"));
+        assert_eq!(
+            template.denied_hosts,
+            HashSet::from(["secrets.internal.myorg.com".to_string()])
+        );
+
+        assert_eq!(template.config_vars.len(), 1);
+        assert!(template.config_vars.contains_key("API_URL"));
+
+        // Write is denied for everything under data, and keys is denied entirely
+        assert_eq!(template.preopened_dirs.len(), 1);
+        assert_eq!(template.preopened_dirs[0].guest_path, "data/reports");
+        assert_eq!(
+            template.preopened_dirs[0].file_perms,
+            wasmtime_wasi::FilePerms::READ
+        );
+    }
+
+    #[test]
+    fn test_nested_storage_deny_is_rejected() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://data"
+        access: ["read"]
+    deny:
+      - uri: "fs://data/secrets"
+        access: ["read"]
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+
+        let err = extract_storage_permissions(&policy, temp_dir.path())
+            .err()
+            .expect("the deny can't be enforced");
+        assert!(err.to_string().contains("can't be enforced"));
+    }
+
+    #[test]
+    fn test_storage_deny_inside_glob_allow_is_rejected() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://data/**"
+        access: ["read"]
+    deny:
+      - uri: "fs://data/secrets"
+        access: ["read"]
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+
+        let err = extract_storage_permissions(&policy, temp_dir.path())
+            .err()
+            .expect("the deny can't be enforced");
+        assert!(err.to_string().contains("can't be enforced"));
+    }
+
+    #[test]
+    fn test_uri_pattern_matches() {
+        assert!(uri_pattern_matches("fs://data/keys", "fs://data/keys"));
+        assert!(uri_pattern_matches("fs://data/*", "fs://data/keys"));
+        assert!(uri_pattern_matches("fs://data/*.pem", "fs://data/key.pem"));
+        assert!(uri_pattern_matches("fs://data/**", "fs://data/a/b/c"));
+        assert!(uri_pattern_matches("fs://**/keys", "fs://data/a/keys"));
+        assert!(!uri_pattern_matches("fs://data/*", "fs://data/a/b"));
+        assert!(!uri_pattern_matches("fs://data/keys", "fs://data/keys2"));
+    }
+
     #[test]
     fn test_create_wasi_state_template_from_policy() {
         let temp_dir = TempDir::new().unwrap();
@@ -687,6 +1408,46 @@ permissions:
         assert_eq!(template.memory_limit, None);
     }
 
+    #[test]
+    fn test_create_wasi_state_template_environment_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+description: "Environment only policy"
+permissions:
+  environment:
+    allow:
+      - key: "CONFIG_VALUE"
+"#,
+        )
+        .unwrap();
+        let env_vars = HashMap::from([
+            ("CONFIG_VALUE".to_string(), "value".to_string()),
+            ("SECRET".to_string(), "secret".to_string()),
+        ]);
+
+        let template =
+            create_wasi_state_template_from_policy(&policy, temp_dir.path(), &env_vars).unwrap();
+
+        // Only the granted config value is exposed, and nothing else is
+        assert_eq!(template.config_vars.len(), 1);
+        assert_eq!(template.config_vars["CONFIG_VALUE"], "value");
+        assert!(template.preopened_dirs.is_empty());
+        assert!(template.allowed_hosts.is_empty());
+        assert!(!template.network_perms.allow_tcp);
+        assert!(!template.network_perms.allow_udp);
+        assert!(!template.network_perms.allow_ip_name_lookup);
+
+        // The narrowest sandbox must still produce a usable WASI state, linked without HTTP and
+        // sockets
+        assert!(template.grants_only_environment());
+        assert!(template.build().unwrap().environment_only);
+
+        // Components without a policy keep the full set of interfaces
+        assert!(!WasiStateTemplate::default().grants_only_environment());
+    }
+
     #[test]
     fn test_extract_memory_limit() {
         // Test with k8s-style memory limit
@@ -742,6 +1503,35 @@ permissions:
         assert!(template.store_limits.is_some());
     }
 
+    #[test]
+    fn test_memory_limits_apply_to_all_memories_together() {
+        use wasmtime::ResourceLimiter;
+
+        let mut limiter =
+            CustomResourceLimiter::new(wasmtime::StoreLimits::default()).with_memory_cap(3 * 65536);
+        // Two memories of one page each, then the first grows to two pages
+        assert!(limiter.memory_growing(0, 65536, None).unwrap());
+        assert!(limiter.memory_growing(0, 65536, None).unwrap());
+        assert!(limiter.memory_growing(65536, 2 * 65536, None).unwrap());
+        // Each memory is under the cap, but growing the second brings the total over it
+        let err = limiter
+            .memory_growing(65536, 2 * 65536, None)
+            .expect_err("the total is over the cap");
+        assert!(err.to_string().contains("total to 262144 bytes"), "{err}");
+
+        let mut limiter = CustomResourceLimiter::new(wasmtime::StoreLimits::default())
+            .with_policy_memory_limit(65536);
+        assert!(limiter.memory_growing(0, 65536, None).unwrap());
+        let err = limiter.memory_growing(0, 65536, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PolicyViolation>(),
+            Some(PolicyViolation::MemoryLimit {
+                limit_bytes: 65536,
+                requested_bytes: 131072
+            })
+        ));
+    }
+
     #[test]
     fn test_memory_resource_end_to_end() -> anyhow::Result<()> {
         let temp_dir = TempDir::new().unwrap();
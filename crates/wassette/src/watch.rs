@@ -0,0 +1,191 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Hot reloading of the components in the plugin directory
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::{LifecycleManager, DOWNLOADS_DIR};
+
+/// How long the plugin directory has to be quiet before changes are applied, so that a file that
+/// is still being written is only loaded once
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Watches the plugin directory until dropped, see [`LifecycleManager::watch`]
+pub struct PluginDirWatcher {
+    _watcher: RecommendedWatcher,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for PluginDirWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl LifecycleManager {
+    /// Watches the plugin directory and loads the `.wasm` files created or modified in it, and
+    /// unloads the components whose file is removed. Policy files are left alone.
+    ///
+    /// Events are debounced, and `on_change` is called after every batch that changed the set of
+    /// loaded components, e.g. to send a `notifications/tools/list_changed` to clients. Files in
+    /// the `downloads` staging directory are ignored, and so are components written by
+    /// [`LifecycleManager::load_component`], which are already loaded.
+    ///
+    /// Must be called from within a tokio runtime. Watching stops when the returned
+    /// [`PluginDirWatcher`] is dropped.
+    pub fn watch(&self, on_change: impl Fn() + Send + Sync + 'static) -> Result<PluginDirWatcher> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            // The receiver is only gone once the watcher is being dropped
+            let _ = tx.send(event);
+        })
+        .context("Failed to create plugin directory watcher")?;
+        watcher
+            .watch(&self.plugin_dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", self.plugin_dir.display()))?;
+
+        let manager = self.clone();
+        let task = tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let mut changed = BTreeSet::new();
+                manager.collect_changes(event, &mut changed);
+                // Keep collecting until no event came in for a while
+                while let Ok(Some(event)) = tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await {
+                    manager.collect_changes(event, &mut changed);
+                }
+                if !changed.is_empty() && manager.apply_changes(changed).await {
+                    on_change();
+                }
+            }
+        });
+
+        info!(plugin_dir = %self.plugin_dir.display(), "Watching plugin directory");
+        Ok(PluginDirWatcher {
+            _watcher: watcher,
+            task,
+        })
+    }
+
+    /// Adds the component files touched by `event` to `changed`, as paths in the plugin directory
+    fn collect_changes(&self, event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                warn!(error = %e, "Error watching plugin directory");
+                return;
+            }
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in event.paths {
+            let in_downloads = path
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == DOWNLOADS_DIR);
+            let is_wasm = path.extension().is_some_and(|ext| ext == "wasm");
+            if in_downloads || !is_wasm {
+                continue;
+            }
+            // The watcher may report canonicalized paths, so rebuild them from the plugin dir
+            if let Some(file_name) = path.file_name() {
+                changed.insert(self.plugin_dir.join(file_name));
+            }
+        }
+    }
+
+    /// Loads, reloads or unloads the components of the given files. Returns whether any component
+    /// was loaded or unloaded.
+    async fn apply_changes(&self, changed: BTreeSet<PathBuf>) -> bool {
+        let mut any_changed = false;
+        for path in changed {
+            let Some(component_id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !path.is_file() {
+                if self.forget_component(component_id).await {
+                    info!(component_id, "Component file removed, unloaded component");
+                    any_changed = true;
+                }
+                continue;
+            }
+            let result = match self.prepare_component(component_id, &path, None).await {
+                Ok(Some(prepared)) => self.register_prepared(component_id, prepared).await,
+                Ok(None) => {
+                    debug!(component_id, "Component unchanged, skipping");
+                    continue;
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => any_changed = true,
+                Err(e) => warn!(component_id, error = %e, "Failed to reload component"),
+            }
+        }
+        any_changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use test_log::test;
+
+    use super::*;
+    use crate::test_fixtures::*;
+
+    /// Waits up to five seconds for the loaded components to be `expected`
+    async fn wait_for_components(manager: &LifecycleManager, expected: &[&str]) {
+        for _ in 0..100 {
+            let mut ids = manager.list_components().await;
+            ids.sort();
+            if ids == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!(
+            "expected components {expected:?}, found {:?}",
+            manager.list_components().await
+        );
+    }
+
+    #[test(tokio::test(flavor = "multi_thread"))]
+    async fn test_watch_loads_and_unloads_components() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new(&tempdir).await?;
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counter = changes.clone();
+        let _watcher = manager.watch(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })?;
+
+        tokio::fs::write(tempdir.path().join("answer.wasm"), ANSWER_WAT).await?;
+        // Files being downloaded are not components yet
+        tokio::fs::write(
+            tempdir.path().join(DOWNLOADS_DIR).join("staged.wasm"),
+            ANSWER_WAT,
+        )
+        .await?;
+        wait_for_components(&manager, &["answer"]).await;
+        assert!(manager.get_component_id_for_tool("answer").await.is_ok());
+        assert!(changes.load(Ordering::SeqCst) >= 1);
+
+        let before_removal = changes.load(Ordering::SeqCst);
+        tokio::fs::remove_file(tempdir.path().join("answer.wasm")).await?;
+        wait_for_components(&manager, &[]).await;
+        assert!(changes.load(Ordering::SeqCst) > before_removal);
+
+        Ok(())
+    }
+}
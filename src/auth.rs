@@ -0,0 +1,116 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! API key authentication for the HTTP transports
+
+use std::sync::Arc;
+
+use axum::extract::Request;
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+
+/// Returns `router` with every request rejected with `401 Unauthorized`, unless it has an
+/// `Authorization: Bearer <key>` header with one of `api_keys`. Without API keys, the router is
+/// returned unchanged and accepts every request.
+pub(crate) fn require_api_key(router: Router, api_keys: Arc<Vec<String>>) -> Router {
+    if api_keys.is_empty() {
+        return router;
+    }
+    router.layer(middleware::from_fn(move |request: Request, next: Next| {
+        let api_keys = api_keys.clone();
+        async move {
+            let authorized = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|key| api_keys.iter().any(|api_key| keys_equal(api_key, key)));
+            if authorized {
+                next.run(request).await
+            } else {
+                tracing::warn!(uri = %request.uri(), "Rejecting request without a valid API key");
+                unauthorized()
+            }
+        }
+    }))
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        "A valid API key is required",
+    )
+        .into_response()
+}
+
+/// Compares two keys in a time that only depends on their lengths, so that a key can't be
+/// guessed byte by byte from how long the comparison takes
+fn keys_equal(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+
+    use super::*;
+
+    /// Serves `router` on a free port, returning its URL
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{address}/mcp")
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_an_api_key() {
+        let router = Router::new().route("/mcp", get(|| async { "ok" }));
+        let url = serve(require_api_key(
+            router,
+            Arc::new(vec!["first-key".to_string(), "second-key".to_string()]),
+        ))
+        .await;
+        let client = reqwest::Client::new();
+
+        for key in ["first-key", "second-key"] {
+            let response = client.get(&url).bearer_auth(key).send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            assert_eq!(response.text().await.unwrap(), "ok");
+        }
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+        for authorization in ["Bearer wrong-key", "Bearer first-ke", "Basic first-key"] {
+            let response = client
+                .get(&url)
+                .header("authorization", authorization)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status(),
+                reqwest::StatusCode::UNAUTHORIZED,
+                "{authorization}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_api_keys_accepts_every_request() {
+        let router = Router::new().route("/mcp", get(|| async { "ok" }));
+        let url = serve(require_api_key(router, Arc::default())).await;
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+}
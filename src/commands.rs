@@ -77,22 +77,61 @@ pub struct Serve {
     #[arg(long = "env-file")]
     #[serde(skip)]
     pub env_file: Option<PathBuf>,
+
+    /// Load and unload components as their files are added to or removed from the plugin
+    /// directory
+    #[arg(long)]
+    #[serde(skip)]
+    pub watch: bool,
+
+    /// Require requests to the SSE and streamable HTTP transports to have an
+    /// `Authorization: Bearer <key>` header with this key. Can be specified multiple times to
+    /// accept several keys. Prefer setting `WASSETTE_API_KEYS` to a comma-separated list of keys,
+    /// since the command line is visible to every local user.
+    #[arg(
+        long = "api-key",
+        env = "WASSETTE_API_KEYS",
+        value_delimiter = ',',
+        hide_env_values = true
+    )]
+    #[serde(skip)]
+    pub api_keys: Vec<String>,
+
+    /// Log every tool call and its result to this file, with the time it happened. The log is
+    /// rotated when it grows past 10 MiB.
+    #[arg(long = "audit-log")]
+    #[serde(skip)]
+    pub audit_log: Option<PathBuf>,
+
+    /// Stream the strings returned by tools with a `list<string>` result as progress
+    /// notifications, to clients that send a progress token with their tool calls
+    #[arg(long)]
+    #[serde(skip)]
+    pub stream_results: bool,
+
+    /// Export a trace span for every component call to this OTLP/HTTP endpoint, e.g.
+    /// `http://localhost:4318/v1/traces`
+    #[arg(long = "otlp-endpoint")]
+    #[serde(skip)]
+    pub otlp_endpoint: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum ComponentCommands {
-    /// Load a WebAssembly component from a file path or OCI registry.
+    /// Load WebAssembly components from file paths or OCI registries.
     Load {
-        /// Path to the component (file:// or oci://)
-        path: String,
+        /// Paths to the components (file:// or oci://)
+        #[arg(required = true)]
+        paths: Vec<String>,
         /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
         #[arg(long)]
         plugin_dir: Option<PathBuf>,
     },
-    /// Unload a WebAssembly component.
+    /// Unload WebAssembly components.
     Unload {
-        /// Component ID to unload
-        id: String,
+        /// IDs of the components to unload
+        #[arg(required = true)]
+        ids: Vec<String>,
         /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
         #[arg(long)]
         plugin_dir: Option<PathBuf>,
@@ -105,6 +144,9 @@ pub enum ComponentCommands {
         /// Output format
         #[arg(short = 'o', long = "output-format", default_value = "json")]
         output_format: OutputFormat,
+        /// Show the size, SHA-256 hash and load time of the components in table format
+        #[arg(short = 'v', long)]
+        verbose: bool,
     },
 }
 
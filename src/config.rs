@@ -119,6 +119,11 @@ mod tests {
             streamable_http: false,
             env_vars: vec![],
             env_file: None,
+            watch: false,
+            api_keys: vec![],
+            audit_log: None,
+            stream_results: false,
+            otlp_endpoint: None,
         }
     }
 
@@ -130,6 +135,11 @@ mod tests {
             streamable_http: false,
             env_vars: vec![],
             env_file: None,
+            watch: false,
+            api_keys: vec![],
+            audit_log: None,
+            stream_results: false,
+            otlp_endpoint: None,
         }
     }
 
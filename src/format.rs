@@ -9,9 +9,10 @@ use rmcp::model::CallToolResult;
 use serde_json::{Map, Value};
 
 /// Output format options for CLI commands
-#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
 pub enum OutputFormat {
     /// JSON format
+    #[default]
     Json,
     /// YAML format
     Yaml,
@@ -19,25 +20,29 @@ pub enum OutputFormat {
     Table,
 }
 
-impl Default for OutputFormat {
-    fn default() -> Self {
-        Self::Json
-    }
-}
-
 /// Format a JSON value as YAML string
 pub fn format_as_yaml(value: &Value) -> Result<String> {
     serde_yaml::to_string(value).map_err(|e| anyhow::anyhow!("Failed to format as YAML: {}", e))
 }
 
-/// Format a JSON value as a table string
-pub fn format_as_table(value: &Value) -> Result<String> {
+/// Format a JSON value as a table string. With `details`, component lists also show the size,
+/// SHA-256 hash and load time of every component
+pub fn format_as_table_with_details(value: &Value, details: bool) -> Result<String> {
     // Check if this is a component list output
     if let Some(obj) = value.as_object() {
         if let Some(components) = obj.get("components").and_then(|v| v.as_array()) {
             let mut table = String::new();
-            table.push_str("ID                    | Tools Count\n");
-            table.push_str("----------------------|-------------\n");
+            if details {
+                table.push_str(
+                    "ID                    | Tools Count | Size (bytes) | Loaded At  | SHA-256\n",
+                );
+                table.push_str(
+                    "----------------------|-------------|--------------|------------|--------\n",
+                );
+            } else {
+                table.push_str("ID                    | Tools Count\n");
+                table.push_str("----------------------|-------------\n");
+            }
 
             for component in components {
                 if let Some(comp_obj) = component.as_object() {
@@ -49,7 +54,25 @@ pub fn format_as_table(value: &Value) -> Result<String> {
                         .get("tools_count")
                         .and_then(|v| v.as_u64())
                         .unwrap_or(0);
-                    table.push_str(&format!("{id:<21} | {tools_count}\n"));
+                    if details {
+                        let size = comp_obj
+                            .get("wasm_size_bytes")
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or(0);
+                        let loaded_at = comp_obj
+                            .get("loaded_at")
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or(0);
+                        let sha256 = comp_obj
+                            .get("sha256")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("unknown");
+                        table.push_str(&format!(
+                            "{id:<21} | {tools_count:<11} | {size:<12} | {loaded_at:<10} | {sha256}\n"
+                        ));
+                    } else {
+                        table.push_str(&format!("{id:<21} | {tools_count}\n"));
+                    }
                 }
             }
             return Ok(table);
@@ -85,6 +108,16 @@ pub fn format_as_table(value: &Value) -> Result<String> {
 
 /// Print the result of a tool call with the specified format
 pub fn print_result(result: &CallToolResult, output_format: OutputFormat) -> Result<()> {
+    print_result_with_details(result, output_format, false)
+}
+
+/// Print the result of a tool call with the specified format, see
+/// [`format_as_table_with_details`] for what `details` shows
+pub fn print_result_with_details(
+    result: &CallToolResult,
+    output_format: OutputFormat,
+    details: bool,
+) -> Result<()> {
     if let Some(contents) = &result.content {
         for content in contents {
             // Check if we can get text content from the annotated content
@@ -102,7 +135,7 @@ pub fn print_result(result: &CallToolResult, output_format: OutputFormat) -> Res
                         }
                         OutputFormat::Table => {
                             // Format as table
-                            println!("{}", format_as_table(&json_value)?);
+                            println!("{}", format_as_table_with_details(&json_value, details)?);
                         }
                     }
                 } else {
@@ -118,3 +151,86 @@ pub fn print_result(result: &CallToolResult, output_format: OutputFormat) -> Res
 
     Ok(())
 }
+
+/// Format the outcome of a batch operation as a table with one row per item
+pub fn format_batch_results(results: &[(String, Result<String>)]) -> String {
+    let width = results
+        .iter()
+        .map(|(item, _)| item.len())
+        .max()
+        .unwrap_or(0)
+        .max("Item".len());
+
+    let mut table = String::new();
+    table.push_str(&format!("{:<width$} | Status | Details\n", "Item"));
+    table.push_str(&format!("{}-|--------|--------\n", "-".repeat(width)));
+    for (item, result) in results {
+        let (status, details) = match result {
+            Ok(details) => ("ok", details.clone()),
+            Err(e) => ("failed", format!("{e:#}")),
+        };
+        table.push_str(&format!("{item:<width$} | {status:<6} | {details}\n"));
+    }
+    table
+}
+
+/// Print the outcome of a batch operation as a table, returning an error if any item failed
+pub fn print_batch_results(results: &[(String, Result<String>)]) -> Result<()> {
+    println!("{}", format_batch_results(results));
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} operations failed", results.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_component_table_with_details() {
+        let value = serde_json::json!({
+            "components": [{
+                "id": "fetch_rs",
+                "tools_count": 1,
+                "wasm_size_bytes": 1024,
+                "loaded_at": 1640995200,
+                "sha256": "ab12"
+            }]
+        });
+
+        let table = format_as_table_with_details(&value, false).unwrap();
+        assert_eq!(table.lines().nth(2), Some("fetch_rs              | 1"));
+
+        let table = format_as_table_with_details(&value, true).unwrap();
+        assert_eq!(
+            table.lines().nth(2),
+            Some("fetch_rs              | 1           | 1024         | 1640995200 | ab12")
+        );
+    }
+
+    #[test]
+    fn test_format_batch_results() {
+        let results = vec![
+            ("file:///a.wasm".to_string(), Ok("a (new)".to_string())),
+            (
+                "file:///b.wasm".to_string(),
+                Err(anyhow::anyhow!("not a component")),
+            ),
+        ];
+        let table = format_batch_results(&results);
+        let lines: Vec<_> = table.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "Item           | Status | Details");
+        assert_eq!(lines[2], "file:///a.wasm | ok     | a (new)");
+        assert_eq!(lines[3], "file:///b.wasm | failed | not a component");
+
+        // A failed item is reported to the caller instead of exiting
+        let err = print_batch_results(&results).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 operations failed");
+        assert!(print_batch_results(&results[..1]).is_ok());
+    }
+}
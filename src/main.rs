@@ -9,6 +9,7 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
@@ -17,31 +18,37 @@ use mcp_server::components::{
 };
 use mcp_server::tools::*;
 use mcp_server::{
-    handle_prompts_list, handle_resources_list, handle_tools_call, handle_tools_list,
-    LifecycleManager,
+    handle_prompts_list, handle_resources_list, handle_resources_read, handle_tools_call,
+    handle_tools_list, AuditLogMiddleware, ChunkSink, LifecycleManager, ProgressNotifier,
+    ToolCallMiddleware,
 };
 use rmcp::model::{
-    CallToolRequestParam, CallToolResult, ErrorData, ListPromptsResult, ListResourcesResult,
-    ListToolsResult, PaginatedRequestParam, ServerCapabilities, ServerInfo, ToolsCapability,
+    CallToolRequestParam, CallToolResult, Content, ErrorData, ListPromptsResult,
+    ListResourcesResult, ListToolsResult, PaginatedRequestParam, ReadResourceRequestParam,
+    ReadResourceResult, ResourcesCapability, ServerCapabilities, ServerInfo, ToolsCapability,
 };
-use rmcp::service::{serve_server, RequestContext, RoleServer};
+use rmcp::service::{serve_server, NotificationContext, RequestContext, RoleServer};
+use rmcp::transport::sse_server::SseServerConfig;
 use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
 use rmcp::transport::streamable_http_server::StreamableHttpService;
 use rmcp::transport::{stdio as stdio_transport, SseServer};
-use rmcp::ServerHandler;
+use rmcp::{Peer, ServerHandler};
 use serde_json::{json, Map, Value};
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
 
+mod auth;
 mod commands;
 mod config;
 mod format;
+mod metrics;
+mod telemetry;
 
 use commands::{
     Cli, Commands, ComponentCommands, GrantPermissionCommands, PermissionCommands, PolicyCommands,
     RevokePermissionCommands, Serve,
 };
-use format::{print_result, OutputFormat};
+use format::{print_batch_results, print_result, print_result_with_details, OutputFormat};
 
 /// Represents the different types of tools available in the MCP server
 #[derive(Debug, Clone, PartialEq)]
@@ -192,6 +199,29 @@ const BIND_ADDRESS: &str = "127.0.0.1:9001";
 #[derive(Clone)]
 pub struct McpServer {
     lifecycle_manager: LifecycleManager,
+    /// The connected clients, which are told when the tools change
+    peers: Arc<Mutex<Vec<Peer<RoleServer>>>>,
+    /// The API keys accepted by the HTTP transports. Every request is accepted if there are none
+    api_keys: Arc<Vec<String>>,
+    /// Called in order before and after every tool call
+    middleware: Arc<Vec<Box<dyn ToolCallMiddleware + Send + Sync>>>,
+    /// Whether `list<string>` results are streamed to clients that ask for progress notifications
+    stream_results: bool,
+}
+
+/// Sends a `notifications/tools/list_changed` to every connected client, forgetting the clients
+/// that have disconnected
+fn notify_peers_tools_changed(peers: &Mutex<Vec<Peer<RoleServer>>>) {
+    let mut peers = peers.lock().unwrap();
+    peers.retain(|peer| !peer.is_transport_closed());
+    for peer in peers.iter() {
+        let peer = peer.clone();
+        tokio::spawn(async move {
+            if let Err(e) = peer.notify_tool_list_changed().await {
+                tracing::error!(error = %e, "Failed to send tool list change notification");
+            }
+        });
+    }
 }
 
 /// Handle CLI tool commands by creating appropriate tool call requests
@@ -208,7 +238,7 @@ async fn handle_tool_cli_command(
         arguments: Some(args),
     };
 
-    let result = match tool {
+    let mut result = match tool {
         ToolName::LoadComponent => handle_load_component_cli(&req, lifecycle_manager).await?,
         ToolName::UnloadComponent => handle_unload_component_cli(&req, lifecycle_manager).await?,
         ToolName::ListComponents => handle_list_components(lifecycle_manager).await?,
@@ -237,6 +267,12 @@ async fn handle_tool_cli_command(
         ToolName::ResetPermission => handle_reset_permission(&req, lifecycle_manager).await?,
     };
 
+    // The text content of a result with structured content only summarizes it, so print the
+    // structured content in the requested format instead
+    if let Some(structured) = &result.structured_content {
+        result.content = Some(vec![Content::text(serde_json::to_string(structured)?)]);
+    }
+
     // Print the result using the format module
     print_result(&result, output_format)?;
 
@@ -263,6 +299,11 @@ async fn create_lifecycle_manager(plugin_dir: Option<PathBuf>) -> Result<Lifecyc
             streamable_http: false,
             env_vars: vec![],
             env_file: None,
+            watch: false,
+            api_keys: vec![],
+            audit_log: None,
+            stream_results: false,
+            otlp_endpoint: None,
         })
         .context("Failed to load configuration")?
     };
@@ -276,7 +317,54 @@ impl McpServer {
     /// # Arguments
     /// * `lifecycle_manager` - The lifecycle manager for handling component operations
     pub fn new(lifecycle_manager: LifecycleManager) -> Self {
-        Self { lifecycle_manager }
+        let peers: Arc<Mutex<Vec<Peer<RoleServer>>>> = Arc::default();
+        lifecycle_manager.subscribe_tools_changed({
+            let peers = peers.clone();
+            move || notify_peers_tools_changed(&peers)
+        });
+        Self {
+            lifecycle_manager,
+            peers,
+            api_keys: Arc::default(),
+            middleware: Arc::default(),
+            stream_results: false,
+        }
+    }
+
+    /// Streams the strings returned by tools with a `list<string>` result as
+    /// `notifications/progress`, one per string, and returns an empty result instead. Only calls
+    /// with a `progressToken` in their `_meta` are streamed, the others get the whole list.
+    pub fn with_streamed_results(mut self, stream_results: bool) -> Self {
+        self.stream_results = stream_results;
+        self
+    }
+
+    /// Runs `middleware` in order before and after every tool call, replacing any middleware set
+    /// before
+    pub fn with_middleware(
+        mut self,
+        middleware: Vec<Box<dyn ToolCallMiddleware + Send + Sync>>,
+    ) -> Self {
+        self.middleware = Arc::new(middleware);
+        self
+    }
+
+    /// Requires requests to the SSE and streamable HTTP transports to have an
+    /// `Authorization: Bearer <key>` header with one of `api_keys`, and rejects the others with
+    /// `401 Unauthorized`. The stdio transport is local and isn't authenticated.
+    pub fn with_auth(mut self, api_keys: Vec<String>) -> Self {
+        self.api_keys = Arc::new(api_keys);
+        self
+    }
+
+    /// Applies the authentication configured with [`McpServer::with_auth`] to an HTTP transport
+    fn authenticate(&self, router: axum::Router) -> axum::Router {
+        auth::require_api_key(router, self.api_keys.clone())
+    }
+
+    /// Tells every connected client that the tools changed
+    fn notify_tools_changed(&self) {
+        notify_peers_tools_changed(&self.peers);
     }
 }
 
@@ -288,6 +376,7 @@ impl ServerHandler for McpServer {
                 tools: Some(ToolsCapability {
                     list_changed: Some(true),
                 }),
+                resources: Some(ResourcesCapability::default()),
                 ..Default::default()
             },
             instructions: Some(
@@ -306,15 +395,39 @@ Key points:
         }
     }
 
+    fn on_initialized<'a>(
+        &'a self,
+        context: NotificationContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            tracing::info!("Client initialized");
+            self.peers.lock().unwrap().push(context.peer);
+        })
+    }
+
     fn call_tool<'a>(
         &'a self,
         params: CallToolRequestParam,
         ctx: RequestContext<RoleServer>,
     ) -> Pin<Box<dyn Future<Output = Result<CallToolResult, ErrorData>> + Send + 'a>> {
-        let peer_clone = ctx.peer.clone();
-
         Box::pin(async move {
-            let result = handle_tools_call(params, &self.lifecycle_manager, peer_clone).await;
+            let caller_context = telemetry::caller_context(&ctx.extensions);
+            let progress_notifier = ctx
+                .meta
+                .get_progress_token()
+                .filter(|_| self.stream_results)
+                .map(|token| ProgressNotifier::new(ctx.peer.clone(), token));
+            let call = handle_tools_call(
+                params,
+                &self.lifecycle_manager,
+                &self.middleware,
+                progress_notifier
+                    .as_ref()
+                    .map(|notifier| notifier as &dyn ChunkSink),
+            );
+            // `anyhow::Context` has a `with_context` too, so the extension trait isn't imported
+            let result =
+                opentelemetry::context::FutureExt::with_context(call, caller_context).await;
             match result {
                 Ok(value) => serde_json::from_value(value).map_err(|e| {
                     ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
@@ -326,11 +439,12 @@ Key points:
 
     fn list_tools<'a>(
         &'a self,
-        _params: Option<PaginatedRequestParam>,
+        params: Option<PaginatedRequestParam>,
         _ctx: RequestContext<RoleServer>,
     ) -> Pin<Box<dyn Future<Output = Result<ListToolsResult, ErrorData>> + Send + 'a>> {
         Box::pin(async move {
-            let result = handle_tools_list(&self.lifecycle_manager).await;
+            let cursor = params.and_then(|params| params.cursor);
+            let result = handle_tools_list(&self.lifecycle_manager, cursor).await;
             match result {
                 Ok(value) => serde_json::from_value(value).map_err(|e| {
                     ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
@@ -362,7 +476,7 @@ Key points:
         _ctx: RequestContext<RoleServer>,
     ) -> Pin<Box<dyn Future<Output = Result<ListResourcesResult, ErrorData>> + Send + 'a>> {
         Box::pin(async move {
-            let result = handle_resources_list(serde_json::Value::Null).await;
+            let result = handle_resources_list(&self.lifecycle_manager).await;
             match result {
                 Ok(value) => serde_json::from_value(value).map_err(|e| {
                     ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
@@ -371,6 +485,22 @@ Key points:
             }
         })
     }
+
+    fn read_resource<'a>(
+        &'a self,
+        params: ReadResourceRequestParam,
+        _ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<ReadResourceResult, ErrorData>> + Send + 'a>> {
+        Box::pin(async move {
+            let result = handle_resources_read(&params.uri, &self.lifecycle_manager).await;
+            match result {
+                Ok(value) => serde_json::from_value(value).map_err(|e| {
+                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
+                }),
+                Err(err) => Err(ErrorData::resource_not_found(err.to_string(), None)),
+            }
+        })
+    }
 }
 
 /// Formats build information similar to agentgateway's version output
@@ -466,11 +596,36 @@ async fn main() -> Result<()> {
                 let config =
                     config::Config::from_serve(cfg).context("Failed to load configuration")?;
 
-                let lifecycle_manager =
-                    LifecycleManager::new_with_env(&config.plugin_dir, config.environment_vars)
-                        .await?;
+                let tracer_provider = cfg
+                    .otlp_endpoint
+                    .as_deref()
+                    .map(telemetry::init_tracer_provider)
+                    .transpose()?;
+                let mut builder = LifecycleManager::builder(&config.plugin_dir)
+                    .with_environment_vars(config.environment_vars);
+                if let Some(tracer_provider) = &tracer_provider {
+                    builder = builder.with_tracer_provider(tracer_provider);
+                }
+                let lifecycle_manager = builder.build().await?;
 
-                let server = McpServer::new(lifecycle_manager);
+                let mut middleware: Vec<Box<dyn ToolCallMiddleware + Send + Sync>> = Vec::new();
+                if let Some(path) = &cfg.audit_log {
+                    middleware.push(Box::new(AuditLogMiddleware::new(path)?));
+                }
+                let server = McpServer::new(lifecycle_manager)
+                    .with_auth(cfg.api_keys.clone())
+                    .with_middleware(middleware)
+                    .with_streamed_results(cfg.stream_results);
+                let _watcher = if cfg.watch {
+                    let watching = server.clone();
+                    Some(
+                        server
+                            .lifecycle_manager
+                            .watch(move || watching.notify_tools_changed())?,
+                    )
+                } else {
+                    None
+                };
 
                 if use_stdio_transport {
                     tracing::info!("Starting MCP server with stdio transport");
@@ -485,12 +640,19 @@ async fn main() -> Result<()> {
                         BIND_ADDRESS
                     );
                     let service = StreamableHttpService::new(
-                        move || Ok(server.clone()),
+                        {
+                            let server = server.clone();
+                            move || Ok(server.clone())
+                        },
                         LocalSessionManager::default().into(),
                         Default::default(),
                     );
 
-                    let router = axum::Router::new().nest_service("/mcp", service);
+                    let router = server.authenticate(
+                        axum::Router::new()
+                            .nest_service("/mcp", service)
+                            .merge(metrics::metrics_router(server.lifecycle_manager.clone())),
+                    );
                     let tcp_listener = tokio::net::TcpListener::bind(BIND_ADDRESS).await?;
                     let _ = axum::serve(tcp_listener, router)
                         .with_graceful_shutdown(async { tokio::signal::ctrl_c().await.unwrap() })
@@ -500,54 +662,109 @@ async fn main() -> Result<()> {
                         "Starting MCP server on {} with SSE HTTP transport",
                         BIND_ADDRESS
                     );
-                    let ct = SseServer::serve(BIND_ADDRESS.parse().unwrap())
-                        .await?
-                        .with_service(move || server.clone());
+                    // Served like `SseServer::serve` does, with authentication in front of it
+                    let (sse_server, router) = SseServer::new(SseServerConfig {
+                        bind: BIND_ADDRESS.parse().unwrap(),
+                        sse_path: "/sse".to_string(),
+                        post_path: "/message".to_string(),
+                        ct: Default::default(),
+                        sse_keep_alive: None,
+                    });
+                    let router = server.authenticate(
+                        router.merge(metrics::metrics_router(server.lifecycle_manager.clone())),
+                    );
+                    let tcp_listener =
+                        tokio::net::TcpListener::bind(sse_server.config.bind).await?;
+                    let http_ct = sse_server.config.ct.child_token();
+                    tokio::spawn(async move {
+                        let shutdown = async move { http_ct.cancelled().await };
+                        if let Err(e) = axum::serve(tcp_listener, router)
+                            .with_graceful_shutdown(shutdown)
+                            .await
+                        {
+                            tracing::error!(error = %e, "SSE server shut down with an error");
+                        }
+                    });
+                    let ct = sse_server.with_service(move || server.clone());
 
                     tokio::signal::ctrl_c().await?;
                     ct.cancel();
                 }
 
                 tracing::info!("MCP server shutting down");
+                if let Some(tracer_provider) = tracer_provider {
+                    if let Err(e) = tracer_provider.shutdown() {
+                        tracing::error!(error = %e, "Failed to export the remaining spans");
+                    }
+                }
             }
             Commands::Component { command } => match command {
-                ComponentCommands::Load { path, plugin_dir } => {
+                ComponentCommands::Load { paths, plugin_dir } => {
                     let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
-                    let mut args = Map::new();
-                    args.insert("path".to_string(), json!(path));
-                    handle_tool_cli_command(
-                        &lifecycle_manager,
-                        "load-component",
-                        args,
-                        OutputFormat::Json,
-                    )
-                    .await?;
+                    if let [path] = paths.as_slice() {
+                        let mut args = Map::new();
+                        args.insert("path".to_string(), json!(path));
+                        handle_tool_cli_command(
+                            &lifecycle_manager,
+                            "load-component",
+                            args,
+                            OutputFormat::Json,
+                        )
+                        .await?;
+                    } else {
+                        let results = lifecycle_manager
+                            .load_components(paths)
+                            .await
+                            .into_iter()
+                            .map(|(path, result)| {
+                                let result = result
+                                    .map(|(id, load_result)| {
+                                        format!(
+                                            "{id} ({})",
+                                            format!("{load_result:?}").to_lowercase()
+                                        )
+                                    })
+                                    .map_err(Into::into);
+                                (path, result)
+                            })
+                            .collect::<Vec<_>>();
+                        print_batch_results(&results)?;
+                    }
                 }
-                ComponentCommands::Unload { id, plugin_dir } => {
+                ComponentCommands::Unload { ids, plugin_dir } => {
                     let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
-                    let mut args = Map::new();
-                    args.insert("id".to_string(), json!(id));
-                    handle_tool_cli_command(
-                        &lifecycle_manager,
-                        "unload-component",
-                        args,
-                        OutputFormat::Json,
-                    )
-                    .await?;
+                    if let [id] = ids.as_slice() {
+                        let mut args = Map::new();
+                        args.insert("id".to_string(), json!(id));
+                        handle_tool_cli_command(
+                            &lifecycle_manager,
+                            "unload-component",
+                            args,
+                            OutputFormat::Json,
+                        )
+                        .await?;
+                    } else {
+                        let results = lifecycle_manager
+                            .unload_components(ids)
+                            .await
+                            .into_iter()
+                            .map(|(id, result)| {
+                                let result =
+                                    result.map(|()| "unloaded".to_string()).map_err(Into::into);
+                                (id, result)
+                            })
+                            .collect::<Vec<_>>();
+                        print_batch_results(&results)?;
+                    }
                 }
                 ComponentCommands::List {
                     plugin_dir,
                     output_format,
+                    verbose,
                 } => {
                     let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
-                    let args = Map::new();
-                    handle_tool_cli_command(
-                        &lifecycle_manager,
-                        "list-components",
-                        args,
-                        *output_format,
-                    )
-                    .await?;
+                    let result = handle_list_components(&lifecycle_manager).await?;
+                    print_result_with_details(&result, *output_format, *verbose)?;
                 }
             },
             Commands::Policy { command } => match command {
@@ -954,6 +1171,30 @@ mod cli_tests {
         let args = vec!["wassette", "component", "list"];
         let cli = Cli::try_parse_from(args).unwrap();
         matches!(cli.command, Some(Commands::Component { .. }));
+        let args = vec!["wassette", "component", "list", "--verbose", "-o", "table"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Commands::Component {
+                command: ComponentCommands::List { verbose, .. },
+            }) => assert!(verbose),
+            _ => panic!("Expected component list command"),
+        }
+
+        // Test loading several components at once
+        let args = vec![
+            "wassette",
+            "component",
+            "load",
+            "file:///tmp/a.wasm",
+            "oci://ghcr.io/example/b:latest",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Some(Commands::Component {
+                command: ComponentCommands::Load { paths, .. },
+            }) => assert_eq!(paths.len(), 2),
+            _ => panic!("Expected component load command"),
+        }
+        assert!(Cli::try_parse_from(vec!["wassette", "component", "load"]).is_err());
 
         // Test policy commands
         let args = vec!["wassette", "policy", "get", "test-component"];
@@ -978,6 +1219,38 @@ mod cli_tests {
         let args = vec!["wassette", "serve", "--sse"];
         let cli = Cli::try_parse_from(args).unwrap();
         matches!(cli.command, Some(Commands::Serve(_)));
+
+        let args = vec!["wassette", "serve", "--stdio", "--watch"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Commands::Serve(cfg)) => assert!(cfg.watch),
+            _ => panic!("Expected serve command"),
+        }
+
+        let args = vec![
+            "wassette",
+            "serve",
+            "--sse",
+            "--api-key",
+            "first",
+            "--api-key",
+            "second",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Commands::Serve(cfg)) => assert_eq!(cfg.api_keys, ["first", "second"]),
+            _ => panic!("Expected serve command"),
+        }
+
+        temp_env::with_var(
+            "WASSETTE_API_KEYS",
+            Some("first,second"),
+            || match Cli::try_parse_from(["wassette", "serve", "--sse"])
+                .unwrap()
+                .command
+            {
+                Some(Commands::Serve(cfg)) => assert_eq!(cfg.api_keys, ["first", "second"]),
+                _ => panic!("Expected serve command"),
+            },
+        );
     }
 
     #[test]
@@ -0,0 +1,70 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The `/metrics` endpoint of the HTTP transports, serving the Prometheus metrics of the
+//! components
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use prometheus::{Encoder as _, TextEncoder};
+use wassette::LifecycleManager;
+
+/// Returns a router serving the metrics of `lifecycle_manager` in the Prometheus text format at
+/// `/metrics`
+pub(crate) fn metrics_router(lifecycle_manager: LifecycleManager) -> Router {
+    Router::new()
+        .route("/metrics", get(serve_metrics))
+        .with_state(lifecycle_manager)
+}
+
+async fn serve_metrics(State(lifecycle_manager): State<LifecycleManager>) -> Response {
+    let encoder = TextEncoder::new();
+    match encoder.encode_to_string(&lifecycle_manager.prometheus_registry().gather()) {
+        Ok(metrics) => (
+            [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+            metrics,
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to encode the metrics");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wassette::test_fixtures::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_are_scraped() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let lifecycle_manager = LifecycleManager::new(tempdir.path().join("plugins")).await?;
+        load_wat_component(&lifecycle_manager, "answer", ANSWER_WAT).await?;
+        lifecycle_manager
+            .execute_component_call("answer", "answer", "{}")
+            .await?;
+
+        let response = serve_metrics(State(lifecycle_manager)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()?
+            .starts_with("text/plain"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let scraped = String::from_utf8(body.to_vec())?;
+        assert!(
+            scraped.contains(
+                r#"wassette_tool_calls_total{component="answer",function="answer",status="ok"} 1"#
+            ),
+            "{scraped}"
+        );
+        assert!(scraped.contains("wassette_components_loaded 1"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Exporting traces of component calls with OpenTelemetry
+
+use anyhow::{Context as _, Result};
+use axum::http::request::Parts;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::Context;
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig as _;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use rmcp::model::Extensions;
+
+/// Returns a tracer provider exporting spans over OTLP/HTTP to `endpoint`, e.g.
+/// `http://localhost:4318/v1/traces`
+pub(crate) fn init_tracer_provider(endpoint: &str) -> Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .context("Failed to create the OTLP exporter")?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("wassette").build())
+        .build())
+}
+
+/// Returns the trace context of the caller of an MCP request, from the `traceparent` and
+/// `tracestate` headers of the HTTP request that carried it. Requests that didn't come over HTTP,
+/// or without these headers, start a new trace.
+pub(crate) fn caller_context(extensions: &Extensions) -> Context {
+    match extensions.get::<Parts>() {
+        Some(parts) => TraceContextPropagator::new().extract(&HeaderExtractor(&parts.headers)),
+        None => Context::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{SpanId, TraceContextExt as _, TraceId};
+
+    use super::*;
+
+    #[test]
+    fn test_caller_context_from_headers() {
+        let (parts, ()) = axum::http::Request::builder()
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(())
+            .unwrap()
+            .into_parts();
+        let mut extensions = Extensions::new();
+        extensions.insert(parts);
+
+        let context = caller_context(&extensions);
+        let span = context.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            span_context.span_id(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+
+        // Without an HTTP request, there is no caller to continue the trace of
+        assert!(!caller_context(&Extensions::new()).has_active_span());
+    }
+}